@@ -4,7 +4,10 @@ extern mod servo;
 use std::test::{TestOpts, run_tests_console, TestDesc};
 use std::getopts::{getopts, reqopt, opt_str, fail_str};
 use os::list_dir_path;
-use servo::run_pipeline_png;
+use servo::run_pipeline_image;
+use servo::image::encode::encoding::PngEncoding;
+use servo::opts::default_viewport;
+use servo::gfx::render_task::DEFAULT_RENDER_WORKERS;
 use servo::image::base::Image;
 
 fn main(args: ~[~str]) {
@@ -140,7 +143,8 @@ fn render_servo(config: Config, file: ~str) -> Image {
     let infile = ~"file://" + os::make_absolute(&Path(file)).to_str();
     let outfilename = Path(file).filename().get().to_str() + ".png";
     let outfile = Path(config.work_dir).push(outfilename).to_str();
-    run_pipeline_png(infile, outfile);
+    run_pipeline_image(infile, outfile, PngEncoding, default_viewport(),
+                       DEFAULT_RENDER_WORKERS);
     return sanitize_image(outfile);
 }
 
@@ -15,7 +15,8 @@ use io::{read_whole_file, println};
 
 use dom::document::Document;
 use dom::node::{Node, NodeScope, define_bindings};
-use dom::event::{Event, ResizeEvent, ReflowEvent};
+use dom::event::{Event, ResizeEvent, ReflowEvent, WheelEvent, KeyScrollEvent, ScrollKey};
+use dom::event::{ScrollLineUp, ScrollLineDown, ScrollPageUp, ScrollPageDown, ScrollHome, ScrollEnd};
 use dom::window::Window;
 use gfx::compositor::Compositor;
 use html::lexer::spawn_html_lexer_task;
@@ -30,6 +31,8 @@ use js::rust::{cx, methods};
 use js::global::{global_class, debug_fns};
 
 use either::{Either, Left, Right};
+use geom::point::Point2D;
+use geom::size::Size2D;
 
 use dom::bindings::utils::rust_box;
 use js::rust::compartment;
@@ -48,6 +51,9 @@ use js::jsapi::{JSContext, jsval};
 use js::jsapi::bindgen::{JS_CallFunctionValue, JS_GetContextPrivate};
 use ptr::null;
 
+// How far the arrow keys scroll
+const SCROLL_LINE_PX: int = 40;
+
 enum ControlMsg {
     ParseMsg(Url),
     ExecuteMsg(Url),
@@ -64,9 +70,11 @@ type ContentTask = Chan<ControlMsg>;
 fn ContentTask<S: Compositor Send Copy>(layout_task: LayoutTask,
                                         +compositor: S,
                                         resource_task: ResourceTask,
-                                        img_cache_task: ImageCacheTask) -> ContentTask {
+                                        img_cache_task: ImageCacheTask,
+                                        window_size: Size2D<int>) -> ContentTask {
     do task().sched_mode(SingleThreaded).spawn_listener::<ControlMsg> |from_master| {
-        let content = Content(layout_task, from_master, resource_task, img_cache_task);
+        let content = Content(layout_task, from_master, resource_task, img_cache_task,
+                              window_size);
         compositor.add_event_listener(content.event_port.chan());
         content.start();
     }
@@ -85,6 +93,10 @@ struct Content {
     mut document: Option<@Document>,
     mut window:   Option<@Window>,
     mut doc_url: Option<Url>,
+    // The size of the window, in px, which media queries are evaluated against
+    mut window_size: Size2D<int>,
+    // The point of the document at the top left of the window, in px
+    mut scroll_position: Point2D<int>,
 
     resource_task: ResourceTask,
 
@@ -94,7 +106,8 @@ struct Content {
 fn Content(layout_task: LayoutTask, 
            from_master: Port<ControlMsg>,
            resource_task: ResourceTask,
-           img_cache_task: ImageCacheTask) -> Content {
+           img_cache_task: ImageCacheTask,
+           window_size: Size2D<int>) -> Content {
     
     let jsrt = jsrt();
     let cx = jsrt.cx();
@@ -121,6 +134,8 @@ fn Content(layout_task: LayoutTask,
         document : None,
         window   : None,
         doc_url  : None,
+        window_size : window_size,
+        scroll_position : Point2D(0, 0),
 
         resource_task : resource_task,
         compartment : compartment
@@ -174,6 +189,7 @@ impl Content {
 
             let document = Document(root, self.scope, css_rules);
             let window   = Window(self.from_master);
+            self.scroll_position = Point2D(0, 0);
             self.relayout(document, &url);
             self.document = Some(@document);
             self.window   = Some(@window);
@@ -259,7 +275,9 @@ impl Content {
 
         // Send new document and relevant styles to layout
         // FIXME: Put CSS rules in an arc or something.
-        self.layout_task.send(BuildMsg(document.root, clone(&document.css_rules), copy *doc_url, self.event_port.chan()));
+        self.layout_task.send(BuildMsg(document.root, clone(&document.css_rules), copy *doc_url,
+                                       self.event_port.chan(), copy self.window_size,
+                                       copy self.scroll_position));
 
         // Indicate that reader was forked so any further
         // changes will be isolated.
@@ -268,13 +286,65 @@ impl Content {
 
      fn query_layout(query: layout_task::LayoutQuery) -> layout_task::LayoutQueryResponse {
          self.relayout(*self.document.get(), &self.doc_url.get());
+         self.query_last_layout(query)
+    }
+
+    /**
+       Asks layout about the last layout it did, without laying the document
+       out again.
+    */
+    fn query_last_layout(query: layout_task::LayoutQuery) -> layout_task::LayoutQueryResponse {
          self.join_layout();
-         
+
          let response_port = Port();
          self.layout_task.send(layout_task::QueryMsg(query, response_port.chan()));
          return response_port.recv()
     }
 
+    /**
+       Scrolls the window so that this point of the document is at its top left,
+       as far as the size of the document allows. The document is not laid out
+       again: layout asks for that when the display list it has does not cover
+       what comes into view.
+    */
+    fn scroll_to(x: int, y: int) {
+        let document = match copy self.document {
+            Some(document) => document,
+            None => return
+        };
+
+        let size = match self.query_last_layout(layout_task::DocumentSize(document.root)) {
+            Ok(layout_task::ContentSize(size)) => size,
+            Err(()) => Size2D(0, 0)
+        };
+        let max_x = int::max(0, size.width - self.window_size.width);
+        let max_y = int::max(0, size.height - self.window_size.height);
+        let position = Point2D(int::min(int::max(0, x), max_x), int::min(int::max(0, y), max_y));
+
+        if position != self.scroll_position {
+            debug!("content: scrolling to %d, %d", position.x, position.y);
+            self.scroll_position = position;
+            self.layout_task.send(layout_task::ScrollMsg(position));
+        }
+    }
+
+    fn scroll_by(dx: int, dy: int) {
+        self.scroll_to(self.scroll_position.x + dx, self.scroll_position.y + dy);
+    }
+
+    fn scroll_by_key(key: ScrollKey) {
+        // Paging keeps a line of what was in view
+        let page = int::max(SCROLL_LINE_PX, self.window_size.height - SCROLL_LINE_PX);
+        match key {
+            ScrollLineUp => self.scroll_by(0, -SCROLL_LINE_PX),
+            ScrollLineDown => self.scroll_by(0, SCROLL_LINE_PX),
+            ScrollPageUp => self.scroll_by(0, -page),
+            ScrollPageDown => self.scroll_by(0, page),
+            ScrollHome => self.scroll_to(self.scroll_position.x, 0),
+            ScrollEnd => self.scroll_to(self.scroll_position.x, int::max_value)
+        }
+    }
+
     /**
        This is the main entry point for receiving and dispatching DOM events.
     */
@@ -283,6 +353,7 @@ impl Content {
         match event {
           ResizeEvent(new_width, new_height) => {
             debug!("content got resize event: %d, %d", new_width, new_height);
+            self.window_size = Size2D(new_width, new_height);
             match copy self.document {
                 None => {
                     // Nothing to do.
@@ -290,6 +361,8 @@ impl Content {
                 Some(document) => {
                     assert self.doc_url.is_some();
                     self.relayout(*document, &self.doc_url.get());
+                    // A larger window may show past the end of the document
+                    self.scroll_to(self.scroll_position.x, self.scroll_position.y);
                 }
             }
             return true;
@@ -307,6 +380,16 @@ impl Content {
             }
             return true;
           }
+          WheelEvent(dx, dy) => {
+            debug!("content got wheel event: %d, %d", dx, dy);
+            self.scroll_by(dx, dy);
+            return true;
+          }
+          KeyScrollEvent(key) => {
+            debug!("content got scroll key event");
+            self.scroll_by_key(key);
+            return true;
+          }
         }
     }
 }
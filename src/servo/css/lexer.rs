@@ -1,252 +1,557 @@
-#[doc = "Code to lex and tokenize css files."]
+#[doc = "Code to lex and tokenize css files, following CSS Syntax Level 3, Section 4."]
 
-use option::is_none;
-use str::from_bytes;
+use str::from_chars;
 use vec::push;
 
 use pipes::{Port, Chan};
 
-use html::lexer_util::*;
-
 use std::net::url::Url;
-use resource::resource_task::{ResourceTask, ProgressMsg, Load};
-
-enum ParserState {
-    CssElement,
-    CssRelation,
-    CssDescription,
-    CssAttribute
-}
-
-type CssLexer = {
-    input_state: InputState,
-    mut parser_state: ParserState
-};
+use resource::resource_task::{ResourceTask, ProgressMsg, Load, Payload, Done};
 
 enum Token {
-    StartDescription,
-    EndDescription,
-    Descendant,
-    Child,
-    Sibling,
+    Ident(~str),
+    Function(~str),
+    AtKeyword(~str),
+    Hash(~str),
+    QuotedString(~str),
+    // A string interrupted by a newline
+    BadString,
+    Url(~str),
+    BadUrl,
+    Delim(char),
+    // Numeric tokens keep the text they were written as, since the value
+    // parsers (and the An+B microsyntax) care about things like signs.
+    Number(float, ~str),
+    Percentage(float, ~str),
+    Dimension(float, ~str, ~str),
+    WhiteSpace,
+    CDO,
+    CDC,
+    Colon,
+    Semicolon,
     Comma,
-    Element(~str),
-    Attr(css::values::Attr), 
-    Description(~str, ~str),
+    LeftBracket,
+    RightBracket,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
     Eof
 }
 
-trait CssLexerMethods {
-    fn parse_css() -> Token;
-    fn parse_css_relation(c : u8) -> Token;
-    fn parse_css_element(c : u8) -> Token;
-    fn parse_css_attribute(c : u8) -> Token;
-    fn parse_css_description(c: u8) -> Token;
+// Preprocessing replaces NUL, so it can stand in for the end of the input
+const EOF_CHAR: char = '\x00';
+const REPLACEMENT_CHAR: char = '\uFFFD';
+
+pure fn is_whitespace(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n'
 }
 
-impl CssLexer : CssLexerMethods {
-    fn parse_css() -> Token {
-        let mut ch: u8;
-        match self.input_state.get() {
-            CoeChar(c) => ch = c,
-            CoeEof => { return Eof; }
-        }
+pure fn is_digit(c: char) -> bool {
+    c >= '0' && c <= '9'
+}
 
-        let token = match self.parser_state {
-          CssDescription => self.parse_css_description(ch),
-          CssAttribute => self.parse_css_attribute(ch),
-          CssElement => self.parse_css_element(ch),
-          CssRelation => self.parse_css_relation(ch)
-        };
+pure fn hex_value(c: char) -> Option<uint> {
+    if is_digit(c) { Some(c as uint - '0' as uint) }
+    else if c >= 'a' && c <= 'f' { Some(c as uint - 'a' as uint + 10u) }
+    else if c >= 'A' && c <= 'F' { Some(c as uint - 'A' as uint + 10u) }
+    else { None }
+}
 
-        #debug["token=%?", token];
-        return token;
-    }
+pure fn is_name_start(c: char) -> bool {
+    (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_' || c as uint >= 0x80u
+}
 
-    fn parse_css_relation(c : u8) -> Token {
-        self.parser_state = CssElement;
+pure fn is_name_char(c: char) -> bool {
+    is_name_start(c) || is_digit(c) || c == '-'
+}
 
-        let token = match c {
-          '{' as u8 => { self.parser_state = CssDescription; StartDescription }
-          '>' as u8 => { Child }
-          '+' as u8 => { Sibling }
-          ',' as u8 => { Comma }
-          _ => { self.input_state.unget(c); Descendant }
-        };
+pure fn is_non_printable(c: char) -> bool {
+    let c = c as uint;
+    c <= 0x8u || c == 0xBu || (c >= 0xEu && c <= 0x1Fu) || c == 0x7Fu
+}
+
+pure fn is_valid_escape(c1: char, c2: char) -> bool {
+    c1 == '\\' && c2 != '\n'
+}
 
-        self.input_state.eat_whitespace();
-        
-        return token;
+pure fn would_start_identifier(c1: char, c2: char, c3: char) -> bool {
+    if c1 == '-' {
+        is_name_start(c2) || c2 == '-' || is_valid_escape(c2, c3)
+    } else if c1 == '\\' {
+        is_valid_escape(c1, c2)
+    } else {
+        is_name_start(c1)
     }
+}
+
+pure fn would_start_number(c1: char, c2: char, c3: char) -> bool {
+    if c1 == '+' || c1 == '-' {
+        is_digit(c2) || (c2 == '.' && is_digit(c3))
+    } else if c1 == '.' {
+        is_digit(c2)
+    } else {
+        is_digit(c1)
+    }
+}
 
-    fn parse_css_element(c : u8) -> Token {
-        assert is_none(self.input_state.lookahead);
-
-        /* Check for special attributes with an implied element,
-        or a wildcard which is not a alphabet character.*/
-        if c == '.' as u8 || c == '#' as u8 {
-            self.parser_state = CssAttribute;
-            self.input_state.unget(c);
-            return Element(~"*");
-        } else if c == '*' as u8 {
-            self.parser_state = CssAttribute;
-            return Element(~"*");
+#[doc = "Normalizes newlines and replaces NUL characters (CSS Syntax Level 3, Section 3.3)."]
+fn preprocess(input: &str) -> ~[char] {
+    let source = str::chars(input);
+    let mut chars = ~[];
+    let mut i = 0u;
+    while i < source.len() {
+        let c = source[i];
+        if c == '\r' {
+            push(chars, '\n');
+            if i + 1u < source.len() && source[i + 1u] == '\n' { i += 1u; }
+        } else if c == '\x0c' {
+            push(chars, '\n');
+        } else if c == EOF_CHAR {
+            push(chars, REPLACEMENT_CHAR);
+        } else {
+            push(chars, c);
         }
+        i += 1u;
+    }
+    return chars;
+}
 
-        self.input_state.unget(c);
-        let element = self.input_state.parse_ident();
+#[doc = "Decodes a stylesheet as UTF-8, falling back to Latin-1 which can decode anything."]
+fn decode_stylesheet(bytes: &[u8]) -> ~str {
+    if str::is_utf8(bytes) {
+        str::from_bytes(bytes)
+    } else {
+        from_chars(bytes.map(|b| *b as char))
+    }
+}
 
-        self.parser_state = CssAttribute;
-        
-        return Element(element);
+type CssTokenizer = {
+    input: ~[char],
+    mut pos: uint
+};
+
+fn CssTokenizer(input: &str) -> CssTokenizer {
+    {input: preprocess(input), mut pos: 0u}
+}
+
+trait CssTokenizerMethods {
+    fn char_at(offset: uint) -> char;
+    fn consume() -> char;
+    fn reconsume();
+    fn next_token() -> Token;
+    fn consume_comments();
+    fn consume_name() -> ~str;
+    fn consume_escape() -> char;
+    fn consume_number() -> (float, ~str);
+    fn consume_digits();
+    fn consume_numeric() -> Token;
+    fn consume_ident_like() -> Token;
+    fn consume_string(ending: char) -> Token;
+    fn consume_url() -> Token;
+    fn consume_bad_url_remnants();
+}
+
+impl CssTokenizer : CssTokenizerMethods {
+    fn char_at(offset: uint) -> char {
+        let i = self.pos + offset;
+        if i < self.input.len() { self.input[i] } else { EOF_CHAR }
     }
 
-    fn parse_css_attribute(c : u8) -> Token {
-        let mut ch = c;
-        
-        /* If we've reached the end of this list of attributes,
-        look for the relation to the next element.*/
-        if c.is_whitespace() {
-            self.parser_state = CssRelation;
-            self.input_state.eat_whitespace();
-
-            match self.input_state.get() {
-              CoeChar(c) => { ch = c }
-              CoeEof => { fail ~"File ended before description of style" }
-            }
+    fn consume() -> char {
+        let c = self.char_at(0u);
+        self.pos += 1u;
+        return c;
+    }
 
-            return self.parse_css_relation(ch);
+    fn reconsume() {
+        self.pos -= 1u;
+    }
+
+    fn next_token() -> Token {
+        self.consume_comments();
+        if self.pos >= self.input.len() { return Eof; }
+
+        let c = self.consume();
+        if is_whitespace(c) {
+            while is_whitespace(self.char_at(0u)) { self.pos += 1u; }
+            return WhiteSpace;
+        }
+        if is_digit(c) {
+            self.reconsume();
+            return self.consume_numeric();
+        }
+        if is_name_start(c) {
+            self.reconsume();
+            return self.consume_ident_like();
         }
-        
-        match ch {
-          '.' as u8 => return Attr(css::values::Includes(~"class", self.input_state.parse_ident())),
-          '#' as u8 => return Attr(css::values::Includes(~"id", self.input_state.parse_ident())),
-          '[' as u8 => {
-            let attr_name = self.input_state.parse_ident();
-            
-            match self.input_state.get() {
-              CoeChar(c) => { ch = c; }
-              CoeEof => { fail ~"File ended before description finished"; }
-            }
 
-            if ch == ']' as u8 {
-                return Attr(css::values::Exists(attr_name));
-            } else if ch == '=' as u8 {
-                let attr_val = self.input_state.parse_ident();
-                self.input_state.expect(']' as u8);
-                return Attr(css::values::Exact(attr_name, attr_val));
-            } else if ch == '~' as u8 {
-                self.input_state.expect('=' as u8);
-                let attr_val = self.input_state.parse_ident();
-                self.input_state.expect(']' as u8);
-                return Attr(css::values::Includes(attr_name, attr_val));
-            } else if ch == '|' as u8 {
-                self.input_state.expect('=' as u8);
-                let attr_val = self.input_state.parse_ident();
-                self.input_state.expect(']' as u8);
-                return Attr(css::values::StartsWith(attr_name, attr_val));
+        match c {
+          '"' | '\'' => self.consume_string(c),
+          '#' => {
+            if is_name_char(self.char_at(0u)) || is_valid_escape(self.char_at(0u), self.char_at(1u)) {
+                Hash(self.consume_name())
+            } else {
+                Delim(c)
             }
-            
-            fail #fmt("Unexpected symbol %c in attribute", ch as char);
           }
-          _ => { fail #fmt("Unexpected symbol %c in attribute", ch as char); }
+          '(' => LeftParen,
+          ')' => RightParen,
+          '[' => LeftBracket,
+          ']' => RightBracket,
+          '{' => LeftBrace,
+          '}' => RightBrace,
+          ',' => Comma,
+          ':' => Colon,
+          ';' => Semicolon,
+          '+' | '.' => {
+            if would_start_number(c, self.char_at(0u), self.char_at(1u)) {
+                self.reconsume();
+                self.consume_numeric()
+            } else {
+                Delim(c)
+            }
+          }
+          '-' => {
+            if would_start_number(c, self.char_at(0u), self.char_at(1u)) {
+                self.reconsume();
+                self.consume_numeric()
+            } else if self.char_at(0u) == '-' && self.char_at(1u) == '>' {
+                self.pos += 2u;
+                CDC
+            } else if would_start_identifier(c, self.char_at(0u), self.char_at(1u)) {
+                self.reconsume();
+                self.consume_ident_like()
+            } else {
+                Delim(c)
+            }
+          }
+          '<' => {
+            if self.char_at(0u) == '!' && self.char_at(1u) == '-' && self.char_at(2u) == '-' {
+                self.pos += 3u;
+                CDO
+            } else {
+                Delim(c)
+            }
+          }
+          '@' => {
+            if would_start_identifier(self.char_at(0u), self.char_at(1u), self.char_at(2u)) {
+                AtKeyword(self.consume_name())
+            } else {
+                Delim(c)
+            }
+          }
+          '\\' => {
+            if is_valid_escape(c, self.char_at(0u)) {
+                self.reconsume();
+                self.consume_ident_like()
+            } else {
+                #debug("Invalid escape in CSS");
+                Delim(c)
+            }
+          }
+          _ => Delim(c)
         }
     }
 
-    fn parse_css_description(c: u8) -> Token {
-        let mut ch = c;
-
-        if ch == '}' as u8 {
-            self.parser_state = CssElement;
-            self.input_state.eat_whitespace();
-            return EndDescription;
-        } else if ch.is_whitespace() {
-            self.input_state.eat_whitespace();
-
-            match self.input_state.get() {
-              CoeChar(c) => { ch = c }
-              CoeEof => { fail ~"Reached end of file in CSS description" }
+    fn consume_comments() {
+        while self.char_at(0u) == '/' && self.char_at(1u) == '*' {
+            self.pos += 2u;
+            loop {
+                // An unterminated comment runs to the end of the input
+                if self.pos >= self.input.len() { return; }
+                if self.char_at(0u) == '*' && self.char_at(1u) == '/' {
+                    self.pos += 2u;
+                    break;
+                }
+                self.pos += 1u;
             }
         }
-        
-        let mut desc_name = ~[];
-        
-        // Get the name of the descriptor
+    }
+
+    fn consume_name() -> ~str {
+        let mut name = ~[];
         loop {
-            if ch.is_whitespace() {
-                self.input_state.eat_whitespace();
-            } else if ch == ':' as u8 {
-                if desc_name.len() == 0u {
-                    fail ~"Expected descriptor name";
-                } else {
-                    break;
+            let c = self.char_at(0u);
+            if is_name_char(c) {
+                self.pos += 1u;
+                push(name, c);
+            } else if is_valid_escape(c, self.char_at(1u)) {
+                self.pos += 1u;
+                push(name, self.consume_escape());
+            } else {
+                return from_chars(name);
+            }
+        }
+    }
+
+    // Consumes an escaped code point; the backslash has already been consumed
+    fn consume_escape() -> char {
+        let c = self.consume();
+        match hex_value(c) {
+          Some(digit) => {
+            let mut value = digit;
+            let mut count = 1u;
+            while count < 6u {
+                match hex_value(self.char_at(0u)) {
+                  Some(digit) => { value = value * 16u + digit; self.pos += 1u; }
+                  None => { break; }
                 }
+                count += 1u;
+            }
+            if is_whitespace(self.char_at(0u)) { self.pos += 1u; }
+
+            if value == 0u || (value >= 0xD800u && value <= 0xDFFFu) || value > 0x10FFFFu {
+                REPLACEMENT_CHAR
             } else {
-                push(desc_name, ch);
+                value as char
+            }
+          }
+          None if c == EOF_CHAR => REPLACEMENT_CHAR,
+          None => c
+        }
+    }
+
+    fn consume_number() -> (float, ~str) {
+        let start = self.pos;
+        if self.char_at(0u) == '+' || self.char_at(0u) == '-' { self.pos += 1u; }
+        self.consume_digits();
+        if self.char_at(0u) == '.' && is_digit(self.char_at(1u)) {
+            self.pos += 1u;
+            self.consume_digits();
+        }
+        let (e, next) = (self.char_at(0u), self.char_at(1u));
+        if e == 'e' || e == 'E' {
+            if is_digit(next) {
+                self.pos += 1u;
+                self.consume_digits();
+            } else if (next == '+' || next == '-') && is_digit(self.char_at(2u)) {
+                self.pos += 2u;
+                self.consume_digits();
             }
+        }
+
+        let repr = from_chars(vec::slice(self.input, start, self.pos));
+        // float::from_str doesn't accept an explicit '+'
+        let digits = if repr.starts_with(~"+") { repr.substr(1u, repr.len() - 1u) } else { copy repr };
+        let value = match float::from_str(digits) {
+          Some(value) => value,
+          None => { #debug("Couldn't convert number '%s'", repr); 0.0 }
+        };
+        return (value, repr);
+    }
+
+    fn consume_digits() {
+        while is_digit(self.char_at(0u)) { self.pos += 1u; }
+    }
+
+    fn consume_numeric() -> Token {
+        let (value, repr) = self.consume_number();
+        if would_start_identifier(self.char_at(0u), self.char_at(1u), self.char_at(2u)) {
+            Dimension(value, repr, self.consume_name())
+        } else if self.char_at(0u) == '%' {
+            self.pos += 1u;
+            Percentage(value, repr)
+        } else {
+            Number(value, repr)
+        }
+    }
+
+    fn consume_ident_like() -> Token {
+        let name = self.consume_name();
+        if self.char_at(0u) != '(' { return Ident(name); }
+        self.pos += 1u;
 
-            match self.input_state.get() {
-              CoeChar(c) => { ch = c }
-              CoeEof => { fail ~"Reached end of file in CSS description" }
+        if name.to_lower() == ~"url" {
+            while is_whitespace(self.char_at(0u)) && is_whitespace(self.char_at(1u)) {
+                self.pos += 1u;
+            }
+            // A quoted url is an ordinary function taking a string
+            let (c1, c2) = (self.char_at(0u), self.char_at(1u));
+            if c1 == '"' || c1 == '\'' || (is_whitespace(c1) && (c2 == '"' || c2 == '\'')) {
+                return Function(name);
             }
+            return self.consume_url();
         }
 
-        self.input_state.eat_whitespace();
-        let mut desc_val = ~[];
+        return Function(name);
+    }
 
-        // Get the value of the descriptor
+    fn consume_string(ending: char) -> Token {
+        let mut value = ~[];
         loop {
-            match self.input_state.get() {
-              CoeChar(c) => { ch = c }
-              CoeEof => { fail ~"Reached end of file in CSS description" }
+            let c = self.consume();
+            if c == EOF_CHAR || c == ending {
+                return QuotedString(from_chars(value));
+            } else if c == '\n' {
+                self.reconsume();
+                return BadString;
+            } else if c == '\\' {
+                let next = self.char_at(0u);
+                if next == '\n' {
+                    // An escaped newline continues the string
+                    self.pos += 1u;
+                } else if next != EOF_CHAR {
+                    push(value, self.consume_escape());
+                }
+            } else {
+                push(value, c);
             }
+        }
+    }
 
-            if ch.is_whitespace() {
-                self.input_state.eat_whitespace();
-            } else if ch == '}' as u8 {
-                if desc_val.len() == 0u {
-                    fail ~"Expected descriptor value";
-                } else {
-                    self.input_state.unget('}' as u8);
-                    break;
+    fn consume_url() -> Token {
+        let mut value = ~[];
+        while is_whitespace(self.char_at(0u)) { self.pos += 1u; }
+
+        loop {
+            let c = self.consume();
+            if c == ')' || c == EOF_CHAR {
+                return Url(from_chars(value));
+            } else if is_whitespace(c) {
+                while is_whitespace(self.char_at(0u)) { self.pos += 1u; }
+                let next = self.char_at(0u);
+                if next == ')' || next == EOF_CHAR {
+                    self.pos += 1u;
+                    return Url(from_chars(value));
                 }
-            } else if ch == ';' as u8 {
-                if desc_val.len() == 0u {
-                    fail ~"Expected descriptor value";
-                } else {
-                    break;
+                self.consume_bad_url_remnants();
+                return BadUrl;
+            } else if c == '"' || c == '\'' || c == '(' || is_non_printable(c) {
+                self.consume_bad_url_remnants();
+                return BadUrl;
+            } else if c == '\\' {
+                if !is_valid_escape(c, self.char_at(0u)) {
+                    self.consume_bad_url_remnants();
+                    return BadUrl;
                 }
+                push(value, self.consume_escape());
             } else {
-                push(desc_val, ch);
+                push(value, c);
             }
         }
+    }
 
-        return Description(from_bytes(desc_name), from_bytes(desc_val));
+    fn consume_bad_url_remnants() {
+        loop {
+            let c = self.consume();
+            if c == ')' || c == EOF_CHAR { return; }
+            if is_valid_escape(c, self.char_at(0u)) { self.consume_escape(); }
+        }
     }
 }
 
-fn parser(input_port: comm::Port<ProgressMsg>, state : ParserState) -> CssLexer {
-    return {
-           input_state: {
-               mut lookahead: None,
-               mut buffer: ~[],
-               input_port: input_port,
-               mut eof: false
-           },
-           mut parser_state: state
-    };
+trait TokenMethods {
+    fn to_css_str() -> ~str;
+}
+
+impl Token : TokenMethods {
+    #[doc = "Writes this token back out as CSS text."]
+    fn to_css_str() -> ~str {
+        match copy self {
+          Ident(name) => name,
+          Function(name) => name + ~"(",
+          AtKeyword(name) => ~"@" + name,
+          Hash(name) => ~"#" + name,
+          QuotedString(value) => ~"\"" + value + ~"\"",
+          Url(value) => ~"url(" + value + ~")",
+          BadString | BadUrl | Eof => ~"",
+          Delim(c) => str::from_char(c),
+          Number(_, repr) => repr,
+          Percentage(_, repr) => repr + ~"%",
+          Dimension(_, repr, unit) => repr + unit,
+          WhiteSpace => ~" ",
+          CDO => ~"<!--",
+          CDC => ~"-->",
+          Colon => ~":",
+          Semicolon => ~";",
+          Comma => ~",",
+          LeftBracket => ~"[",
+          RightBracket => ~"]",
+          LeftParen => ~"(",
+          RightParen => ~")",
+          LeftBrace => ~"{",
+          RightBrace => ~"}"
+        }
+    }
+}
+
+impl Token : cmp::Eq {
+    pure fn eq(other: &Token) -> bool {
+        match (copy self, copy *other) {
+          (Ident(a), Ident(b))
+          | (Function(a), Function(b))
+          | (AtKeyword(a), AtKeyword(b))
+          | (Hash(a), Hash(b))
+          | (QuotedString(a), QuotedString(b))
+          | (Url(a), Url(b)) => a == b,
+
+          (Delim(a), Delim(b)) => a == b,
+
+          (Number(a, a_repr), Number(b, b_repr))
+          | (Percentage(a, a_repr), Percentage(b, b_repr)) => a == b && a_repr == b_repr,
+          (Dimension(a, a_repr, a_unit), Dimension(b, b_repr, b_unit)) => {
+            a == b && a_repr == b_repr && a_unit == b_unit
+          }
+
+          (BadString, BadString) | (BadUrl, BadUrl) | (WhiteSpace, WhiteSpace)
+          | (CDO, CDO) | (CDC, CDC) | (Colon, Colon) | (Semicolon, Semicolon)
+          | (Comma, Comma) | (LeftBracket, LeftBracket) | (RightBracket, RightBracket)
+          | (LeftParen, LeftParen) | (RightParen, RightParen)
+          | (LeftBrace, LeftBrace) | (RightBrace, RightBrace) | (Eof, Eof) => true,
+
+          (Ident(*), _)
+          | (Function(*), _)
+          | (AtKeyword(*), _)
+          | (Hash(*), _)
+          | (QuotedString(*), _)
+          | (BadString, _)
+          | (Url(*), _)
+          | (BadUrl, _)
+          | (Delim(*), _)
+          | (Number(*), _)
+          | (Percentage(*), _)
+          | (Dimension(*), _)
+          | (WhiteSpace, _)
+          | (CDO, _)
+          | (CDC, _)
+          | (Colon, _)
+          | (Semicolon, _)
+          | (Comma, _)
+          | (LeftBracket, _)
+          | (RightBracket, _)
+          | (LeftParen, _)
+          | (RightParen, _)
+          | (LeftBrace, _)
+          | (RightBrace, _)
+          | (Eof, _) => false
+        }
+    }
+    pure fn ne(other: &Token) -> bool {
+        return !self.eq(other);
+    }
 }
 
 fn lex_css_from_bytes(+input_port: comm::Port<ProgressMsg>, result_chan : Chan<Token>) {
-    let lexer = parser(input_port, CssElement);
+    // Stylesheets are small, so gather the whole thing before decoding it
+    let mut bytes = ~[];
+    loop {
+        match input_port.recv() {
+          Payload(data) => { bytes += data; }
+          Done(*) => { break; }
+        }
+    }
+
+    let tokenizer = CssTokenizer(decode_stylesheet(bytes));
 
     loop {
-        let token = lexer.parse_css();
+        let token = tokenizer.next_token();
         let should_break = match token { Eof => true, _ => false };
 
+        #debug["token=%?", token];
         result_chan.send(token);
 
-        if should_break { 
+        if should_break {
             break;
         }
     }
@@ -271,7 +576,6 @@ fn spawn_css_lexer_task(-url: Url, resource_task: ResourceTask) -> pipes::Port<T
     let (result_chan, result_port) = pipes::stream();
 
     do task::spawn || {
-        assert url.path.ends_with(".css");
         let input_port = Port();
         // TODO: change copy to move once the compiler permits it
         resource_task.send(Load(copy url, input_port.chan()));
@@ -281,3 +585,69 @@ fn spawn_css_lexer_task(-url: Url, resource_task: ResourceTask) -> pipes::Port<T
 
     return result_port;
 }
+
+#[cfg(test)]
+mod test {
+    fn tokenize(input: ~str) -> ~[Token] {
+        let tokenizer = CssTokenizer(input);
+        let mut tokens = ~[];
+        loop {
+            match tokenizer.next_token() {
+              Eof => { return tokens; }
+              token => { push(tokens, token); }
+            }
+        }
+    }
+
+    #[test]
+    fn should_tokenize_rules() {
+        assert tokenize(~"p.a > #b{color:red}") ==
+            ~[Ident(~"p"), Delim('.'), Ident(~"a"), WhiteSpace, Delim('>'), WhiteSpace,
+              Hash(~"b"), LeftBrace, Ident(~"color"), Colon, Ident(~"red"), RightBrace];
+        assert tokenize(~"@media screen{}") ==
+            ~[AtKeyword(~"media"), WhiteSpace, Ident(~"screen"), LeftBrace, RightBrace];
+        assert tokenize(~"rgb(1,2)") ==
+            ~[Function(~"rgb"), Number(1.0, ~"1"), Comma, Number(2.0, ~"2"), RightParen];
+    }
+
+    #[test]
+    fn should_tokenize_numbers() {
+        assert tokenize(~"12px 50% -1.5 +3 1e2 .5em") ==
+            ~[Dimension(12.0, ~"12", ~"px"), WhiteSpace, Percentage(50.0, ~"50"), WhiteSpace,
+              Number(-1.5, ~"-1.5"), WhiteSpace, Number(3.0, ~"+3"), WhiteSpace,
+              Number(100.0, ~"1e2"), WhiteSpace, Dimension(0.5, ~".5", ~"em")];
+        // The 'n' of an An+B expression starts a dimension
+        assert tokenize(~"2n+1") == ~[Dimension(2.0, ~"2", ~"n"), Number(1.0, ~"+1")];
+    }
+
+    #[test]
+    fn should_tokenize_strings_and_escapes() {
+        assert tokenize(~"'a\\'b' \"c\\\nd\"") ==
+            ~[QuotedString(~"a'b"), WhiteSpace, QuotedString(~"cd")];
+        assert tokenize(~"\"bad\nx") == ~[BadString, WhiteSpace, Ident(~"x")];
+        assert tokenize(~"\\31 23 a\\-b") == ~[Ident(~"123"), WhiteSpace, Ident(~"a-b")];
+        assert tokenize(~"\"unterminated") == ~[QuotedString(~"unterminated")];
+    }
+
+    #[test]
+    fn should_tokenize_urls() {
+        assert tokenize(~"url(foo.png) url( bar.png )") ==
+            ~[Url(~"foo.png"), WhiteSpace, Url(~"bar.png")];
+        assert tokenize(~"url('x')") ==
+            ~[Function(~"url"), QuotedString(~"x"), RightParen];
+        assert tokenize(~"url(a b) c") == ~[BadUrl, WhiteSpace, Ident(~"c")];
+    }
+
+    #[test]
+    fn should_skip_comments() {
+        assert tokenize(~"<!-- /* hi */a/**/b -->") ==
+            ~[CDO, WhiteSpace, Ident(~"a"), Ident(~"b"), WhiteSpace, CDC];
+        assert tokenize(~"a /* unterminated") == ~[Ident(~"a"), WhiteSpace];
+    }
+
+    #[test]
+    fn should_normalize_newlines() {
+        assert tokenize(~"a\r\n\r\x0cb") == ~[Ident(~"a"), WhiteSpace, Ident(~"b")];
+        assert tokenize(~"'a\r\nb'") == ~[BadString, WhiteSpace, Ident(~"b"), QuotedString(~"")];
+    }
+}
@@ -0,0 +1,129 @@
+/*!
+Evaluates media queries (Media Queries, Section 3) against the viewport, and walks the
+rules of a stylesheet that apply to it.
+*/
+
+use au = gfx::geometry;
+use au::au;
+use css::resolve::apply::MEDIUM_FONT_SIZE;
+use css::values::*;
+use geom::size::Size2D;
+
+// Relative lengths in media queries are relative to the initial font size
+pure fn media_length_px(length: Length) -> float {
+    match length {
+      Px(x) => x,
+      Em(x) => x * MEDIUM_FONT_SIZE
+    }
+}
+
+impl MediaExpression {
+    pure fn matches(viewport: &Size2D<au>) -> bool {
+        let width = au::to_frac_px(viewport.width);
+        let height = au::to_frac_px(viewport.height);
+
+        match self {
+          MinWidth(length) => width >= media_length_px(length),
+          MaxWidth(length) => width <= media_length_px(length),
+          MinHeight(length) => height >= media_length_px(length),
+          MaxHeight(length) => height <= media_length_px(length)
+        }
+    }
+}
+
+impl MediaQuery {
+    pure fn matches(viewport: &Size2D<au>) -> bool {
+        let type_matches = match self.media_type {
+          AllMedia | ScreenMedia => true,
+          PrintMedia | UnknownMedia => false
+        };
+        let matches = type_matches && self.expressions.all(|expr| expr.matches(viewport));
+        matches != self.negated
+    }
+}
+
+pure fn media_list_matches(media: &[MediaQuery], viewport: &Size2D<au>) -> bool {
+    media.is_empty() || media.any(|query| query.matches(viewport))
+}
+
+/**
+   Calls `f` on each rule in `rules`, in order, that applies to the viewport, looking
+   inside the @media rules that do. Unloaded @import rules are skipped.
+*/
+fn each_applicable_rule(rules: &[~CSSRule], viewport: &Size2D<au>,
+                        f: fn(&CSSRule) -> bool) -> bool {
+    for rules.each |rule| {
+        match **rule {
+          MediaRule(ref media, ref nested) => {
+            if media_list_matches(*media, viewport) {
+                if !each_applicable_rule(*nested, viewport, f) { return false; }
+            }
+          }
+          ImportRule(*) => { }
+          StyleRule(*) | FontFaceRule(*) => {
+            if !f(*rule) { return false; }
+          }
+        }
+    }
+    return true;
+}
+
+#[cfg(test)]
+mod test {
+    fn viewport(width: int, height: int) -> Size2D<au> {
+        Size2D(au::from_px(width), au::from_px(height))
+    }
+
+    #[test]
+    fn should_match_media_types() {
+        let screen = MediaQuery(false, ScreenMedia, ~[]);
+        let print = MediaQuery(false, PrintMedia, ~[]);
+        let not_print = MediaQuery(true, PrintMedia, ~[]);
+
+        assert screen.matches(&viewport(800, 600));
+        assert !print.matches(&viewport(800, 600));
+        assert not_print.matches(&viewport(800, 600));
+        assert media_list_matches(~[], &viewport(800, 600));
+        assert media_list_matches(~[print, screen], &viewport(800, 600));
+    }
+
+    #[test]
+    fn should_match_media_expressions() {
+        let narrow = MediaQuery(false, AllMedia, ~[MaxWidth(Px(600.0))]);
+        let tall = MediaQuery(false, ScreenMedia, ~[MinWidth(Em(10.0)), MinHeight(Px(700.0))]);
+
+        assert narrow.matches(&viewport(600, 600));
+        assert !narrow.matches(&viewport(601, 600));
+        assert tall.matches(&viewport(160, 700));
+        assert !tall.matches(&viewport(159, 700));
+        assert !tall.matches(&viewport(800, 600));
+    }
+
+    #[test]
+    fn should_walk_applicable_rules() {
+        let rule = |tag: ~str| ~StyleRule((~[~Element(tag, ~[])], ~[]));
+        let narrow = ~[MediaQuery(false, AllMedia, ~[MaxWidth(Px(600.0))])];
+        let sheet = ~[rule(~"a"),
+                      ~MediaRule(copy narrow, ~[rule(~"b")]),
+                      ~ImportRule(~"print.css", ~[]),
+                      ~MediaRule(~[], ~[~MediaRule(~[MediaQuery(false, PrintMedia, ~[])],
+                                                   ~[rule(~"c")]),
+                                        rule(~"d")])];
+
+        let tags = |width: int| {
+            let mut tags = ~[];
+            for each_applicable_rule(sheet, &viewport(width, 600)) |rule| {
+                match *rule {
+                  StyleRule((sels, _)) => {
+                    match *sels[0] { Element(tag, _) => vec::push(tags, tag), _ => fail }
+                  }
+                  _ => fail
+                }
+            }
+            tags
+        };
+
+        assert tags(500) == ~[~"a", ~"b", ~"d"];
+        assert tags(800) == ~[~"a", ~"d"];
+    }
+}
@@ -1,19 +1,18 @@
-#[doc="Constructs a list of css style rules from a token stream"]
-
-// TODO: fail according to the css spec instead of failing when things
-// are not as expected
+#[doc="Constructs a list of css style rules from a token stream. Malformed rules and
+       declarations are skipped as described by CSS Syntax Level 3, Section 5."]
 
 use css::values::*;
 // Disambiguate parsed Selector, Rule values from tokens
 use css = css::values;
 use tok = lexer;
-use lexer::Token;
-use comm::recv;
-use option::{map, is_none};
+use lexer::{Token, TokenMethods, spawn_css_lexer_from_string, spawn_css_lexer_task};
+use option::is_none;
 use vec::push;
 use parser_util::*;
+use resource::resource_task::{ResourceTask, Load, Payload, Done};
+use std::net::url::Url;
 use util::color::parsing::parse_color;
-use vec::push;
+use util::url::make_url;
 
 type TokenReader = {stream : pipes::Port<Token>, mut lookahead : Option<Token>};
 
@@ -36,182 +35,1207 @@ impl TokenReader : TokenReaderMethods {
     }
 }
 
+#[doc="A token, or a block or function along with everything inside it."]
+enum ComponentValue {
+    PreservedToken(Token),
+    // The opening token, one of '{', '[' or '(', and the contents of the block
+    SimpleBlock(Token, ~[ComponentValue]),
+    FunctionBlock(~str, ~[ComponentValue])
+}
+
 trait ParserMethods {
-    fn parse_element() -> Option<~css::Selector>;
-    fn parse_selector() -> Option<~[~css::Selector]>;
-    fn parse_description() -> Option<~[StyleDeclaration]>;
-    fn parse_rule() -> Option<~css::Rule>;
+    fn consume_component_value(-token : Token) -> ComponentValue;
+    fn consume_simple_block(ending : Token) -> ~[ComponentValue];
 }
 
 impl TokenReader : ParserMethods {
-    fn parse_element() -> Option<~css::Selector> {
-        // Get the current element type
-         let elmt_name = match self.get() {
-           tok::Element(tag) => { copy tag }
-           tok::Eof => { return None; }
-           _ => { fail ~"Expected an element" }
-         };
-
-         let mut attr_list = ~[];
-
-         // Get the attributes associated with that element
-         loop {
-             let token = self.get();
-             match token {
-               tok::Attr(attr) => { push(attr_list, copy attr); }
-               tok::StartDescription | tok::Descendant | tok::Child | tok::Sibling | tok::Comma => {
-                 self.unget(token); 
-                 break;
-               }
-               tok::Eof => { return None; }
-               tok::Element(_) => fail ~"Unexpected second element without relation to first element",
-               tok::EndDescription => fail ~"Unexpected '}'",
-               tok::Description(_, _) => fail ~"Unexpected description"
-             }
-         }
-        return Some(~css::Element(elmt_name, attr_list));
-    }
-
-    fn parse_selector() -> Option<~[~css::Selector]> {
-        let mut sel_list = ~[];
-
-        // Collect all the selectors that this rule applies to
+    fn consume_component_value(-token : Token) -> ComponentValue {
+        match copy token {
+          tok::LeftBrace => SimpleBlock(token, self.consume_simple_block(tok::RightBrace)),
+          tok::LeftBracket => SimpleBlock(token, self.consume_simple_block(tok::RightBracket)),
+          tok::LeftParen => SimpleBlock(token, self.consume_simple_block(tok::RightParen)),
+          tok::Function(name) => FunctionBlock(name, self.consume_simple_block(tok::RightParen)),
+          _ => PreservedToken(token)
+        }
+    }
+
+    #[doc="Consumes the contents of a block whose opening token has already been read.
+           A block left open at the end of the stylesheet is closed there."]
+    fn consume_simple_block(ending : Token) -> ~[ComponentValue] {
+        let mut contents = ~[];
         loop {
-            let mut cur_sel;
-
-            match self.parse_element() {
-              Some(elmt) => { cur_sel = copy elmt; }
-              None => { return None; } // we hit an eof in the middle of a rule
-            }
-
-            loop {
-                let tok = self.get();
-                let built_sel <- cur_sel;
-                
-                match tok {
-                  tok::Descendant => {
-                    match self.parse_element() {
-                      Some(elmt) => { 
-                        let new_sel = copy elmt;
-                        cur_sel <- ~css::Descendant(built_sel, new_sel)
-                      }
-                      None => { return None; }
-                    }
-                  }
-                  tok::Child => {
-                    match self.parse_element() {
-                      Some(elmt) => { 
-                        let new_sel = copy elmt;
-                        cur_sel <- ~css::Child(built_sel, new_sel)
-                      }
-                      None => { return None; }
-                    }
-                  }
-                  tok::Sibling => {
-                    match self.parse_element() {
-                      Some(elmt) => { 
-                        let new_sel = copy elmt;
-                        cur_sel <- ~css::Sibling(built_sel, new_sel)
-                      }
-                      None => { return None; }
-                    }
-                  }
-                  tok::StartDescription => {
-                    push(sel_list, built_sel);
-                    self.unget(tok::StartDescription);
-                    break;
-                  }
-                  tok::Comma => {
-                    push(sel_list, built_sel);
-                    self.unget(tok::Comma);
-                    break;
-                  }
-                  tok::Attr(_) | tok::EndDescription | tok::Element(_) | tok::Description(_, _) => {
-                    fail #fmt["Unexpected token %? in elements", tok];
-                  }
-                  tok::Eof => { return None; }
-                }
+            let token = self.get();
+            if token == ending { return contents; }
+            match token {
+              tok::Eof => { self.unget(token); return contents; }
+              _ => { push(contents, self.consume_component_value(token)); }
+            }
+        }
+    }
+}
+
+pure fn is_whitespace_value(value : &ComponentValue) -> bool {
+    match *value {
+      PreservedToken(tok::WhiteSpace) => true,
+      _ => false
+    }
+}
+
+#[doc="Strips leading and trailing whitespace."]
+fn trim_whitespace(values : &[ComponentValue]) -> ~[ComponentValue] {
+    let mut start = 0u;
+    let mut end = values.len();
+    while start < end && is_whitespace_value(&values[start]) { start += 1u; }
+    while end > start && is_whitespace_value(&values[end - 1u]) { end -= 1u; }
+    return vec::slice(values, start, end);
+}
+
+#[doc="Splits a list of component values on a separator, like ',' or ';'. Separators
+       inside blocks and functions are not split on."]
+fn split_values(values : &[ComponentValue], separator : Token) -> ~[~[ComponentValue]] {
+    let mut parts = ~[];
+    let mut current = ~[];
+    for values.each |value| {
+        match copy *value {
+          PreservedToken(token) if token == separator => {
+            push(parts, copy current);
+            current = ~[];
+          }
+          value => { push(current, value); }
+        }
+    }
+    push(parts, current);
+    return parts;
+}
+
+#[doc="Writes component values back out as CSS text, for the value parsers. These
+       expect no whitespace inside functions, as in 'rgb(1,2,3)'."]
+fn serialize_values(values : &[ComponentValue]) -> ~str {
+    let mut result = ~"";
+    for values.each |value| {
+        result += match copy *value {
+          PreservedToken(token) => token.to_css_str(),
+          SimpleBlock(opening, contents) => {
+            let closing = match opening {
+              tok::LeftBracket => ~"]",
+              tok::LeftParen => ~")",
+              _ => ~"}"
+            };
+            opening.to_css_str() + serialize_values(contents) + closing
+          }
+          FunctionBlock(name, args) => {
+            name + ~"(" + serialize_values(args.filter(|v| !is_whitespace_value(v))) + ~")"
+          }
+        };
+    }
+    return result;
+}
+
+type ValueCursor = {values : ~[ComponentValue], mut pos : uint};
+
+fn ValueCursor(+values : ~[ComponentValue]) -> ValueCursor {
+    {values : values, mut pos : 0u}
+}
+
+enum Combinator {
+    DescendantCombinator,
+    ChildCombinator,
+    SiblingCombinator
+}
+
+trait ValueParserMethods {
+    fn at_end() -> bool;
+    fn peek() -> Option<ComponentValue>;
+    fn next() -> Option<ComponentValue>;
+    fn skip_whitespace() -> bool;
+    fn consume_at_rule() -> (~[ComponentValue], Option<~[ComponentValue]>);
+    fn consume_qualified_rule() -> Option<(~[ComponentValue], ~[ComponentValue])>;
+    fn parse_selector() -> Option<~css::Selector>;
+    fn parse_compound_selector() -> Option<~css::Selector>;
+    fn parse_pseudo_class() -> Option<css::Attr>;
+}
+
+impl ValueCursor : ValueParserMethods {
+    fn at_end() -> bool {
+        self.pos >= self.values.len()
+    }
+
+    fn peek() -> Option<ComponentValue> {
+        // FIXME: Lots of copying here
+        if self.at_end() { None } else { Some(copy self.values[self.pos]) }
+    }
+
+    fn next() -> Option<ComponentValue> {
+        let value = self.peek();
+        if !self.at_end() { self.pos += 1u; }
+        return value;
+    }
+
+    // Returns whether any whitespace was skipped
+    fn skip_whitespace() -> bool {
+        let start = self.pos;
+        while !self.at_end() && is_whitespace_value(&self.values[self.pos]) { self.pos += 1u; }
+        return self.pos != start;
+    }
+
+    #[doc="Consumes the prelude and, if there is one, the block of an at-rule whose
+           keyword has already been read."]
+    fn consume_at_rule() -> (~[ComponentValue], Option<~[ComponentValue]>) {
+        let mut prelude = ~[];
+        loop {
+            match self.next() {
+              None | Some(PreservedToken(tok::Semicolon)) => { return (prelude, None); }
+              Some(SimpleBlock(tok::LeftBrace, block)) => { return (prelude, Some(block)); }
+              Some(value) => { push(prelude, value); }
             }
+        }
+    }
 
-            // check if we should break out of the nesting loop as well
-            // TODO: fix this when rust gets labelled loops
-            let tok = self.get();
-            match tok {
-              tok::StartDescription => { break; }
-              tok::Comma => { }
-              _ => { self.unget(tok); }
+    #[doc="Consumes a prelude and the block that follows it. Returns None if the
+           values run out before the block starts."]
+    fn consume_qualified_rule() -> Option<(~[ComponentValue], ~[ComponentValue])> {
+        let mut prelude = ~[];
+        loop {
+            match self.next() {
+              None => {
+                #debug("Ignoring a rule with no block at the end of the stylesheet");
+                return None;
+              }
+              Some(SimpleBlock(tok::LeftBrace, block)) => { return Some((prelude, block)); }
+              Some(value) => { push(prelude, value); }
             }
         }
-        
-        return Some(sel_list);
     }
 
-    fn parse_description() -> Option<~[StyleDeclaration]> {
-        let mut desc_list : ~[StyleDeclaration]= ~[];
-        
-        // Get the description to be applied to the selector
+    #[doc="Parses compound selectors joined by combinators, which must make up all of
+           the remaining values."]
+    fn parse_selector() -> Option<~css::Selector> {
+        self.skip_whitespace();
+        let mut selector = match self.parse_compound_selector() {
+          Some(sel) => sel,
+          None => { return None; }
+        };
+
+        loop {
+            let saw_whitespace = self.skip_whitespace();
+            if self.at_end() { return Some(selector); }
+
+            let combinator = match self.peek() {
+              Some(PreservedToken(tok::Delim('>'))) => { self.pos += 1u; ChildCombinator }
+              Some(PreservedToken(tok::Delim('+'))) => { self.pos += 1u; SiblingCombinator }
+              _ if saw_whitespace => DescendantCombinator,
+              _ => { return None; }
+            };
+            self.skip_whitespace();
+
+            let right = match self.parse_compound_selector() {
+              Some(sel) => sel,
+              None => { return None; }
+            };
+            selector = match combinator {
+              DescendantCombinator => ~css::Descendant(selector, right),
+              ChildCombinator => ~css::Child(selector, right),
+              SiblingCombinator => ~css::Sibling(selector, right)
+            };
+        }
+    }
+
+    #[doc="Parses an element name, or an implied '*', followed by any number of
+           id, class, attribute and pseudo-class selectors."]
+    fn parse_compound_selector() -> Option<~css::Selector> {
+        let (tag, has_tag) = match self.peek() {
+          Some(PreservedToken(tok::Ident(name))) => { self.pos += 1u; (name.to_lower(), true) }
+          Some(PreservedToken(tok::Delim('*'))) => { self.pos += 1u; (~"*", true) }
+          _ => (~"*", false)
+        };
+
+        let mut attr_list = ~[];
         loop {
-            let tok = self.get();
-            match tok {
-              tok::EndDescription => { break; }
-              tok::Description(prop, val) => {
-                let desc : Option<StyleDeclaration> = match prop {
-                  // TODO: have color parsing return a ParseResult instead of a real value
-                  ~"background-color" => parse_color(val).map(|res| BackgroundColor(Specified(BgColor(res)))),
-                  ~"color" => parse_color(val).map(|res| Color(Specified(TextColor(res)))),
-                  ~"display" => parse_display_type(val).extract(|res| Display(res)),
-                  ~"font-size" => parse_font_size(val).extract(|res| FontSize(res)),
-                  ~"height" => parse_box_sizing(val).extract(|res| Height(res)),
-                  ~"width" => parse_box_sizing(val).extract(|res| Width(res)),
-                  _ => { #debug["Recieved unknown style property '%s'", val]; None }
-                };
-                match desc {
-                  Some(d) => push(desc_list, d),
-                  None => { #debug["Couldn't parse value '%s' for property '%s'", val, prop] }
+            let attr = match self.peek() {
+              Some(PreservedToken(tok::Hash(name))) => { self.pos += 1u; Some(css::Id(name)) }
+              Some(PreservedToken(tok::Delim('.'))) => {
+                self.pos += 1u;
+                match self.next() {
+                  Some(PreservedToken(tok::Ident(name))) => Some(css::Class(name)),
+                  _ => None
+                }
+              }
+              Some(SimpleBlock(tok::LeftBracket, contents)) => {
+                self.pos += 1u;
+                parse_attribute_selector(contents)
+              }
+              Some(PreservedToken(tok::Colon)) => {
+                self.pos += 1u;
+                self.parse_pseudo_class()
+              }
+              _ => { break; }
+            };
+
+            match attr {
+              Some(attr) => { push(attr_list, attr); }
+              None => { return None; }
+            }
+        }
+
+        if !has_tag && attr_list.is_empty() { return None; }
+        return Some(~css::Element(tag, attr_list));
+    }
+
+    fn parse_pseudo_class() -> Option<css::Attr> {
+        match self.next() {
+          Some(PreservedToken(tok::Ident(name))) => {
+            match name.to_lower() {
+              ~"first-child" => Some(css::Pseudo(css::FirstChild)),
+              ~"last-child" => Some(css::Pseudo(css::LastChild)),
+              _ => { #debug("Unsupported pseudo-class ':%s'", name); None }
+            }
+          }
+          Some(FunctionBlock(name, args)) => {
+            match name.to_lower() {
+              ~"nth-child" => {
+                match parse_nth_child_expr(serialize_values(args)) {
+                  Some((a, b)) => Some(css::Pseudo(css::NthChild(a, b))),
+                  None => None
                 }
               }
-              tok::Eof => { return None; }
-              tok::StartDescription | tok::Descendant |  tok::Child | tok::Sibling 
-              | tok::Comma | tok::Element(_) | tok::Attr(_) => {
-                fail #fmt["Unexpected token %? in description", tok]; 
+              ~"not" => {
+                let arg = ValueCursor(args);
+                arg.skip_whitespace();
+                match arg.parse_compound_selector() {
+                  Some(sel) => {
+                    arg.skip_whitespace();
+                    if arg.at_end() { Some(css::Pseudo(css::Not(sel))) } else { None }
+                  }
+                  None => None
+                }
               }
+              _ => { #debug("Unsupported pseudo-class ':%s()'", name); None }
             }
+          }
+          _ => None
         }
-        
-        return Some(desc_list);
     }
+}
+
+#[doc="Parses the contents of '[...]' into an attribute selector."]
+fn parse_attribute_selector(contents : &[ComponentValue]) -> Option<css::Attr> {
+    let cursor = ValueCursor(trim_whitespace(contents));
+
+    let name = match cursor.next() {
+      Some(PreservedToken(tok::Ident(name))) => name,
+      _ => { return None; }
+    };
+    cursor.skip_whitespace();
+
+    let operator = match cursor.next() {
+      None => { return Some(css::Exists(name)); }
+      Some(PreservedToken(tok::Delim('='))) => '=',
+      Some(PreservedToken(tok::Delim(c))) if c == '~' || c == '|' => {
+        match cursor.next() {
+          Some(PreservedToken(tok::Delim('='))) => c,
+          _ => { return None; }
+        }
+      }
+      _ => { return None; }
+    };
+    cursor.skip_whitespace();
 
-    fn parse_rule() -> Option<~css::Rule> {
-        // TODO: get rid of copies once match move works
-        let sel_list = match self.parse_selector() {
-          Some(list) => { copy list }
+    let value = match cursor.next() {
+      Some(PreservedToken(tok::Ident(value))) | Some(PreservedToken(tok::QuotedString(value))) => {
+        value
+      }
+      _ => { return None; }
+    };
+    if !cursor.at_end() { return None; }
+
+    return Some(match operator {
+      '~' => css::Includes(name, value),
+      '|' => css::StartsWith(name, value),
+      _ => css::Exact(name, value)
+    });
+}
+
+#[doc="Parses a comma-separated group of selectors. If any of them is invalid, the
+       whole group is (CSS 2.1, Section 4.1.7)."]
+fn parse_selector_group(prelude : &[ComponentValue]) -> Option<~[~css::Selector]> {
+    let mut sel_list = ~[];
+    for split_values(prelude, tok::Comma).each |part| {
+        match ValueCursor(copy *part).parse_selector() {
+          Some(sel) => { push(sel_list, sel); }
           None => { return None; }
-        };
+        }
+    }
+    return Some(sel_list);
+}
 
-        #debug("sel_list: %?", sel_list);
-        
-        // Get the description to be applied to the selector
-        let desc_list = match self.parse_description() {
-          Some(list) => { copy list }
+#[doc="Splits a trailing '!important' off a declaration value."]
+fn split_importance(values : &[ComponentValue]) -> (~[ComponentValue], Importance) {
+    let values = trim_whitespace(values);
+    let len = values.len();
+    if len == 0u { return (values, Normal); }
+
+    match copy values[len - 1u] {
+      PreservedToken(tok::Ident(word)) if word.to_lower() == ~"important" => {
+        let rest = trim_whitespace(vec::slice(values, 0u, len - 1u));
+        let rest_len = rest.len();
+        if rest_len > 0u {
+            match copy rest[rest_len - 1u] {
+              PreservedToken(tok::Delim('!')) => {
+                return (trim_whitespace(vec::slice(rest, 0u, rest_len - 1u)), Important);
+              }
+              _ => { }
+            }
+        }
+      }
+      _ => { }
+    }
+
+    return (values, Normal);
+}
+
+#[doc="Expands the one to four values of a box shorthand such as 'margin' into its top,
+       right, bottom and left values (CSS 2.1, Section 8.3)."]
+fn parse_box_sides<T : Copy>(val : ~str, parse : fn(~str) -> ParseResult<T>)
+    -> Option<~[CSSValue<T>]> {
+    let mut sides = ~[];
+    for str::words(val).each |word| {
+        match parse(copy *word).extract(|value| value) {
+          Some(value) => { push(sides, value); }
           None => { return None; }
+        }
+    }
+
+    match sides.len() {
+      1u => Some(~[sides[0], sides[0], sides[0], sides[0]]),
+      2u => Some(~[sides[0], sides[1], sides[0], sides[1]]),
+      3u => Some(~[sides[0], sides[1], sides[2], sides[1]]),
+      4u => Some(sides),
+      _ => None
+    }
+}
+
+#[doc="Parses the width, style and color, in any order, of a border shorthand such as
+       'border-top'. Those that are left out are reset to their initial values."]
+fn parse_border_side(val : ~str)
+    -> Option<(CSSValue<Length>, CSSValue<CSSBorderStyle>, CSSValue<CSSColor>)> {
+    if val == ~"inherit" { return Some((Inherit, Inherit, Inherit)); }
+
+    let mut width = Initial;
+    let mut style = Initial;
+    let mut color = Initial;
+    let mut seen_width = false;
+    let mut seen_style = false;
+    let mut seen_color = false;
+    for str::words(val).each |word| {
+        match parse_border_style(copy *word) {
+          Value(s) if !seen_style => { style = Specified(s); seen_style = true; }
+          _ => match parse_border_width(copy *word) {
+            Value(w) if !seen_width => { width = Specified(w); seen_width = true; }
+            _ => match parse_border_color(copy *word) {
+              Value(c) if !seen_color => { color = Specified(c); seen_color = true; }
+              _ => { return None; }
+            }
+          }
+        }
+    }
+
+    return Some((width, style, color));
+}
+
+#[doc="Parses the value of a property, expanding shorthands into the declarations of
+       the properties they set."]
+fn parse_declaration_value(prop : ~str, val : ~str) -> Option<~[StyleDeclaration]> {
+    match prop {
+      // TODO: have color parsing return a ParseResult instead of a real value
+      ~"background-color" => parse_color(val).map(|res| ~[BackgroundColor(Specified(BgColor(res)))]),
+      ~"color" => parse_color(val).map(|res| ~[Color(Specified(TextColor(res)))]),
+      ~"display" => parse_display_type(val).extract(|res| ~[Display(res)]),
+      ~"font-size" => parse_font_size(val).extract(|res| ~[FontSize(res)]),
+      ~"height" => parse_box_sizing(val).extract(|res| ~[Height(res)]),
+      ~"width" => parse_box_sizing(val).extract(|res| ~[Width(res)]),
+      ~"float" => parse_float(val).extract(|res| ~[Float(res)]),
+      ~"clear" => parse_clear(val).extract(|res| ~[Clear(res)]),
+      ~"position" => parse_position(val).extract(|res| ~[Position(res)]),
+      // Offsets take the same values as widths, and may also be negative
+      ~"top" => parse_box_sizing(val).extract(|res| ~[Top(res)]),
+      ~"right" => parse_box_sizing(val).extract(|res| ~[Right(res)]),
+      ~"bottom" => parse_box_sizing(val).extract(|res| ~[Bottom(res)]),
+      ~"left" => parse_box_sizing(val).extract(|res| ~[Left(res)]),
+      ~"z-index" => parse_z_index(val).extract(|res| ~[ZIndex(res)]),
+
+      ~"border-collapse" => parse_border_collapse(val).extract(|res| ~[BorderCollapse(res)]),
+      ~"border-spacing" => do parse_border_spacing(val).map |spacing| {
+        let (horizontal, vertical) = spacing;
+        ~[BorderSpacingHorizontal(horizontal), BorderSpacingVertical(vertical)]
+      },
+      ~"table-layout" => parse_table_layout(val).extract(|res| ~[TableLayout(res)]),
+      ~"caption-side" => parse_caption_side(val).extract(|res| ~[CaptionSide(res)]),
+
+      ~"white-space" => parse_white_space(val).extract(|res| ~[WhiteSpace(res)]),
+      ~"vertical-align" => parse_vertical_align(val).extract(|res| ~[VerticalAlign(res)]),
+
+      ~"font-family" => parse_font_family(val).extract(|res| ~[FontFamily(res)]),
+      ~"font-weight" => parse_font_weight(val).extract(|res| ~[FontWeight(res)]),
+      ~"font-style" => parse_font_style(val).extract(|res| ~[FontStyle(res)]),
+      ~"line-height" => parse_line_height(val).extract(|res| ~[LineHeight(res)]),
+      ~"text-align" => parse_text_align(val).extract(|res| ~[TextAlign(res)]),
+      ~"text-decoration" => parse_text_decoration(val).extract(|res| ~[TextDecoration(res)]),
+      ~"text-transform" => parse_text_transform(val).extract(|res| ~[TextTransform(res)]),
+
+      ~"direction" => parse_direction(val).extract(|res| ~[Direction(res)]),
+      ~"unicode-bidi" => parse_unicode_bidi(val).extract(|res| ~[UnicodeBidi(res)]),
+
+      ~"margin-top" => parse_margin(val).extract(|res| ~[MarginTop(res)]),
+      ~"margin-right" => parse_margin(val).extract(|res| ~[MarginRight(res)]),
+      ~"margin-bottom" => parse_margin(val).extract(|res| ~[MarginBottom(res)]),
+      ~"margin-left" => parse_margin(val).extract(|res| ~[MarginLeft(res)]),
+      ~"margin" => do parse_box_sides(val, parse_margin).map |v| {
+        ~[MarginTop(v[0]), MarginRight(v[1]), MarginBottom(v[2]), MarginLeft(v[3])]
+      },
+
+      ~"padding-top" => parse_padding(val).extract(|res| ~[PaddingTop(res)]),
+      ~"padding-right" => parse_padding(val).extract(|res| ~[PaddingRight(res)]),
+      ~"padding-bottom" => parse_padding(val).extract(|res| ~[PaddingBottom(res)]),
+      ~"padding-left" => parse_padding(val).extract(|res| ~[PaddingLeft(res)]),
+      ~"padding" => do parse_box_sides(val, parse_padding).map |v| {
+        ~[PaddingTop(v[0]), PaddingRight(v[1]), PaddingBottom(v[2]), PaddingLeft(v[3])]
+      },
+
+      ~"border-top-width" => parse_border_width(val).extract(|res| ~[BorderTopWidth(res)]),
+      ~"border-right-width" => parse_border_width(val).extract(|res| ~[BorderRightWidth(res)]),
+      ~"border-bottom-width" => parse_border_width(val).extract(|res| ~[BorderBottomWidth(res)]),
+      ~"border-left-width" => parse_border_width(val).extract(|res| ~[BorderLeftWidth(res)]),
+      ~"border-width" => do parse_box_sides(val, parse_border_width).map |v| {
+        ~[BorderTopWidth(v[0]), BorderRightWidth(v[1]),
+          BorderBottomWidth(v[2]), BorderLeftWidth(v[3])]
+      },
+
+      ~"border-top-style" => parse_border_style(val).extract(|res| ~[BorderTopStyle(res)]),
+      ~"border-right-style" => parse_border_style(val).extract(|res| ~[BorderRightStyle(res)]),
+      ~"border-bottom-style" => parse_border_style(val).extract(|res| ~[BorderBottomStyle(res)]),
+      ~"border-left-style" => parse_border_style(val).extract(|res| ~[BorderLeftStyle(res)]),
+      ~"border-style" => do parse_box_sides(val, parse_border_style).map |v| {
+        ~[BorderTopStyle(v[0]), BorderRightStyle(v[1]),
+          BorderBottomStyle(v[2]), BorderLeftStyle(v[3])]
+      },
+
+      ~"border-top-color" => parse_border_color(val).extract(|res| ~[BorderTopColor(res)]),
+      ~"border-right-color" => parse_border_color(val).extract(|res| ~[BorderRightColor(res)]),
+      ~"border-bottom-color" => parse_border_color(val).extract(|res| ~[BorderBottomColor(res)]),
+      ~"border-left-color" => parse_border_color(val).extract(|res| ~[BorderLeftColor(res)]),
+      ~"border-color" => do parse_box_sides(val, parse_border_color).map |v| {
+        ~[BorderTopColor(v[0]), BorderRightColor(v[1]),
+          BorderBottomColor(v[2]), BorderLeftColor(v[3])]
+      },
+
+      ~"border-top" => do parse_border_side(val).map |side| {
+        let (width, style, color) = side;
+        ~[BorderTopWidth(width), BorderTopStyle(style), BorderTopColor(color)]
+      },
+      ~"border-right" => do parse_border_side(val).map |side| {
+        let (width, style, color) = side;
+        ~[BorderRightWidth(width), BorderRightStyle(style), BorderRightColor(color)]
+      },
+      ~"border-bottom" => do parse_border_side(val).map |side| {
+        let (width, style, color) = side;
+        ~[BorderBottomWidth(width), BorderBottomStyle(style), BorderBottomColor(color)]
+      },
+      ~"border-left" => do parse_border_side(val).map |side| {
+        let (width, style, color) = side;
+        ~[BorderLeftWidth(width), BorderLeftStyle(style), BorderLeftColor(color)]
+      },
+      ~"border" => do parse_border_side(val).map |side| {
+        let (width, style, color) = side;
+        ~[BorderTopWidth(width), BorderTopStyle(style), BorderTopColor(color),
+          BorderRightWidth(width), BorderRightStyle(style), BorderRightColor(color),
+          BorderBottomWidth(width), BorderBottomStyle(style), BorderBottomColor(color),
+          BorderLeftWidth(width), BorderLeftStyle(style), BorderLeftColor(color)]
+      },
+
+      _ => { #debug["Recieved unknown style property '%s'", prop]; None }
+    }
+}
+
+#[doc="Splits a declaration into its lowercased name and its value, or returns None if
+       it isn't of the form 'name: value'."]
+fn split_declaration(+values : ~[ComponentValue]) -> Option<(~str, ~[ComponentValue])> {
+    let cursor = ValueCursor(values);
+    cursor.skip_whitespace();
+    if cursor.at_end() { return None; }
+
+    let name = match cursor.next() {
+      Some(PreservedToken(tok::Ident(name))) => name.to_lower(),
+      _ => { #debug["Ignoring declaration without a name"]; return None; }
+    };
+    cursor.skip_whitespace();
+    match cursor.next() {
+      Some(PreservedToken(tok::Colon)) => { }
+      _ => { #debug["Ignoring declaration of '%s' without a ':'", name]; return None; }
+    }
+
+    return Some((name, vec::slice(cursor.values, cursor.pos, cursor.values.len())));
+}
+
+#[doc="Parses one declaration into the declarations it sets, returning none for
+       anything invalid so that it is skipped."]
+fn parse_declaration(+values : ~[ComponentValue]) -> ~[Declaration] {
+    let (prop, value) = match split_declaration(values) {
+      Some(decl) => decl,
+      None => { return ~[]; }
+    };
+
+    let (value, importance) = split_importance(value);
+    let val = serialize_values(value);
+    if val.is_empty() { return ~[]; }
+
+    match parse_declaration_value(copy prop, copy val) {
+      Some(decls) => decls.map(|d| (copy *d, importance)),
+      None => { #debug["Couldn't parse value '%s' for property '%s'", val, prop]; ~[] }
+    }
+}
+
+fn parse_declaration_list(values : &[ComponentValue]) -> ~[Declaration] {
+    let mut desc_list = ~[];
+    for split_values(values, tok::Semicolon).each |part| {
+        desc_list += parse_declaration(copy *part);
+    }
+    return desc_list;
+}
+
+#[doc="Turns a qualified rule into a style rule, or None if its selector is invalid."]
+fn parse_style_rule(prelude : &[ComponentValue], block : &[ComponentValue]) -> Option<css::Rule> {
+    let sel_list = match parse_selector_group(prelude) {
+      Some(list) => list,
+      None => {
+        #debug("Ignoring rule with invalid selector '%s'", serialize_values(prelude));
+        return None;
+      }
+    };
+    #debug("sel_list: %?", sel_list);
+
+    let desc_list = parse_declaration_list(block);
+    #debug("desc_list: %?", desc_list);
+
+    return Some((sel_list, desc_list));
+}
+
+#[doc="Parses a media feature test, the contents of '(...)'. An exact 'width' or 'height'
+       becomes a pair of bounds."]
+fn parse_media_expression(contents : &[ComponentValue]) -> Option<~[css::MediaExpression]> {
+    let (feature, value) = match split_declaration(trim_whitespace(contents)) {
+      Some(expr) => expr,
+      None => { return None; }
+    };
+    let length = match parse_length(serialize_values(trim_whitespace(value))) {
+      Some(length) => length,
+      None => { return None; }
+    };
+
+    match feature {
+      ~"min-width" => Some(~[css::MinWidth(length)]),
+      ~"max-width" => Some(~[css::MaxWidth(length)]),
+      ~"width" => Some(~[css::MinWidth(length), css::MaxWidth(length)]),
+      ~"min-height" => Some(~[css::MinHeight(length)]),
+      ~"max-height" => Some(~[css::MaxHeight(length)]),
+      ~"height" => Some(~[css::MinHeight(length), css::MaxHeight(length)]),
+      _ => { #debug("Unsupported media feature '%s'", feature); None }
+    }
+}
+
+#[doc="Parses a single media query, such as 'not screen and (max-width: 600px)'."]
+fn parse_media_query(+values : ~[ComponentValue]) -> Option<css::MediaQuery> {
+    let cursor = ValueCursor(values);
+    cursor.skip_whitespace();
+
+    let mut negated = false;
+    let mut media_type = css::AllMedia;
+    // Expressions after a media type are joined to it with 'and'
+    let mut needs_and = false;
+
+    match cursor.peek() {
+      Some(PreservedToken(tok::Ident(word))) => {
+        cursor.pos += 1u;
+        let mut word = word.to_lower();
+        if word == ~"not" || word == ~"only" {
+            negated = word == ~"not";
+            cursor.skip_whitespace();
+            word = match cursor.next() {
+              Some(PreservedToken(tok::Ident(word))) => word.to_lower(),
+              _ => { return None; }
+            };
+        }
+        media_type = match word {
+          ~"all" => css::AllMedia,
+          ~"screen" => css::ScreenMedia,
+          ~"print" => css::PrintMedia,
+          _ => css::UnknownMedia
         };
+        needs_and = true;
+      }
+      _ => { }
+    }
+
+    let mut expressions = ~[];
+    loop {
+        cursor.skip_whitespace();
+        if cursor.at_end() { break; }
+
+        if needs_and {
+            match cursor.next() {
+              Some(PreservedToken(tok::Ident(word))) if word.to_lower() == ~"and" => { }
+              _ => { return None; }
+            }
+            cursor.skip_whitespace();
+        }
+        match cursor.next() {
+          Some(SimpleBlock(tok::LeftParen, contents)) => {
+            match parse_media_expression(contents) {
+              Some(exprs) => { expressions += exprs; }
+              None => { return None; }
+            }
+          }
+          _ => { return None; }
+        }
+        needs_and = true;
+    }
+
+    // Nothing at all, as between two commas
+    if !needs_and { return None; }
+
+    return Some(css::MediaQuery(negated, media_type, expressions));
+}
+
+#[doc="Parses a media query list. Malformed queries become 'not all', which matches
+       nothing (Media Queries, Section 3.1)."]
+fn parse_media_query_list(values : &[ComponentValue]) -> css::MediaQueryList {
+    let values = trim_whitespace(values);
+    if values.is_empty() { return ~[]; }
+
+    do split_values(values, tok::Comma).map |part| {
+        match parse_media_query(copy *part) {
+          Some(query) => query,
+          None => {
+            #debug("Ignoring malformed media query '%s'", serialize_values(*part));
+            css::MediaQuery(true, css::AllMedia, ~[])
+          }
+        }
+    }
+}
+
+#[doc="Gets the url out of 'url(...)', quoted or not."]
+fn parse_url(+value : ComponentValue) -> Option<~str> {
+    match value {
+      PreservedToken(tok::Url(url)) => Some(url),
+      FunctionBlock(name, args) if name.to_lower() == ~"url" => {
+        let args = trim_whitespace(args);
+        if args.len() != 1u { return None; }
+        match copy args[0] {
+          PreservedToken(tok::QuotedString(url)) => Some(url),
+          _ => None
+        }
+      }
+      _ => None
+    }
+}
+
+#[doc="Parses the descriptors of an @font-face rule. Only 'font-family', 'font-weight',
+       'font-style' and the urls in 'src' are used; local() sources are skipped."]
+fn parse_font_face(block : &[ComponentValue]) -> Option<css::FontFace> {
+    let mut family = None;
+    let mut weight = 400;
+    let mut italic = false;
+    let mut sources = ~[];
+
+    for split_values(block, tok::Semicolon).each |part| {
+        match split_declaration(copy *part) {
+          Some((~"font-family", value)) => {
+            let value = trim_whitespace(value);
+            family = match value.len() {
+              1u => match copy value[0] {
+                PreservedToken(tok::QuotedString(name)) | PreservedToken(tok::Ident(name)) => Some(name),
+                _ => None
+              },
+              _ => {
+                // An unquoted family name can be several identifiers
+                if value.all(|v| match *v { PreservedToken(tok::Ident(_)) => true,
+                                            _ => is_whitespace_value(v) }) {
+                    Some(serialize_values(value))
+                } else {
+                    None
+                }
+              }
+            };
+          }
+          Some((~"font-weight", value)) => {
+            match parse_font_weight(serialize_values(trim_whitespace(value))) {
+              Value(FontWeightAbsolute(w)) => weight = w,
+              _ => #debug("Ignoring @font-face weight")
+            }
+          }
+          Some((~"font-style", value)) => {
+            match parse_font_style(serialize_values(trim_whitespace(value))) {
+              Value(FontStyleNormal) => italic = false,
+              Value(FontStyleItalic) | Value(FontStyleOblique) => italic = true,
+              _ => #debug("Ignoring @font-face style")
+            }
+          }
+          Some((~"src", value)) => {
+            for split_values(value, tok::Comma).each |source| {
+                let source = trim_whitespace(*source);
+                if source.is_not_empty() {
+                    match parse_url(copy source[0]) {
+                      Some(url) => { push(sources, url); }
+                      None => { }
+                    }
+                }
+            }
+          }
+          Some((name, _)) => { #debug("Ignoring @font-face descriptor '%s'", name); }
+          None => { }
+        }
+    }
+
+    match family {
+      Some(family) if sources.is_not_empty() => {
+        Some(FontFace { family: family, weight: weight, italic: italic, sources: sources,
+                        data: None })
+      }
+      _ => { #debug("Ignoring @font-face without a family or src"); None }
+    }
+}
 
-        #debug("desc_list: %?", desc_list);
-        
-        return Some(~(sel_list, desc_list));
+fn parse_at_rule(name : ~str, prelude : &[ComponentValue], +block : Option<~[ComponentValue]>,
+                 imports_allowed : bool) -> Option<~css::CSSRule> {
+    match (name, block) {
+      (~"import", None) if imports_allowed => {
+        let prelude = trim_whitespace(prelude);
+        if prelude.is_empty() { return None; }
+        let href = match copy prelude[0] {
+          PreservedToken(tok::QuotedString(href)) => Some(href),
+          value => parse_url(value)
+        };
+        match href {
+          Some(href) => Some(~css::ImportRule(href, parse_media_query_list(vec::tail(prelude)))),
+          None => None
+        }
+      }
+      (~"media", Some(block)) => {
+        Some(~css::MediaRule(parse_media_query_list(prelude), parse_rule_list(block, false)))
+      }
+      (~"font-face", Some(block)) => {
+        match parse_font_face(block) {
+          Some(face) => Some(~css::FontFaceRule(face)),
+          None => None
+        }
+      }
+      (name, _) => { #debug("Ignoring unsupported or misplaced at-rule '@%s'", name); None }
     }
 }
 
-fn build_stylesheet(+stream : pipes::Port<Token>) -> ~[~css::Rule] {
+#[doc="Parses a list of rules, either a whole stylesheet or the contents of an @media
+       block. @import rules are only allowed at the start of a stylesheet."]
+fn parse_rule_list(+values : ~[ComponentValue], top_level : bool) -> ~[~css::CSSRule] {
+    let cursor = ValueCursor(values);
     let mut rule_list = ~[];
-    let reader = {stream : stream, mut lookahead : None};
+    let mut imports_allowed = top_level;
 
     loop {
-        match reader.parse_rule() {
-          Some(rule) => { push(rule_list, copy rule); }
+        match cursor.peek() {
           None => { break; }
+          Some(PreservedToken(tok::WhiteSpace)) => { cursor.pos += 1u; }
+          Some(PreservedToken(tok::CDO)) | Some(PreservedToken(tok::CDC)) if top_level => {
+            cursor.pos += 1u;
+          }
+          Some(PreservedToken(tok::AtKeyword(name))) => {
+            cursor.pos += 1u;
+            let name = name.to_lower();
+            let (prelude, block) = cursor.consume_at_rule();
+            match parse_at_rule(copy name, prelude, block, imports_allowed) {
+              Some(rule) => { push(rule_list, rule); }
+              None => { }
+            }
+            if name != ~"import" && name != ~"charset" { imports_allowed = false; }
+          }
+          Some(_) => {
+            imports_allowed = false;
+            match cursor.consume_qualified_rule() {
+              Some((prelude, block)) => {
+                match parse_style_rule(prelude, block) {
+                  Some(rule) => { push(rule_list, ~css::StyleRule(rule)); }
+                  None => { }
+                }
+              }
+              None => { }
+            }
+          }
         }
     }
 
     return rule_list;
 }
+
+fn consume_component_values(+stream : pipes::Port<Token>) -> ~[ComponentValue] {
+    let reader = {stream : stream, mut lookahead : None};
+    let mut values = ~[];
+
+    loop {
+        let token = reader.get();
+        match token {
+          tok::Eof => { break; }
+          _ => { push(values, reader.consume_component_value(token)); }
+        }
+    }
+
+    return values;
+}
+
+fn build_stylesheet(+stream : pipes::Port<Token>) -> Stylesheet {
+    return parse_rule_list(consume_component_values(stream), true);
+}
+
+#[doc="Parses the declarations in the `style` attribute of an element."]
+fn parse_style_attribute(-source : ~str) -> ~[Declaration] {
+    let values = consume_component_values(spawn_css_lexer_from_string(source));
+    return parse_declaration_list(values);
+}
+
+// Imports nested deeper than this are ignored. Cycles are caught before that,
+// by the URLs of the sheets that imported the one being loaded.
+const MAX_IMPORT_DEPTH: uint = 8u;
+
+fn load_bytes(+url : Url, resource_task : ResourceTask) -> Option<~[u8]> {
+    let input_port = comm::Port();
+    resource_task.send(Load(url, input_port.chan()));
+
+    let mut data = ~[];
+    loop {
+        match input_port.recv() {
+          Payload(chunk) => { data += chunk; }
+          Done(Ok(*)) => { return Some(data); }
+          Done(Err(*)) => { return None; }
+        }
+    }
+}
+
+fn load_font_face(+face : FontFace, base_url : &Url, resource_task : ResourceTask) -> FontFace {
+    for face.sources.each |source| {
+        let url = make_url(copy *source, Some(copy *base_url));
+        match load_bytes(copy url, resource_task) {
+          Some(data) => {
+            return FontFace { family: copy face.family, weight: face.weight, italic: face.italic,
+                              sources: copy face.sources, data: Some(data) };
+          }
+          None => { #warn("Couldn't load font %s", url.to_str()); }
+        }
+    }
+    return face;
+}
+
+// `importers` holds the URLs of the sheets on the chain of imports that led to
+// these rules. A sheet importing one of them again is left empty.
+fn load_rules(rules : &[~css::CSSRule], base_url : &Url, resource_task : ResourceTask,
+              importers : &[~str]) -> ~[~css::CSSRule] {
+    do rules.map |rule| {
+        match copy **rule {
+          css::ImportRule(href, media) => {
+            let url = make_url(href, Some(copy *base_url));
+            let nested = if vec::contains(importers, &url.to_str()) {
+                #warn("Ignoring @import of %s, which imports itself", url.to_str());
+                ~[]
+            } else if importers.len() > MAX_IMPORT_DEPTH {
+                #warn("Ignoring @import of %s nested too deeply", url.to_str());
+                ~[]
+            } else {
+                load_imported_stylesheet(url, resource_task, importers)
+            };
+            ~css::MediaRule(media, nested)
+          }
+          css::MediaRule(media, nested) => {
+            ~css::MediaRule(media, load_rules(nested, base_url, resource_task, importers))
+          }
+          css::FontFaceRule(face) => ~css::FontFaceRule(load_font_face(face, base_url, resource_task)),
+          rule => ~rule
+        }
+    }
+}
+
+fn load_imported_stylesheet(+url : Url, resource_task : ResourceTask,
+                            importers : &[~str]) -> Stylesheet {
+    let rules = build_stylesheet(spawn_css_lexer_task(copy url, resource_task));
+    let importers = vec::append_one(vec::slice(importers, 0u, importers.len()), url.to_str());
+    return load_rules(rules, &url, resource_task, importers);
+}
+
+#[doc="Loads and parses the stylesheet at `url`, along with everything it refers to.
+       Imported stylesheets replace their @import rules, and the data of downloadable
+       fonts is stored in their @font-face rules."]
+fn load_stylesheet(+url : Url, resource_task : ResourceTask) -> Stylesheet {
+    load_imported_stylesheet(url, resource_task, ~[])
+}
+
+#[doc="Parses the contents of a <style> element, loading what it refers to relative
+       to the document at `base_url`."]
+fn load_inline_stylesheet(-source : ~str, +base_url : Url, resource_task : ResourceTask) -> Stylesheet {
+    let rules = build_stylesheet(spawn_css_lexer_from_string(source));
+    return load_rules(rules, &base_url, resource_task, ~[]);
+}
+
+#[cfg(test)]
+mod test {
+    fn parse(input: ~str) -> Stylesheet {
+        build_stylesheet(spawn_css_lexer_from_string(input))
+    }
+
+    fn style_rule(rule: &CSSRule) -> css::Rule {
+        match copy *rule {
+          StyleRule(rule) => rule,
+          _ => fail ~"expected a style rule"
+        }
+    }
+
+    fn selectors(sheet: &[~CSSRule]) -> ~[~[~css::Selector]] {
+        sheet.map(|rule| { let (sels, _) = style_rule(*rule); sels })
+    }
+
+    #[test]
+    fn should_skip_malformed_declarations() {
+        let sheet = parse(~"p { width: ; color red; 12px: auto; height: auto !important; width:auto }");
+        assert sheet.len() == 1u;
+
+        let (_, decls) = style_rule(sheet[0]);
+        assert decls.len() == 2u;
+        match decls[0] { (Height(Specified(BoxAuto)), Important) => (), _ => fail };
+        match decls[1] { (Width(Specified(BoxAuto)), Normal) => (), _ => fail };
+    }
+
+    #[test]
+    fn should_recover_from_stray_braces() {
+        // The stray '}' becomes part of the next rule's selector, which drops that rule
+        let sheet = parse(~"p {width:auto} } div {height:auto} span {width:auto}");
+        assert selectors(sheet) == ~[~[~Element(~"p", ~[])], ~[~Element(~"span", ~[])]];
+
+        // Braces inside a declaration are matched up rather than ending the rule early
+        let sheet = parse(~"p {width: {auto; height:auto}; height:auto} span {width:auto}");
+        assert selectors(sheet) == ~[~[~Element(~"p", ~[])], ~[~Element(~"span", ~[])]];
+
+        let (_, decls) = style_rule(sheet[0]);
+        assert decls.len() == 1u;
+    }
+
+    #[test]
+    fn should_skip_unknown_at_rules() {
+        let sheet = parse(~"@charset \"utf-8\"; @foo { p {width:auto} } div {height:auto}");
+        assert selectors(sheet) == ~[~[~Element(~"div", ~[])]];
+    }
+
+    #[test]
+    fn should_close_blocks_at_eof() {
+        let sheet = parse(~"<!-- p /* comment */ {width:auto; height: auto");
+        assert selectors(sheet) == ~[~[~Element(~"p", ~[])]];
+
+        let (_, decls) = style_rule(sheet[0]);
+        assert decls.len() == 2u;
+
+        assert parse(~"div").len() == 0u;
+    }
+
+    #[test]
+    fn should_parse_attribute_selectors() {
+        let sheet = parse(~"[lang|=en] a[ href ] div[class~='x y'] *[id=main] {width:auto}");
+        let expected = ~Descendant(
+            ~Descendant(
+                ~Descendant(~Element(~"*", ~[StartsWith(~"lang", ~"en")]),
+                            ~Element(~"a", ~[Exists(~"href")])),
+                ~Element(~"div", ~[Includes(~"class", ~"x y")])),
+            ~Element(~"*", ~[Exact(~"id", ~"main")]));
+        assert selectors(sheet) == ~[~[expected]];
+    }
+
+    #[test]
+    fn should_parse_combinators() {
+        let sheet = parse(~"a>b+c d , e {width:auto}");
+        let expected = ~Descendant(~Sibling(~Child(~Element(~"a", ~[]), ~Element(~"b", ~[])),
+                                            ~Element(~"c", ~[])),
+                                   ~Element(~"d", ~[]));
+        assert selectors(sheet) == ~[~[expected, ~Element(~"e", ~[])]];
+
+        // Dangling combinators and empty selectors are invalid
+        assert parse(~"a > {width:auto} , b {width:auto} c,, d {width:auto}").len() == 0u;
+    }
+
+    #[test]
+    fn should_parse_media_rules() {
+        let sheet = parse(~"@media screen and (max-width: 600px), print { p {width:auto} } \
+                            @media { div {width:auto} } @media screen and, (color) { a {width:auto} }");
+        assert sheet.len() == 3u;
+
+        match copy *sheet[0] {
+          MediaRule(media, rules) => {
+            assert media == ~[MediaQuery(false, ScreenMedia, ~[MaxWidth(Px(600.0))]),
+                              MediaQuery(false, PrintMedia, ~[])];
+            assert selectors(rules) == ~[~[~Element(~"p", ~[])]];
+          }
+          _ => fail
+        }
+        match copy *sheet[1] {
+          MediaRule(media, _) => { assert media.is_empty(); }
+          _ => fail
+        }
+        // Malformed and unsupported queries never match
+        match copy *sheet[2] {
+          MediaRule(media, _) => {
+            assert media == ~[MediaQuery(true, AllMedia, ~[]), MediaQuery(true, AllMedia, ~[])];
+          }
+          _ => fail
+        }
+    }
+
+    #[test]
+    fn should_parse_media_queries() {
+        let query = |input: ~str| {
+            match copy *parse(~"@media " + input + ~" {}")[0] {
+              MediaRule(media, _) => media,
+              _ => fail
+            }
+        };
+
+        assert query(~"not print") == ~[MediaQuery(true, PrintMedia, ~[])];
+        assert query(~"only screen") == ~[MediaQuery(false, ScreenMedia, ~[])];
+        assert query(~"tv") == ~[MediaQuery(false, UnknownMedia, ~[])];
+        assert query(~"(min-width:20em) and (height: 300px)") ==
+            ~[MediaQuery(false, AllMedia, ~[MinWidth(Em(20.0)), MinHeight(Px(300.0)),
+                                            MaxHeight(Px(300.0))])];
+    }
+
+    #[test]
+    fn should_only_allow_imports_first() {
+        let sheet = parse(~"@charset \"utf-8\"; @import url(a.css); @import 'b.css' print; \
+                            p {width:auto} @import url(c.css);");
+        assert sheet.len() == 3u;
+
+        match copy *sheet[0] {
+          ImportRule(href, media) => { assert href == ~"a.css"; assert media.is_empty(); }
+          _ => fail
+        }
+        match copy *sheet[1] {
+          ImportRule(href, media) => {
+            assert href == ~"b.css";
+            assert media == ~[MediaQuery(false, PrintMedia, ~[])];
+          }
+          _ => fail
+        }
+
+        // Not even inside @media
+        match copy *parse(~"@media { @import url(a.css); }")[0] {
+          MediaRule(_, rules) => { assert rules.is_empty(); }
+          _ => fail
+        }
+    }
+
+    #[test]
+    fn should_parse_font_faces() {
+        let sheet = parse(~"@font-face { font-family: Josefin Sans; font-weight: bold; \
+                                         font-style: oblique; \
+                                         src: local(Josefin), url(josefin.ttf) format('truetype'), \
+                                              url('josefin.otf') } \
+                            @font-face { font-family: 'No Source' }");
+        assert sheet.len() == 1u;
+
+        match copy *sheet[0] {
+          FontFaceRule(face) => {
+            assert face.family == ~"Josefin Sans";
+            assert face.weight == 700 && face.italic;
+            assert face.sources == ~[~"josefin.ttf", ~"josefin.otf"];
+            assert face.data.is_none();
+          }
+          _ => fail
+        }
+    }
+
+    // A resource task that serves the stylesheets with these paths under test://sheets/
+    fn serve_stylesheets(+sheets: ~[(~str, ~str)]) -> ResourceTask {
+        use resource::resource_task::{create_resource_task_with_loaders, ProgressMsg};
+
+        let loader = fn~(+url: Url, progress_chan: comm::Chan<ProgressMsg>, copy sheets) {
+            for sheets.each |sheet| {
+                let (path, source) = copy *sheet;
+                if path == url.path {
+                    progress_chan.send(Payload(str::to_bytes(source)));
+                    progress_chan.send(Done(Ok(())));
+                    return;
+                }
+            }
+            progress_chan.send(Done(Err(())));
+        };
+        create_resource_task_with_loaders(~[(~"test", loader)])
+    }
+
+    fn imported_rules(rule: &CSSRule) -> ~[~CSSRule] {
+        match copy *rule {
+          MediaRule(_, rules) => rules,
+          _ => fail ~"expected an imported sheet"
+        }
+    }
+
+    #[test]
+    fn should_import_stylesheets_without_a_css_suffix() {
+        let resource_task = serve_stylesheets(~[
+            (~"/main.css", ~"@import url(\"theme.php\"); @import \"x.css?v=2\"; @import url(cdn);"),
+            (~"/theme.php", ~"p { color: red }"),
+            (~"/x.css", ~"em { color: blue }"),
+            (~"/cdn", ~"b { color: green }")]);
+        let sheet = load_stylesheet(make_url(~"test://sheets/main.css", None), resource_task);
+        assert sheet.len() == 3u;
+        for sheet.each |rule| {
+            assert imported_rules(*rule).len() == 1u;
+        }
+        resource_task.send(resource::resource_task::Exit);
+    }
+
+    #[test]
+    fn should_leave_imports_of_importing_sheets_empty() {
+        let resource_task = serve_stylesheets(~[
+            (~"/a.css", ~"@import url(a.css); @import url(b.css); @import url(b.css); p {}"),
+            (~"/b.css", ~"@import url(a.css); @import url(a.css); em {}")]);
+        let sheet = load_stylesheet(make_url(~"test://sheets/a.css", None), resource_task);
+        assert sheet.len() == 4u;
+        assert imported_rules(sheet[0]).is_empty();
+        for uint::range(1u, 3u) |i| {
+            let b_rules = imported_rules(sheet[i]);
+            assert b_rules.len() == 3u;
+            assert imported_rules(b_rules[0]).is_empty() && imported_rules(b_rules[1]).is_empty();
+        }
+        resource_task.send(resource::resource_task::Exit);
+    }
+
+    #[test]
+    fn should_parse_style_attributes() {
+        let decls = parse_style_attribute(~"width: auto; color: ; height: auto !important;");
+        assert decls.len() == 2u;
+        match decls[0] { (Width(Specified(BoxAuto)), Normal) => (), _ => fail };
+        match decls[1] { (Height(Specified(BoxAuto)), Important) => (), _ => fail };
+
+        assert parse_style_attribute(~"p { width: auto }").is_empty();
+    }
+
+    #[test]
+    fn should_expand_box_model_shorthands() {
+        let decls = parse_style_attribute(~"margin: 0 auto 2px; padding: 1em 5%");
+        assert decls.len() == 8u;
+        match decls[0] { (MarginTop(Specified(BoxLength(Px(0.0)))), Normal) => (), _ => fail };
+        match decls[1] { (MarginRight(Specified(BoxAuto)), Normal) => (), _ => fail };
+        match decls[2] { (MarginBottom(Specified(BoxLength(Px(2.0)))), Normal) => (), _ => fail };
+        match decls[3] { (MarginLeft(Specified(BoxAuto)), Normal) => (), _ => fail };
+        match decls[5] { (PaddingRight(Specified(BoxPercent(5.0))), Normal) => (), _ => fail };
+        match decls[7] { (PaddingLeft(Specified(BoxPercent(5.0))), Normal) => (), _ => fail };
+
+        let decls = parse_style_attribute(~"border-left: red thick; border: 1px solid; \
+                                            border-width: 1px 2px 3px 4px 5px; padding: auto");
+        assert decls.len() == 15u;
+        match decls[0] { (BorderLeftWidth(Specified(Px(5.0))), Normal) => (), _ => fail };
+        match decls[1] { (BorderLeftStyle(Initial), Normal) => (), _ => fail };
+        match decls[2] { (BorderLeftColor(Specified(_)), Normal) => (), _ => fail };
+        match decls[13] { (BorderLeftStyle(Specified(BdrStyleSolid)), Normal) => (), _ => fail };
+        match decls[14] { (BorderLeftColor(Initial), Normal) => (), _ => fail };
+    }
+}
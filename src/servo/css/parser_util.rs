@@ -4,11 +4,38 @@ use css::values::*;
 use str::{pop_char, from_chars};
 use float::from_str;
 use option::map;
+use util::color::parsing::parse_color;
 
+export parse_length;
 export parse_font_size;
 export parse_size;
 export parse_box_sizing;
 export parse_display_type;
+export parse_float;
+export parse_clear;
+export parse_position;
+export parse_z_index;
+export parse_border_collapse;
+export parse_border_spacing;
+export parse_table_layout;
+export parse_caption_side;
+export parse_white_space;
+export parse_vertical_align;
+export parse_font_family;
+export parse_font_weight;
+export parse_font_style;
+export parse_line_height;
+export parse_text_align;
+export parse_text_decoration;
+export parse_text_transform;
+export parse_direction;
+export parse_unicode_bidi;
+export parse_margin;
+export parse_padding;
+export parse_border_width;
+export parse_border_style;
+export parse_border_color;
+export parse_nth_child_expr;
 
 
 fn parse_length(str : ~str) -> Option<Length> {
@@ -18,6 +45,8 @@ fn parse_length(str : ~str) -> Option<Length> {
     const PX_PER_PT: float = 1.0 / 0.75;
 
     match str {
+      // A length of zero needs no unit
+      ~"0" | ~"+0" | ~"-0" => Some(Px(0.0)),
       s if s.ends_with(~"in") => from_str(str.substr(0, str.len() - 2)).map(|f| Px(1.0/0.75 * 72.0 * f)),
       s if s.ends_with(~"cm") => from_str(str.substr(0, str.len() - 2)).map(|f| Px(f / 2.54 * 72.0 * 1.0/0.75)),
       s if s.ends_with(~"mm") => from_str(str.substr(0, str.len() - 2)).map(|f| Px(f * 0.1 / 2.54 * 72.0 * 1.0/0.75)),
@@ -51,9 +80,30 @@ fn parse_relative_size(str: ~str) -> ParseResult<RelativeSize> {
     }
 }
 
-fn parse_font_size(_str: ~str) -> ParseResult<CSSFontSize> {
-    // TODO: complete me
-    Value(LengthSize(Px(14.0)))
+fn parse_percent(str : ~str) -> Option<float> {
+    if !str.ends_with(~"%") { return None; }
+    from_str(str.substr(0, str.len() - 1))
+}
+
+fn parse_font_size(str: ~str) -> ParseResult<CSSFontSize> {
+    if str == ~"inherit" { return CSSInherit; }
+
+    match parse_absolute_size(copy str) {
+      Value(size) => return Value(AbsoluteSize(size)),
+      _ => {}
+    }
+    match parse_relative_size(copy str) {
+      Value(size) => return Value(RelativeSize(size)),
+      _ => {}
+    }
+    match parse_percent(copy str) {
+      Some(percent) => return Value(PercentSize(percent)),
+      None => {}
+    }
+    match parse_length(str) {
+      Some(length) => Value(LengthSize(length)),
+      None => Fail
+    }
 }
 
 // For width / height, and anything else with the same attribute values
@@ -61,7 +111,67 @@ fn parse_box_sizing(str : ~str) -> ParseResult<BoxSizing> {
     match str {
       ~"auto" => Value(BoxAuto),
       ~"inherit" => CSSInherit,
-      _ => Fail,
+      _ => match parse_percent(copy str) {
+        Some(percent) => Value(BoxPercent(percent)),
+        None => match parse_length(str) {
+          Some(length) => Value(BoxLength(length)),
+          None => Fail
+        }
+      }
+    }
+}
+
+// Margins take the same values as widths, and may also be negative
+fn parse_margin(str : ~str) -> ParseResult<BoxSizing> {
+    parse_box_sizing(str)
+}
+
+// Padding can't be 'auto' or negative
+fn parse_padding(str : ~str) -> ParseResult<BoxSizing> {
+    match parse_box_sizing(str) {
+      Value(BoxAuto) => Fail,
+      Value(BoxLength(Px(x))) | Value(BoxLength(Em(x))) | Value(BoxPercent(x)) if x < 0.0 => Fail,
+      result => result
+    }
+}
+
+fn parse_border_width(str : ~str) -> ParseResult<Length> {
+    match str {
+      ~"thin" => Value(Px(1.0)),
+      ~"medium" => Value(Px(3.0)),
+      ~"thick" => Value(Px(5.0)),
+      ~"inherit" => CSSInherit,
+      _ => match parse_length(str) {
+        Some(Px(x)) | Some(Em(x)) if x < 0.0 => Fail,
+        Some(length) => Value(length),
+        None => Fail
+      }
+    }
+}
+
+fn parse_border_style(str : ~str) -> ParseResult<CSSBorderStyle> {
+    match str {
+      ~"none" => Value(BdrStyleNone),
+      ~"hidden" => Value(BdrStyleHidden),
+      ~"dotted" => Value(BdrStyleDotted),
+      ~"dashed" => Value(BdrStyleDashed),
+      ~"solid" => Value(BdrStyleSolid),
+      ~"double" => Value(BdrStyleDouble),
+      ~"groove" => Value(BdrStyleGroove),
+      ~"ridge" => Value(BdrStyleRidge),
+      ~"inset" => Value(BdrStyleInset),
+      ~"outset" => Value(BdrStyleOutset),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_border_color(str : ~str) -> ParseResult<CSSColor> {
+    if str == ~"inherit" { return CSSInherit; }
+
+    match parse_color(str) {
+      Some(color) => Value(TextColor(color)),
+      None => Fail
     }
 }
 
@@ -69,29 +179,359 @@ fn parse_display_type(str : ~str) -> ParseResult<CSSDisplay> {
     match str {
       ~"inline" => Value(DisplayInline),
       ~"block" => Value(DisplayBlock),
+      ~"list-item" => Value(DisplayListItem),
+      ~"inline-block" => Value(DisplayInlineBlock),
+      ~"table" => Value(DisplayTable),
+      ~"inline-table" => Value(DisplayInlineTable),
+      ~"table-row-group" => Value(DisplayTableRowGroup),
+      ~"table-header-group" => Value(DisplayTableHeaderGroup),
+      ~"table-footer-group" => Value(DisplayTableFooterGroup),
+      ~"table-row" => Value(DisplayTableRow),
+      ~"table-column-group" => Value(DisplayTableColumnGroup),
+      ~"table-column" => Value(DisplayTableColumn),
+      ~"table-cell" => Value(DisplayTableCell),
+      ~"table-caption" => Value(DisplayTableCaption),
       ~"none" => Value(DisplayNone),
+      ~"inherit" => CSSInherit,
       _ => { #debug["Recieved unknown display value '%s'", str]; Fail }
     }
 }
 
+fn parse_float(str : ~str) -> ParseResult<CSSFloat> {
+    match str {
+      ~"left" => Value(FloatLeft),
+      ~"right" => Value(FloatRight),
+      ~"none" => Value(FloatNone),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_clear(str : ~str) -> ParseResult<CSSClear> {
+    match str {
+      ~"left" => Value(ClearLeft),
+      ~"right" => Value(ClearRight),
+      ~"both" => Value(ClearBoth),
+      ~"none" => Value(ClearNone),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_position(str : ~str) -> ParseResult<CSSPosition> {
+    match str {
+      ~"static" => Value(PosStatic),
+      ~"relative" => Value(PosRelative),
+      ~"absolute" => Value(PosAbsolute),
+      ~"fixed" => Value(PosFixed),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_z_index(str : ~str) -> ParseResult<CSSZIndex> {
+    match str {
+      ~"auto" => Value(ZIndexAuto),
+      ~"inherit" => CSSInherit,
+      _ => match parse_signed_int(str) {
+        Some(n) => Value(ZIndexInteger(n)),
+        None => Fail
+      }
+    }
+}
+
+fn parse_border_collapse(str : ~str) -> ParseResult<CSSBorderCollapse> {
+    match str {
+      ~"separate" => Value(BorderCollapseSeparate),
+      ~"collapse" => Value(BorderCollapseCollapse),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+/** Parses 'border-spacing' into its horizontal and vertical spacing. A single length
+    is used for both (CSS 2.1, Section 17.6.1). */
+fn parse_border_spacing(str : ~str) -> Option<(CSSValue<Length>, CSSValue<Length>)> {
+    if str == ~"inherit" { return Some((Inherit, Inherit)); }
+
+    let mut lengths = ~[];
+    for str::words(str).each |word| {
+        match parse_length(copy *word) {
+          Some(Px(x)) | Some(Em(x)) if x < 0.0 => return None,
+          Some(length) => vec::push(lengths, length),
+          None => return None
+        }
+    }
+
+    match lengths.len() {
+      1u => Some((Specified(lengths[0]), Specified(lengths[0]))),
+      2u => Some((Specified(lengths[0]), Specified(lengths[1]))),
+      _ => None
+    }
+}
+
+fn parse_table_layout(str : ~str) -> ParseResult<CSSTableLayout> {
+    match str {
+      ~"auto" => Value(TableLayoutAuto),
+      ~"fixed" => Value(TableLayoutFixed),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_caption_side(str : ~str) -> ParseResult<CSSCaptionSide> {
+    match str {
+      ~"top" => Value(CaptionSideTop),
+      ~"bottom" => Value(CaptionSideBottom),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_white_space(str : ~str) -> ParseResult<CSSWhiteSpace> {
+    match str {
+      ~"normal" => Value(WhiteSpaceNormal),
+      ~"pre" => Value(WhiteSpacePre),
+      ~"nowrap" => Value(WhiteSpaceNowrap),
+      ~"pre-wrap" => Value(WhiteSpacePreWrap),
+      ~"pre-line" => Value(WhiteSpacePreLine),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_vertical_align(str : ~str) -> ParseResult<CSSVerticalAlign> {
+    match str {
+      ~"baseline" => Value(VerticalAlignBaseline),
+      ~"sub" => Value(VerticalAlignSub),
+      ~"super" => Value(VerticalAlignSuper),
+      ~"top" => Value(VerticalAlignTop),
+      ~"text-top" => Value(VerticalAlignTextTop),
+      ~"middle" => Value(VerticalAlignMiddle),
+      ~"bottom" => Value(VerticalAlignBottom),
+      ~"text-bottom" => Value(VerticalAlignTextBottom),
+      ~"inherit" => CSSInherit,
+      _ => match parse_percent(copy str) {
+        Some(percent) => Value(VerticalAlignPercent(percent)),
+        None => match parse_length(str) {
+          Some(length) => Value(VerticalAlignLength(length)),
+          None => Fail
+        }
+      }
+    }
+}
+
+/** Parses a comma-separated list of font families. Quoted names are taken as they are,
+    and unquoted names are sequences of identifiers, which may be generic family keywords. */
+fn parse_font_family(str : ~str) -> ParseResult<~[CSSFontFamily]> {
+    if str == ~"inherit" { return CSSInherit; }
+
+    let mut families = ~[];
+    for str::split_char(str, ',').each |part| {
+        let name = str::trim(*part);
+        if name.is_empty() { return Fail; }
+
+        let family = if name.starts_with(~"\"") || name.starts_with(~"'") {
+            if name.len() < 2u || name[name.len() - 1u] != name[0] { return Fail; }
+            FamilyName(name.substr(1u, name.len() - 2u))
+        } else {
+            match str::to_lower(name) {
+              ~"serif" => FamilySerif,
+              ~"sans-serif" => FamilySansSerif,
+              ~"cursive" => FamilyCursive,
+              ~"fantasy" => FamilyFantasy,
+              ~"monospace" => FamilyMonospace,
+              _ => FamilyName(str::connect(str::words(name), ~" "))
+            }
+        };
+        vec::push(families, family);
+    }
+    return Value(families);
+}
+
+fn parse_font_weight(str : ~str) -> ParseResult<CSSFontWeight> {
+    match str {
+      ~"normal" => Value(FontWeightAbsolute(400)),
+      ~"bold" => Value(FontWeightAbsolute(700)),
+      ~"bolder" => Value(FontWeightBolder),
+      ~"lighter" => Value(FontWeightLighter),
+      ~"inherit" => CSSInherit,
+      _ => match uint::from_str(str) {
+        Some(weight) if weight >= 100 && weight <= 900 && weight % 100 == 0 => {
+            Value(FontWeightAbsolute(weight))
+        }
+        _ => Fail
+      }
+    }
+}
+
+fn parse_font_style(str : ~str) -> ParseResult<CSSFontStyle> {
+    match str {
+      ~"normal" => Value(FontStyleNormal),
+      ~"italic" => Value(FontStyleItalic),
+      ~"oblique" => Value(FontStyleOblique),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_line_height(str : ~str) -> ParseResult<CSSLineHeight> {
+    match str {
+      ~"normal" => Value(LineHeightNormal),
+      ~"inherit" => CSSInherit,
+      _ => match parse_percent(copy str) {
+        Some(percent) if percent >= 0.0 => Value(LineHeightPercent(percent)),
+        Some(_) => Fail,
+        None => match from_str(copy str) {
+          Some(number) if number >= 0.0 => Value(LineHeightNumber(number)),
+          Some(_) => Fail,
+          None => match parse_length(str) {
+            Some(Px(x)) | Some(Em(x)) if x < 0.0 => Fail,
+            Some(length) => Value(LineHeightLength(length)),
+            None => Fail
+          }
+        }
+      }
+    }
+}
+
+fn parse_text_align(str : ~str) -> ParseResult<CSSTextAlign> {
+    match str {
+      ~"start" => Value(TextAlignStart),
+      ~"end" => Value(TextAlignEnd),
+      ~"left" => Value(TextAlignLeft),
+      ~"right" => Value(TextAlignRight),
+      ~"center" => Value(TextAlignCenter),
+      ~"justify" => Value(TextAlignJustify),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+/** Parses 'text-decoration' into the lines it draws, each at most once. */
+fn parse_text_decoration(str : ~str) -> ParseResult<~[CSSTextDecoration]> {
+    match str {
+      ~"none" => return Value(~[]),
+      ~"inherit" => return CSSInherit,
+      _ => {}
+    }
+
+    let mut lines = ~[];
+    for str::words(str).each |word| {
+        let line = match *word {
+          ~"underline" => DecorationUnderline,
+          ~"overline" => DecorationOverline,
+          ~"line-through" => DecorationLineThrough,
+          ~"blink" => DecorationBlink,
+          _ => return Fail
+        };
+        if vec::contains(lines, &line) { return Fail; }
+        vec::push(lines, line);
+    }
+    return Value(lines);
+}
+
+fn parse_text_transform(str : ~str) -> ParseResult<CSSTextTransform> {
+    match str {
+      ~"none" => Value(TextTransformNone),
+      ~"capitalize" => Value(TextTransformCapitalize),
+      ~"uppercase" => Value(TextTransformUppercase),
+      ~"lowercase" => Value(TextTransformLowercase),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_direction(str : ~str) -> ParseResult<CSSDirection> {
+    match str {
+      ~"ltr" => Value(DirectionLtr),
+      ~"rtl" => Value(DirectionRtl),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_unicode_bidi(str : ~str) -> ParseResult<CSSUnicodeBidi> {
+    match str {
+      ~"normal" => Value(UnicodeBidiNormal),
+      ~"embed" => Value(UnicodeBidiEmbed),
+      ~"bidi-override" => Value(UnicodeBidiOverride),
+      ~"inherit" => CSSInherit,
+      _ => Fail
+    }
+}
+
+fn parse_signed_int(str : ~str) -> Option<int> {
+    if str.starts_with(~"+") {
+        int::from_str(str.substr(1, str.len() - 1))
+    } else {
+        int::from_str(str)
+    }
+}
+
+/** Parses the 'an+b' argument of ':nth-child()' into (a, b). */
+fn parse_nth_child_expr(str : ~str) -> Option<(int, int)> {
+    let expr = str::replace(str::trim(str).to_lower(), ~" ", ~"");
+
+    match expr {
+      ~"odd" => Some((2, 1)),
+      ~"even" => Some((2, 0)),
+      _ => match str::find_char(expr, 'n') {
+        None => parse_signed_int(expr).map(|b| (0, b)),
+        Some(idx) => {
+            let a_str = expr.substr(0, idx);
+            let b_str = expr.substr(idx + 1, expr.len() - idx - 1);
+            let a = match a_str {
+              ~"" | ~"+" => Some(1),
+              ~"-" => Some(-1),
+              _ => parse_signed_int(a_str)
+            };
+            let b = if b_str.is_empty() { Some(0) } else { parse_signed_int(b_str) };
+            match (a, b) {
+              (Some(a), Some(b)) => Some((a, b)),
+              _ => None
+            }
+        }
+      }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use css::lexer::spawn_css_lexer_from_string;
     use css::parser::build_stylesheet;
-    use css::values::{Stylesheet, Element, FontSize, Width, Height};
+    use css::values::{CSSRule, Selector, Stylesheet, StyleRule, Element, FontSize, Width, Height};
+    use css::values::Normal;
+    use css::parser_util::{parse_font_size, parse_display_type, parse_box_sizing};
+    use css::parser_util::{parse_length, parse_padding, parse_border_width, parse_border_style};
+    use css::parser_util::{parse_nth_child_expr, parse_float, parse_clear};
+    use css::parser_util::{parse_position, parse_z_index};
+    use css::parser_util::{parse_border_collapse, parse_border_spacing, parse_caption_side};
+    use css::parser_util::{parse_white_space, parse_vertical_align};
+    use css::parser_util::{parse_font_family, parse_font_weight, parse_line_height};
+    use css::parser_util::{parse_text_align, parse_text_decoration};
+    use css::parser_util::{parse_direction, parse_unicode_bidi};
+    use css::values::{Class, Id, Pseudo, Not, NthChild, FirstChild};
     
     // TODO: use helper methods to create test values
 
+    fn selectors(rule: &CSSRule) -> ~[~Selector] {
+        match copy *rule {
+          StyleRule((sels, _)) => sels,
+          _ => fail ~"expected a style rule"
+        }
+    }
+
     #[test]
     fn should_match_font_sizes() {
         let input = ~"* {font-size:12px; font-size:inherit; font-size:200%; font-size:x-small}";
         let token_port = spawn_css_lexer_from_string(input);
         let _actual_rule = build_stylesheet(token_port);
-        let _expected_rule : Stylesheet = ~[~(~[~Element(~"*", ~[])],
-                                             ~[FontSize(Specified(LengthSize(Px(12.0)))),
-                                               FontSize(Specified(PercentSize(100.0))),
-                                               FontSize(Specified(PercentSize(200.0))),
-                                               FontSize(Specified(LengthSize(Px(12.0))))])];
+        let _expected_rule : Stylesheet = ~[~StyleRule((~[~Element(~"*", ~[])],
+                                             ~[(FontSize(Specified(LengthSize(Px(12.0)))), Normal),
+                                               (FontSize(Specified(PercentSize(100.0))), Normal),
+                                               (FontSize(Specified(PercentSize(200.0))), Normal),
+                                               (FontSize(Specified(LengthSize(Px(12.0)))), Normal)]))];
 
         // TODO: fix me once StyleDeclaration is a trait, not an enum
         //assert actual_rule == expected_rule;
@@ -102,15 +542,167 @@ mod test {
         let input = ~"* {width:20%; height:auto; width:20px; width:3in; height:70px; height:30px}";
         let token_port = spawn_css_lexer_from_string(input);
         let _actual_rule = build_stylesheet(token_port);
-        let _expected_rule : Stylesheet = ~[~(~[~Element(~"*", ~[])],
-                                             ~[Width(Specified(BoxPercent(20.0))),
-                                               Height(Specified(BoxAuto)),
-                                               Width(Specified(BoxLength(Px(20.0)))),
-                                               Width(Specified(BoxLength(Px(216.0)))),
-                                               Height(Specified(BoxLength(Px(70.0)))),
-                                               Height(Specified(BoxLength(Px(30.0))))])];
+        let _expected_rule : Stylesheet = ~[~StyleRule((~[~Element(~"*", ~[])],
+                                             ~[(Width(Specified(BoxPercent(20.0))), Normal),
+                                               (Height(Specified(BoxAuto)), Normal),
+                                               (Width(Specified(BoxLength(Px(20.0)))), Normal),
+                                               (Width(Specified(BoxLength(Px(216.0)))), Normal),
+                                               (Height(Specified(BoxLength(Px(70.0)))), Normal),
+                                               (Height(Specified(BoxLength(Px(30.0)))), Normal)]))];
 
         // TODO: fix me once StyleDeclaration is a trait, not an enum
         //assert actual_rule == expected_rule;
     }
+
+    #[test]
+    fn should_parse_font_size_values() {
+        assert parse_font_size(~"x-small") == Value(AbsoluteSize(XSmall));
+        assert parse_font_size(~"larger") == Value(RelativeSize(Larger));
+        assert parse_font_size(~"200%") == Value(PercentSize(200.0));
+        assert parse_font_size(~"1.5em") == Value(LengthSize(Em(1.5)));
+        assert parse_font_size(~"12px") == Value(LengthSize(Px(12.0)));
+        assert parse_font_size(~"inherit") == CSSInherit;
+        assert parse_font_size(~"enormous") == Fail;
+    }
+
+    #[test]
+    fn should_parse_box_sizing_values() {
+        assert parse_box_sizing(~"auto") == Value(BoxAuto);
+        assert parse_box_sizing(~"20%") == Value(BoxPercent(20.0));
+        assert parse_box_sizing(~"20px") == Value(BoxLength(Px(20.0)));
+        assert parse_box_sizing(~"2em") == Value(BoxLength(Em(2.0)));
+        assert parse_box_sizing(~"wide") == Fail;
+    }
+
+    #[test]
+    fn should_parse_box_model_values() {
+        assert parse_length(~"0") == Some(Px(0.0));
+        assert parse_length(~"3") == None;
+        assert parse_padding(~"5%") == Value(BoxPercent(5.0));
+        assert parse_padding(~"auto") == Fail;
+        assert parse_padding(~"-1px") == Fail;
+        assert parse_border_width(~"thick") == Value(Px(5.0));
+        assert parse_border_width(~"0.5em") == Value(Em(0.5));
+        assert parse_border_width(~"-1px") == Fail;
+        assert parse_border_style(~"dashed") == Value(BdrStyleDashed);
+        assert parse_border_style(~"wavy") == Fail;
+    }
+
+    #[test]
+    fn should_parse_display_values() {
+        assert parse_display_type(~"list-item") == Value(DisplayListItem);
+        assert parse_display_type(~"table-cell") == Value(DisplayTableCell);
+        assert parse_display_type(~"inline-block") == Value(DisplayInlineBlock);
+        assert parse_display_type(~"flex") == Fail;
+    }
+
+    #[test]
+    fn should_parse_float_and_clear_values() {
+        assert parse_float(~"left") == Value(FloatLeft);
+        assert parse_float(~"none") == Value(FloatNone);
+        assert parse_float(~"center") == Fail;
+        assert parse_clear(~"both") == Value(ClearBoth);
+        assert parse_clear(~"inherit") == CSSInherit;
+        assert parse_clear(~"top") == Fail;
+    }
+
+    #[test]
+    fn should_parse_positioning_values() {
+        assert parse_position(~"absolute") == Value(PosAbsolute);
+        assert parse_position(~"sticky") == Fail;
+        assert parse_z_index(~"auto") == Value(ZIndexAuto);
+        assert parse_z_index(~"-2") == Value(ZIndexInteger(-2));
+        assert parse_z_index(~"+3") == Value(ZIndexInteger(3));
+        assert parse_z_index(~"1.5") == Fail;
+    }
+
+    #[test]
+    fn should_parse_table_values() {
+        assert parse_border_collapse(~"collapse") == Value(BorderCollapseCollapse);
+        assert parse_border_collapse(~"merge") == Fail;
+        assert parse_border_spacing(~"2px") == Some((Specified(Px(2.0)), Specified(Px(2.0))));
+        assert parse_border_spacing(~"1px 0.5em") == Some((Specified(Px(1.0)), Specified(Em(0.5))));
+        assert parse_border_spacing(~"-1px") == None;
+        assert parse_border_spacing(~"1px 2px 3px") == None;
+        assert parse_caption_side(~"bottom") == Value(CaptionSideBottom);
+        assert parse_caption_side(~"left") == Fail;
+    }
+
+    #[test]
+    fn should_parse_inline_formatting_values() {
+        assert parse_white_space(~"pre-wrap") == Value(WhiteSpacePreWrap);
+        assert parse_white_space(~"wrap") == Fail;
+        assert parse_vertical_align(~"text-bottom") == Value(VerticalAlignTextBottom);
+        assert parse_vertical_align(~"-25%") == Value(VerticalAlignPercent(-25.0));
+        assert parse_vertical_align(~"0.5em") == Value(VerticalAlignLength(Em(0.5)));
+        assert parse_vertical_align(~"center") == Fail;
+    }
+
+    #[test]
+    fn should_parse_font_families() {
+        assert parse_font_family(~"\"Times  New Roman\", Georgia , serif")
+            == Value(~[FamilyName(~"Times  New Roman"), FamilyName(~"Georgia"), FamilySerif]);
+        assert parse_font_family(~"Josefin   Sans,MONOSPACE")
+            == Value(~[FamilyName(~"Josefin Sans"), FamilyMonospace]);
+        assert parse_font_family(~"Georgia,") == Fail;
+        assert parse_font_family(~"'Unclosed") == Fail;
+    }
+
+    #[test]
+    fn should_parse_text_styling_values() {
+        assert parse_font_weight(~"bold") == Value(FontWeightAbsolute(700));
+        assert parse_font_weight(~"300") == Value(FontWeightAbsolute(300));
+        assert parse_font_weight(~"350") == Fail;
+        assert parse_line_height(~"1.5") == Value(LineHeightNumber(1.5));
+        assert parse_line_height(~"120%") == Value(LineHeightPercent(120.0));
+        assert parse_line_height(~"2em") == Value(LineHeightLength(Em(2.0)));
+        assert parse_line_height(~"-1px") == Fail;
+        assert parse_text_decoration(~"none") == Value(~[]);
+        assert parse_text_decoration(~"underline line-through")
+            == Value(~[DecorationUnderline, DecorationLineThrough]);
+        assert parse_text_decoration(~"underline underline") == Fail;
+        assert parse_text_align(~"end") == Value(TextAlignEnd);
+    }
+
+    #[test]
+    fn should_parse_bidi_values() {
+        assert parse_direction(~"rtl") == Value(DirectionRtl);
+        assert parse_direction(~"left") == Fail;
+        assert parse_unicode_bidi(~"bidi-override") == Value(UnicodeBidiOverride);
+        assert parse_unicode_bidi(~"isolate") == Fail;
+    }
+
+    #[test]
+    fn should_parse_nth_child_exprs() {
+        assert parse_nth_child_expr(~"odd") == Some((2, 1));
+        assert parse_nth_child_expr(~"even") == Some((2, 0));
+        assert parse_nth_child_expr(~"3") == Some((0, 3));
+        assert parse_nth_child_expr(~"2n+1") == Some((2, 1));
+        assert parse_nth_child_expr(~" -n + 3 ") == Some((-1, 3));
+        assert parse_nth_child_expr(~"n") == Some((1, 0));
+        assert parse_nth_child_expr(~"3n-2") == Some((3, -2));
+        assert parse_nth_child_expr(~"banana") == None;
+    }
+
+    #[test]
+    fn should_parse_class_id_and_pseudo_class_selectors() {
+        let input = ~"li.todo:nth-child(2n+1) {width:auto} #main:not(.wide) {height:auto} \
+                      p:first-child {width:auto}";
+        let sheet = build_stylesheet(spawn_css_lexer_from_string(input));
+        assert sheet.len() == 3u;
+
+        assert selectors(sheet[0]) == ~[~Element(~"li", ~[Class(~"todo"), Pseudo(NthChild(2, 1))])];
+        let not_wide = Pseudo(Not(~Element(~"*", ~[Class(~"wide")])));
+        assert selectors(sheet[1]) == ~[~Element(~"*", ~[Id(~"main"), not_wide])];
+        assert selectors(sheet[2]) == ~[~Element(~"p", ~[Pseudo(FirstChild)])];
+    }
+
+    #[test]
+    fn should_drop_rules_with_invalid_selectors() {
+        let input = ~"a:hover, p {width:auto} p:nth-child(banana) {width:auto} div {height:auto}";
+        let sheet = build_stylesheet(spawn_css_lexer_from_string(input));
+        assert sheet.len() == 1u;
+
+        assert selectors(sheet[0]) == ~[~Element(~"div", ~[])];
+    }
 }
@@ -5,13 +5,15 @@
 use au = gfx::geometry;
 use dom::node::{Node, NodeTree};
 use dom::element::*;
-use layout::box::{RenderBox, SpecifiedStyle, RenderBoxTree};
+use layout::box::{RenderBox, RenderBoxTree};
 use layout::context::LayoutContext;
-use layout::traverse_parallel::top_down_traversal;
 use image::ImageHolder;
 use resource::image_cache_task::ImageCacheTask;
 use std::net::url::Url;
+use util::color::Color;
+use util::color::css_colors::black;
 
+use css::styles::{ComputedStyle, SpecifiedStyle, StyleMethods};
 use css::values::*;
 
 trait ResolveMethods<T> {
@@ -22,10 +24,83 @@ impl CSSValue<CSSBackgroundColor> : ResolveMethods<CSSBackgroundColor> {
     pure fn initial() -> CSSBackgroundColor { return BgColorTransparent; }
 }
 
+impl CSSValue<CSSBackgroundImage> : ResolveMethods<CSSBackgroundImage> {
+    pure fn initial() -> CSSBackgroundImage { return BgImageNone; }
+}
+
 impl CSSValue<CSSDisplay> : ResolveMethods<CSSDisplay> {
     pure fn initial() -> CSSDisplay { return DisplayInline; }
 }
 
+impl CSSValue<CSSFloat> : ResolveMethods<CSSFloat> {
+    pure fn initial() -> CSSFloat { return FloatNone; }
+}
+
+impl CSSValue<CSSClear> : ResolveMethods<CSSClear> {
+    pure fn initial() -> CSSClear { return ClearNone; }
+}
+
+impl CSSValue<CSSPosition> : ResolveMethods<CSSPosition> {
+    pure fn initial() -> CSSPosition { return PosStatic; }
+}
+
+impl CSSValue<CSSZIndex> : ResolveMethods<CSSZIndex> {
+    pure fn initial() -> CSSZIndex { return ZIndexAuto; }
+}
+
+impl CSSValue<CSSBorderCollapse> : ResolveMethods<CSSBorderCollapse> {
+    pure fn initial() -> CSSBorderCollapse { return BorderCollapseSeparate; }
+}
+
+impl CSSValue<CSSTableLayout> : ResolveMethods<CSSTableLayout> {
+    pure fn initial() -> CSSTableLayout { return TableLayoutAuto; }
+}
+
+impl CSSValue<CSSCaptionSide> : ResolveMethods<CSSCaptionSide> {
+    pure fn initial() -> CSSCaptionSide { return CaptionSideTop; }
+}
+
+impl CSSValue<CSSWhiteSpace> : ResolveMethods<CSSWhiteSpace> {
+    pure fn initial() -> CSSWhiteSpace { return WhiteSpaceNormal; }
+}
+
+impl CSSValue<CSSVerticalAlign> : ResolveMethods<CSSVerticalAlign> {
+    pure fn initial() -> CSSVerticalAlign { return VerticalAlignBaseline; }
+}
+
+impl CSSValue<~[CSSFontFamily]> : ResolveMethods<~[CSSFontFamily]> {
+    pure fn initial() -> ~[CSSFontFamily] { return ~[FamilySerif]; }
+}
+
+impl CSSValue<CSSFontWeight> : ResolveMethods<CSSFontWeight> {
+    pure fn initial() -> CSSFontWeight { return FontWeightAbsolute(400); }
+}
+
+impl CSSValue<CSSFontStyle> : ResolveMethods<CSSFontStyle> {
+    pure fn initial() -> CSSFontStyle { return FontStyleNormal; }
+}
+
+impl CSSValue<CSSLineHeight> : ResolveMethods<CSSLineHeight> {
+    pure fn initial() -> CSSLineHeight { return LineHeightNormal; }
+}
+
+impl CSSValue<CSSTextAlign> : ResolveMethods<CSSTextAlign> {
+    // Left-aligned in 'ltr' text and right-aligned in 'rtl' text
+    pure fn initial() -> CSSTextAlign { return TextAlignStart; }
+}
+
+impl CSSValue<CSSTextTransform> : ResolveMethods<CSSTextTransform> {
+    pure fn initial() -> CSSTextTransform { return TextTransformNone; }
+}
+
+impl CSSValue<CSSDirection> : ResolveMethods<CSSDirection> {
+    pure fn initial() -> CSSDirection { return DirectionLtr; }
+}
+
+impl CSSValue<CSSUnicodeBidi> : ResolveMethods<CSSUnicodeBidi> {
+    pure fn initial() -> CSSUnicodeBidi { return UnicodeBidiNormal; }
+}
+
 impl CSSValue<BoxSizing> : ResolveMethods<BoxSizing> {
     pure fn initial() -> BoxSizing { return BoxAuto; }
 }
@@ -34,96 +109,598 @@ impl CSSValue<CSSFontSize> : ResolveMethods<CSSFontSize> {
     pure fn initial() -> CSSFontSize { return AbsoluteSize(Medium); }
 }
 
+impl CSSValue<CSSColor> : ResolveMethods<CSSColor> {
+    pure fn initial() -> CSSColor { return TextColor(black()); }
+}
 
-struct StyleApplicator {
-    node: Node,
-    reflow: fn~(),
+impl CSSValue<CSSBorderStyle> : ResolveMethods<CSSBorderStyle> {
+    pure fn initial() -> CSSBorderStyle { return BdrStyleNone; }
 }
 
-// TODO: normalize this into a normal preorder tree traversal function
-fn apply_style(layout_ctx: &LayoutContext, node: Node, reflow: fn~()) {
-    let applicator = StyleApplicator {
-        node: node,
-        reflow: reflow
+impl CSSValue<Length> : ResolveMethods<Length> {
+    // The initial border width, 'medium'
+    pure fn initial() -> Length { return Px(3.0); }
+}
+
+// The font size of 'medium', in px
+const MEDIUM_FONT_SIZE: float = 16.0;
+// The ratio between adjacent absolute font sizes (CSS 2.1, Section 15.7)
+const FONT_SIZE_SCALE: float = 1.2;
+
+/** The style of the root element's (nonexistent) parent, which the root inherits from. */
+fn initial_computed_style() -> ComputedStyle {
+    ComputedStyle {
+        background_color: BgColorTransparent,
+        background_image: BgImageNone,
+        display_type: DisplayInline,
+        font_size: MEDIUM_FONT_SIZE,
+        height: BoxAuto,
+        text_color: black(),
+        width: BoxAuto,
+        float_type: FloatNone,
+        clear: ClearNone,
+        position: PosStatic,
+        top: BoxAuto,
+        right: BoxAuto,
+        bottom: BoxAuto,
+        left: BoxAuto,
+        z_index: ZIndexAuto,
+        border_collapse: BorderCollapseSeparate,
+        border_spacing_horizontal: 0.0,
+        border_spacing_vertical: 0.0,
+        table_layout: TableLayoutAuto,
+        caption_side: CaptionSideTop,
+        white_space: WhiteSpaceNormal,
+        vertical_align: VerticalAlignBaseline,
+        font_family: ~[FamilySerif],
+        font_weight: 400,
+        font_style: FontStyleNormal,
+        line_height: LineHeightNormal,
+        text_align: TextAlignStart,
+        text_decorations: ~[],
+        text_transform: TextTransformNone,
+        direction: DirectionLtr,
+        unicode_bidi: UnicodeBidiNormal,
+        margin_top: BoxLength(Px(0.0)),
+        margin_right: BoxLength(Px(0.0)),
+        margin_bottom: BoxLength(Px(0.0)),
+        margin_left: BoxLength(Px(0.0)),
+        padding_top: BoxLength(Px(0.0)),
+        padding_right: BoxLength(Px(0.0)),
+        padding_bottom: BoxLength(Px(0.0)),
+        padding_left: BoxLength(Px(0.0)),
+        border_top_width: 0.0,
+        border_right_width: 0.0,
+        border_bottom_width: 0.0,
+        border_left_width: 0.0,
+        border_top_style: BdrStyleNone,
+        border_right_style: BdrStyleNone,
+        border_bottom_style: BdrStyleNone,
+        border_left_style: BdrStyleNone,
+        border_top_color: black(),
+        border_right_color: black(),
+        border_bottom_color: black(),
+        border_left_color: black()
+    }
+}
+
+pure fn compute_absolute_size(size: AbsoluteSize) -> float {
+    match size {
+      XXSmall => 9.0,
+      XSmall => 10.0,
+      Small => 13.0,
+      Medium => MEDIUM_FONT_SIZE,
+      Large => 18.0,
+      XLarge => 24.0,
+      XXLarge => 32.0
+    }
+}
+
+/** Computes a font size in px. Relative sizes are relative to the parent's font size. */
+pure fn compute_font_size(specified: CSSValue<CSSFontSize>, parent_size: float) -> float {
+    let size = match specified {
+      Initial => specified.initial(),
+      Inherit => return parent_size,
+      Specified(size) => size
     };
 
-    applicator.apply_css_style(layout_ctx);
+    match size {
+      AbsoluteSize(size) => compute_absolute_size(size),
+      RelativeSize(Larger) => parent_size * FONT_SIZE_SCALE,
+      RelativeSize(Smaller) => parent_size / FONT_SIZE_SCALE,
+      LengthSize(Em(n)) => n * parent_size,
+      LengthSize(Px(n)) => n,
+      PercentSize(percent) => parent_size * percent / 100.0
+    }
 }
 
-// TODO: this is misleadingly-named. It is actually trying to resolve CSS 'inherit' values.
+/** Computes a width or height. Lengths in em are relative to the element's own font size. */
+pure fn compute_box_sizing(specified: CSSValue<BoxSizing>, font_size: float,
+                           parent_value: BoxSizing) -> BoxSizing {
+    match specified {
+      Initial => specified.initial(),
+      Inherit => parent_value,
+      Specified(BoxLength(Em(n))) => BoxLength(Px(n * font_size)),
+      Specified(value) => value
+    }
+}
 
-/** A wrapper around a set of functions that can be applied as a
- * top-down traversal of layout boxes.
- */
-fn inheritance_wrapper(layout_ctx: &LayoutContext, node : Node, reflow: fn~()) {
-    let applicator = StyleApplicator {
-        node: node,
-        reflow: reflow
+/** Computes a margin or padding, which are initially zero rather than 'auto'. */
+pure fn compute_box_edge(specified: CSSValue<BoxSizing>, font_size: float,
+                         parent_value: BoxSizing) -> BoxSizing {
+    match specified {
+      Initial => BoxLength(Px(0.0)),
+      _ => compute_box_sizing(specified, font_size, parent_value)
+    }
+}
+
+pure fn compute_border_style(specified: CSSValue<CSSBorderStyle>,
+                             parent_value: CSSBorderStyle) -> CSSBorderStyle {
+    match specified {
+      Initial => specified.initial(),
+      Inherit => parent_value,
+      Specified(style) => style
+    }
+}
+
+/** Computes a border width in px. Borders without a style have no width (CSS 2.1,
+    Section 8.5.1). */
+pure fn compute_border_width(specified: CSSValue<Length>, style: CSSBorderStyle,
+                             font_size: float, parent_value: float) -> float {
+    match style {
+      BdrStyleNone | BdrStyleHidden => return 0.0,
+      _ => {}
+    }
+
+    match specified {
+      Initial => specified.initial().abs(),
+      Inherit => parent_value,
+      Specified(Em(n)) => n * font_size,
+      Specified(Px(n)) => n
+    }
+}
+
+/** Computes one of the lengths of 'border-spacing' in px. It is initially zero. */
+pure fn compute_border_spacing(specified: CSSValue<Length>, font_size: float,
+                               parent_value: float) -> float {
+    match specified {
+      Initial => 0.0,
+      Inherit => parent_value,
+      Specified(Em(n)) => n * font_size,
+      Specified(Px(n)) => n
+    }
+}
+
+/** Computes 'vertical-align', converting lengths to px. Percentages refer to the line
+    height, which is left to layout. */
+pure fn compute_vertical_align(specified: CSSValue<CSSVerticalAlign>, font_size: float,
+                               parent_value: CSSVerticalAlign) -> CSSVerticalAlign {
+    match specified {
+      Initial => specified.initial(),
+      Inherit => parent_value,
+      Specified(VerticalAlignLength(Em(n))) => VerticalAlignLength(Px(n * font_size)),
+      Specified(align) => align
+    }
+}
+
+/** Computes a font weight from 100 to 900. 'bolder' and 'lighter' go to the next weight
+    a family is likely to have (CSS Fonts Level 3, Section 3.2). */
+pure fn compute_font_weight(specified: CSSValue<CSSFontWeight>, parent_value: uint) -> uint {
+    let weight = match specified {
+      Initial => specified.initial(),
+      Inherit => return parent_value,
+      Specified(weight) => weight
     };
-    applicator.resolve_style(layout_ctx);
+
+    match weight {
+      FontWeightAbsolute(weight) => weight,
+      FontWeightBolder if parent_value < 400 => 400,
+      FontWeightBolder if parent_value < 600 => 700,
+      FontWeightBolder => 900,
+      FontWeightLighter if parent_value < 600 => 100,
+      FontWeightLighter if parent_value < 800 => 400,
+      FontWeightLighter => 700
+    }
 }
 
-/*
-fn resolve_fontsize(box : @RenderBox) {
-    // TODO: complete this
-    return
+/** Computes 'line-height'. Numbers are kept, so that children scale them by their own
+    font sizes, and lengths and percentages become px. */
+pure fn compute_line_height(specified: CSSValue<CSSLineHeight>, font_size: float,
+                            parent_value: CSSLineHeight) -> CSSLineHeight {
+    match specified {
+      Initial => specified.initial(),
+      Inherit => parent_value,
+      Specified(LineHeightLength(Em(n))) => LineHeightLength(Px(n * font_size)),
+      Specified(LineHeightPercent(percent)) => LineHeightLength(Px(font_size * percent / 100.0)),
+      Specified(line_height) => line_height
+    }
 }
 
-fn resolve_height(box : @RenderBox) -> au {
-    let style = box.node.get_style();
-    let inherit_val = match box.tree.parent {
-        None => au(0),
-        Some(parent) => parent.data.computed_size.height
+/** Computes the text decorations in effect on an element: those of its parent, unless
+    it is floated, absolutely positioned or an inline-block (CSS 2.1, Section 16.3.1), and
+    its own, drawn in its color. 'inherit' adds no lines of its own, since those of the
+    parent already propagate. */
+fn compute_text_decorations(specified: &CSSValue<~[CSSTextDecoration]>, text_color: Color,
+                            display: CSSDisplay, position: CSSPosition, float_type: CSSFloat,
+                            parent_value: &[(CSSTextDecoration, Color)])
+                         -> ~[(CSSTextDecoration, Color)] {
+    let propagates = match (display, position, float_type) {
+      (DisplayInlineBlock, _, _) | (DisplayInlineTable, _, _) => false,
+      (_, PosAbsolute, _) | (_, PosFixed, _) => false,
+      (_, _, FloatNone) => true,
+      (_, _, _) => false
     };
 
-    box.appearance.height = match style.height {
-        Initial => style.height.initial(),
-        Inherit => inherit_val,
-        Specified(val) => match val { // BoxSizing
-            BoxPercent(*) | BoxAuto | BoxLength(Px(_)) => val,
-            BoxLength(Em(n)) => BoxLength(Px(n * box.appearance.font_size.abs()))
+    let mut decorations = ~[];
+    if propagates {
+        vec::push_all(decorations, parent_value);
+    }
+    match *specified {
+      Specified(ref lines) => {
+        for lines.each |line| {
+            vec::push(decorations, (*line, text_color));
         }
+      }
+      Initial | Inherit => {}
     }
+    return decorations;
 }
 
-fn resolve_width(box : @RenderBox) {
-    let style = box.node.get_specified_style();
-    let inherit_val = match box.tree.parent {
-        None => style.height.initial(),
-        Some(node) => node.appearance.width
+/** Computes a border color. Borders are initially the color of the element's text. */
+pure fn compute_border_color(specified: CSSValue<CSSColor>, text_color: Color,
+                             parent_value: Color) -> Color {
+    match specified {
+      Initial => text_color,
+      Inherit => parent_value,
+      Specified(TextColor(color)) => color
+    }
+}
+
+/** Computes 'display'. Floated and absolutely positioned boxes are laid out as
+    blocks (CSS 2.1, Section 9.7). */
+pure fn compute_display(specified: CSSValue<CSSDisplay>, position: CSSPosition,
+                        float_type: CSSFloat, parent_value: CSSDisplay) -> CSSDisplay {
+    let display = match specified {
+      Initial => specified.initial(),
+      Inherit => parent_value,
+      Specified(display) => display
     };
 
-    box.appearance.width = match style.width {
-        Initial => style.width.initial(),
-        Inherit => inherit_val,
-        Specified(val) => match val { // BoxSizing
-            BoxPercent(*) | BoxAuto | BoxLength(Px(_)) => val,
-            BoxLength(Em(n)) => BoxLength(Px(n * box.appearance.font_size.abs()))
-        }
+    let blockify = match (position, float_type) {
+      (PosAbsolute, _) | (PosFixed, _) => true,
+      (_, FloatNone) => false,
+      (_, _) => true
+    };
+    if !blockify { return display; }
+
+    match display {
+      DisplayInlineTable => DisplayTable,
+      DisplayInline | DisplayInlineBlock | DisplayTableRowGroup | DisplayTableHeaderGroup
+      | DisplayTableFooterGroup | DisplayTableRow | DisplayTableColumnGroup
+      | DisplayTableColumn | DisplayTableCell | DisplayTableCaption => DisplayBlock,
+      _ => display
     }
-}*/
+}
+
+/** Computes the style of a node from its specified style and its parent's computed style. */
+fn compute_style(specified: &SpecifiedStyle, parent: &ComputedStyle) -> ComputedStyle {
+    let font_size = compute_font_size(specified.font_size, parent.font_size);
+    let text_color = match specified.text_color {
+        Initial => match specified.text_color.initial() { TextColor(color) => color },
+        Inherit => parent.text_color,
+        Specified(TextColor(color)) => color
+    };
+    let position = match specified.position {
+        Initial => specified.position.initial(),
+        Inherit => parent.position,
+        Specified(position) => position
+    };
+    // Absolutely positioned boxes do not float
+    let float_type = match (position, specified.float_type) {
+        (PosAbsolute, _) | (PosFixed, _) => FloatNone,
+        (_, Initial) => specified.float_type.initial(),
+        (_, Inherit) => parent.float_type,
+        (_, Specified(float_type)) => float_type
+    };
+    let display_type = compute_display(specified.display_type, position, float_type,
+                                       parent.display_type);
+    let border_top_style = compute_border_style(specified.border_top_style,
+                                                parent.border_top_style);
+    let border_right_style = compute_border_style(specified.border_right_style,
+                                                  parent.border_right_style);
+    let border_bottom_style = compute_border_style(specified.border_bottom_style,
+                                                   parent.border_bottom_style);
+    let border_left_style = compute_border_style(specified.border_left_style,
+                                                 parent.border_left_style);
+
+    ComputedStyle {
+        background_color: match specified.background_color {
+            Initial => specified.background_color.initial(),
+            Inherit => parent.background_color,
+            Specified(color) => color
+        },
+        background_image: match copy specified.background_image {
+            Initial => specified.background_image.initial(),
+            Inherit => copy parent.background_image,
+            Specified(image) => image
+        },
+        display_type: display_type,
+        font_size: font_size,
+        height: compute_box_sizing(specified.height, font_size, parent.height),
+        text_color: text_color,
+        width: compute_box_sizing(specified.width, font_size, parent.width),
+        float_type: float_type,
+        clear: match specified.clear {
+            Initial => specified.clear.initial(),
+            Inherit => parent.clear,
+            Specified(clear) => clear
+        },
+        position: position,
+        top: compute_box_sizing(specified.top, font_size, parent.top),
+        right: compute_box_sizing(specified.right, font_size, parent.right),
+        bottom: compute_box_sizing(specified.bottom, font_size, parent.bottom),
+        left: compute_box_sizing(specified.left, font_size, parent.left),
+        z_index: match specified.z_index {
+            Initial => specified.z_index.initial(),
+            Inherit => parent.z_index,
+            Specified(z_index) => z_index
+        },
+        border_collapse: match specified.border_collapse {
+            Initial => specified.border_collapse.initial(),
+            Inherit => parent.border_collapse,
+            Specified(collapse) => collapse
+        },
+        border_spacing_horizontal: compute_border_spacing(specified.border_spacing_horizontal,
+                                                          font_size,
+                                                          parent.border_spacing_horizontal),
+        border_spacing_vertical: compute_border_spacing(specified.border_spacing_vertical,
+                                                        font_size, parent.border_spacing_vertical),
+        table_layout: match specified.table_layout {
+            Initial => specified.table_layout.initial(),
+            Inherit => parent.table_layout,
+            Specified(table_layout) => table_layout
+        },
+        caption_side: match specified.caption_side {
+            Initial => specified.caption_side.initial(),
+            Inherit => parent.caption_side,
+            Specified(side) => side
+        },
+        white_space: match specified.white_space {
+            Initial => specified.white_space.initial(),
+            Inherit => parent.white_space,
+            Specified(white_space) => white_space
+        },
+        vertical_align: compute_vertical_align(specified.vertical_align, font_size,
+                                               parent.vertical_align),
+        font_family: match copy specified.font_family {
+            Initial => specified.font_family.initial(),
+            Inherit => copy parent.font_family,
+            Specified(families) => families
+        },
+        font_weight: compute_font_weight(specified.font_weight, parent.font_weight),
+        font_style: match specified.font_style {
+            Initial => specified.font_style.initial(),
+            Inherit => parent.font_style,
+            Specified(font_style) => font_style
+        },
+        line_height: compute_line_height(specified.line_height, font_size, parent.line_height),
+        text_align: match specified.text_align {
+            Initial => specified.text_align.initial(),
+            Inherit => parent.text_align,
+            Specified(text_align) => text_align
+        },
+        text_decorations: compute_text_decorations(&specified.text_decoration, text_color,
+                                                   display_type, position, float_type,
+                                                   parent.text_decorations),
+        text_transform: match specified.text_transform {
+            Initial => specified.text_transform.initial(),
+            Inherit => parent.text_transform,
+            Specified(transform) => transform
+        },
+        direction: match specified.direction {
+            Initial => specified.direction.initial(),
+            Inherit => parent.direction,
+            Specified(direction) => direction
+        },
+        unicode_bidi: match specified.unicode_bidi {
+            Initial => specified.unicode_bidi.initial(),
+            Inherit => parent.unicode_bidi,
+            Specified(unicode_bidi) => unicode_bidi
+        },
+        margin_top: compute_box_edge(specified.margin_top, font_size, parent.margin_top),
+        margin_right: compute_box_edge(specified.margin_right, font_size, parent.margin_right),
+        margin_bottom: compute_box_edge(specified.margin_bottom, font_size, parent.margin_bottom),
+        margin_left: compute_box_edge(specified.margin_left, font_size, parent.margin_left),
+        padding_top: compute_box_edge(specified.padding_top, font_size, parent.padding_top),
+        padding_right: compute_box_edge(specified.padding_right, font_size, parent.padding_right),
+        padding_bottom: compute_box_edge(specified.padding_bottom, font_size,
+                                         parent.padding_bottom),
+        padding_left: compute_box_edge(specified.padding_left, font_size, parent.padding_left),
+        border_top_width: compute_border_width(specified.border_top_width, border_top_style,
+                                               font_size, parent.border_top_width),
+        border_right_width: compute_border_width(specified.border_right_width, border_right_style,
+                                                 font_size, parent.border_right_width),
+        border_bottom_width: compute_border_width(specified.border_bottom_width,
+                                                  border_bottom_style, font_size,
+                                                  parent.border_bottom_width),
+        border_left_width: compute_border_width(specified.border_left_width, border_left_style,
+                                                font_size, parent.border_left_width),
+        border_top_style: border_top_style,
+        border_right_style: border_right_style,
+        border_bottom_style: border_bottom_style,
+        border_left_style: border_left_style,
+        border_top_color: compute_border_color(specified.border_top_color, text_color,
+                                               parent.border_top_color),
+        border_right_color: compute_border_color(specified.border_right_color, text_color,
+                                                 parent.border_right_color),
+        border_bottom_color: compute_border_color(specified.border_bottom_color, text_color,
+                                                  parent.border_bottom_color),
+        border_left_color: compute_border_color(specified.border_left_color, text_color,
+                                                parent.border_left_color)
+    }
+}
+
+struct StyleApplicator {
+    node: Node,
+    reflow: fn~(),
+}
+
+/** Resolves the computed style of every node in the subtree rooted at `node`, in preorder
+    so that each node's parent has been resolved before it. */
+fn apply_style(layout_ctx: &LayoutContext, node: Node, reflow: fn~()) {
+    let applicator = StyleApplicator {
+        node: node,
+        reflow: reflow
+    };
+
+    applicator.apply_css_style(layout_ctx);
+}
 
 impl StyleApplicator {
     fn apply_css_style(layout_ctx: &LayoutContext) {
-        let reflow = copy self.reflow;
+        self.resolve_style(layout_ctx);
 
+        let reflow = copy self.reflow;
         do NodeTree.each_child(self.node) |child| {
-            inheritance_wrapper(layout_ctx, child, reflow); true
+            apply_style(layout_ctx, child, copy reflow); true
         }
     }
 
-    /** 
-     * Convert the cascaded, specified style for this node into a resolved style:
-     * one which additionally resolves the values of Initial, Inherit based on 
-     * defaults and node parent style. It also converts Node attributes into 
-     * equivalent inline style declarations (TODO: where is this defined??)
+    /**
+     * Convert the cascaded, specified style for this node into a computed style:
+     * one which additionally resolves the values of Initial, Inherit based on
+     * defaults and node parent style, and converts relative lengths to px.
+     * TODO: convert Node attributes into equivalent inline style declarations.
      */
     fn resolve_style(_layout_ctx: &LayoutContext) {
-        // TODO: implement
+        let parent_style = match self.node.read(|n| n.tree.parent) {
+            Some(parent) => parent.computed_style(),
+            None => initial_computed_style()
+        };
+
+        let computed = compute_style(&self.node.style(), &parent_style);
+        do self.node.aux |data| {
+            data.computed_style = Some(~copy computed);
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    /* TODO: rewrite once cascade and resolve written. */
-}
\ No newline at end of file
+    use css::styles::empty_style_for_node_kind;
+    use dom::element::{ElementData, HTMLDivElement};
+    use dom::node::{Element, NodeScope, Text};
+    use util::color::css_colors::{black, red};
+
+    #[test]
+    fn should_compute_font_sizes() {
+        assert compute_font_size(Initial, 20.0) == 16.0;
+        assert compute_font_size(Inherit, 20.0) == 20.0;
+        assert compute_font_size(Specified(AbsoluteSize(XXLarge)), 20.0) == 32.0;
+        assert compute_font_size(Specified(LengthSize(Em(2.0))), 20.0) == 40.0;
+        assert compute_font_size(Specified(LengthSize(Px(12.0))), 20.0) == 12.0;
+        assert compute_font_size(Specified(PercentSize(50.0)), 20.0) == 10.0;
+        assert compute_font_size(Specified(RelativeSize(Larger)), 20.0) == 24.0;
+    }
+
+    #[test]
+    fn should_compute_em_widths_from_own_font_size() {
+        let width = compute_box_sizing(Specified(BoxLength(Em(2.0))), 10.0, BoxAuto);
+        assert width == BoxLength(Px(20.0));
+        assert compute_box_sizing(Initial, 10.0, BoxPercent(50.0)) == BoxAuto;
+        assert compute_box_sizing(Inherit, 10.0, BoxPercent(50.0)) == BoxPercent(50.0);
+    }
+
+    #[test]
+    fn should_inherit_color_and_font_size_but_not_width() {
+        let scope = NodeScope();
+        let div = scope.new_node(Element(ElementData(~"div", ~HTMLDivElement)));
+        let parent_style = empty_style_for_node_kind(div.read(|n| copy *n.kind));
+        parent_style.text_color = Specified(TextColor(red()));
+        parent_style.font_size = Specified(LengthSize(Px(30.0)));
+        parent_style.width = Specified(BoxLength(Px(100.0)));
+        let parent = compute_style(&parent_style, &initial_computed_style());
+
+        let text = scope.new_node(Text(~"firecracker"));
+        let child = compute_style(&empty_style_for_node_kind(text.read(|n| copy *n.kind)),
+                                  &parent);
+
+        assert parent.text_color == red();
+        assert child.text_color == red();
+        assert child.font_size == 30.0;
+        assert child.width == BoxAuto;
+        assert initial_computed_style().text_color == black();
+    }
+
+    #[test]
+    fn should_compute_borders_and_edges() {
+        assert compute_border_width(Initial, BdrStyleSolid, 10.0, 1.0) == 3.0;
+        assert compute_border_width(Specified(Em(0.5)), BdrStyleDashed, 10.0, 1.0) == 5.0;
+        assert compute_border_width(Specified(Px(4.0)), BdrStyleNone, 10.0, 1.0) == 0.0;
+        assert compute_border_width(Inherit, BdrStyleSolid, 10.0, 1.0) == 1.0;
+        assert compute_border_color(Initial, red(), black()) == red();
+        assert compute_box_edge(Initial, 10.0, BoxAuto) == BoxLength(Px(0.0));
+        assert compute_box_edge(Specified(BoxLength(Em(2.0))), 10.0, BoxAuto) == BoxLength(Px(20.0));
+        assert compute_box_edge(Specified(BoxAuto), 10.0, BoxPercent(5.0)) == BoxAuto;
+    }
+
+    #[test]
+    fn should_compute_vertical_align() {
+        assert compute_vertical_align(Initial, 10.0, VerticalAlignSub) == VerticalAlignBaseline;
+        assert compute_vertical_align(Specified(VerticalAlignLength(Em(-0.5))), 10.0,
+                                      VerticalAlignBaseline) == VerticalAlignLength(Px(-5.0));
+        assert compute_vertical_align(Specified(VerticalAlignPercent(50.0)), 10.0,
+                                      VerticalAlignBaseline) == VerticalAlignPercent(50.0);
+    }
+
+    #[test]
+    fn should_compute_font_weights() {
+        assert compute_font_weight(Initial, 700) == 400;
+        assert compute_font_weight(Inherit, 700) == 700;
+        assert compute_font_weight(Specified(FontWeightBolder), 300) == 400;
+        assert compute_font_weight(Specified(FontWeightBolder), 400) == 700;
+        assert compute_font_weight(Specified(FontWeightBolder), 700) == 900;
+        assert compute_font_weight(Specified(FontWeightLighter), 700) == 400;
+        assert compute_font_weight(Specified(FontWeightLighter), 900) == 700;
+    }
+
+    #[test]
+    fn should_compute_line_heights() {
+        assert compute_line_height(Initial, 10.0, LineHeightNumber(2.0)) == LineHeightNormal;
+        assert compute_line_height(Inherit, 10.0, LineHeightNumber(2.0)) == LineHeightNumber(2.0);
+        assert compute_line_height(Specified(LineHeightLength(Em(1.5))), 10.0, LineHeightNormal)
+            == LineHeightLength(Px(15.0));
+        assert compute_line_height(Specified(LineHeightPercent(120.0)), 10.0, LineHeightNormal)
+            == LineHeightLength(Px(12.0));
+    }
+
+    #[test]
+    fn should_propagate_text_decorations_to_in_flow_descendants() {
+        let parent = ~[(DecorationUnderline, black())];
+        let own = Specified(~[DecorationLineThrough]);
+
+        let decorations = compute_text_decorations(&own, red(), DisplayInline, PosStatic,
+                                                   FloatNone, parent);
+        assert decorations == ~[(DecorationUnderline, black()), (DecorationLineThrough, red())];
+        let decorations = compute_text_decorations(&Initial, red(), DisplayInlineBlock,
+                                                   PosStatic, FloatNone, parent);
+        assert decorations.is_empty();
+        let decorations = compute_text_decorations(&own, red(), DisplayBlock, PosStatic,
+                                                   FloatLeft, parent);
+        assert decorations == ~[(DecorationLineThrough, red())];
+    }
+
+    #[test]
+    fn should_blockify_floats() {
+        assert compute_display(Initial, PosStatic, FloatLeft, DisplayBlock) == DisplayBlock;
+        assert compute_display(Specified(DisplayInlineBlock), PosStatic, FloatRight, DisplayBlock)
+            == DisplayBlock;
+        assert compute_display(Specified(DisplayInlineTable), PosStatic, FloatLeft, DisplayBlock)
+            == DisplayTable;
+        assert compute_display(Specified(DisplayNone), PosStatic, FloatLeft, DisplayBlock)
+            == DisplayNone;
+        assert compute_display(Initial, PosStatic, FloatNone, DisplayBlock) == DisplayInline;
+        assert compute_display(Initial, PosAbsolute, FloatNone, DisplayBlock) == DisplayBlock;
+        assert compute_display(Initial, PosRelative, FloatNone, DisplayBlock) == DisplayInline;
+    }
+}
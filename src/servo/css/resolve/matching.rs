@@ -1,10 +1,17 @@
 #[doc="Performs CSS selector matching."]
 
+use au = gfx::geometry;
+use au::au;
 use dom::node::{LayoutData, Node, Text};
 use dom::element::ElementData;
 
+use geom::size::Size2D;
+use media::each_applicable_rule;
+use std::arc::get;
+use std::sort::merge_sort;
 use values::*;
 use styles::{SpecifiedStyle};
+use vec::push;
 
 #[doc="Check if a CSS attribute matches the attribute of an HTML element."]
 fn attrs_match(attr: Attr, elmt: ElementData) -> bool {
@@ -47,15 +54,85 @@ fn attrs_match(attr: Attr, elmt: ElementData) -> bool {
           }
         }
       }
+      Class(name) => {
+        match elmt.get_attr(~"class") {
+          Some(value) => str::words(value).contains(name),
+          None => false
+        }
+      }
+      Id(name) => {
+        match elmt.get_attr(~"id") {
+          Some(value) => value == name,
+          None => false
+        }
+      }
+      // Pseudo-classes depend on the element's place in the tree, see matches_pseudo_class
+      Pseudo(_) => false
     }
 }
 
+pure fn is_element(node: Node) -> bool {
+    match node.read(|n| copy *n.kind) {
+      dom::node::Element(_) => true,
+      _ => false
+    }
+}
+
+#[doc="Checks whether `n` is a valid 1-based index into the sequence an+b for some n >= 0."]
+pure fn nth_child_matches(a: int, b: int, index: int) -> bool {
+    if a == 0 { return index == b; }
+    let offset = index - b;
+    offset / a >= 0 && offset % a == 0
+}
+
 trait PrivMatchingMethods {
+    fn element_index() -> int;
+    fn is_last_element_child() -> bool;
+    fn matches_pseudo_class(pseudo: PseudoClass) -> bool;
     fn matches_element(sel: ~Selector) -> bool;
     fn matches_selector(sel : ~Selector) -> bool;
 }
 
 impl Node : PrivMatchingMethods {
+    #[doc="The 1-based position of this node among its parent's element children."]
+    fn element_index() -> int {
+        let mut index = 1;
+        let mut cur_sib = self.read(|n| n.tree.prev_sibling);
+        loop {
+            match cur_sib {
+              Some(sib) => {
+                if is_element(sib) { index += 1; }
+                cur_sib = sib.read(|n| n.tree.prev_sibling);
+              }
+              None => { return index; }
+            }
+        }
+    }
+
+    fn is_last_element_child() -> bool {
+        let mut cur_sib = self.read(|n| n.tree.next_sibling);
+        loop {
+            match cur_sib {
+              Some(sib) => {
+                if is_element(sib) { return false; }
+                cur_sib = sib.read(|n| n.tree.next_sibling);
+              }
+              None => { return true; }
+            }
+        }
+    }
+
+    #[doc="Checks a pseudo-class against this node. Text nodes are skipped when counting
+           siblings, as required by Selectors Level 3."]
+    fn matches_pseudo_class(pseudo: PseudoClass) -> bool {
+        match pseudo {
+          FirstChild => self.element_index() == 1,
+          LastChild => self.is_last_element_child(),
+          NthChild(a, b) => nth_child_matches(a, b, self.element_index()),
+          Not(sel) => !self.matches_element(sel)
+        }
+    }
+
     #[doc="
         Checks if the given CSS selector, which must describe a single element with no relational
         information, describes the given HTML element.
@@ -72,7 +149,11 @@ impl Node : PrivMatchingMethods {
                 
                 let mut i = 0u;
                 while i < attrs.len() {
-                    if !attrs_match(attrs[i], elmt) { return false; }
+                    let matches = match copy attrs[i] {
+                      Pseudo(pseudo) => self.matches_pseudo_class(pseudo),
+                      attr => attrs_match(attr, elmt)
+                    };
+                    if !matches { return false; }
                     i += 1u;
                 }
 
@@ -171,35 +252,181 @@ impl Node : PrivStyleMethods {
               FontSize(size) => layout.style.font_size = size,
               Height(size) => layout.style.height = size,
               Color(col) => layout.style.text_color = col,
-              Width(size) => layout.style.width = size
+              Width(size) => layout.style.width = size,
+              Float(float_type) => layout.style.float_type = float_type,
+              Clear(clear) => layout.style.clear = clear,
+              Position(position) => layout.style.position = position,
+              Top(offset) => layout.style.top = offset,
+              Right(offset) => layout.style.right = offset,
+              Bottom(offset) => layout.style.bottom = offset,
+              Left(offset) => layout.style.left = offset,
+              ZIndex(z_index) => layout.style.z_index = z_index,
+              BorderCollapse(collapse) => layout.style.border_collapse = collapse,
+              BorderSpacingHorizontal(spacing) => layout.style.border_spacing_horizontal = spacing,
+              BorderSpacingVertical(spacing) => layout.style.border_spacing_vertical = spacing,
+              TableLayout(table_layout) => layout.style.table_layout = table_layout,
+              CaptionSide(side) => layout.style.caption_side = side,
+              WhiteSpace(white_space) => layout.style.white_space = white_space,
+              VerticalAlign(align) => layout.style.vertical_align = align,
+              FontFamily(families) => layout.style.font_family = families,
+              FontWeight(weight) => layout.style.font_weight = weight,
+              FontStyle(font_style) => layout.style.font_style = font_style,
+              LineHeight(line_height) => layout.style.line_height = line_height,
+              TextAlign(text_align) => layout.style.text_align = text_align,
+              TextDecoration(lines) => layout.style.text_decoration = lines,
+              TextTransform(transform) => layout.style.text_transform = transform,
+              Direction(direction) => layout.style.direction = direction,
+              UnicodeBidi(unicode_bidi) => layout.style.unicode_bidi = unicode_bidi,
+              MarginTop(size) => layout.style.margin_top = size,
+              MarginRight(size) => layout.style.margin_right = size,
+              MarginBottom(size) => layout.style.margin_bottom = size,
+              MarginLeft(size) => layout.style.margin_left = size,
+              PaddingTop(size) => layout.style.padding_top = size,
+              PaddingRight(size) => layout.style.padding_right = size,
+              PaddingBottom(size) => layout.style.padding_bottom = size,
+              PaddingLeft(size) => layout.style.padding_left = size,
+              BorderTopWidth(width) => layout.style.border_top_width = width,
+              BorderRightWidth(width) => layout.style.border_right_width = width,
+              BorderBottomWidth(width) => layout.style.border_bottom_width = width,
+              BorderLeftWidth(width) => layout.style.border_left_width = width,
+              BorderTopStyle(style) => layout.style.border_top_style = style,
+              BorderRightStyle(style) => layout.style.border_right_style = style,
+              BorderBottomStyle(style) => layout.style.border_bottom_style = style,
+              BorderLeftStyle(style) => layout.style.border_left_style = style,
+              BorderTopColor(col) => layout.style.border_top_color = col,
+              BorderRightColor(col) => layout.style.border_right_color = col,
+              BorderBottomColor(col) => layout.style.border_bottom_color = col,
+              BorderLeftColor(col) => layout.style.border_left_color = col
             };
         })
     }
 }
 
+/**
+   A declaration that applies to a node, along with everything needed to
+   find its place in the cascade.
+*/
+struct MatchedDeclaration {
+    decl: StyleDeclaration,
+    // Origin and importance, see `cascade_level`
+    level: uint,
+    // Declarations from the element's style attribute beat those from selectors
+    style_attribute: bool,
+    specificity: Specificity,
+    // Position of the declaration across all stylesheets, in source order
+    order: uint
+}
+
+/**
+   The rank of a declaration's origin and importance, following CSS 2.1,
+   Section 6.4.1. Declarations with a higher level win regardless of
+   specificity:
+
+   1. user agent declarations
+   2. user normal declarations
+   3. author normal declarations
+   4. author important declarations
+   5. user important declarations
+*/
+pure fn cascade_level(origin: Origin, importance: Importance) -> uint {
+    match (origin, importance) {
+      (UserAgentOrigin, _) => 0u,
+      (UserOrigin, Normal) => 1u,
+      (AuthorOrigin, Normal) => 2u,
+      (AuthorOrigin, Important) => 3u,
+      (UserOrigin, Important) => 4u
+    }
+}
+
+/** Orders matched declarations from lowest to highest precedence. */
+pure fn cascade_le(a: &MatchedDeclaration, b: &MatchedDeclaration) -> bool {
+    if a.level != b.level { return a.level < b.level; }
+    if a.style_attribute != b.style_attribute { return b.style_attribute; }
+    if a.specificity != b.specificity { return a.specificity < b.specificity; }
+    a.order <= b.order
+}
+
 trait MatchingMethods {
-    fn match_css_style(styles : Stylesheet);
+    fn matching_declarations(sheets: &[OriginSheet], viewport: &Size2D<au>) -> ~[MatchedDeclaration];
+    fn match_css_style(sheets: &[OriginSheet], viewport: &Size2D<au>);
 }
 
 impl Node : MatchingMethods {
-    #[doc="Compare an html element to a list of css rules and update its
-           style according to the rules matching it."]
-    fn match_css_style(styles : Stylesheet) {
-        // Loop over each rule, see if our node matches what is described in the rule. If it
-        // matches, update its style. As we don't currently have priorities of style information,
-        // the latest rule takes precedence over the others. So we just overwrite style
-        // information as we go.
-
-        for styles.each |sty| {
-            let (selectors, decls) = copy **sty;
-            for selectors.each |sel| {
-                if self.matches_selector(*sel) {
+    #[doc="Collects every declaration from the given stylesheets whose rule matches
+           this node and applies to the viewport, sorted into cascade order."]
+    fn matching_declarations(sheets: &[OriginSheet], viewport: &Size2D<au>) -> ~[MatchedDeclaration] {
+        let mut matched = ~[];
+        let mut order = 0u;
+
+        for sheets.each |origin_sheet| {
+            let sheet = get(&origin_sheet.sheet);
+            for each_applicable_rule(*sheet, viewport) |rule| {
+                let (selectors, decls) = match copy *rule {
+                  StyleRule(rule) => rule,
+                  // Other rules don't style elements, so they match nothing
+                  _ => (~[], ~[])
+                };
+
+                // A rule listing several selectors applies with the specificity of the most
+                // specific selector that matches.
+                let mut specificity = None;
+                for selectors.each |sel| {
+                    if self.matches_selector(*sel) {
+                        let sel_specificity = sel.specificity();
+                        specificity = match specificity {
+                            Some(s) if s >= sel_specificity => Some(s),
+                            _ => Some(sel_specificity)
+                        };
+                    }
+                }
+
+                match specificity {
+                  Some(specificity) => {
                     for decls.each |decl| {
-                        self.update_style(*decl);
+                        let (style_decl, importance) = copy *decl;
+                        push(matched, MatchedDeclaration {
+                            decl: style_decl,
+                            level: cascade_level(origin_sheet.origin, importance),
+                            style_attribute: false,
+                            specificity: specificity,
+                            order: order
+                        });
+                        order += 1u;
                     }
+                  }
+                  None => { order += decls.len(); }
                 }
             }
         }
+
+        // The style attribute is part of the author's styles (CSS 2.1, Section 6.4.3)
+        let style_attribute = match self.read(|n| copy *n.kind) {
+          dom::node::Element(elmt) => copy elmt.style_attribute,
+          _ => ~[]
+        };
+        for style_attribute.each |decl| {
+            let (style_decl, importance) = copy *decl;
+            push(matched, MatchedDeclaration {
+                decl: style_decl,
+                level: cascade_level(AuthorOrigin, importance),
+                style_attribute: true,
+                specificity: Specificity(0u, 0u, 0u),
+                order: order
+            });
+            order += 1u;
+        }
+
+        return merge_sort(cascade_le, matched);
+    }
+
+    #[doc="Compare an html element to a list of css rules and update its
+           style according to the rules matching it."]
+    fn match_css_style(sheets: &[OriginSheet], viewport: &Size2D<au>) {
+        // Apply the declarations from lowest to highest precedence, so that
+        // the winning declaration for each property is written last.
+        for self.matching_declarations(sheets, viewport).each |matched| {
+            self.update_style(matched.decl);
+        }
         
         self.aux(|a| #debug["Changed the style to: %?", copy *a.style]);
     }
@@ -210,12 +437,14 @@ mod test {
     use dom::element::{Attr, HTMLDivElement, HTMLHeadElement, HTMLImageElement, UnknownElement};
     use dom::node::NodeScope;
     use dvec::DVec;
+    use std::arc::ARC;
 
     #[allow(non_implicitly_copyable_typarams)]
     fn new_node_from_attr(scope: NodeScope, -name: ~str, -val: ~str) -> Node {
         let elmt = ElementData(~"div", ~HTMLDivElement);
         let attr = ~Attr(name, val);
         elmt.attrs.push(attr);
+        elmt.update_style_attribute();
         return scope.new_node(dom::node::Element(elmt));
     }
 
@@ -339,4 +568,169 @@ mod test {
         assert ggchild.matches_selector(~copy sel4);
         assert gggchild.matches_selector(~sel4);
     }
+
+    fn matched_decls_at(node: Node, sheets: &[OriginSheet], width: int) -> ~[StyleDeclaration] {
+        let viewport = Size2D(au::from_px(width), au::from_px(600));
+        node.matching_declarations(sheets, &viewport).map(|m| copy m.decl)
+    }
+
+    fn matched_decls(node: Node, sheets: &[OriginSheet]) -> ~[StyleDeclaration] {
+        matched_decls_at(node, sheets, 800)
+    }
+
+    #[test]
+    fn test_specificity() {
+        let sel1 = Element(~"div", ~[]);
+        let sel2 = Element(~"*", ~[Includes(~"class", ~"blue")]);
+        let sel3 = Descendant(~Element(~"*", ~[Id(~"green")]),
+                              ~Element(~"div", ~[Exists(~"flag")]));
+
+        assert sel1.specificity() == Specificity(0u, 0u, 1u);
+        assert sel2.specificity() == Specificity(0u, 1u, 0u);
+        assert sel3.specificity() == Specificity(1u, 1u, 1u);
+        assert sel1.specificity() < sel2.specificity();
+        assert sel2.specificity() < sel3.specificity();
+
+        let sel4 = Element(~"li", ~[Pseudo(Not(~Element(~"*", ~[Class(~"done")])))]);
+        assert sel4.specificity() == Specificity(0u, 1u, 1u);
+    }
+
+    #[test]
+    fn test_match_class_and_id() {
+        let scope = NodeScope();
+        let node1 = new_node_from_attr(scope, ~"class", ~"mad  hatter");
+        let node2 = new_node_from_attr(scope, ~"id", ~"hatter");
+
+        assert node1.matches_selector(~Element(~"div", ~[Class(~"hatter")]));
+        assert !node1.matches_selector(~Element(~"div", ~[Class(~"hat")]));
+        assert !node1.matches_selector(~Element(~"div", ~[Id(~"hatter")]));
+        assert node2.matches_selector(~Element(~"*", ~[Id(~"hatter")]));
+        assert !node2.matches_selector(~Element(~"*", ~[Class(~"hatter")]));
+    }
+
+    #[test]
+    fn test_match_structural_pseudo_classes() {
+        let scope = NodeScope();
+
+        let root = new_node_from_attr(scope, ~"class", ~"list");
+        let child1 = new_node_from_attr(scope, ~"class", ~"done");
+        let text = scope.new_node(dom::node::Text(~"between"));
+        let child2 = new_node_from_attr(scope, ~"class", ~"todo");
+        let child3 = new_node_from_attr(scope, ~"class", ~"todo");
+
+        scope.add_child(root, child1);
+        scope.add_child(root, text);
+        scope.add_child(root, child2);
+        scope.add_child(root, child3);
+
+        let first = Element(~"*", ~[Pseudo(FirstChild)]);
+        assert child1.matches_selector(~copy first);
+        assert !child2.matches_selector(~copy first);
+
+        let last = Element(~"*", ~[Pseudo(LastChild)]);
+        assert !child2.matches_selector(~copy last);
+        assert child3.matches_selector(~copy last);
+
+        // The text node doesn't count, so child2 is the second child
+        let even = Element(~"*", ~[Pseudo(NthChild(2, 0))]);
+        assert !child1.matches_selector(~copy even);
+        assert child2.matches_selector(~copy even);
+        assert !child3.matches_selector(~copy even);
+
+        let third = Element(~"*", ~[Pseudo(NthChild(0, 3))]);
+        assert child3.matches_selector(~copy third);
+        assert !child2.matches_selector(~copy third);
+
+        let not_done = Element(~"div", ~[Pseudo(Not(~Element(~"*", ~[Class(~"done")])))]);
+        assert !child1.matches_selector(~copy not_done);
+        assert child2.matches_selector(~copy not_done);
+    }
+
+    #[test]
+    fn test_nth_child_matches() {
+        // :nth-child(-n+2) matches the first two children
+        assert nth_child_matches(-1, 2, 1);
+        assert nth_child_matches(-1, 2, 2);
+        assert !nth_child_matches(-1, 2, 3);
+        // :nth-child(3n+1)
+        assert nth_child_matches(3, 1, 1);
+        assert nth_child_matches(3, 1, 4);
+        assert !nth_child_matches(3, 1, 3);
+    }
+
+    #[test]
+    fn test_cascade_specificity_beats_order() {
+        let scope = NodeScope();
+        let node = new_node_from_attr(scope, ~"class", ~"blue");
+
+        let specific = ~StyleRule((~[~Element(~"div", ~[Includes(~"class", ~"blue")])],
+                         ~[(Width(Specified(BoxAuto)), Normal)]));
+        let general = ~StyleRule((~[~Element(~"*", ~[])],
+                        ~[(Height(Specified(BoxAuto)), Normal)]));
+        let sheets = ~[OriginSheet(AuthorOrigin, ARC(~[specific, general]))];
+
+        let decls = matched_decls(node, sheets);
+        assert decls.len() == 2u;
+        match decls[1] { Width(*) => (), _ => fail };
+    }
+
+    #[test]
+    fn test_cascade_important_and_origin() {
+        let scope = NodeScope();
+        let node = new_node_from_attr(scope, ~"class", ~"blue");
+
+        let ua_important = ~StyleRule((~[~Element(~"div", ~[])],
+                             ~[(Width(Specified(BoxAuto)), Important)]));
+        let author_important = ~StyleRule((~[~Element(~"*", ~[])],
+                                 ~[(Height(Specified(BoxAuto)), Important)]));
+        let author_normal = ~StyleRule((~[~Element(~"div", ~[Includes(~"class", ~"blue")])],
+                              ~[(Display(Specified(DisplayBlock)), Normal)]));
+        let sheets = ~[OriginSheet(UserAgentOrigin, ARC(~[ua_important])),
+                       OriginSheet(AuthorOrigin, ARC(~[author_important, author_normal]))];
+
+        let decls = matched_decls(node, sheets);
+        assert decls.len() == 3u;
+        match decls[0] { Width(*) => (), _ => fail };
+        match decls[1] { Display(*) => (), _ => fail };
+        match decls[2] { Height(*) => (), _ => fail };
+    }
+
+    #[test]
+    fn test_media_rules_apply_by_viewport() {
+        let scope = NodeScope();
+        let node = new_node_from_attr(scope, ~"class", ~"blue");
+
+        let narrow = ~[MediaQuery(false, ScreenMedia, ~[MaxWidth(Px(600.0))])];
+        let width_rule = ~StyleRule((~[~Element(~"div", ~[])],
+                                     ~[(Width(Specified(BoxAuto)), Normal)]));
+        let height_rule = ~StyleRule((~[~Element(~"div", ~[])],
+                                      ~[(Height(Specified(BoxAuto)), Normal)]));
+        let sheets = ~[OriginSheet(AuthorOrigin,
+                                   ARC(~[width_rule, ~MediaRule(narrow, ~[height_rule])]))];
+
+        assert matched_decls_at(node, sheets, 800).len() == 1u;
+        let decls = matched_decls_at(node, sheets, 500);
+        assert decls.len() == 2u;
+        match decls[1] { Height(*) => (), _ => fail };
+    }
+
+    #[test]
+    fn test_cascade_style_attribute() {
+        let scope = NodeScope();
+        let node = new_node_from_attr(scope, ~"style", ~"width: auto; height: auto !important");
+
+        let id_rule = ~StyleRule((~[~Element(~"div", ~[Id(~"main")]), ~Element(~"*", ~[])],
+                                  ~[(Width(Specified(BoxLength(Px(10.0)))), Normal)]));
+        let important_rule = ~StyleRule((~[~Element(~"*", ~[])],
+                                         ~[(Width(Specified(BoxLength(Px(20.0)))), Important),
+                                           (Height(Specified(BoxLength(Px(20.0)))), Important)]));
+        let sheets = ~[OriginSheet(AuthorOrigin, ARC(~[important_rule, id_rule]))];
+
+        // The style attribute beats any selector, but not an !important declaration
+        let decls = matched_decls(node, sheets);
+        assert decls.len() == 5u;
+        match decls[1] { Width(Specified(BoxAuto)) => (), _ => fail };
+        match decls[2] { Width(Specified(BoxLength(Px(20.0)))) => (), _ => fail };
+        match decls[4] { Height(Specified(BoxAuto)) => (), _ => fail };
+    }
 }
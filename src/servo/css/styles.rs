@@ -1,17 +1,13 @@
 /**
  * High-level interface to CSS selector matching.
  */
-use std::arc::{ARC, get, clone};
-
 use core::dvec::DVec;
 use css::values::*;
-use css::values::Stylesheet;
-use dom::element::{HTMLDivElement, HTMLHeadElement, HTMLImageElement, UnknownElement, HTMLScriptElement};
+use css::values::{OriginSheet, Stylesheet};
 use dom::node::{Comment, Doctype, Element, Text,
                 Node, NodeKind, NodeTree, LayoutData};
-use util::color::{Color, rgb};
-use util::color::css_colors::{white, black};
 use layout::context::LayoutContext;
+use util::color::Color;
 
 type SpecifiedStyle = {mut background_color : CSSValue<CSSBackgroundColor>,
                        mut background_image : CSSValue<CSSBackgroundImage>,
@@ -19,77 +15,198 @@ type SpecifiedStyle = {mut background_color : CSSValue<CSSBackgroundColor>,
                         mut font_size : CSSValue<CSSFontSize>,
                         mut height : CSSValue<BoxSizing>,
                         mut text_color : CSSValue<CSSColor>,
-                        mut width : CSSValue<BoxSizing>
+                        mut width : CSSValue<BoxSizing>,
+                        mut float_type : CSSValue<CSSFloat>,
+                        mut clear : CSSValue<CSSClear>,
+                        mut position : CSSValue<CSSPosition>,
+                        mut top : CSSValue<BoxSizing>,
+                        mut right : CSSValue<BoxSizing>,
+                        mut bottom : CSSValue<BoxSizing>,
+                        mut left : CSSValue<BoxSizing>,
+                        mut z_index : CSSValue<CSSZIndex>,
+                        mut border_collapse : CSSValue<CSSBorderCollapse>,
+                        mut border_spacing_horizontal : CSSValue<Length>,
+                        mut border_spacing_vertical : CSSValue<Length>,
+                        mut table_layout : CSSValue<CSSTableLayout>,
+                        mut caption_side : CSSValue<CSSCaptionSide>,
+                        mut white_space : CSSValue<CSSWhiteSpace>,
+                        mut vertical_align : CSSValue<CSSVerticalAlign>,
+                        mut font_family : CSSValue<~[CSSFontFamily]>,
+                        mut font_weight : CSSValue<CSSFontWeight>,
+                        mut font_style : CSSValue<CSSFontStyle>,
+                        mut line_height : CSSValue<CSSLineHeight>,
+                        mut text_align : CSSValue<CSSTextAlign>,
+                        mut text_decoration : CSSValue<~[CSSTextDecoration]>,
+                        mut text_transform : CSSValue<CSSTextTransform>,
+                        mut direction : CSSValue<CSSDirection>,
+                        mut unicode_bidi : CSSValue<CSSUnicodeBidi>,
+                        mut margin_top : CSSValue<BoxSizing>,
+                        mut margin_right : CSSValue<BoxSizing>,
+                        mut margin_bottom : CSSValue<BoxSizing>,
+                        mut margin_left : CSSValue<BoxSizing>,
+                        mut padding_top : CSSValue<BoxSizing>,
+                        mut padding_right : CSSValue<BoxSizing>,
+                        mut padding_bottom : CSSValue<BoxSizing>,
+                        mut padding_left : CSSValue<BoxSizing>,
+                        mut border_top_width : CSSValue<Length>,
+                        mut border_right_width : CSSValue<Length>,
+                        mut border_bottom_width : CSSValue<Length>,
+                        mut border_left_width : CSSValue<Length>,
+                        mut border_top_style : CSSValue<CSSBorderStyle>,
+                        mut border_right_style : CSSValue<CSSBorderStyle>,
+                        mut border_bottom_style : CSSValue<CSSBorderStyle>,
+                        mut border_left_style : CSSValue<CSSBorderStyle>,
+                        mut border_top_color : CSSValue<CSSColor>,
+                        mut border_right_color : CSSValue<CSSColor>,
+                        mut border_bottom_color : CSSValue<CSSColor>,
+                        mut border_left_color : CSSValue<CSSColor>
                        };
 
-trait DefaultStyleMethods {
-    fn default_color() -> Color;
-    fn default_display_type() -> CSSDisplay;
-    fn default_width() -> BoxSizing;
-    fn default_height() -> BoxSizing;
-}
-
-/// Default styles for various attributes in case they don't get initialized from CSS selectors.
-impl NodeKind : DefaultStyleMethods {
-    fn default_color() -> Color {
-        match self {
-          Text(*) => white(),
-          Element(*) => white(),
-            _ => fail ~"unstyleable node type encountered"
-        }
-    }
-
-    /* TODO: this belongs in the UA stylesheet */
-    fn default_display_type() -> CSSDisplay {
-        match self {
-          Text(*) => DisplayInline,
-          Element(element) => {
-            match *element.kind {
-              HTMLDivElement => DisplayBlock,
-              HTMLHeadElement => DisplayNone,
-              HTMLImageElement(*) => DisplayInline,
-              HTMLScriptElement => DisplayNone,
-              _ => DisplayInline,
-            }
-          },
-          Comment(*) | Doctype(*) => DisplayNone
-        }
-    }
-    
-    fn default_width() -> BoxSizing {
-        BoxAuto
-    }
-
-    fn default_height() -> BoxSizing {
-        BoxAuto
-    }
+/**
+ * The computed style of a node (CSS 2.1, Section 6.1.2): every value is resolved against
+ * the parent's computed style, so that 'initial', 'inherit' and relative lengths no longer
+ * appear. Layout and display list building read this rather than the specified style.
+ */
+struct ComputedStyle {
+    background_color: CSSBackgroundColor,
+    background_image: CSSBackgroundImage,
+    display_type: CSSDisplay,
+    // In px
+    font_size: float,
+    // Lengths are always in px
+    height: BoxSizing,
+    text_color: Color,
+    // Lengths are always in px
+    width: BoxSizing,
+    float_type: CSSFloat,
+    clear: CSSClear,
+    position: CSSPosition,
+    // Offsets are lengths in px, percentages or auto
+    top: BoxSizing,
+    right: BoxSizing,
+    bottom: BoxSizing,
+    left: BoxSizing,
+    z_index: CSSZIndex,
+    border_collapse: CSSBorderCollapse,
+    // In px
+    border_spacing_horizontal: float,
+    border_spacing_vertical: float,
+    table_layout: CSSTableLayout,
+    caption_side: CSSCaptionSide,
+    white_space: CSSWhiteSpace,
+    // Lengths are always in px
+    vertical_align: CSSVerticalAlign,
+    font_family: ~[CSSFontFamily],
+    // From 100 to 900
+    font_weight: uint,
+    font_style: CSSFontStyle,
+    // 'normal', a number, or a length in px
+    line_height: CSSLineHeight,
+    text_align: CSSTextAlign,
+    // The lines drawn through the text of the element: its own and those of its ancestors,
+    // which are not inherited but propagate to the text inside them (CSS 2.1, Section
+    // 16.3.1), in the colors of the elements they come from
+    text_decorations: ~[(CSSTextDecoration, Color)],
+    text_transform: CSSTextTransform,
+    direction: CSSDirection,
+    unicode_bidi: CSSUnicodeBidi,
+    // Margins and padding are lengths in px or percentages; only margins can be auto
+    margin_top: BoxSizing,
+    margin_right: BoxSizing,
+    margin_bottom: BoxSizing,
+    margin_left: BoxSizing,
+    padding_top: BoxSizing,
+    padding_right: BoxSizing,
+    padding_bottom: BoxSizing,
+    padding_left: BoxSizing,
+    // In px, and zero when the border's style is 'none' or 'hidden'
+    border_top_width: float,
+    border_right_width: float,
+    border_bottom_width: float,
+    border_left_width: float,
+    border_top_style: CSSBorderStyle,
+    border_right_style: CSSBorderStyle,
+    border_bottom_style: CSSBorderStyle,
+    border_left_style: CSSBorderStyle,
+    border_top_color: Color,
+    border_right_color: Color,
+    border_bottom_color: Color,
+    border_left_color: Color
 }
 
 /**
  * Create a specified style that can be used to initialize a node before selector matching.
  *
- * Everything is initialized to none except the display style. The default value of the display
- * style is computed so that it can be used to short-circuit selector matching to avoid computing
- * style for children of display:none objects.
+ * Inherited properties start out as 'inherit' and everything else as 'initial'; the user
+ * agent stylesheet supplies the defaults for each kind of element. Doctypes and comments are
+ * never rendered, so they are given 'display: none' up front; selectors never match them.
  */
 fn empty_style_for_node_kind(kind: NodeKind) -> SpecifiedStyle {
-    let display_type = kind.default_display_type();
+    let display_type = match kind {
+        Comment(*) | Doctype(*) => Specified(DisplayNone),
+        Text(*) | Element(*) => Initial
+    };
 
     {mut background_color : Initial,
      mut background_image: Initial,
-     mut display_type : Specified(display_type),
-     mut font_size : Initial,
+     mut display_type : display_type,
+     mut font_size : Inherit,
      mut height : Initial,
-     mut text_color : Initial,
-     mut width : Initial}
+     mut text_color : Inherit,
+     mut width : Initial,
+     mut float_type : Initial,
+     mut clear : Initial,
+     mut position : Initial,
+     mut top : Initial,
+     mut right : Initial,
+     mut bottom : Initial,
+     mut left : Initial,
+     mut z_index : Initial,
+     mut border_collapse : Inherit,
+     mut border_spacing_horizontal : Inherit,
+     mut border_spacing_vertical : Inherit,
+     mut table_layout : Initial,
+     mut caption_side : Inherit,
+     mut white_space : Inherit,
+     mut vertical_align : Initial,
+     mut font_family : Inherit,
+     mut font_weight : Inherit,
+     mut font_style : Inherit,
+     mut line_height : Inherit,
+     mut text_align : Inherit,
+     mut text_decoration : Initial,
+     mut text_transform : Inherit,
+     mut direction : Inherit,
+     mut unicode_bidi : Initial,
+     mut margin_top : Initial,
+     mut margin_right : Initial,
+     mut margin_bottom : Initial,
+     mut margin_left : Initial,
+     mut padding_top : Initial,
+     mut padding_right : Initial,
+     mut padding_bottom : Initial,
+     mut padding_left : Initial,
+     mut border_top_width : Initial,
+     mut border_right_width : Initial,
+     mut border_bottom_width : Initial,
+     mut border_left_width : Initial,
+     mut border_top_style : Initial,
+     mut border_right_style : Initial,
+     mut border_bottom_style : Initial,
+     mut border_left_style : Initial,
+     mut border_top_color : Initial,
+     mut border_right_color : Initial,
+     mut border_bottom_color : Initial,
+     mut border_left_color : Initial}
 }
 
 trait StyleMethods {
     fn initialize_layout_data() -> Option<@LayoutData>;
 
     fn style() -> SpecifiedStyle;
+    fn computed_style() -> ComputedStyle;
     fn initialize_style_for_subtree(ctx: &LayoutContext, refs: &DVec<@LayoutData>);
-    fn recompute_style_for_subtree(ctx: &LayoutContext, styles : ARC<Stylesheet>);
+    fn recompute_style_for_subtree(ctx: &LayoutContext, sheets : &[OriginSheet]);
 }
 
 impl Node : StyleMethods {
@@ -102,6 +219,7 @@ impl Node : StyleMethods {
                 let node_kind = self.read(|n| copy *n.kind);
                 let data = @LayoutData({
                     mut style : ~empty_style_for_node_kind(node_kind),
+                    mut computed_style : None,
                     mut flow  : None
                 });
                 self.set_aux(data); Some(data)
@@ -122,6 +240,20 @@ impl Node : StyleMethods {
         return copy *self.aux(|x| copy x).style;
     }
 
+    /**
+     * Returns the computed style for the given node. If style resolution
+     * has not yet been performed, fails.
+     */
+    fn computed_style() -> ComputedStyle {
+        if !self.has_aux() {
+            fail ~"computed_style() called on a node without a style!";
+        }
+        match copy self.aux(|x| copy x).computed_style {
+            Some(style) => copy *style,
+            None => fail ~"computed_style() called before style resolution!"
+        }
+    }
+
     /**
      * Initializes layout data and styles for a Node tree, if any nodes do not have
      * this data already. Append created layout data to the task's GC roots.
@@ -136,23 +268,19 @@ impl Node : StyleMethods {
     }
 
     /**
-     * Performs CSS selector matching on a subtree.
+     * Performs CSS selector matching and cascading on a subtree, using
+     * the given stylesheets in cascade order.
 
      * This is, importantly, the function that updates the layout data for
      * the node (the reader-auxiliary box in the RCU model) with the
      * computed style.
      */
-    fn recompute_style_for_subtree(ctx: &LayoutContext, styles : ARC<Stylesheet>) {
-        let mut i = 0u;
-        
-        // Compute the styles of each of our children in parallel
+    fn recompute_style_for_subtree(ctx: &LayoutContext, sheets : &[OriginSheet]) {
+        // TODO: compute the styles of each of our children in parallel
         for NodeTree.each_child(self) |kid| {
-            i = i + 1u;
-            let new_styles = clone(&styles);
-            
-            kid.recompute_style_for_subtree(ctx, new_styles); 
+            kid.recompute_style_for_subtree(ctx, sheets); 
         }
 
-        self.match_css_style(*get(&styles));
+        self.match_css_style(sheets, &ctx.screen_size.size);
     }
 }
@@ -0,0 +1,68 @@
+/*!
+The built-in HTML user agent stylesheet. It gives elements their default
+presentation (CSS 2.1, Appendix D), is parsed the same way as author
+stylesheets, and cascades below them.
+*/
+
+use css::lexer::spawn_css_lexer_from_string;
+use css::parser::build_stylesheet;
+use css::values::Stylesheet;
+
+fn user_agent_stylesheet() -> Stylesheet {
+    let source = str::from_slice(#include_str("user-agent.css"));
+    build_stylesheet(spawn_css_lexer_from_string(move source))
+}
+
+#[cfg(test)]
+mod test {
+    use css::values::{StyleRule, Element, StyleDeclaration, Display, Specified};
+    use css::values::{DisplayBlock, DisplayNone};
+
+    // The declarations of the rules whose selectors include a bare `element`
+    fn declarations_for(sheet: &Stylesheet, element: &str) -> ~[StyleDeclaration] {
+        let mut result = ~[];
+        for sheet.each |rule| {
+            match copy **rule {
+              StyleRule((selectors, declarations)) => {
+                let matches = do selectors.any |selector| {
+                    match **selector {
+                      Element(ref name, ref attrs) => *name == element.to_str() && attrs.is_empty(),
+                      _ => false
+                    }
+                };
+                if matches {
+                    for declarations.each |declaration| {
+                        let (declaration, _) = copy *declaration;
+                        result.push(move declaration);
+                    }
+                }
+              }
+              _ => ()
+            }
+        }
+        move result
+    }
+
+    #[test]
+    fn should_keep_every_rule_of_the_user_agent_stylesheet() {
+        // A rule the parser gave up on would be missing from the sheet
+        let source = str::from_slice(#include_str("user-agent.css"));
+        let blocks = str::to_chars(source).count(&'{');
+        let sheet = user_agent_stylesheet();
+        assert sheet.len() == blocks;
+        for sheet.each |rule| {
+            match **rule {
+              StyleRule((ref selectors, _)) => assert selectors.is_not_empty(),
+              _ => fail ~"expected only style rules"
+            }
+        }
+    }
+
+    #[test]
+    fn should_give_elements_their_default_display() {
+        let sheet = user_agent_stylesheet();
+        assert declarations_for(&sheet, "div").contains(&Display(Specified(DisplayBlock)));
+        assert declarations_for(&sheet, "head").contains(&Display(Specified(DisplayNone)));
+        assert declarations_for(&sheet, "span").is_empty();
+    }
+}
@@ -1,5 +1,6 @@
 use SharedColor = util::color::Color;
 use cmp::Eq;
+use std::arc::ARC;
 use std::net::url::Url;
 
 #[doc = "
@@ -36,6 +37,21 @@ pure fn extract<T : Copy, U>(res: ParseResult<T>, f: fn(CSSValue<T>) -> U) -> Op
     }
 }
 
+impl<T: Eq Copy> ParseResult<T> : Eq {
+    pure fn eq(other: &ParseResult<T>) -> bool {
+        match (self, *other) {
+            (Value(a), Value(b)) => a == b,
+            (CSSInitial, CSSInitial) => true,
+            (CSSInherit, CSSInherit) => true,
+            (Fail, Fail) => true,
+            _ => false
+        }
+    }
+    pure fn ne(other: &ParseResult<T>) -> bool {
+        return !self.eq(other);
+    }
+}
+
 impl<T: Eq Copy> CSSValue<T> : Eq {
     pure fn eq(other: &CSSValue<T>) -> bool {
         match (self, *other) {
@@ -66,8 +82,8 @@ impl Length {
     }
     pure fn abs() -> float {
         match self {
-            Em(x) => x,
-            _ => fail ~"attempted to access relative unit of an absolute length"
+            Px(x) => x,
+            _ => fail ~"attempted to access absolute unit of a relative length"
         }
     }
 }
@@ -117,6 +133,19 @@ enum CSSBackgroundImage {
     BgImageNone,
 }
 
+enum CSSBorderStyle {
+    BdrStyleNone,
+    BdrStyleHidden,
+    BdrStyleDotted,
+    BdrStyleDashed,
+    BdrStyleSolid,
+    BdrStyleDouble,
+    BdrStyleGroove,
+    BdrStyleRidge,
+    BdrStyleInset,
+    BdrStyleOutset
+}
+
 enum CSSColor {
     TextColor(SharedColor)
 }
@@ -126,6 +155,13 @@ enum CSSDirection {
     DirectionRtl
 }
 
+// How an element takes part in the Unicode bidirectional algorithm (CSS 2.1, Section 9.10)
+enum CSSUnicodeBidi {
+    UnicodeBidiNormal,
+    UnicodeBidiEmbed,
+    UnicodeBidiOverride
+}
+
 enum CSSDisplay {
     DisplayInline,
     DisplayBlock,
@@ -150,6 +186,61 @@ enum CSSFloat {
     FloatNone
 }
 
+enum CSSClear {
+    ClearLeft,
+    ClearRight,
+    ClearBoth,
+    ClearNone
+}
+
+enum CSSPosition {
+    PosStatic,
+    PosRelative,
+    PosAbsolute,
+    PosFixed
+}
+
+enum CSSZIndex {
+    ZIndexAuto,
+    ZIndexInteger(int)
+}
+
+enum CSSBorderCollapse {
+    BorderCollapseSeparate,
+    BorderCollapseCollapse
+}
+
+enum CSSTableLayout {
+    TableLayoutAuto,
+    TableLayoutFixed
+}
+
+enum CSSCaptionSide {
+    CaptionSideTop,
+    CaptionSideBottom
+}
+
+enum CSSWhiteSpace {
+    WhiteSpaceNormal,
+    WhiteSpacePre,
+    WhiteSpaceNowrap,
+    WhiteSpacePreWrap,
+    WhiteSpacePreLine
+}
+
+enum CSSVerticalAlign {
+    VerticalAlignBaseline,
+    VerticalAlignSub,
+    VerticalAlignSuper,
+    VerticalAlignTop,
+    VerticalAlignTextTop,
+    VerticalAlignMiddle,
+    VerticalAlignBottom,
+    VerticalAlignTextBottom,
+    VerticalAlignLength(Length),
+    VerticalAlignPercent(float)
+}
+
 enum CSSFontSize {
     AbsoluteSize(AbsoluteSize),
     RelativeSize(RelativeSize),
@@ -157,6 +248,63 @@ enum CSSFontSize {
     PercentSize(float)
 }
 
+// A family name, or a generic family that stands for a font of the user agent's choosing
+enum CSSFontFamily {
+    FamilyName(~str),
+    FamilySerif,
+    FamilySansSerif,
+    FamilyCursive,
+    FamilyFantasy,
+    FamilyMonospace
+}
+
+// 'normal' and 'bold' are the weights 400 and 700
+enum CSSFontWeight {
+    FontWeightAbsolute(uint),
+    FontWeightBolder,
+    FontWeightLighter
+}
+
+enum CSSFontStyle {
+    FontStyleNormal,
+    FontStyleItalic,
+    FontStyleOblique
+}
+
+// A number is a multiple of the element's font size, and is inherited as such
+enum CSSLineHeight {
+    LineHeightNormal,
+    LineHeightNumber(float),
+    LineHeightLength(Length),
+    LineHeightPercent(float)
+}
+
+// 'start' and 'end' are the left and right sides of the line in 'ltr' text, and the
+// other way around in 'rtl' text
+enum CSSTextAlign {
+    TextAlignStart,
+    TextAlignEnd,
+    TextAlignLeft,
+    TextAlignRight,
+    TextAlignCenter,
+    TextAlignJustify
+}
+
+// A line drawn through text. 'text-decoration: none' is an empty list of them.
+enum CSSTextDecoration {
+    DecorationUnderline,
+    DecorationOverline,
+    DecorationLineThrough,
+    DecorationBlink
+}
+
+enum CSSTextTransform {
+    TextTransformNone,
+    TextTransformCapitalize,
+    TextTransformUppercase,
+    TextTransformLowercase
+}
+
 // Stylesheet parts
 
 enum StyleDeclaration {
@@ -165,16 +313,88 @@ enum StyleDeclaration {
     FontSize(CSSValue<CSSFontSize>),
     Height(CSSValue<BoxSizing>),
     Color(CSSValue<CSSColor>),
-    Width(CSSValue<BoxSizing>)
-}
-
+    Width(CSSValue<BoxSizing>),
+    Float(CSSValue<CSSFloat>),
+    Clear(CSSValue<CSSClear>),
+
+    // Positioning (CSS 2.1, Chapter 9)
+    Position(CSSValue<CSSPosition>),
+    Top(CSSValue<BoxSizing>),
+    Right(CSSValue<BoxSizing>),
+    Bottom(CSSValue<BoxSizing>),
+    Left(CSSValue<BoxSizing>),
+    ZIndex(CSSValue<CSSZIndex>),
+
+    // Tables (CSS 2.1, Chapter 17). 'border-spacing' is split into its two lengths.
+    BorderCollapse(CSSValue<CSSBorderCollapse>),
+    BorderSpacingHorizontal(CSSValue<Length>),
+    BorderSpacingVertical(CSSValue<Length>),
+    TableLayout(CSSValue<CSSTableLayout>),
+    CaptionSide(CSSValue<CSSCaptionSide>),
+
+    // Inline formatting (CSS 2.1, Sections 10.8 and 16.6)
+    WhiteSpace(CSSValue<CSSWhiteSpace>),
+    VerticalAlign(CSSValue<CSSVerticalAlign>),
+
+    // Fonts and text (CSS 2.1, Chapters 15 and 16)
+    FontFamily(CSSValue<~[CSSFontFamily]>),
+    FontWeight(CSSValue<CSSFontWeight>),
+    FontStyle(CSSValue<CSSFontStyle>),
+    LineHeight(CSSValue<CSSLineHeight>),
+    TextAlign(CSSValue<CSSTextAlign>),
+    TextDecoration(CSSValue<~[CSSTextDecoration]>),
+    TextTransform(CSSValue<CSSTextTransform>),
+
+    // Bidirectional text (CSS 2.1, Section 9.10)
+    Direction(CSSValue<CSSDirection>),
+    UnicodeBidi(CSSValue<CSSUnicodeBidi>),
+
+    // The box model (CSS 2.1, Chapter 8). Shorthands are expanded by the parser.
+    MarginTop(CSSValue<BoxSizing>),
+    MarginRight(CSSValue<BoxSizing>),
+    MarginBottom(CSSValue<BoxSizing>),
+    MarginLeft(CSSValue<BoxSizing>),
+    PaddingTop(CSSValue<BoxSizing>),
+    PaddingRight(CSSValue<BoxSizing>),
+    PaddingBottom(CSSValue<BoxSizing>),
+    PaddingLeft(CSSValue<BoxSizing>),
+    BorderTopWidth(CSSValue<Length>),
+    BorderRightWidth(CSSValue<Length>),
+    BorderBottomWidth(CSSValue<Length>),
+    BorderLeftWidth(CSSValue<Length>),
+    BorderTopStyle(CSSValue<CSSBorderStyle>),
+    BorderRightStyle(CSSValue<CSSBorderStyle>),
+    BorderBottomStyle(CSSValue<CSSBorderStyle>),
+    BorderLeftStyle(CSSValue<CSSBorderStyle>),
+    // 'Initial' is the element's own color
+    BorderTopColor(CSSValue<CSSColor>),
+    BorderRightColor(CSSValue<CSSColor>),
+    BorderBottomColor(CSSValue<CSSColor>),
+    BorderLeftColor(CSSValue<CSSColor>)
+}
+
+/* A condition that an element must satisfy in addition to its tag name:
+   an attribute test, a class or id, or a pseudo-class */
 enum Attr{
     Exists(~str),
     Exact(~str, ~str),
     Includes(~str, ~str),
-    StartsWith(~str, ~str)
+    StartsWith(~str, ~str),
+    Class(~str),
+    Id(~str),
+    Pseudo(PseudoClass)
+}
+
+// Structural pseudo-classes (Selectors Level 3, Section 6.6.5) and negation
+enum PseudoClass {
+    FirstChild,
+    LastChild,
+    // :nth-child(an+b), stored as (a, b)
+    NthChild(int, int),
+    // The argument is a single Element selector
+    Not(~Selector)
 }
-    
+
 enum Selector{
     Element(~str, ~[Attr]),
     Child(~Selector, ~Selector),
@@ -182,10 +402,182 @@ enum Selector{
     Sibling(~Selector, ~Selector)
 }
 
-type Rule = (~[~Selector], ~[StyleDeclaration]);
+// Whether a declaration was marked '!important' (CSS 2.1, Section 6.4.2)
+enum Importance {
+    Normal,
+    Important
+}
+
+type Declaration = (StyleDeclaration, Importance);
+
+type Rule = (~[~Selector], ~[Declaration]);
+
+// The media types of Media Queries, Section 2. We only ever render to a screen.
+enum MediaType {
+    AllMedia,
+    ScreenMedia,
+    PrintMedia,
+    UnknownMedia
+}
+
+// A media feature test such as '(min-width: 600px)'. An exact 'width' sets both bounds.
+enum MediaExpression {
+    MinWidth(Length),
+    MaxWidth(Length),
+    MinHeight(Length),
+    MaxHeight(Length)
+}
+
+struct MediaQuery {
+    // Whether the query started with 'not', which inverts the whole query
+    negated: bool,
+    media_type: MediaType,
+    expressions: ~[MediaExpression]
+}
+
+fn MediaQuery(negated: bool, media_type: MediaType, -expressions: ~[MediaExpression]) -> MediaQuery {
+    MediaQuery {
+        negated: negated,
+        media_type: media_type,
+        expressions: move expressions
+    }
+}
+
+// A comma-separated list of queries, which applies if any of them do. An empty list
+// applies everywhere.
+type MediaQueryList = ~[MediaQuery];
+
+// A downloadable font (CSS Fonts Level 3, Section 4)
+struct FontFace {
+    family: ~str,
+    // The weight and slant of the font, which decide the text it is used for
+    weight: uint,
+    italic: bool,
+    // The urls from 'src', in order of preference
+    sources: ~[~str],
+    // The data of the first source that loaded, filled in when the stylesheet is loaded
+    data: Option<~[u8]>
+}
+
+enum CSSRule {
+    StyleRule(Rule),
+    MediaRule(MediaQueryList, ~[~CSSRule]),
+    // An import that hasn't been loaded yet. Loading replaces it with a MediaRule
+    // holding the imported rules.
+    ImportRule(~str, MediaQueryList),
+    FontFaceRule(FontFace)
+}
+
+type Stylesheet = ~[~CSSRule];
+
+// Where a stylesheet came from, which decides its place in the cascade (CSS 2.1, Section 6.4)
+enum Origin {
+    UserAgentOrigin,
+    UserOrigin,
+    AuthorOrigin
+}
+
+struct OriginSheet {
+    origin: Origin,
+    sheet: ARC<Stylesheet>
+}
+
+fn OriginSheet(origin: Origin, -sheet: ARC<Stylesheet>) -> OriginSheet {
+    OriginSheet {
+        origin: origin,
+        sheet: move sheet
+    }
+}
+
+/**
+   The specificity of a selector, as defined in CSS 2.1, Section 6.4.3: the number of id
+   attributes, of other attributes and pseudo-classes, and of element names in the selector.
+   Inline style attributes are handled separately by the cascade.
+*/
+struct Specificity {
+    ids: uint,
+    classes: uint,
+    elements: uint
+}
+
+fn Specificity(ids: uint, classes: uint, elements: uint) -> Specificity {
+    Specificity {
+        ids: ids,
+        classes: classes,
+        elements: elements
+    }
+}
+
+impl Specificity {
+    pure fn add(other: &Specificity) -> Specificity {
+        Specificity(self.ids + other.ids,
+                    self.classes + other.classes,
+                    self.elements + other.elements)
+    }
+}
+
+impl Selector {
+    pure fn specificity() -> Specificity {
+        match copy self {
+          Element(tag, attrs) => {
+            let elements = if tag == ~"*" { 0u } else { 1u };
+            let mut specificity = Specificity(0u, 0u, elements);
+            for attrs.each |attr| {
+                let attr_specificity = match copy *attr {
+                  Id(_) => Specificity(1u, 0u, 0u),
+                  // The negation pseudo-class counts as its argument does
+                  Pseudo(Not(sel)) => sel.specificity(),
+                  _ => Specificity(0u, 1u, 0u)
+                };
+                specificity = specificity.add(&attr_specificity);
+            }
+            specificity
+          }
+          Child(sel1, sel2) | Descendant(sel1, sel2) | Sibling(sel1, sel2) => {
+            sel1.specificity().add(&sel2.specificity())
+          }
+        }
+    }
+}
+
+
+impl Importance: cmp::Eq {
+    pure fn eq(other: &Importance) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &Importance) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl Origin: cmp::Eq {
+    pure fn eq(other: &Origin) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &Origin) -> bool {
+        return !self.eq(other);
+    }
+}
 
-type Stylesheet = ~[~Rule];
+impl Specificity: cmp::Eq {
+    pure fn eq(other: &Specificity) -> bool {
+        self.ids == other.ids && self.classes == other.classes && self.elements == other.elements
+    }
+    pure fn ne(other: &Specificity) -> bool {
+        return !self.eq(other);
+    }
+}
 
+impl Specificity: cmp::Ord {
+    pure fn lt(other: &Specificity) -> bool {
+        if self.ids != other.ids { return self.ids < other.ids; }
+        if self.classes != other.classes { return self.classes < other.classes; }
+        self.elements < other.elements
+    }
+    pure fn le(other: &Specificity) -> bool { !other.lt(&self) }
+    pure fn ge(other: &Specificity) -> bool { !self.lt(other) }
+    pure fn gt(other: &Specificity) -> bool { other.lt(&self) }
+}
 
 impl Length: cmp::Eq {
     pure fn eq(other: &Length) -> bool {
@@ -259,6 +651,15 @@ impl CSSColor: cmp::Eq {
     }
 }
 
+impl CSSBorderStyle: cmp::Eq {
+    pure fn eq(other: &CSSBorderStyle) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSBorderStyle) -> bool {
+        return !self.eq(other);
+    }
+}
+
 impl CSSDisplay: cmp::Eq {
     pure fn eq(other: &CSSDisplay) -> bool {
         self as uint == (*other) as uint
@@ -268,6 +669,102 @@ impl CSSDisplay: cmp::Eq {
     }
 }
 
+impl CSSFloat: cmp::Eq {
+    pure fn eq(other: &CSSFloat) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSFloat) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSClear: cmp::Eq {
+    pure fn eq(other: &CSSClear) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSClear) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSPosition: cmp::Eq {
+    pure fn eq(other: &CSSPosition) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSPosition) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSZIndex: cmp::Eq {
+    pure fn eq(other: &CSSZIndex) -> bool {
+        match (self, *other) {
+            (ZIndexAuto, ZIndexAuto) => true,
+            (ZIndexInteger(a), ZIndexInteger(b)) => a == b,
+            (_, _) => false
+        }
+    }
+    pure fn ne(other: &CSSZIndex) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSBorderCollapse: cmp::Eq {
+    pure fn eq(other: &CSSBorderCollapse) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSBorderCollapse) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSTableLayout: cmp::Eq {
+    pure fn eq(other: &CSSTableLayout) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSTableLayout) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSCaptionSide: cmp::Eq {
+    pure fn eq(other: &CSSCaptionSide) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSCaptionSide) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSWhiteSpace: cmp::Eq {
+    pure fn eq(other: &CSSWhiteSpace) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSWhiteSpace) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSVerticalAlign: cmp::Eq {
+    pure fn eq(other: &CSSVerticalAlign) -> bool {
+        match (self, *other) {
+            (VerticalAlignBaseline, VerticalAlignBaseline) => true,
+            (VerticalAlignSub, VerticalAlignSub) => true,
+            (VerticalAlignSuper, VerticalAlignSuper) => true,
+            (VerticalAlignTop, VerticalAlignTop) => true,
+            (VerticalAlignTextTop, VerticalAlignTextTop) => true,
+            (VerticalAlignMiddle, VerticalAlignMiddle) => true,
+            (VerticalAlignBottom, VerticalAlignBottom) => true,
+            (VerticalAlignTextBottom, VerticalAlignTextBottom) => true,
+            (VerticalAlignLength(a), VerticalAlignLength(b)) => a == b,
+            (VerticalAlignPercent(a), VerticalAlignPercent(b)) => a == b,
+            (_, _) => false
+        }
+    }
+    pure fn ne(other: &CSSVerticalAlign) -> bool {
+        return !self.eq(other);
+    }
+}
 
 impl CSSFontSize: cmp::Eq {
     pure fn eq(other: &CSSFontSize) -> bool {
@@ -283,6 +780,106 @@ impl CSSFontSize: cmp::Eq {
         return !self.eq(other);
     }
 }
+impl CSSFontFamily: cmp::Eq {
+    pure fn eq(other: &CSSFontFamily) -> bool {
+        match (copy self, copy *other) {
+            (FamilyName(a), FamilyName(b)) => a == b,
+            (FamilySerif, FamilySerif) => true,
+            (FamilySansSerif, FamilySansSerif) => true,
+            (FamilyCursive, FamilyCursive) => true,
+            (FamilyFantasy, FamilyFantasy) => true,
+            (FamilyMonospace, FamilyMonospace) => true,
+            (_, _) => false
+        }
+    }
+    pure fn ne(other: &CSSFontFamily) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSFontWeight: cmp::Eq {
+    pure fn eq(other: &CSSFontWeight) -> bool {
+        match (self, *other) {
+            (FontWeightAbsolute(a), FontWeightAbsolute(b)) => a == b,
+            (FontWeightBolder, FontWeightBolder) => true,
+            (FontWeightLighter, FontWeightLighter) => true,
+            (_, _) => false
+        }
+    }
+    pure fn ne(other: &CSSFontWeight) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSFontStyle: cmp::Eq {
+    pure fn eq(other: &CSSFontStyle) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSFontStyle) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSLineHeight: cmp::Eq {
+    pure fn eq(other: &CSSLineHeight) -> bool {
+        match (self, *other) {
+            (LineHeightNormal, LineHeightNormal) => true,
+            (LineHeightNumber(a), LineHeightNumber(b)) => a == b,
+            (LineHeightLength(a), LineHeightLength(b)) => a == b,
+            (LineHeightPercent(a), LineHeightPercent(b)) => a == b,
+            (_, _) => false
+        }
+    }
+    pure fn ne(other: &CSSLineHeight) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSTextAlign: cmp::Eq {
+    pure fn eq(other: &CSSTextAlign) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSTextAlign) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSTextDecoration: cmp::Eq {
+    pure fn eq(other: &CSSTextDecoration) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSTextDecoration) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSTextTransform: cmp::Eq {
+    pure fn eq(other: &CSSTextTransform) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSTextTransform) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSDirection: cmp::Eq {
+    pure fn eq(other: &CSSDirection) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSDirection) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl CSSUnicodeBidi: cmp::Eq {
+    pure fn eq(other: &CSSUnicodeBidi) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &CSSUnicodeBidi) -> bool {
+        return !self.eq(other);
+    }
+}
+
 /*
 impl StyleDeclaration: cmp::Eq {
     pure fn eq(&&other: StyleDeclaration) -> bool {
@@ -313,10 +910,16 @@ impl Attr: cmp::Eq {
           | (Includes(a, aa), Includes(b, bb))
           | (StartsWith(a, aa), StartsWith(b, bb)) => a == b && aa == bb,
 
+          (Class(a), Class(b)) | (Id(a), Id(b)) => a == b,
+          (Pseudo(a), Pseudo(b)) => a == b,
+
           (Exists(*), _)
           | (Exact(*), _)
           | (Includes(*), _)
-          | (StartsWith(*), _) => false
+          | (StartsWith(*), _)
+          | (Class(*), _)
+          | (Id(*), _)
+          | (Pseudo(*), _) => false
         }
     }
     pure fn ne(other: &Attr) -> bool {
@@ -324,6 +927,24 @@ impl Attr: cmp::Eq {
     }
 }
 
+impl PseudoClass: cmp::Eq {
+    pure fn eq(other: &PseudoClass) -> bool {
+        match (copy self, copy *other) {
+          (FirstChild, FirstChild) | (LastChild, LastChild) => true,
+          (NthChild(a, b), NthChild(aa, bb)) => a == aa && b == bb,
+          (Not(a), Not(b)) => a == b,
+
+          (FirstChild, _)
+          | (LastChild, _)
+          | (NthChild(*), _)
+          | (Not(*), _) => false
+        }
+    }
+    pure fn ne(other: &PseudoClass) -> bool {
+        return !self.eq(other);
+    }
+}
+
 impl Selector: cmp::Eq {
     pure fn eq(other: &Selector) -> bool {
         // FIXME: Lots of copying here
@@ -346,3 +967,41 @@ impl Selector: cmp::Eq {
         return !self.eq(other);
     }
 }
+
+impl MediaType: cmp::Eq {
+    pure fn eq(other: &MediaType) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &MediaType) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl MediaExpression: cmp::Eq {
+    pure fn eq(other: &MediaExpression) -> bool {
+        match (self, *other) {
+          (MinWidth(a), MinWidth(b))
+          | (MaxWidth(a), MaxWidth(b))
+          | (MinHeight(a), MinHeight(b))
+          | (MaxHeight(a), MaxHeight(b)) => a == b,
+
+          (MinWidth(*), _)
+          | (MaxWidth(*), _)
+          | (MinHeight(*), _)
+          | (MaxHeight(*), _) => false
+        }
+    }
+    pure fn ne(other: &MediaExpression) -> bool {
+        return !self.eq(other);
+    }
+}
+
+impl MediaQuery: cmp::Eq {
+    pure fn eq(other: &MediaQuery) -> bool {
+        self.negated == other.negated && self.media_type == other.media_type
+            && self.expressions == other.expressions
+    }
+    pure fn ne(other: &MediaQuery) -> bool {
+        return !self.eq(other);
+    }
+}
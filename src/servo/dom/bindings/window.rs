@@ -1,10 +1,11 @@
 use js::rust::{bare_compartment, methods};
 use js::{JS_ARGV, JSCLASS_HAS_RESERVED_SLOTS, JSPROP_ENUMERATE, JSPROP_SHARED, JSVAL_NULL,
+            JSPROP_NATIVE_ACCESSORS,
             JS_THIS_OBJECT, JS_SET_RVAL};
 use js::jsapi::{JSContext, jsval, JSObject, JSBool, jsid, JSClass, JSFreeOp};
 use js::jsapi::bindgen::{JS_ValueToString, JS_GetStringCharsZAndLength, JS_ReportError,
-                            JS_GetReservedSlot, JS_SetReservedSlot, JS_NewStringCopyN,
-    JS_DefineFunctions, JS_DefineProperty, JS_DefineProperties, JS_EncodeString, JS_free};
+                            JS_ValueToNumber, JS_GetReservedSlot, JS_SetReservedSlot,
+    JS_NewStringCopyN, JS_DefineFunctions, JS_DefineProperty, JS_DefineProperties, JS_EncodeString, JS_free};
 use js::glue::bindgen::*;
 use js::global::jsval_to_rust_str;
 use js::crust::{JS_PropertyStub, JS_StrictPropertyStub, JS_EnumerateStub, JS_ConvertStub, JS_ResolveStub};
@@ -13,6 +14,7 @@ use ptr::null;
 use libc::c_uint;
 use utils::{rust_box, squirrel_away, jsval_to_str};
 use bindings::node::create;
+use content::content_task::{Content, task_from_context};
 use dom::window::{Window, TimerMessage_Fire};
 use dom::node::Node;
 use dvec::DVec;
@@ -71,6 +73,50 @@ extern fn setTimeout(cx: *JSContext, argc: c_uint, vp: *jsval) -> JSBool unsafe
     return 1;
 }
 
+// A scroll coordinate argument in px, converted as by ToNumber and truncated.
+// Missing arguments, NaN and the infinities are 0 (CSSOM View, Section 5), and
+// numbers too large for the page are clamped. None if the conversion threw.
+fn coordinate_arg(cx: *JSContext, argc: c_uint, argv: *jsval, i: uint) -> Option<int> unsafe {
+    if i >= argc as uint {
+        return Some(0);
+    }
+    let mut n = 0f as libc::c_double;
+    if JS_ValueToNumber(cx, *ptr::offset(argv, i), ptr::addr_of(n)) == 0 {
+        return None;
+    }
+    let n = n as float;
+    if float::is_NaN(n) || float::is_infinite(n) {
+        Some(0)
+    } else {
+        Some(float::max(-1e9, float::min(n, 1e9)) as int)
+    }
+}
+
+extern fn scrollTo(cx: *JSContext, argc: c_uint, vp: *jsval) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+
+    // An exception thrown converting an argument propagates to the script
+    let x = match coordinate_arg(cx, argc, argv, 0u) { Some(x) => x, None => return 0 };
+    let y = match coordinate_arg(cx, argc, argv, 1u) { Some(y) => y, None => return 0 };
+    let content: &Content = task_from_context(cx);
+    content.scroll_to(x, y);
+
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn getScrollX(cx: *JSContext, _argc: c_uint, vp: *mut jsval) -> JSBool unsafe {
+    let content: &Content = task_from_context(cx);
+    *vp = RUST_INT_TO_JSVAL(content.scroll_position.x as libc::c_int);
+    return 1;
+}
+
+extern fn getScrollY(cx: *JSContext, _argc: c_uint, vp: *mut jsval) -> JSBool unsafe {
+    let content: &Content = task_from_context(cx);
+    *vp = RUST_INT_TO_JSVAL(content.scroll_position.y as libc::c_int);
+    return 1;
+}
+
 unsafe fn unwrap(obj: *JSObject) -> *rust_box<Window> {
     let val = JS_GetReservedSlot(obj, 0);
     cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
@@ -102,12 +148,33 @@ fn init(compartment: bare_compartment, win: @Window) {
                      call: {op: setTimeout, info: null()},
                      nargs: 2,
                      flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"scrollTo"),
+                     call: {op: scrollTo, info: null()},
+                     nargs: 2,
+                     flags: 0,
                      selfHostedName: null()}];
 
     vec::as_imm_buf(methods, |fns, _len| {
         JS_DefineFunctions(compartment.cx.ptr, proto.ptr, fns);
     });
 
+    let attrs = @~[
+        {name: compartment.add_name(~"scrollX"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getScrollX, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"scrollY"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getScrollY, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        JS_DefineProperties(compartment.cx.ptr, proto.ptr, specs);
+    });
+
     unsafe {
         let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(win));
         JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
@@ -1,5 +1,7 @@
 use au = gfx::geometry;
 use au::au;
+use css::parser::parse_style_attribute;
+use css::values::Declaration;
 use dvec::DVec;
 use geom::size::Size2D;
 use std::net::url::Url;
@@ -8,6 +10,8 @@ struct ElementData {
     tag_name: ~str,
     kind: ~ElementKind,
     attrs: DVec<~Attr>,
+    // The parsed declarations of the style attribute, if any
+    mut style_attribute: ~[Declaration],
 }
 
 impl ElementData {
@@ -22,7 +26,20 @@ impl ElementData {
     fn set_attr(name: ~str, value: ~str) {
         let idx = do self.attrs.position |attr| { attr.name == name };
         match idx {
-            Some(idx) => self.attrs.set_elt(idx, ~Attr(name, value)),
+            Some(idx) => {
+                if name == ~"style" {
+                    self.style_attribute = parse_style_attribute(copy value);
+                }
+                self.attrs.set_elt(idx, ~Attr(name, value))
+            }
+            None => {}
+        }
+    }
+
+    #[doc="Parses the style attribute, once the attributes have been added."]
+    fn update_style_attribute() {
+        match self.get_attr(~"style") {
+            Some(style) => self.style_attribute = parse_style_attribute(style),
             None => {}
         }
     }
@@ -33,6 +50,7 @@ fn ElementData(tag_name: ~str, kind: ~ElementKind) -> ElementData {
         tag_name : tag_name,
         kind : kind,
         attrs : DVec(),
+        style_attribute : ~[],
     }
 }
 
@@ -1,5 +1,17 @@
 enum Event {
     ResizeEvent(int, int),
-    ReflowEvent        
+    ReflowEvent,
+    // A turn of the mouse wheel, scrolling the window by this many px across and down
+    WheelEvent(int, int),
+    KeyScrollEvent(ScrollKey)
 }
 
+// The keys that scroll the window
+enum ScrollKey {
+    ScrollLineUp,
+    ScrollLineDown,
+    ScrollPageUp,
+    ScrollPageDown,
+    ScrollHome,
+    ScrollEnd
+}
@@ -1,5 +1,5 @@
 /* The core DOM types. Defines the basic DOM hierarchy as well as all the HTML elements. */
-use css::styles::SpecifiedStyle;
+use css::styles::{ComputedStyle, SpecifiedStyle};
 use css::values::Stylesheet;
 use dom::bindings;
 use dom::document::Document;
@@ -124,12 +124,13 @@ fn define_bindings(compartment: bare_compartment, doc: @Document,
 
 
 /** The RCU rd_aux data is a (weak) pointer to the layout data,
-   defined by this `LayoutData` enum. It contains the CSS style object
-   as well as the primary `RenderBox`.
+   defined by this `LayoutData` enum. It contains the specified and
+   computed CSS style objects as well as the primary `RenderBox`.
 
    Note that there may be multiple boxes per DOM node. */
 enum LayoutData = {
     mut style: ~SpecifiedStyle,
+    mut computed_style: Option<~ComputedStyle>,
     mut flow:  Option<@FlowContext>
 };
 
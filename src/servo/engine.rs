@@ -1,8 +1,9 @@
 export EngineTask, EngineTask_, EngineProto;
 
 use gfx::compositor::Compositor;
+use opts::Viewport;
 use gfx::render_task;
-use render_task::RenderTask;
+use render_task::{RenderTask, RenderTask_};
 use pipes::{spawn_service, select};
 use layout::layout_task;
 use layout_task::LayoutTask;
@@ -22,22 +23,28 @@ fn macros() {
 
 type EngineTask = EngineProto::client::Running;
 
-fn EngineTask<C: Compositor Send Copy>(+compositor: C) -> EngineTask {
+fn EngineTask<C: Compositor Send Copy>(+compositor: C, render_workers: uint) -> EngineTask {
     let resource_task = ResourceTask();
     let image_cache_task = ImageCacheTask(resource_task);
-    EngineTask_(compositor, resource_task, image_cache_task)
+    EngineTask_(compositor, resource_task, image_cache_task, opts::default_viewport(),
+                render_workers, None)
 }
 
 fn EngineTask_<C: Compositor Send Copy>(
     +compositor: C,
     resource_task: ResourceTask,
-    image_cache_task: ImageCacheTask
+    image_cache_task: ImageCacheTask,
+    viewport: Viewport,
+    render_workers: uint,
+    +display_list_output: Option<comm::Chan<~str>>
 ) -> EngineTask {
-    do spawn_service(EngineProto::init) |request, move compositor| {
+    do spawn_service(EngineProto::init) |request, move compositor, move display_list_output| {
 
-        let render_task = RenderTask(compositor);
-        let layout_task = LayoutTask(render_task, image_cache_task);
-        let content_task = ContentTask(layout_task, compositor, resource_task, image_cache_task);
+        let render_task = RenderTask_(compositor, viewport.device_pixel_ratio, render_workers,
+                                      display_list_output);
+        let layout_task = LayoutTask(render_task, image_cache_task, viewport.full_page);
+        let content_task = ContentTask(layout_task, compositor, resource_task, image_cache_task,
+                                       viewport.size);
 
         Engine {
             compositor: compositor,
@@ -1,5 +1,6 @@
 use dom::event::Event;
 use azure::azure_hl::DrawTarget;
+use geom::size::Size2D;
 
 #[doc = "
 The interface used to by the renderer to aquire draw targets for
@@ -8,6 +9,9 @@ each rendered frame and submit them to be drawn to the display
 trait Compositor {
     fn begin_drawing(+next_dt: pipes::Chan<DrawTarget>);
     fn draw(+next_dt: pipes::Chan<DrawTarget>, +draw_me: DrawTarget);
+    // Sends back a draw target of `size` pixels in place of `old`, or `old`
+    // itself if the compositor cannot change the size of what it shows
+    fn resize(+next_dt: pipes::Chan<DrawTarget>, +old: DrawTarget, size: Size2D<int>);
     fn add_event_listener(listener: comm::Chan<Event>);
 }
 
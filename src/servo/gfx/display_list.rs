@@ -1,18 +1,25 @@
-use azure::azure_hl::DrawTarget;
-use gfx::render_task::{draw_solid_color, draw_image, draw_glyphs};
-use gfx::geometry::*;
+use css::values::CSSBorderStyle;
+use au = gfx::geometry;
+use au::au;
+use geom::point::Point2D;
 use geom::rect::Rect;
+use geom::size::Size2D;
 use image::base::Image;
-use render_task::RenderContext;
 
-use std::arc::{ARC, clone};
+use std::arc::{ARC, clone, get};
 use dvec::DVec;
+use text::font::FontDescriptor;
 use text::glyph::Glyph;
+use util::color::Color;
 
 pub use layout::display_list_builder::DisplayListBuilder;
 
+/**
+A display item is plain data, so that display lists can be sent to the
+renderer, and saved and compared as text (see `display_list_text`). The
+renderer draws each kind of item in `render_task::draw_display_list`.
+*/
 struct DisplayItem {
-    draw: ~fn((&DisplayItem), (&RenderContext)),
     bounds : Rect<au>, // TODO: whose coordinate system should this use?
     data : DisplayItemData
 }
@@ -21,7 +28,17 @@ enum DisplayItemData {
     SolidColorData(u8, u8, u8),
     GlyphData(GlyphRun),
     ImageData(ARC<~image::base::Image>),
-    PaddingData(u8, u8, u8, u8) // This is a hack to make fonts work (?)
+    BorderData(BorderSides),
+    // The items up to the matching pop are clipped to the bounds of this item
+    PushClipData,
+    PopClipData,
+    // The items up to the matching pop are drawn by themselves, then blended
+    // with what is below them with this opacity, between 0 and 1
+    PushOpacityData(float),
+    PopOpacityData,
+    // The items up to the matching pop are drawn through this transform
+    PushTransformData(Transform),
+    PopTransformData
 }
 
 /**
@@ -30,34 +47,199 @@ structure used by layout in that this must be sendable, whereas the text
 shaping data structures may end up unsendable.
 */
 struct GlyphRun {
-    glyphs: ~[Glyph]
+    glyphs: ~[Glyph],
+    // The font the renderer draws the glyphs with
+    font: FontDescriptor,
+    color: Color,
+    // The distance from the top of the bounds of the item down to the baseline
+    baseline: au
+}
+
+/// One side of the border of a box, drawn inside the bounds of the border item
+struct BorderSide {
+    width: au,
+    color: Color,
+    style: CSSBorderStyle
+}
+
+struct BorderSides {
+    top: BorderSide,
+    right: BorderSide,
+    bottom: BorderSide,
+    left: BorderSide
+}
+
+/**
+A 2D affine transform. A point (x, y) is mapped to
+(xx * x + xy * y + x0, yx * x + yy * y + y0).
+*/
+struct Transform {
+    xx: float,
+    yx: float,
+    xy: float,
+    yy: float,
+    x0: au,
+    y0: au
+}
+
+fn Transform(xx: float, yx: float, xy: float, yy: float, x0: au, y0: au) -> Transform {
+    Transform { xx: xx, yx: yx, xy: xy, yy: yy, x0: x0, y0: y0 }
+}
+
+fn identity_transform() -> Transform {
+    Transform(1f, 0f, 0f, 1f, au(0), au(0))
+}
+
+fn translation(x: au, y: au) -> Transform {
+    Transform(1f, 0f, 0f, 1f, x, y)
+}
+
+impl Transform {
+    /// The transform that applies `inner` first and then this one
+    fn multiply(inner: &Transform) -> Transform {
+        let x0 = self.xx * au::to_frac_px(inner.x0) + self.xy * au::to_frac_px(inner.y0);
+        let y0 = self.yx * au::to_frac_px(inner.x0) + self.yy * au::to_frac_px(inner.y0);
+        Transform(self.xx * inner.xx + self.xy * inner.yx,
+                  self.yx * inner.xx + self.yy * inner.yx,
+                  self.xx * inner.xy + self.xy * inner.yy,
+                  self.yx * inner.xy + self.yy * inner.yy,
+                  self.x0.add(au::from_frac_px(x0)),
+                  self.y0.add(au::from_frac_px(y0)))
+    }
+
+    fn transform_point(point: &Point2D<au>) -> Point2D<au> {
+        let x = au::to_frac_px(point.x);
+        let y = au::to_frac_px(point.y);
+        Point2D(au::from_frac_px(self.xx * x + self.xy * y).add(self.x0),
+                au::from_frac_px(self.yx * x + self.yy * y).add(self.y0))
+    }
+
+    /// The smallest rectangle that contains the transformed rectangle
+    fn transform_rect(rect: &Rect<au>) -> Rect<au> {
+        let right = rect.origin.x.add(rect.size.width);
+        let bottom = rect.origin.y.add(rect.size.height);
+        let corners = [self.transform_point(&rect.origin),
+                       self.transform_point(&Point2D(right, rect.origin.y)),
+                       self.transform_point(&Point2D(rect.origin.x, bottom)),
+                       self.transform_point(&Point2D(right, bottom))];
+        let mut min = copy corners[0];
+        let mut max = copy corners[0];
+        for corners.each |corner| {
+            min = Point2D(au::min(min.x, corner.x), au::min(min.y, corner.y));
+            max = Point2D(au::max(max.x, corner.x), au::max(max.y, corner.y));
+        }
+        Rect(min, Size2D(max.x.sub(min.x), max.y.sub(min.y)))
+    }
+}
+
+impl DisplayItem {
+    // Images are shared with the clone rather than copied
+    fn clone() -> DisplayItem {
+        let data = match self.data {
+            ImageData(ref image) => ImageData(clone(image)),
+            SolidColorData(r, g, b) => SolidColorData(r, g, b),
+            GlyphData(ref run) => GlyphData(copy *run),
+            BorderData(sides) => BorderData(sides),
+            PushClipData => PushClipData,
+            PopClipData => PopClipData,
+            PushOpacityData(opacity) => PushOpacityData(opacity),
+            PopOpacityData => PopOpacityData,
+            PushTransformData(transform) => PushTransformData(transform),
+            PopTransformData => PopTransformData
+        };
+        DisplayItem { bounds: copy self.bounds, data: move data }
+    }
+}
+
+impl DisplayItem : cmp::Eq {
+    pure fn eq(other: &DisplayItem) -> bool {
+        self.bounds == other.bounds && self.data == other.data
+    }
+    pure fn ne(other: &DisplayItem) -> bool {
+        !self.eq(other)
+    }
+}
+
+// Images are equal when they are the same image, which is all the renderer can tell cheaply
+impl DisplayItemData : cmp::Eq {
+    pure fn eq(other: &DisplayItemData) -> bool {
+        match self {
+            SolidColorData(r, g, b) => match *other {
+                SolidColorData(r2, g2, b2) => r == r2 && g == g2 && b == b2,
+                _ => false
+            },
+            GlyphData(ref run) => match *other {
+                GlyphData(ref run2) => *run == *run2,
+                _ => false
+            },
+            ImageData(ref image) => match *other {
+                ImageData(ref image2) => ptr::ref_eq(get(image), get(image2)),
+                _ => false
+            },
+            BorderData(ref sides) => match *other {
+                BorderData(ref sides2) => *sides == *sides2,
+                _ => false
+            },
+            PushClipData => match *other { PushClipData => true, _ => false },
+            PopClipData => match *other { PopClipData => true, _ => false },
+            PushOpacityData(opacity) => match *other {
+                PushOpacityData(opacity2) => opacity == opacity2,
+                _ => false
+            },
+            PopOpacityData => match *other { PopOpacityData => true, _ => false },
+            PushTransformData(ref transform) => match *other {
+                PushTransformData(ref transform2) => *transform == *transform2,
+                _ => false
+            },
+            PopTransformData => match *other { PopTransformData => true, _ => false }
+        }
+    }
+    pure fn ne(other: &DisplayItemData) -> bool {
+        !self.eq(other)
+    }
+}
+
+impl GlyphRun : cmp::Eq {
+    pure fn eq(other: &GlyphRun) -> bool {
+        self.glyphs == other.glyphs && self.font == other.font && self.color == other.color
+            && self.baseline == other.baseline
+    }
+    pure fn ne(other: &GlyphRun) -> bool {
+        !self.eq(other)
+    }
 }
 
-fn draw_SolidColor(self: &DisplayItem, ctx: &RenderContext) {
-    match self.data {
-        SolidColorData(r,g,b) => draw_solid_color(ctx, &self.bounds, r, g, b),
-        _ => fail
-    }        
+impl BorderSide : cmp::Eq {
+    pure fn eq(other: &BorderSide) -> bool {
+        self.width == other.width && self.color == other.color && self.style == other.style
+    }
+    pure fn ne(other: &BorderSide) -> bool {
+        !self.eq(other)
+    }
 }
 
-fn draw_Glyphs(self: &DisplayItem, ctx: &RenderContext) {
-    match self.data {
-        GlyphData(run) => draw_glyphs(ctx, self.bounds, &run),
-        _ => fail
-    }        
+impl BorderSides : cmp::Eq {
+    pure fn eq(other: &BorderSides) -> bool {
+        self.top == other.top && self.right == other.right && self.bottom == other.bottom
+            && self.left == other.left
+    }
+    pure fn ne(other: &BorderSides) -> bool {
+        !self.eq(other)
+    }
 }
 
-fn draw_Image(self: &DisplayItem, ctx: &RenderContext) {
-    match self.data {
-        ImageData(img) => draw_image(ctx, self.bounds, img),
-        _ => fail
-    }        
+impl Transform : cmp::Eq {
+    pure fn eq(other: &Transform) -> bool {
+        self.xx == other.xx && self.yx == other.yx && self.xy == other.xy && self.yy == other.yy
+            && self.x0 == other.x0 && self.y0 == other.y0
+    }
+    pure fn ne(other: &Transform) -> bool {
+        !self.eq(other)
+    }
 }
 
 fn SolidColor(bounds: Rect<au>, r: u8, g: u8, b: u8) -> DisplayItem {
-    DisplayItem { 
-        // TODO: this seems wrong.
-        draw: |self, ctx| draw_SolidColor(self, ctx),
+    DisplayItem {
         bounds: bounds,
         data: SolidColorData(r, g, b)
     }
@@ -65,33 +247,79 @@ fn SolidColor(bounds: Rect<au>, r: u8, g: u8, b: u8) -> DisplayItem {
 
 fn Glyphs(bounds: Rect<au>, run: GlyphRun) -> DisplayItem {
     DisplayItem {
-        draw: |self, ctx| draw_Glyphs(self, ctx),
         bounds: bounds,
         data: GlyphData(run)
     }
 }
 
-// ARC should be cloned into ImageData, but Images are not sendable
+// The image is shared with the renderer rather than copied
 fn Image(bounds: Rect<au>, image: ARC<~image::base::Image>) -> DisplayItem {
     DisplayItem {
-        // TODO: this seems wrong.
-        draw: |self, ctx| draw_Image(self, ctx),
         bounds: bounds,
         data: ImageData(clone(&image))
     }
 }
 
-type DisplayList = DVec<~DisplayItem>;
+fn Border(bounds: Rect<au>, sides: BorderSides) -> DisplayItem {
+    DisplayItem {
+        bounds: bounds,
+        data: BorderData(sides)
+    }
+}
+
+fn PushClip(bounds: Rect<au>) -> DisplayItem {
+    DisplayItem {
+        bounds: bounds,
+        data: PushClipData
+    }
+}
 
-trait DisplayListMethods {
-    fn draw(ctx: &RenderContext);
+fn PopClip() -> DisplayItem {
+    DisplayItem {
+        bounds: au::zero_rect(),
+        data: PopClipData
+    }
 }
 
-impl DisplayList : DisplayListMethods {
-    fn draw(ctx: &RenderContext) {
-        for self.each |item| {
-            debug!("drawing %?", *item);
-            item.draw(*item, ctx);
-        }
+// The bounds are those of everything in the group, or are empty if unknown
+fn PushOpacity(bounds: Rect<au>, opacity: float) -> DisplayItem {
+    DisplayItem {
+        bounds: bounds,
+        data: PushOpacityData(opacity)
+    }
+}
+
+fn PopOpacity() -> DisplayItem {
+    DisplayItem {
+        bounds: au::zero_rect(),
+        data: PopOpacityData
+    }
+}
+
+fn PushTransform(bounds: Rect<au>, transform: Transform) -> DisplayItem {
+    DisplayItem {
+        bounds: bounds,
+        data: PushTransformData(transform)
+    }
+}
+
+fn PopTransform() -> DisplayItem {
+    DisplayItem {
+        bounds: au::zero_rect(),
+        data: PopTransformData
     }
 }
+
+type DisplayList = DVec<~DisplayItem>;
+
+#[test]
+fn should_apply_the_inner_transform_first() {
+    let scale = Transform(2f, 0f, 0f, 2f, au(0), au(0));
+    let combined = translation(au::from_px(10), au::from_px(20)).multiply(&scale);
+    let point = combined.transform_point(&Point2D(au::from_px(1), au::from_px(2)));
+    assert point == Point2D(au::from_px(12), au::from_px(24));
+
+    let combined = scale.multiply(&translation(au::from_px(10), au::from_px(20)));
+    let point = combined.transform_point(&Point2D(au::from_px(1), au::from_px(2)));
+    assert point == Point2D(au::from_px(22), au::from_px(44));
+}
@@ -0,0 +1,593 @@
+/*!
+A text form of display lists, so that they can be dumped, diffed in
+regression tests, and replayed through the renderer without running layout.
+
+Each item is written on its own line as its kind followed by its fields,
+separated by spaces. Every item starts with its bounds as `x y width height`.
+All lengths are in app units, so that a list survives a round trip exactly.
+Colors are written as `r g b a`, and font families as quoted strings. The
+items between a push and its pop are indented by two more spaces.
+
+    solid-color 0 0 48000 36000 255 255 255
+    push-clip 0 0 6000 6000
+      border 0 0 6000 6000 60 0 0 0 1 solid 60 0 0 0 1 solid ...
+    pop-clip
+*/
+
+use au = gfx::geometry;
+use au::au;
+use css::parser_util::parse_border_style;
+use css::values::{CSSBorderStyle, Value, BdrStyleNone, BdrStyleHidden, BdrStyleDotted};
+use css::values::{BdrStyleDashed, BdrStyleSolid, BdrStyleDouble, BdrStyleGroove, BdrStyleRidge};
+use css::values::{BdrStyleInset, BdrStyleOutset};
+use display_list::{DisplayList, DisplayItem, GlyphRun, BorderSide, BorderSides, Transform};
+use display_list::{SolidColorData, GlyphData, ImageData, BorderData, PushClipData, PopClipData};
+use display_list::{PushOpacityData, PopOpacityData, PushTransformData, PopTransformData};
+use dvec::DVec;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use image::base::Image;
+use io::WriterUtil;
+use servo_text::font::FontDescriptor;
+use servo_text::glyph::{Glyph, GlyphPos};
+use std::arc::ARC;
+use util::color::{Color, rgba};
+
+export to_text;
+export from_text;
+
+fn to_text(list: &DisplayList) -> ~str {
+    do io::with_str_writer |writer| {
+        let mut depth = 0u;
+        for list.each |item| {
+            match item.data {
+                PopClipData | PopOpacityData | PopTransformData => depth -= 1u,
+                _ => {}
+            }
+            for uint::range(0u, depth) |_i| {
+                writer.write_str(~"  ");
+            }
+            writer.write_str(item_to_text(*item));
+            writer.write_char('\n');
+            match item.data {
+                PushClipData | PushOpacityData(*) | PushTransformData(*) => depth += 1u,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn item_to_text(item: &DisplayItem) -> ~str {
+    let fields = DVec();
+    let bounds = fmt!("%d %d %d %d", *item.bounds.origin.x as int, *item.bounds.origin.y as int,
+                      *item.bounds.size.width as int, *item.bounds.size.height as int);
+
+    match item.data {
+        SolidColorData(r, g, b) => {
+            fields.push(~"solid-color");
+            fields.push(bounds);
+            fields.push(fmt!("%u %u %u", r as uint, g as uint, b as uint));
+        }
+        GlyphData(run) => {
+            fields.push(~"glyphs");
+            fields.push(bounds);
+            fields.push(au_to_text(run.baseline));
+            fields.push(color_to_text(run.color));
+            fields.push(fmt!("%u %s %s %u", run.font.weight, bool_to_text(run.font.italic),
+                             float_to_text(run.font.size), run.font.families.len()));
+            for run.font.families.each |family| {
+                fields.push(quote(*family));
+            }
+            fields.push(fmt!("%u", run.glyphs.len()));
+            for run.glyphs.each |glyph| {
+                fields.push(fmt!("%u %d %d %d %d %u", glyph.index,
+                                 *glyph.pos.advance.x as int, *glyph.pos.advance.y as int,
+                                 *glyph.pos.offset.x as int, *glyph.pos.offset.y as int,
+                                 glyph.cluster));
+            }
+        }
+        ImageData(image) => {
+            let image = std::arc::get(&image);
+            fields.push(~"image");
+            fields.push(bounds);
+            fields.push(fmt!("%u %u %u", image.width, image.height, image.depth));
+            fields.push(hex_encode(image.data));
+        }
+        BorderData(sides) => {
+            fields.push(~"border");
+            fields.push(bounds);
+            for [sides.top, sides.right, sides.bottom, sides.left].each |side| {
+                fields.push(fmt!("%s %s %s", au_to_text(side.width), color_to_text(side.color),
+                                 border_style_to_text(side.style)));
+            }
+        }
+        PushClipData => {
+            fields.push(~"push-clip");
+            fields.push(bounds);
+        }
+        PopClipData => fields.push(~"pop-clip"),
+        PushOpacityData(opacity) => {
+            fields.push(~"push-opacity");
+            fields.push(bounds);
+            fields.push(float_to_text(opacity));
+        }
+        PopOpacityData => fields.push(~"pop-opacity"),
+        PushTransformData(transform) => {
+            fields.push(~"push-transform");
+            fields.push(bounds);
+            fields.push(fmt!("%s %s %s %s %s %s", float_to_text(transform.xx),
+                             float_to_text(transform.yx), float_to_text(transform.xy),
+                             float_to_text(transform.yy), au_to_text(transform.x0),
+                             au_to_text(transform.y0)));
+        }
+        PopTransformData => fields.push(~"pop-transform")
+    }
+
+    do fields.borrow |fields| {
+        str::connect(fields, ~" ")
+    }
+}
+
+fn au_to_text(value: au) -> ~str {
+    fmt!("%d", *value as int)
+}
+
+fn float_to_text(value: float) -> ~str {
+    float::to_str(value, 6u)
+}
+
+fn bool_to_text(value: bool) -> ~str {
+    if value { ~"1" } else { ~"0" }
+}
+
+fn color_to_text(color: Color) -> ~str {
+    fmt!("%u %u %u %s", color.red as uint, color.green as uint, color.blue as uint,
+         float_to_text(color.alpha))
+}
+
+fn border_style_to_text(style: CSSBorderStyle) -> ~str {
+    match style {
+        BdrStyleNone => ~"none",
+        BdrStyleHidden => ~"hidden",
+        BdrStyleDotted => ~"dotted",
+        BdrStyleDashed => ~"dashed",
+        BdrStyleSolid => ~"solid",
+        BdrStyleDouble => ~"double",
+        BdrStyleGroove => ~"groove",
+        BdrStyleRidge => ~"ridge",
+        BdrStyleInset => ~"inset",
+        BdrStyleOutset => ~"outset"
+    }
+}
+
+fn quote(s: &str) -> ~str {
+    let mut quoted = ~"\"";
+    for str::each_char(s) |c| {
+        if c == '"' || c == '\\' {
+            str::push_char(&mut quoted, '\\');
+        }
+        str::push_char(&mut quoted, c);
+    }
+    str::push_char(&mut quoted, '"');
+    return quoted;
+}
+
+fn hex_encode(data: &[u8]) -> ~str {
+    let mut hex = ~"";
+    for data.each |byte| {
+        str::push_str(&mut hex, fmt!("%02x", *byte as uint));
+    }
+    return hex;
+}
+
+/**
+Reads a display list written by `to_text`. Blank lines are skipped, and
+every push has to be closed by a pop of the same kind.
+*/
+fn from_text(text: &str) -> Result<DisplayList, ~str> {
+    let list = DVec();
+    let open = DVec();
+    let mut line_number = 0u;
+
+    for str::lines_each(text) |line| {
+        line_number += 1u;
+        let tokens = match tokenize(line) {
+            Ok(tokens) => tokens,
+            Err(e) => return Err(fmt!("line %u: %s", line_number, e))
+        };
+        if tokens.is_empty() {
+            loop;
+        }
+
+        let item = match parse_item(tokens) {
+            Ok(item) => item,
+            Err(e) => return Err(fmt!("line %u: %s", line_number, e))
+        };
+
+        match item.data {
+            PushClipData => open.push(~"clip"),
+            PushOpacityData(*) => open.push(~"opacity"),
+            PushTransformData(*) => open.push(~"transform"),
+            PopClipData | PopOpacityData | PopTransformData => {
+                let kind = match item.data {
+                    PopClipData => ~"clip",
+                    PopOpacityData => ~"opacity",
+                    _ => ~"transform"
+                };
+                if open.len() == 0u || open.last() != kind {
+                    return Err(fmt!("line %u: pop-%s does not match a push", line_number, kind));
+                }
+                open.pop();
+            }
+            _ => {}
+        }
+
+        list.push(~item);
+    }
+
+    if open.len() != 0u {
+        return Err(fmt!("push-%s is never popped", open.last()));
+    }
+    return Ok(list);
+}
+
+// Splits a line at spaces, keeping quoted strings whole and unescaped
+fn tokenize(line: &str) -> Result<~[~str], ~str> {
+    let mut tokens = ~[];
+    let mut token = ~"";
+    let mut in_token = false;
+    let mut in_quote = false;
+    let mut escaped = false;
+
+    for str::each_char(line) |c| {
+        if in_quote {
+            if escaped {
+                str::push_char(&mut token, c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_quote = false;
+            } else {
+                str::push_char(&mut token, c);
+            }
+        } else if c == ' ' || c == '\t' || c == '\r' {
+            if in_token {
+                vec::push(tokens, move token);
+                token = ~"";
+                in_token = false;
+            }
+        } else if c == '"' {
+            in_token = true;
+            in_quote = true;
+        } else {
+            str::push_char(&mut token, c);
+            in_token = true;
+        }
+    }
+
+    if in_quote {
+        return Err(~"unterminated string");
+    }
+    if in_token {
+        vec::push(tokens, move token);
+    }
+    return Ok(tokens);
+}
+
+/**
+The fields of an item, read in order. A field that is missing or malformed
+records the first error and reads as zero, so that an item can be read in one
+go and checked at the end.
+*/
+struct Fields {
+    tokens: ~[~str],
+    mut next: uint,
+    mut error: Option<~str>
+}
+
+impl Fields {
+    fn fail(+message: ~str) {
+        if self.error.is_none() {
+            self.error = Some(move message);
+        }
+    }
+
+    fn token() -> ~str {
+        if self.next >= self.tokens.len() {
+            self.fail(~"missing field");
+            return ~"";
+        }
+        self.next += 1u;
+        copy self.tokens[self.next - 1u]
+    }
+
+    fn int() -> int {
+        let token = self.token();
+        match int::from_str(token) {
+            Some(value) => value,
+            None => {
+                self.fail(fmt!("expected an integer, found '%s'", token));
+                0
+            }
+        }
+    }
+
+    fn uint() -> uint {
+        let token = self.token();
+        match uint::from_str(token) {
+            Some(value) => value,
+            None => {
+                self.fail(fmt!("expected an unsigned integer, found '%s'", token));
+                0u
+            }
+        }
+    }
+
+    fn float() -> float {
+        let token = self.token();
+        match float::from_str(token) {
+            Some(value) => value,
+            None => {
+                self.fail(fmt!("expected a number, found '%s'", token));
+                0f
+            }
+        }
+    }
+
+    fn u8() -> u8 {
+        let value = self.uint();
+        if value > 255u {
+            self.fail(fmt!("%u is not a color component", value));
+        }
+        value as u8
+    }
+
+    fn au() -> au {
+        au(self.int() as i32)
+    }
+
+    fn bool() -> bool {
+        match self.uint() {
+            0u => false,
+            1u => true,
+            value => {
+                self.fail(fmt!("expected 0 or 1, found %u", value));
+                false
+            }
+        }
+    }
+
+    fn rect() -> Rect<au> {
+        let x = self.au();
+        let y = self.au();
+        let width = self.au();
+        let height = self.au();
+        Rect(Point2D(x, y), Size2D(width, height))
+    }
+
+    fn color() -> Color {
+        let r = self.u8();
+        let g = self.u8();
+        let b = self.u8();
+        let a = self.float();
+        rgba(r, g, b, a)
+    }
+
+    fn border_side() -> BorderSide {
+        let width = self.au();
+        let color = self.color();
+        let token = self.token();
+        let style = match parse_border_style(copy token) {
+            Value(style) => style,
+            _ => {
+                self.fail(fmt!("unknown border style '%s'", token));
+                BdrStyleNone
+            }
+        };
+        BorderSide { width: width, color: color, style: style }
+    }
+
+    fn glyph_run() -> GlyphRun {
+        let baseline = self.au();
+        let color = self.color();
+        let weight = self.uint();
+        let italic = self.bool();
+        let size = self.float();
+        let mut families = ~[];
+        for uint::range(0u, self.uint()) |_i| {
+            vec::push(families, self.token());
+        }
+        let mut glyphs = ~[];
+        for uint::range(0u, self.uint()) |_i| {
+            let index = self.uint();
+            let advance = self.point();
+            let offset = self.point();
+            let cluster = self.uint();
+            vec::push(glyphs, Glyph(index, GlyphPos(advance, offset), cluster));
+        }
+        GlyphRun {
+            glyphs: move glyphs,
+            font: FontDescriptor(move families, weight, italic, size),
+            color: color,
+            baseline: baseline
+        }
+    }
+
+    fn point() -> Point2D<au> {
+        let x = self.au();
+        let y = self.au();
+        Point2D(x, y)
+    }
+
+    fn image() -> ARC<~Image> {
+        let width = self.uint();
+        let height = self.uint();
+        let depth = self.uint();
+        let data = match hex_decode(self.token()) {
+            Ok(data) => data,
+            Err(e) => {
+                self.fail(e);
+                ~[]
+            }
+        };
+        if data.len() != width * height * depth {
+            self.fail(~"the image data does not match its size");
+        }
+        ARC(~Image(width, height, depth, move data))
+    }
+
+    fn transform() -> Transform {
+        let xx = self.float();
+        let yx = self.float();
+        let xy = self.float();
+        let yy = self.float();
+        let x0 = self.au();
+        let y0 = self.au();
+        Transform(xx, yx, xy, yy, x0, y0)
+    }
+}
+
+fn parse_item(+tokens: ~[~str]) -> Result<DisplayItem, ~str> {
+    let kind = copy tokens[0];
+    let fields = Fields { tokens: move tokens, next: 1u, error: None };
+
+    let item = match kind {
+        ~"solid-color" => {
+            let bounds = fields.rect();
+            let r = fields.u8();
+            let g = fields.u8();
+            let b = fields.u8();
+            DisplayItem { bounds: bounds, data: SolidColorData(r, g, b) }
+        }
+        ~"glyphs" => {
+            let bounds = fields.rect();
+            DisplayItem { bounds: bounds, data: GlyphData(fields.glyph_run()) }
+        }
+        ~"image" => {
+            let bounds = fields.rect();
+            DisplayItem { bounds: bounds, data: ImageData(fields.image()) }
+        }
+        ~"border" => {
+            let bounds = fields.rect();
+            let top = fields.border_side();
+            let right = fields.border_side();
+            let bottom = fields.border_side();
+            let left = fields.border_side();
+            let sides = BorderSides { top: top, right: right, bottom: bottom, left: left };
+            DisplayItem { bounds: bounds, data: BorderData(sides) }
+        }
+        ~"push-clip" => DisplayItem { bounds: fields.rect(), data: PushClipData },
+        ~"pop-clip" => DisplayItem { bounds: au::zero_rect(), data: PopClipData },
+        ~"push-opacity" => {
+            let bounds = fields.rect();
+            DisplayItem { bounds: bounds, data: PushOpacityData(fields.float()) }
+        }
+        ~"pop-opacity" => DisplayItem { bounds: au::zero_rect(), data: PopOpacityData },
+        ~"push-transform" => {
+            let bounds = fields.rect();
+            DisplayItem { bounds: bounds, data: PushTransformData(fields.transform()) }
+        }
+        ~"pop-transform" => DisplayItem { bounds: au::zero_rect(), data: PopTransformData },
+        _ => return Err(fmt!("unknown display item '%s'", kind))
+    };
+
+    if fields.error.is_none() && fields.next != fields.tokens.len() {
+        fields.fail(fmt!("too many fields for %s", kind));
+    }
+    match copy fields.error {
+        Some(e) => Err(e),
+        None => Ok(item)
+    }
+}
+
+fn hex_decode(hex: &str) -> Result<~[u8], ~str> {
+    if hex.len() % 2u != 0u {
+        return Err(~"odd number of hex digits");
+    }
+    let mut data = ~[];
+    let mut i = 0u;
+    while i < hex.len() {
+        match uint::from_str_radix(hex.slice(i, i + 2u), 16u) {
+            Some(byte) => vec::push(data, byte as u8),
+            None => return Err(~"invalid hex digits")
+        }
+        i += 2u;
+    }
+    return Ok(data);
+}
+
+#[cfg(test)]
+fn sample_list() -> DisplayList {
+    use display_list::{SolidColor, Glyphs, Border, PushClip, PopClip, PushOpacity, PopOpacity};
+    use display_list::{PushTransform, PopTransform, translation};
+    use util::color::rgb;
+
+    let bounds = Rect(Point2D(au::from_px(1), au::from_px(2)),
+                      Size2D(au::from_px(30), au::from_px(40)));
+    let side = BorderSide {
+        width: au::from_px(2),
+        color: rgb(1u8, 2u8, 3u8),
+        style: BdrStyleDashed
+    };
+    let run = GlyphRun {
+        glyphs: ~[Glyph(36u, GlyphPos(Point2D(au::from_px(7), au(0)), Point2D(au(0), au(0))), 0u)],
+        font: FontDescriptor(~[~"Deja \"Vu\" Sans", ~"serif"], 700u, true, 16f),
+        color: rgba(10u8, 20u8, 30u8, 0.5f),
+        baseline: au::from_px(12)
+    };
+
+    let list = DVec();
+    list.push(~SolidColor(copy bounds, 255u8, 255u8, 255u8));
+    list.push(~PushClip(copy bounds));
+    list.push(~PushOpacity(copy bounds, 0.25f));
+    let sides = BorderSides { top: side, right: side, bottom: side, left: side };
+    list.push(~Border(copy bounds, sides));
+    list.push(~PopOpacity());
+    list.push(~PushTransform(copy bounds, translation(au::from_px(5), au::from_frac_px(-5f))));
+    list.push(~Glyphs(copy bounds, move run));
+    list.push(~PopTransform());
+    list.push(~PopClip());
+    return list;
+}
+
+#[test]
+fn should_write_one_indented_line_per_item() {
+    let text = to_text(&sample_list());
+    let lines = str::lines(text);
+    assert lines.len() == 9u;
+    assert lines[0] == ~"solid-color 60 120 1800 2400 255 255 255";
+    assert lines[1] == ~"push-clip 60 120 1800 2400";
+    assert lines[2] == ~"  push-opacity 60 120 1800 2400 0.25";
+    assert lines[3].starts_with(~"    border 60 120 1800 2400 120 1 2 3 1 dashed ");
+    assert lines[4] == ~"  pop-opacity";
+    assert lines[6].starts_with(~"    glyphs 60 120 1800 2400 720 10 20 30 0.5 700 1 16 2 ");
+    assert lines[6].contains(~"\"Deja \\\"Vu\\\" Sans\" \"serif\" 1 36 420 0 0 0 0");
+    assert lines[8] == ~"pop-clip";
+}
+
+#[test]
+fn should_read_back_what_it_writes() {
+    let text = to_text(&sample_list());
+    match from_text(text) {
+        Ok(list) => {
+            assert list.len() == 9u;
+            assert to_text(&list) == text;
+        }
+        Err(e) => fail e
+    }
+}
+
+#[test]
+fn should_reject_unbalanced_and_malformed_lists() {
+    assert from_text(~"push-clip 0 0 10 10\npop-opacity\n").is_err();
+    assert from_text(~"push-transform 0 0 10 10 1 0 0 1 0 0\n").is_err();
+    assert from_text(~"pop-clip\n").is_err();
+    assert from_text(~"solid-color 0 0 10 10 255 255\n").is_err();
+    assert from_text(~"solid-color 0 0 10 10 255 255 256\n").is_err();
+    assert from_text(~"border 0 0 10 10 1 0 0 0 1 wavy 1 0 0 0 1 solid 1 0 0 0 1 solid \
+                       1 0 0 0 1 solid\n").is_err();
+    assert from_text(~"sparkles 0 0 10 10\n").is_err();
+    assert from_text(~"\n  solid-color 0 0 10 10 1 2 3\n\n").get().len() == 1u;
+}
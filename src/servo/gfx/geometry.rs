@@ -60,3 +60,11 @@ pure fn from_px(i: int) -> au {
 pure fn to_px(au: au) -> int {
     (*au / 60) as int
 }
+
+pure fn from_frac_px(f: float) -> au {
+    au((f * 60f) as i32)
+}
+
+pure fn to_frac_px(au: au) -> float {
+    (*au as float) / 60f
+}
@@ -1,8 +1,9 @@
 #[doc = "
-A graphics compositor that renders to PNG format buffers
+A graphics compositor that renders to image files in memory
 
 Each time the renderer renders a frame the compositor will output a
-`~[u8]` containing the frame in PNG format.
+`~[u8]` containing the frame in the compositor's encoding, or why the
+encoding could not hold it.
 "];
 
 use libc::{c_int, c_uint, c_void, c_uchar};
@@ -12,9 +13,6 @@ use azure::cairo;
 use azure::azure_hl::DrawTarget;
 use azure::cairo_hl::ImageSurface;
 use cairo::{CAIRO_FORMAT_ARGB32, cairo_surface_t, cairo_status_t, CAIRO_STATUS_SUCCESS};
-use cairo_bg = cairo::bindgen;
-use cairo_bg::{cairo_image_surface_create, cairo_surface_destroy,
-                  cairo_surface_write_to_png_stream};
 use compositor::Compositor;
 use render_task::{RenderTask, RenderMsg};
 use task::spawn_listener;
@@ -26,13 +24,19 @@ use dom::event::Event;
 use dvec::DVec;
 use display_list::DisplayList;
 use std::cell::Cell;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use image::encode::encoding::{Encoding, PngEncoding};
+use surface::image_surface_from_cairo;
 use core::io::BytesWriter;
 
-pub type PngCompositor = Chan<Msg>;
+pub type ImageCompositor = Chan<Msg>;
 
 pub enum Msg {
     BeginDrawing(pipes::Chan<DrawTarget>),
     Draw(pipes::Chan<DrawTarget>, DrawTarget),
+    Resize(pipes::Chan<DrawTarget>, DrawTarget, Size2D<int>),
     Exit
 }
 
@@ -43,25 +47,35 @@ impl Chan<Msg> : Compositor {
     fn draw(+next_dt: pipes::Chan<DrawTarget>, +draw_me: DrawTarget) {
         self.send(Draw(next_dt, draw_me))
     }
+    fn resize(+next_dt: pipes::Chan<DrawTarget>, +old: DrawTarget, size: Size2D<int>) {
+        self.send(Resize(next_dt, old, size))
+    }
     fn add_event_listener(_listener: Chan<Event>) {
         // No events in this compositor.
     }
 }
 
-pub fn PngCompositor(output: Chan<~[u8]>) -> PngCompositor {
+// The size of the surface is in device pixels, and changes when the renderer resizes it
+pub fn ImageCompositor(output: Chan<Result<~[u8], ~str>>, size: Size2D<int>,
+                       encoding: Encoding) -> ImageCompositor {
     do spawn_listener |po: Port<Msg>| {
-        let cairo_surface = ImageSurface(CAIRO_FORMAT_ARGB32, 800, 600);
+        let mut cairo_surface = ImageSurface(CAIRO_FORMAT_ARGB32, size.width, size.height);
         let draw_target = Cell(DrawTarget(cairo_surface));
 
         loop {
             match po.recv() {
                 BeginDrawing(sender) => {
-                    debug!("png_compositor: begin_drawing");
+                    debug!("image_compositor: begin_drawing");
                     sender.send(draw_target.take());
                 }
                 Draw(sender, dt) => {
-                    debug!("png_compositor: draw");
-                    do_draw(sender, dt.clone(), output, cairo_surface);
+                    debug!("image_compositor: draw");
+                    do_draw(sender, dt.clone(), output, cairo_surface, encoding);
+                }
+                Resize(sender, _old, size) => {
+                    debug!("image_compositor: resize to %d, %d", size.width, size.height);
+                    cairo_surface = ImageSurface(CAIRO_FORMAT_ARGB32, size.width, size.height);
+                    sender.send(DrawTarget(cairo_surface));
                 }
                 Exit => break
             }
@@ -71,11 +85,21 @@ pub fn PngCompositor(output: Chan<~[u8]>) -> PngCompositor {
 
 fn do_draw(sender: pipes::Chan<DrawTarget>,
            +dt: DrawTarget,
-           output: Chan<~[u8]>,
-           cairo_surface: ImageSurface) {
-    let buffer = BytesWriter();
-    cairo_surface.write_to_png_stream(&buffer);
-    output.send(buffer.buf.get());
+           output: Chan<Result<~[u8], ~str>>,
+           cairo_surface: ImageSurface,
+           encoding: Encoding) {
+    match encoding {
+      // Cairo writes PNG straight from its own surface
+      PngEncoding => {
+        let buffer = BytesWriter();
+        cairo_surface.write_to_png_stream(&buffer);
+        output.send(Ok(buffer.buf.get()));
+      }
+      _ => {
+        let image = image_surface_from_cairo(&cairo_surface);
+        output.send(encoding.encode_to_bytes(&image));
+      }
+    }
 
     // Send the next draw target to the renderer
     sender.send(move dt);
@@ -84,11 +108,11 @@ fn do_draw(sender: pipes::Chan<DrawTarget>,
 #[test]
 fn sanity_check() {
     do listen |self_channel| {
-        let compositor = PngCompositor(self_channel);
+        let compositor = ImageCompositor(self_channel, Size2D(800, 600), PngEncoding);
         let renderer = RenderTask(compositor);
 
         let dlist : DisplayList = DVec();
-        renderer.send(RenderMsg(dlist));
+        renderer.send(RenderMsg(dlist, Rect(Point2D(0, 0), Size2D(800, 600))));
         let (exit_chan, exit_response_from_engine) = pipes::stream();
         renderer.send(render_task::ExitMsg(exit_chan));
         exit_response_from_engine.recv();
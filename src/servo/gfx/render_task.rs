@@ -8,7 +8,13 @@ use azure::*;
 use azure::bindgen::*;
 use libc::size_t;
 use text::font::Font;
-use display_list::GlyphRun;
+use display_list_text;
+use display_list::{DisplayItem, GlyphRun, BorderSide, BorderSides, Transform};
+use display_list::{SolidColorData, GlyphData, ImageData, BorderData, PushClipData, PopClipData};
+use display_list::{PushOpacityData, PopOpacityData, PushTransformData, PopTransformData};
+use css::values::{CSSBorderStyle, BdrStyleNone, BdrStyleHidden, BdrStyleDotted, BdrStyleDashed};
+use css::values::{BdrStyleSolid, BdrStyleDouble, BdrStyleGroove, BdrStyleRidge, BdrStyleInset};
+use css::values::BdrStyleOutset;
 use geom::size::Size2D;
 use geom::rect::Rect;
 use geom::point::Point2D;
@@ -16,8 +22,13 @@ use azure_hl::{AsAzureRect, B8G8R8A8, Color, ColorPattern, DrawOptions, DrawSurf
 use azure_hl::{DrawTarget, Linear};
 use ptr::addr_of;
 use std::arc::ARC;
+use clone_arc = std::arc::clone;
 use azure::cairo::{cairo_font_face_t, cairo_scaled_font_t};
+use azure::cairo;
+use azure::cairo_hl::ImageSurface;
 use std::cell::Cell;
+use std::map::HashMap;
+use dvec::DVec;
 use compositor::Compositor;
 use servo_text::font_cache::FontCache;
 
@@ -26,7 +37,14 @@ use pipes::{Port, Chan};
 type Renderer = comm::Chan<Msg>;
 
 pub enum Msg {
-    RenderMsg(dl::DisplayList),
+    // A display list in the coordinates of the document, and the part of the
+    // document to show, in px. The canvas is the size of that part.
+    RenderMsg(dl::DisplayList, Rect<int>),
+    // Shows another part of the document, from the last display list
+    ScrollMsg(Rect<int>),
+    // The data of a font downloaded for an @font-face rule: its family, its
+    // weight, whether it is italic, and the font file
+    RegisterFontFaceMsg(~str, uint, bool, ~[u8]),
     ExitMsg(pipes::Chan<()>)
 }
 
@@ -38,54 +56,472 @@ struct RenderContext {
 type RenderTask = comm::Chan<Msg>;
 
 fn RenderTask<C: Compositor Send>(+compositor: C) -> RenderTask {
-    do task::spawn_listener |po: comm::Port<Msg>| {
+    RenderTask_(compositor, 1f, DEFAULT_RENDER_WORKERS, None)
+}
+
+/**
+A render task that draws `device_pixel_ratio` device pixels for each px with
+`render_workers` worker tasks, and that also sends each display list it
+renders, as text, to `display_list_output` (see `display_list_text`).
+*/
+fn RenderTask_<C: Compositor Send>(+compositor: C,
+                                  device_pixel_ratio: float,
+                                  render_workers: uint,
+                                  +display_list_output: Option<comm::Chan<~str>>)
+                                -> RenderTask {
+    do task::spawn_listener |po: comm::Port<Msg>, move display_list_output| {
         let (draw_target_ch, draw_target_po) = pipes::stream();
         let mut draw_target_ch = draw_target_ch;
         let mut draw_target_po = draw_target_po;
 
         let font_cache = FontCache();
+        let tiles = TileCache(device_pixel_ratio, render_workers);
 
         debug!("renderer: beginning rendering loop");
 
         compositor.begin_drawing(draw_target_ch);
 
         loop {
-            match po.recv() {
-              RenderMsg(display_list) => {
+            let (list, visible) = match po.recv() {
+              RenderMsg(display_list, visible) => {
                 #debug("renderer: got render request");
-                let draw_target = Cell(draw_target_po.recv());
-                let (ch, po) = pipes::stream();
-                let mut draw_target_ch_ = Some(ch);
-                draw_target_po = po;
-                #debug("renderer: rendering");
-                do util::time::time(~"rendering") {
-                    let mut draw_target_ch = None;
-                    draw_target_ch_ <-> draw_target_ch;
-                    let draw_target_ch = option::unwrap(draw_target_ch);
-
-                    do draw_target.with_ref |draw_target| {
-                        let ctx = RenderContext {
-                            canvas: draw_target,
-                            font_cache: font_cache
-                        };
-
-                        clear(&ctx);
-                        display_list.draw(&ctx)
-                    }
-
-                    #debug("renderer: returning surface");
-                    compositor.draw(draw_target_ch, draw_target.take());
+                do display_list_output.iter |output| {
+                    output.send(display_list_text::to_text(&display_list));
                 }
+                // The items are frozen so that the workers can share them
+                (ARC(dvec::unwrap(move display_list)), visible)
+              }
+              ScrollMsg(visible) => {
+                #debug("renderer: got scroll request");
+                (clone_arc(&tiles.last_list), visible)
+              }
+              RegisterFontFaceMsg(family, weight, italic, data) => {
+                font_cache.register_font_face(copy family, weight, italic, copy data);
+                tiles.register_font_face(family, weight, italic, data);
+                loop;
               }
               ExitMsg(response_ch) => {
+                tiles.exit();
                 response_ch.send(());
                 break;
               }
+            };
+
+            let draw_target = draw_target_po.recv();
+
+            // Ask for a canvas of the size of the view, which the compositor may not grant
+            let device_size = Size2D((visible.size.width as float * device_pixel_ratio) as int,
+                                     (visible.size.height as float * device_pixel_ratio) as int);
+            let draw_target = if canvas_size(&draw_target) != device_size {
+                let (resized_ch, resized_po) = pipes::stream();
+                compositor.resize(resized_ch, move draw_target, device_size);
+                resized_po.recv()
+            } else {
+                move draw_target
+            };
+
+            let draw_target = Cell(move draw_target);
+            let list = Cell(move list);
+            let (ch, po) = pipes::stream();
+            let mut draw_target_ch_ = Some(ch);
+            draw_target_po = po;
+            #debug("renderer: rendering");
+            do util::time::time(~"rendering") {
+                let mut draw_target_ch = None;
+                draw_target_ch_ <-> draw_target_ch;
+                let draw_target_ch = option::unwrap(draw_target_ch);
+
+                do draw_target.with_ref |draw_target| {
+                    let ctx = RenderContext {
+                        canvas: draw_target,
+                        font_cache: font_cache
+                    };
+
+                    tiles.render(&ctx, list.take(), &visible);
+                }
+
+                #debug("renderer: returning surface");
+                compositor.draw(draw_target_ch, draw_target.take());
             }
         }
     }
 }
 
+// The document is rendered in square tiles of this many pixels, which are
+// kept across frames so that only the ones that change are drawn again
+const TILE_SIZE: int = 256;
+
+// The number of tasks that draw tiles in parallel, unless --render-workers says
+const DEFAULT_RENDER_WORKERS: uint = 4u;
+
+// What the renderer knows of a tile. Its pixels belong to a render worker.
+struct Tile {
+    // In device space, from the top left of the document
+    rect: Rect<au>,
+    // Where the tile is in the grid of tiles, which names it to the workers
+    column: uint,
+    row: uint,
+    // The indices of the items that were drawn into the tile, in the last display list
+    mut items: ~[uint],
+    mut valid: bool
+}
+
+fn Tile(column: uint, row: uint) -> Tile {
+    let size = au::from_px(TILE_SIZE);
+    Tile {
+        rect: Rect(Point2D(au(*size * (column as i32)), au(*size * (row as i32))),
+                   Size2D(size, size)),
+        column: column,
+        row: row,
+        items: ~[],
+        valid: false
+    }
+}
+
+/**
+The tiles of the part of the document in view, and the display list they were
+last drawn from. The list and the tiles are in the coordinates of the
+document, so scrolling only draws the tiles that come into view. A tile is
+drawn again only when the items that cover it are not the same as those of
+the last list, in the same order. The push and pop items cover every tile,
+since they change how everything inside them is drawn.
+
+The tiles are dealt in turn to a pool of render workers, which draw the
+changed ones in parallel. The renderer then stitches every tile onto the
+canvas, moved by the scroll offset.
+*/
+struct TileCache {
+    // Scales px to device pixels
+    base_transform: Transform,
+    mut tiles: ~[Tile],
+    mut last_list: ARC<~[~DisplayItem]>,
+    workers: ~[RenderWorker]
+}
+
+fn TileCache(device_pixel_ratio: float, render_workers: uint) -> TileCache {
+    TileCache {
+        base_transform: dl::Transform(device_pixel_ratio, 0f, 0f, device_pixel_ratio,
+                                      au(0), au(0)),
+        tiles: ~[],
+        last_list: ARC(~[]),
+        workers: vec::from_fn(uint::max(1u, render_workers), |_i| RenderWorker())
+    }
+}
+
+impl TileCache {
+    // Draws the part of the document in view, in px, onto the canvas
+    fn render(ctx: &RenderContext, +list: ARC<~[~DisplayItem]>, visible: &Rect<int>) {
+        let view = self.base_transform.transform_rect(
+            &Rect(Point2D(au::from_px(visible.origin.x), au::from_px(visible.origin.y)),
+                  Size2D(au::from_px(visible.size.width), au::from_px(visible.size.height))));
+        self.tiles = tiles_in_view(&view, self.tiles);
+
+        {
+            let items: &[~DisplayItem] = *list.get();
+            let last_items: &[~DisplayItem] = *self.last_list.get();
+            let bounds = device_bounds(items, &self.base_transform);
+            let requests = vec::from_fn(self.workers.len(), |_i| DVec());
+            let mut redrawn = 0u;
+
+            for self.tiles.each |tile| {
+                let tile_items = items_covering(bounds, &tile.rect);
+                let dirty = !tile.valid ||
+                    !same_items(last_items, tile.items, items, tile_items);
+                if dirty {
+                    redrawn += 1u;
+                }
+                requests[(tile.column + tile.row) % self.workers.len()].push(TileRequest {
+                    key: (tile.row, tile.column),
+                    rect: copy tile.rect,
+                    dirty: dirty
+                });
+                tile.items = move tile_items;
+                tile.valid = true;
+            }
+
+            // The workers share the items, which none of them changes
+            let replies = do vec::mapi(self.workers) |i, worker| {
+                let (reply_chan, reply_port) = pipes::stream();
+                worker.send(DrawTilesMsg(clone_arc(&list), requests[i].get(),
+                                         copy self.base_transform, reply_chan));
+                reply_port
+            };
+
+            for replies.each |reply_port| {
+                for reply_port.recv().each |tile| {
+                    let origin = Point2D(tile.rect.origin.x.sub(view.origin.x),
+                                         tile.rect.origin.y.sub(view.origin.y));
+                    draw_layer(ctx, &tile.draw_target, &origin, 1f);
+                }
+            }
+
+            debug!("renderer: drew %u of %u tiles", redrawn, self.tiles.len());
+        }
+        self.last_list = move list;
+    }
+
+    /* Gives the workers the font, and draws every tile again, since text that
+    asked for the font's family may have been drawn with another font */
+    fn register_font_face(+family: ~str, weight: uint, italic: bool, +data: ~[u8]) {
+        for self.workers.each |worker| {
+            worker.send(RegisterFontFaceWorkerMsg(copy family, weight, italic, copy data));
+        }
+        for self.tiles.each |tile| {
+            tile.valid = false;
+        }
+    }
+
+    fn exit() {
+        for self.workers.each |worker| {
+            worker.send(ExitWorkerMsg);
+        }
+    }
+}
+
+// A tile a worker owns, and whether the items that cover it changed
+struct TileRequest {
+    // The row and column of the tile
+    key: (uint, uint),
+    rect: Rect<au>,
+    dirty: bool
+}
+
+// A tile as drawn by a worker, shared with the renderer to stitch it onto the canvas
+struct RenderedTile {
+    rect: Rect<au>,
+    draw_target: DrawTarget
+}
+
+enum WorkerMsg {
+    // The transform maps the items to device space
+    DrawTilesMsg(ARC<~[~DisplayItem]>, ~[TileRequest], Transform, pipes::Chan<~[RenderedTile]>),
+    // As RegisterFontFaceMsg
+    RegisterFontFaceWorkerMsg(~str, uint, bool, ~[u8]),
+    ExitWorkerMsg
+}
+
+type RenderWorker = comm::Chan<WorkerMsg>;
+
+struct TileSurface {
+    rect: Rect<au>,
+    surface: ImageSurface,
+    draw_target: DrawTarget
+}
+
+fn TileSurface(rect: Rect<au>) -> TileSurface {
+    let surface = ImageSurface(cairo::CAIRO_FORMAT_ARGB32, au::to_px(rect.size.width),
+                               au::to_px(rect.size.height));
+    let draw_target = DrawTarget(surface);
+    TileSurface { rect: rect, surface: surface, draw_target: draw_target }
+}
+
+/**
+A task that keeps the pixels of some of the tiles. The renderer does not send
+it more work before it has stitched the tiles of the last request, so the
+draw targets are never drawn into and read at the same time.
+*/
+fn RenderWorker() -> RenderWorker {
+    do task::spawn_listener |po: comm::Port<WorkerMsg>| {
+        // Fonts are task-local, so each worker needs its own cache
+        let font_cache = FontCache();
+        // By row and column
+        let mut surfaces = HashMap::<(uint, uint), @TileSurface>();
+
+        loop {
+            match po.recv() {
+              DrawTilesMsg(list, requests, base_transform, reply) => {
+                let items: &[~DisplayItem] = *list.get();
+                let bounds = device_bounds(items, &base_transform);
+                // Only the tiles asked for are kept, so the surfaces of tiles that
+                // went away, or went to another worker, are freed
+                let kept = HashMap::<(uint, uint), @TileSurface>();
+                let rendered = do requests.map |request| {
+                    let (tile, is_new) = match surfaces.find(request.key) {
+                        Some(tile) if tile.rect == request.rect => (tile, false),
+                        _ => (@TileSurface(copy request.rect), true)
+                    };
+                    kept.insert(request.key, tile);
+                    if request.dirty || is_new {
+                        draw_tile(font_cache, tile, items, bounds, &base_transform);
+                    }
+                    RenderedTile { rect: copy tile.rect, draw_target: tile.draw_target.clone() }
+                };
+                surfaces = kept;
+                reply.send(move rendered);
+              }
+              RegisterFontFaceWorkerMsg(family, weight, italic, data) => {
+                font_cache.register_font_face(family, weight, italic, data);
+              }
+              ExitWorkerMsg => break
+            }
+        }
+    }
+}
+
+/* The tiles that cover the view, a rect in device space. Those that were in
+view already keep what is known of them. */
+fn tiles_in_view(view: &Rect<au>, old_tiles: &[Tile]) -> ~[Tile] {
+    let (first_column, last_column, first_row, last_row) = tile_range(view);
+    let mut tiles = ~[];
+    for uint::range(first_row, last_row) |row| {
+        for uint::range(first_column, last_column) |column| {
+            match vec::find(old_tiles, |tile| tile.column == column && tile.row == row) {
+                Some(tile) => vec::push(tiles, move tile),
+                None => vec::push(tiles, Tile(column, row))
+            }
+        }
+    }
+    return tiles;
+}
+
+// The columns and rows of the tiles that cover a rect in device space, as
+// half-open ranges
+fn tile_range(view: &Rect<au>) -> (uint, uint, uint, uint) {
+    let first = |start: au| (int::max(0, au::to_px(start)) / TILE_SIZE) as uint;
+    let last = |end: au| ((int::max(0, au::to_px(end)) + TILE_SIZE - 1) / TILE_SIZE) as uint;
+    (first(view.origin.x), last(view.origin.x.add(view.size.width)),
+     first(view.origin.y), last(view.origin.y.add(view.size.height)))
+}
+
+// The bounds of each item in device space, or None for push and pop items
+fn device_bounds(items: &[~DisplayItem], base_transform: &Transform) -> ~[Option<Rect<au>>] {
+    let mut transforms = ~[copy *base_transform];
+    do items.map |item| {
+        match item.data {
+            PushClipData | PushOpacityData(*) => None,
+            PushTransformData(transform) => {
+                let top = transforms.last().multiply(&transform);
+                vec::push(transforms, top);
+                None
+            }
+            PopTransformData => {
+                vec::pop(transforms);
+                None
+            }
+            PopClipData | PopOpacityData => None,
+            _ => Some(transforms.last().transform_rect(&item.bounds))
+        }
+    }
+}
+
+fn items_covering(bounds: &[Option<Rect<au>>], rect: &Rect<au>) -> ~[uint] {
+    let mut covering = ~[];
+    for bounds.eachi |i, item_bounds| {
+        match *item_bounds {
+            Some(ref item_bounds) if !item_bounds.intersects(rect) => {}
+            _ => vec::push(covering, i)
+        }
+    }
+    return covering;
+}
+
+fn same_items(last_list: &[~DisplayItem], last_items: &[uint], list: &[~DisplayItem],
+              items: &[uint]) -> bool {
+    if last_items.len() != items.len() {
+        return false;
+    }
+    for uint::range(0u, items.len()) |i| {
+        if *last_list[last_items[i]] != *list[items[i]] {
+            return false;
+        }
+    }
+    return true;
+}
+
+// Draws the items that cover the tile, on white, with the tile origin at the top left
+fn draw_tile(font_cache: @FontCache, tile: &TileSurface, items: &[~DisplayItem],
+             bounds: &[Option<Rect<au>>], base_transform: &Transform) {
+    let ctx = RenderContext { canvas: &tile.draw_target, font_cache: font_cache };
+    let origin = dl::translation(au(-*tile.rect.origin.x), au(-*tile.rect.origin.y));
+    set_transform(&ctx, &origin);
+    fill_color(&ctx, &tile.rect, util::color::rgb(255u8, 255u8, 255u8));
+
+    let origin = origin.multiply(base_transform);
+    set_transform(&ctx, &origin);
+
+    let visible = do bounds.map |item_bounds| {
+        match *item_bounds {
+            Some(ref item_bounds) => item_bounds.intersects(&tile.rect),
+            None => true
+        }
+    };
+    let end = draw_items(&ctx, items, visible, 0u, &origin);
+    if end != items.len() {
+        fail ~"display list has a pop item without a matching push";
+    }
+}
+
+// In device pixels
+fn canvas_size(canvas: &DrawTarget) -> Size2D<int> {
+    let size = AzDrawTargetGetSize(canvas.azure_draw_target);
+    Size2D(size.width as int, size.height as int)
+}
+
+/**
+Draws the items from `start` until the end of the list or an unmatched pop
+item, whose index is returned. The items in between a push and its pop are
+drawn by a recursive call, with `transform` mapping them to the canvas.
+Items that are not `visible` are skipped.
+*/
+fn draw_items(ctx: &RenderContext, items: &[~DisplayItem], visible: &[bool], start: uint,
+              transform: &Transform) -> uint {
+    let mut i = start;
+    while i < items.len() {
+        let item = &items[i];
+        if !visible[i] {
+            i += 1u;
+            loop;
+        }
+        debug!("drawing %?", *item);
+        i = match item.data {
+            SolidColorData(r, g, b) => {
+                draw_solid_color(ctx, &item.bounds, r, g, b);
+                i + 1u
+            }
+            GlyphData(run) => {
+                draw_glyphs(ctx, item.bounds, &run);
+                i + 1u
+            }
+            ImageData(image) => {
+                draw_image(ctx, item.bounds, image);
+                i + 1u
+            }
+            BorderData(sides) => {
+                draw_border(ctx, &item.bounds, &sides);
+                i + 1u
+            }
+            PushClipData => {
+                push_clip(ctx, &item.bounds);
+                let end = draw_items(ctx, items, visible, i + 1u, transform);
+                pop_clip(ctx);
+                end + 1u
+            }
+            PushOpacityData(opacity) => {
+                let layer = push_opacity(ctx, transform);
+                let layer_ctx = RenderContext {
+                    canvas: &layer.draw_target,
+                    font_cache: ctx.font_cache
+                };
+                let end = draw_items(&layer_ctx, items, visible, i + 1u, transform);
+                pop_opacity(ctx, &layer, opacity, transform);
+                end + 1u
+            }
+            PushTransformData(inner) => {
+                let inner = transform.multiply(&inner);
+                set_transform(ctx, &inner);
+                let end = draw_items(ctx, items, visible, i + 1u, &inner);
+                set_transform(ctx, transform);
+                end + 1u
+            }
+            PopClipData | PopOpacityData | PopTransformData => return i
+        };
+        if i > items.len() {
+            fail ~"display list has a push item without a matching pop";
+        }
+    }
+    return i;
+}
+
 trait to_float {
     fn to_float() -> float;
 }
@@ -116,6 +552,226 @@ pub fn draw_solid_color(ctx: &RenderContext, bounds: &Rect<au>, r: u8, g: u8, b:
     ctx.canvas.fill_rect(bounds.to_azure_rect(), ColorPattern(color));
 }
 
+fn fill_color(ctx: &RenderContext, bounds: &Rect<au>, color: util::color::Color) {
+    let color = Color(color.red.to_float() as AzFloat,
+                      color.green.to_float() as AzFloat,
+                      color.blue.to_float() as AzFloat,
+                      color.alpha as AzFloat);
+
+    ctx.canvas.fill_rect(bounds.to_azure_rect(), ColorPattern(color));
+}
+
+enum BorderEdge {
+    TopEdge,
+    RightEdge,
+    BottomEdge,
+    LeftEdge
+}
+
+/**
+Draws the sides of a border inside `bounds`. Every style is made of
+rectangles: double borders of two bands, dotted and dashed ones of
+segments along the side, and the 3D styles of darker and lighter shades.
+*/
+pub fn draw_border(ctx: &RenderContext, bounds: &Rect<au>, sides: &BorderSides) {
+    let origin = bounds.origin;
+    let size = bounds.size;
+
+    draw_border_side(ctx, TopEdge, &sides.top,
+                     &Rect(copy origin, Size2D(size.width, sides.top.width)));
+    draw_border_side(ctx, RightEdge, &sides.right,
+                     &Rect(Point2D(origin.x.add(size.width).sub(sides.right.width), origin.y),
+                           Size2D(sides.right.width, size.height)));
+    draw_border_side(ctx, BottomEdge, &sides.bottom,
+                     &Rect(Point2D(origin.x, origin.y.add(size.height).sub(sides.bottom.width)),
+                           Size2D(size.width, sides.bottom.width)));
+    draw_border_side(ctx, LeftEdge, &sides.left,
+                     &Rect(copy origin, Size2D(sides.left.width, size.height)));
+}
+
+fn draw_border_side(ctx: &RenderContext, edge: BorderEdge, side: &BorderSide, strip: &Rect<au>) {
+    if strip.size.width <= au(0) || strip.size.height <= au(0) {
+        return;
+    }
+
+    // Top and left sides are lit, bottom and right ones are in the shade
+    let lit = match edge {
+        TopEdge | LeftEdge => true,
+        RightEdge | BottomEdge => false
+    };
+
+    match side.style {
+        BdrStyleNone | BdrStyleHidden => {}
+        BdrStyleSolid => fill_color(ctx, strip, side.color),
+        BdrStyleDouble => {
+            fill_color(ctx, &border_band(strip, edge, 0f, 1f / 3f), side.color);
+            fill_color(ctx, &border_band(strip, edge, 2f / 3f, 1f), side.color);
+        }
+        BdrStyleDotted | BdrStyleDashed => {
+            let width = match edge {
+                TopEdge | BottomEdge => strip.size.height,
+                RightEdge | LeftEdge => strip.size.width
+            };
+            let length = match side.style {
+                BdrStyleDotted => width,
+                _ => au(*width * 3)
+            };
+            draw_border_segments(ctx, edge, strip, length, side.color);
+        }
+        BdrStyleGroove | BdrStyleRidge => {
+            let outer_dark = match side.style {
+                BdrStyleGroove => lit,
+                _ => !lit
+            };
+            let (outer, inner) = if outer_dark {
+                (darker(side.color), lighter(side.color))
+            } else {
+                (lighter(side.color), darker(side.color))
+            };
+            fill_color(ctx, &border_band(strip, edge, 0f, 0.5f), outer);
+            fill_color(ctx, &border_band(strip, edge, 0.5f, 1f), inner);
+        }
+        BdrStyleInset | BdrStyleOutset => {
+            let dark = match side.style {
+                BdrStyleInset => lit,
+                _ => !lit
+            };
+            fill_color(ctx, strip, if dark { darker(side.color) } else { lighter(side.color) });
+        }
+    }
+}
+
+// The part of the strip of a side between two fractions of its width,
+// counted from the outer edge of the border
+fn border_band(strip: &Rect<au>, edge: BorderEdge, from: float, to: float) -> Rect<au> {
+    let origin = strip.origin;
+    let size = strip.size;
+    let part = |width: au, f: float| au::from_frac_px(au::to_frac_px(width) * f);
+
+    match edge {
+        TopEdge => Rect(Point2D(origin.x, origin.y.add(part(size.height, from))),
+                        Size2D(size.width, part(size.height, to - from))),
+        BottomEdge => Rect(Point2D(origin.x, origin.y.add(part(size.height, 1f - to))),
+                           Size2D(size.width, part(size.height, to - from))),
+        LeftEdge => Rect(Point2D(origin.x.add(part(size.width, from)), origin.y),
+                         Size2D(part(size.width, to - from), size.height)),
+        RightEdge => Rect(Point2D(origin.x.add(part(size.width, 1f - to)), origin.y),
+                          Size2D(part(size.width, to - from), size.height))
+    }
+}
+
+// Fills segments of `length` along the strip, with gaps of the same length
+fn draw_border_segments(ctx: &RenderContext, edge: BorderEdge, strip: &Rect<au>, length: au,
+                        color: util::color::Color) {
+    if length <= au(0) {
+        fill_color(ctx, strip, color);
+        return;
+    }
+
+    let horizontal = match edge {
+        TopEdge | BottomEdge => true,
+        RightEdge | LeftEdge => false
+    };
+    let total = if horizontal { strip.size.width } else { strip.size.height };
+    let mut position = au(0);
+    while position < total {
+        let segment = au::min(length, total.sub(position));
+        let rect = if horizontal {
+            Rect(Point2D(strip.origin.x.add(position), strip.origin.y),
+                 Size2D(segment, strip.size.height))
+        } else {
+            Rect(Point2D(strip.origin.x, strip.origin.y.add(position)),
+                 Size2D(strip.size.width, segment))
+        };
+        fill_color(ctx, &rect, color);
+        position = position.add(au(*length * 2));
+    }
+}
+
+fn darker(color: util::color::Color) -> util::color::Color {
+    let shade = |c: u8| (c as uint * 2u / 3u) as u8;
+    util::color::rgba(shade(color.red), shade(color.green), shade(color.blue), color.alpha)
+}
+
+fn lighter(color: util::color::Color) -> util::color::Color {
+    let shade = |c: u8| (c as uint + (255u - c as uint) / 3u) as u8;
+    util::color::rgba(shade(color.red), shade(color.green), shade(color.blue), color.alpha)
+}
+
+fn azure_matrix(transform: &Transform) -> AzMatrix {
+    {
+        _11: transform.xx as AzFloat,
+        _12: transform.yx as AzFloat,
+        _21: transform.xy as AzFloat,
+        _22: transform.yy as AzFloat,
+        _31: au::to_frac_px(transform.x0) as AzFloat,
+        _32: au::to_frac_px(transform.y0) as AzFloat
+    }
+}
+
+fn set_transform(ctx: &RenderContext, transform: &Transform) {
+    let matrix = azure_matrix(transform);
+    AzDrawTargetSetTransform(ctx.canvas.azure_draw_target, addr_of(matrix));
+}
+
+// The clip is in the coordinates of the current transform
+fn push_clip(ctx: &RenderContext, bounds: &Rect<au>) {
+    let rect = bounds.to_azure_rect().as_azure_rect();
+    AzDrawTargetPushClipRect(ctx.canvas.azure_draw_target, addr_of(rect));
+}
+
+fn pop_clip(ctx: &RenderContext) {
+    AzDrawTargetPopClip(ctx.canvas.azure_draw_target);
+}
+
+/// A transparent surface the size of the canvas that an opacity group is drawn into
+struct OpacityLayer {
+    surface: ImageSurface,
+    draw_target: DrawTarget
+}
+
+fn push_opacity(ctx: &RenderContext, transform: &Transform) -> OpacityLayer {
+    let size = AzDrawTargetGetSize(ctx.canvas.azure_draw_target);
+    let surface = ImageSurface(cairo::CAIRO_FORMAT_ARGB32, size.width as int,
+                               size.height as int);
+    let draw_target = DrawTarget(surface);
+    let matrix = azure_matrix(transform);
+    AzDrawTargetSetTransform(draw_target.azure_draw_target, addr_of(matrix));
+    OpacityLayer { surface: surface, draw_target: draw_target }
+}
+
+// Blends the layer onto the canvas, which is in device space like the layer
+fn pop_opacity(ctx: &RenderContext, layer: &OpacityLayer, opacity: float,
+               transform: &Transform) {
+    set_transform(ctx, &dl::identity_transform());
+    draw_layer(ctx, &layer.draw_target, &au::zero_point(), opacity);
+    set_transform(ctx, transform);
+}
+
+// Draws all of `layer` at `origin` on the canvas, which must have no transform
+fn draw_layer(ctx: &RenderContext, layer: &DrawTarget, origin: &Point2D<au>, alpha: float) {
+    use libc::types::common::c99::uint16_t;
+
+    let size = AzDrawTargetGetSize(layer.azure_draw_target);
+    let size = Size2D(size.width as AzFloat, size.height as AzFloat);
+    let source = Rect(Point2D(0 as AzFloat, 0 as AzFloat), copy size).as_azure_rect();
+    let dest = Rect(Point2D(au::to_px(origin.x) as AzFloat, au::to_px(origin.y) as AzFloat),
+                    size).as_azure_rect();
+    let surface_options: AzDrawSurfaceOptions = {
+        mFilter: 0 as AzFilter,
+        fields: 0 as uint16_t
+    };
+    let options: AzDrawOptions = {
+        mAlpha: alpha as AzFloat,
+        fields: 0 as uint16_t
+    };
+
+    let snapshot = AzDrawTargetGetSnapshot(layer.azure_draw_target);
+    AzDrawTargetDrawSurface(ctx.canvas.azure_draw_target, snapshot, addr_of(dest),
+                            addr_of(source), addr_of(surface_options), addr_of(options));
+    AzReleaseSourceSurface(snapshot);
+}
+
 pub fn draw_image(ctx: &RenderContext, bounds: Rect<au>, image: ARC<~Image>) {
     let image = std::arc::get(&image);
     let size = Size2D(image.width as i32, image.height as i32);
@@ -146,8 +802,7 @@ pub fn draw_glyphs(ctx: &RenderContext, bounds: Rect<au>, text_run: &GlyphRun) {
                             AzReleaseColorPattern};
     use azure::cairo::bindgen::cairo_scaled_font_destroy;
 
-    // FIXME: font should be accessible from GlyphRun
-    let font = ctx.font_cache.get_test_font();
+    let font = ctx.font_cache.get_font(&text_run.font);
 
     let nfont: AzNativeFont = {
         mType: AZ_NATIVE_FONT_CAIRO_FONT_FACE,
@@ -160,10 +815,10 @@ pub fn draw_glyphs(ctx: &RenderContext, bounds: Rect<au>, text_run: &GlyphRun) {
     cairo_scaled_font_destroy(cfont);
 
     let color = {
-        r: 0f as AzFloat,
-        g: 0f as AzFloat,
-        b: 0f as AzFloat,
-        a: 1f as AzFloat
+        r: text_run.color.red.to_float() as AzFloat,
+        g: text_run.color.green.to_float() as AzFloat,
+        b: text_run.color.blue.to_float() as AzFloat,
+        a: text_run.color.alpha as AzFloat
     };
     let pattern = AzCreateColorPattern(addr_of(color));
     assert pattern.is_not_null();
@@ -173,28 +828,38 @@ pub fn draw_glyphs(ctx: &RenderContext, bounds: Rect<au>, text_run: &GlyphRun) {
         fields: 0 as uint16_t
     };
 
-    let mut origin = Point2D(bounds.origin.x, bounds.origin.y.add(bounds.size.height));
-    let azglyphs = text_run.glyphs.map(|glyph| {
-        let azglyph: AzGlyph = {
-            mIndex: glyph.index as uint32_t,
-            mPosition: {
-                x: au::to_px(origin.x.add(glyph.pos.offset.x)) as AzFloat,
-                y: au::to_px(origin.y.add(glyph.pos.offset.y)) as AzFloat
-            }
-        };
-        origin = Point2D(origin.x.add(glyph.pos.advance.x),
-                         origin.y.add(glyph.pos.advance.y));
-        azglyph
-    });
-
-    let glyphbuf: AzGlyphBuffer = unsafe {{
-        mGlyphs: to_ptr(azglyphs),
-        mNumGlyphs: azglyphs.len() as uint32_t            
-    }};
+    // Glyphs are made bolder by drawing them again a little to the right
+    let strikes = if font.synthetic_bold {
+        ~[0f, float::fmax(1f, font.descriptor.size / 24f)]
+    } else {
+        ~[0f]
+    };
 
-    // TODO: this call needs to move into azure_hl.rs
-    AzDrawTargetFillGlyphs(ctx.canvas.azure_draw_target, azfont, addr_of(glyphbuf),
-                           pattern, addr_of(options), null());
+    for strikes.each |strike_offset| {
+        let mut origin = Point2D(bounds.origin.x.add(au::from_frac_px(*strike_offset)),
+                                 bounds.origin.y.add(text_run.baseline));
+        let azglyphs = text_run.glyphs.map(|glyph| {
+            let azglyph: AzGlyph = {
+                mIndex: glyph.index as uint32_t,
+                mPosition: {
+                    x: au::to_px(origin.x.add(glyph.pos.offset.x)) as AzFloat,
+                    y: au::to_px(origin.y.add(glyph.pos.offset.y)) as AzFloat
+                }
+            };
+            origin = Point2D(origin.x.add(glyph.pos.advance.x),
+                             origin.y.add(glyph.pos.advance.y));
+            azglyph
+        });
+
+        let glyphbuf: AzGlyphBuffer = unsafe {{
+            mGlyphs: to_ptr(azglyphs),
+            mNumGlyphs: azglyphs.len() as uint32_t
+        }};
+
+        // TODO: this call needs to move into azure_hl.rs
+        AzDrawTargetFillGlyphs(ctx.canvas.azure_draw_target, azfont, addr_of(glyphbuf),
+                               pattern, addr_of(options), null());
+    }
 
     AzReleaseColorPattern(pattern);
     AzReleaseScaledFont(azfont);
@@ -228,7 +893,6 @@ fn get_cairo_font(font: &Font) -> *cairo_scaled_font_t {
     use azure::cairo;
     use cairo::cairo_matrix_t;
     use cairo::bindgen::{cairo_matrix_init_identity,
-                         cairo_matrix_scale,
                          cairo_font_options_create,
                          cairo_scaled_font_create,
                          cairo_font_options_destroy,
@@ -248,9 +912,18 @@ fn get_cairo_font(font: &Font) -> *cairo_scaled_font_t {
     };
     cairo_matrix_init_identity(addr_of(idmatrix));
 
-    let fontmatrix = idmatrix;
-    cairo_matrix_scale(addr_of(fontmatrix),
-                       20f as c_double, 20f as c_double);
+    // Scale the glyphs to the size of the font, slanting them to the right
+    // if it has to be made italic
+    let size = font.descriptor.size;
+    let slant = if font.synthetic_italic { -0.2f * size } else { 0f };
+    let fontmatrix: cairo_matrix_t = {
+        xx: size as c_double,
+        yx: 0 as c_double,
+        xy: slant as c_double,
+        yy: size as c_double,
+        x0: 0 as c_double,
+        y0: 0 as c_double
+    };
     let options = cairo_font_options_create();
     let cfont = cairo_scaled_font_create(face, addr_of(fontmatrix),
                                          addr_of(idmatrix), options);
@@ -260,8 +933,44 @@ fn get_cairo_font(font: &Font) -> *cairo_scaled_font_t {
     return cfont;
 }
 
-fn clear(ctx: &RenderContext) {
-    let pattern = ColorPattern(Color(1f as AzFloat, 1f as AzFloat, 1f as AzFloat, 1f as AzFloat));
-    let rect = Rect(Point2D(0 as AzFloat, 0 as AzFloat), Size2D(800 as AzFloat, 600 as AzFloat));
-    ctx.canvas.fill_rect(rect, pattern);
+#[test]
+fn should_redraw_only_the_tiles_covered_by_changed_items() {
+    use dl::{SolidColor, PushClip, PopClip};
+
+    let rect = |x: int, y: int| Rect(Point2D(au::from_px(x), au::from_px(y)),
+                                     Size2D(au::from_px(10), au::from_px(10)));
+    let left_tile = Rect(Point2D(au(0), au(0)), Size2D(au::from_px(256), au::from_px(256)));
+    let right_tile = Rect(Point2D(au::from_px(256), au(0)),
+                          Size2D(au::from_px(256), au::from_px(256)));
+
+    let last_list = ~[~PushClip(rect(0, 0)), ~SolidColor(rect(10, 10), 0u8, 0u8, 0u8),
+                      ~SolidColor(rect(300, 10), 0u8, 0u8, 0u8), ~PopClip()];
+    let list = ~[~PushClip(rect(0, 0)), ~SolidColor(rect(10, 10), 0u8, 0u8, 0u8),
+                 ~SolidColor(rect(300, 10), 255u8, 0u8, 0u8), ~PopClip()];
+
+    let last_bounds = device_bounds(last_list, &dl::identity_transform());
+    let bounds = device_bounds(list, &dl::identity_transform());
+    assert items_covering(bounds, &left_tile) == ~[0u, 1u, 3u];
+    assert items_covering(bounds, &right_tile) == ~[0u, 2u, 3u];
+
+    assert same_items(last_list, items_covering(last_bounds, &left_tile),
+                      list, items_covering(bounds, &left_tile));
+    assert !same_items(last_list, items_covering(last_bounds, &right_tile),
+                       list, items_covering(bounds, &right_tile));
+}
+
+#[test]
+fn should_cover_the_view_with_tiles_of_the_document() {
+    let view = Rect(Point2D(au::from_px(100), au::from_px(300)),
+                    Size2D(au::from_px(400), au::from_px(256)));
+    // Columns 0 and 1, and rows 1 and 2
+    assert tile_range(&view) == (0u, 2u, 1u, 3u);
+
+    let old_tiles = ~[Tile(1u, 1u)];
+    old_tiles[0].valid = true;
+    let tiles = tiles_in_view(&view, old_tiles);
+    assert tiles.len() == 4u;
+    assert tiles[1].column == 1u && tiles[1].row == 1u && tiles[1].valid;
+    assert !tiles[2].valid;
+    assert tiles[2].rect.origin == Point2D(au(0), au::from_px(512));
 }
@@ -1,4 +1,11 @@
 use geom::size::Size2D;
+use azure::cairo;
+use azure::cairo_hl::ImageSurface;
+use cairo_bg = cairo::bindgen;
+use cairo_bg::{cairo_surface_flush, cairo_surface_mark_dirty, cairo_image_surface_get_data,
+               cairo_image_surface_get_width, cairo_image_surface_get_height,
+               cairo_image_surface_get_stride};
+use cast::reinterpret_cast;
 
 enum format {
     fo_rgba_8888
@@ -38,3 +45,85 @@ fn image_surface(size: Size2D<int>, format: format) -> image_surface {
     }
 }
 
+/**
+Copies the pixels of a cairo ARGB32 surface. Cairo keeps each pixel as a
+native-endian word with premultiplied alpha; the copy is straight RGBA bytes.
+*/
+fn image_surface_from_cairo(surface: &ImageSurface) -> image_surface unsafe {
+    let cairo_surface = surface.cairo_surface;
+    cairo_surface_flush(cairo_surface);
+
+    let width = cairo_image_surface_get_width(cairo_surface) as uint;
+    let height = cairo_image_surface_get_height(cairo_surface) as uint;
+    let stride = cairo_image_surface_get_stride(cairo_surface) as uint;
+    let data = cairo_image_surface_get_data(cairo_surface);
+
+    let mut buffer = vec::from_elem(width * height * 4u, 0u8);
+    for uint::range(0u, height) |y| {
+        for uint::range(0u, width) |x| {
+            let word: *u32 = reinterpret_cast(&ptr::offset(data, y * stride + x * 4u));
+            let pixel = *word;
+            let a = (pixel >> 24) as u8;
+            let i = (y * width + x) * 4u;
+            buffer[i] = unpremultiply((pixel >> 16) as u8, a);
+            buffer[i + 1u] = unpremultiply((pixel >> 8) as u8, a);
+            buffer[i + 2u] = unpremultiply(pixel as u8, a);
+            buffer[i + 3u] = a;
+        }
+    }
+
+    {
+        size: Size2D(width as int, height as int),
+        format: fo_rgba_8888,
+        buffer: move buffer
+    }
+}
+
+/// The inverse of `image_surface_from_cairo`
+fn cairo_from_image_surface(surface: &image_surface) -> ImageSurface unsafe {
+    assert surface.format == fo_rgba_8888;
+
+    let result = ImageSurface(cairo::CAIRO_FORMAT_ARGB32, surface.size.width,
+                              surface.size.height);
+    let cairo_surface = result.cairo_surface;
+    cairo_surface_flush(cairo_surface);
+
+    let width = surface.size.width as uint;
+    let stride = cairo_image_surface_get_stride(cairo_surface) as uint;
+    let data = cairo_image_surface_get_data(cairo_surface);
+
+    for uint::range(0u, surface.size.height as uint) |y| {
+        for uint::range(0u, width) |x| {
+            let i = (y * width + x) * 4u;
+            let a = surface.buffer[i + 3u];
+            let pixel = (a as u32 << 24) |
+                (premultiply(surface.buffer[i], a) as u32 << 16) |
+                (premultiply(surface.buffer[i + 1u], a) as u32 << 8) |
+                (premultiply(surface.buffer[i + 2u], a) as u32);
+            let word: *mut u32 = reinterpret_cast(&ptr::offset(data, y * stride + x * 4u));
+            *word = pixel;
+        }
+    }
+
+    cairo_surface_mark_dirty(cairo_surface);
+    move result
+}
+
+pure fn premultiply(c: u8, a: u8) -> u8 {
+    ((c as uint * (a as uint) + 127u) / 255u) as u8
+}
+
+pure fn unpremultiply(c: u8, a: u8) -> u8 {
+    if a == 0u8 {
+        0u8
+    } else {
+        uint::min(255u, (c as uint * 255u + (a as uint) / 2u) / (a as uint)) as u8
+    }
+}
+
+#[test]
+fn should_undo_premultiplied_alpha() {
+    assert unpremultiply(premultiply(200u8, 255u8), 255u8) == 200u8;
+    assert unpremultiply(premultiply(255u8, 128u8), 128u8) == 255u8;
+    assert unpremultiply(0u8, 0u8) == 0u8;
+}
@@ -10,6 +10,7 @@ use resource::image_cache_task;
 use resource::resource_task::{Done, Load, Payload, ResourceTask};
 
 use comm::{Chan, Port};
+use dvec::DVec;
 use str::from_slice;
 use cast::reinterpret_cast;
 use std::net::url::Url;
@@ -18,6 +19,10 @@ type JSResult = ~[~[u8]];
 
 enum CSSMessage {
     CSSTaskNewFile(Url),
+    // Reserves a place, in document order, for the sheet of a <style> element
+    CSSTaskNewInlineSheet,
+    // The contents of the next reserved <style> element, and the document's URL
+    CSSTaskInlineSheetText(~str, Url),
     CSSTaskExit   
 }
 
@@ -50,6 +55,8 @@ spawned, collates them, and sends them to the given result channel.
 fn css_link_listener(to_parent : comm::Chan<Stylesheet>, from_parent : comm::Port<CSSMessage>,
                      resource_task: ResourceTask) {
     let mut result_vec = ~[];
+    let mut inline_chans = ~[];
+    let mut next_inline = 0u;
 
     loop {
         match from_parent.recv() {
@@ -60,13 +67,28 @@ fn css_link_listener(to_parent : comm::Chan<Stylesheet>, from_parent : comm::Por
             let url = copy url;
             task::spawn(|| {
                 // TODO: change copy to move once we can move into closures
-                let css_stream = css::lexer::spawn_css_lexer_task(copy url, resource_task);
-                let mut css_rules = css::parser::build_stylesheet(css_stream);
+                let css_rules = css::parser::load_stylesheet(copy url, resource_task);
                 result_chan.send(css_rules);
             });
 
             vec::push(result_vec, result_port);
           }
+          CSSTaskNewInlineSheet => {
+            let result_port = comm::Port();
+            vec::push(inline_chans, comm::Chan(result_port));
+            vec::push(result_vec, result_port);
+          }
+          CSSTaskInlineSheetText(text, base_url) => {
+            let result_chan = inline_chans[next_inline];
+            next_inline += 1u;
+            // TODO: change copy to move once we have match move
+            let (text, base_url) = (copy text, copy base_url);
+            task::spawn(|| {
+                let css_rules = css::parser::load_inline_stylesheet(copy text, copy base_url,
+                                                                    resource_task);
+                result_chan.send(css_rules);
+            });
+          }
           CSSTaskExit => {
             break;
           }
@@ -184,6 +206,8 @@ fn parse_html(scope: NodeScope,
     };
 
     let (scope, url) = (@copy scope, @copy url);
+    // The <style> elements, in document order
+    let style_nodes: @DVec<Node> = @DVec();
 
     // Build the root node.
     let root = scope.new_node(Element(ElementData(~"html", ~HTMLDivElement)));
@@ -222,6 +246,8 @@ fn parse_html(scope: NodeScope,
                 elem.attrs.push(~Attr(from_slice(attribute.name),
                                       from_slice(attribute.value)));
             }
+            elem.update_style_attribute();
+            let is_style = match elem.kind { ~HTMLStyleElement => true, _ => false };
 
             // Spawn additional parsing, network loads, etc. from tag and attrs
             match elem.kind {
@@ -244,10 +270,12 @@ fn parse_html(scope: NodeScope,
                         image_cache_task.send(image_cache_task::Prefetch(move img_url));
                     }
                 }
-                //TODO (Issue #86): handle inline styles ('style' attr)
+                // Its sheet is parsed once its contents are known
+                ~HTMLStyleElement => css_chan.send(CSSTaskNewInlineSheet),
                 _ => {}
             }
             let node = scope.new_node(Element(elem));
+            if is_style { style_nodes.push(node); }
             unsafe { reinterpret_cast(&node) }
         },
         create_text: |data| {
@@ -334,6 +362,19 @@ fn parse_html(scope: NodeScope,
         }
     }
 
+    for style_nodes.each |node| {
+        let mut text = ~"";
+        for scope.each_child(*node) |child| {
+            do scope.read(child) |child_contents| {
+                match *child_contents.kind {
+                    Text(ref data) => text += *data,
+                    _ => {}
+                }
+            }
+        }
+        css_chan.send(CSSTaskInlineSheetText(move text, copy *url));
+    }
+
     css_chan.send(CSSTaskExit);
     js_chan.send(JSTaskExit);
 
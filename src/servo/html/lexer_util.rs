@@ -34,6 +34,7 @@ type InputState = {
 trait U8Methods {
     fn is_whitespace() -> bool;
     fn is_alpha() -> bool;
+    fn is_digit() -> bool;
 }
 
 impl u8 : U8Methods {
@@ -45,6 +46,10 @@ impl u8 : U8Methods {
         return (self >= ('A' as u8) && self <= ('Z' as u8)) ||
                (self >= ('a' as u8) && self <= ('z' as u8));
     }
+
+    fn is_digit() -> bool {
+        return self >= ('0' as u8) && self <= ('9' as u8);
+    }
 }
 
 trait InputStateUtil {
@@ -114,7 +119,12 @@ impl InputState : InputStateUtil {
         loop {
             match self.get() {
               CoeChar(c) => {
+                // Identifiers start with a letter, but may continue with digits
+                // and dashes, as in 'h1' or 'font-size'
                 if (c.is_alpha()) { push(result, c); }
+                else if result.len() > 0u && (c.is_digit() || c == '-' as u8) {
+                    push(result, c);
+                }
                 else if result.len() == 0u { self.parse_err(~"expected ident"); }
                 else {
                     self.unget(c);
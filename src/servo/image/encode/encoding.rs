@@ -0,0 +1,115 @@
+//! The image formats a rendered frame can be written in
+
+use geom::size::Size2D;
+use gfx::surface::{image_surface, fo_rgba_8888};
+use image::encode::{png, tga, ppm, raw};
+
+enum Encoding {
+    PngEncoding,
+    TgaEncoding,
+    PpmEncoding,
+    // Bare pixels, for tools that do their own decoding
+    RawRgbaEncoding,
+    RawBgraEncoding
+}
+
+impl Encoding : cmp::Eq {
+    pure fn eq(other: &Encoding) -> bool {
+        (self as uint) == (*other as uint)
+    }
+    pure fn ne(other: &Encoding) -> bool {
+        !self.eq(other)
+    }
+}
+
+impl Encoding {
+    // Fails when the format cannot hold an image of the surface's size
+    fn encode(writer: io::Writer, surface: &image_surface) -> Result<(), ~str> {
+        match self {
+          PngEncoding => png::encode(writer, surface),
+          TgaEncoding => return tga::encode(writer, surface),
+          PpmEncoding => ppm::encode(writer, surface),
+          RawRgbaEncoding => raw::encode_rgba(writer, surface),
+          RawBgraEncoding => raw::encode_bgra(writer, surface)
+        }
+        Ok(())
+    }
+
+    fn encode_to_bytes(surface: &image_surface) -> Result<~[u8], ~str> {
+        let mut result = Ok(());
+        let bytes = io::with_bytes_writer(|writer| result = self.encode(writer, surface));
+        match move result {
+          Ok(()) => Ok(move bytes),
+          Err(move e) => Err(e)
+        }
+    }
+}
+
+// The names accepted by --format, which are also the file extensions
+fn from_name(name: &str) -> Option<Encoding> {
+    match str::to_lower(name) {
+      ~"png" => Some(PngEncoding),
+      ~"tga" => Some(TgaEncoding),
+      ~"ppm" => Some(PpmEncoding),
+      ~"rgba" | ~"raw" => Some(RawRgbaEncoding),
+      ~"bgra" => Some(RawBgraEncoding),
+      _ => None
+    }
+}
+
+fn from_path(path: &str) -> Option<Encoding> {
+    match Path(path).filetype() {
+      Some(extension) => from_name(extension.slice(1u, extension.len())),
+      None => None
+    }
+}
+
+#[cfg(test)]
+fn test_surface() -> image_surface {
+    // A red pixel and a half transparent blue one
+    {
+        size: Size2D(2, 1),
+        format: fo_rgba_8888,
+        buffer: ~[255u8, 0u8, 0u8, 255u8, 0u8, 0u8, 255u8, 128u8]
+    }
+}
+
+#[test]
+fn should_pick_the_encoding_from_the_file_extension() {
+    assert from_path("out.png") == Some(PngEncoding);
+    assert from_path("shots/out.TGA") == Some(TgaEncoding);
+    assert from_path("out.ppm") == Some(PpmEncoding);
+    assert from_path("out.bgra") == Some(RawBgraEncoding);
+    assert from_path("out.raw") == Some(RawRgbaEncoding);
+    assert from_path("out") == None;
+}
+
+#[test]
+fn should_write_ppm_without_alpha() {
+    let bytes = PpmEncoding.encode_to_bytes(&test_surface()).get();
+    let header = str::to_bytes("P6\n2 1\n255\n");
+    assert bytes == header + ~[255u8, 0u8, 0u8, 0u8, 0u8, 255u8];
+}
+
+#[test]
+fn should_write_raw_pixels_in_either_order() {
+    let surface = test_surface();
+    assert RawRgbaEncoding.encode_to_bytes(&surface).get() == copy surface.buffer;
+    assert RawBgraEncoding.encode_to_bytes(&surface).get() ==
+        ~[0u8, 0u8, 255u8, 255u8, 255u8, 0u8, 0u8, 128u8];
+}
+
+#[test]
+fn should_write_tga_pixels_after_the_header() {
+    let bytes = TgaEncoding.encode_to_bytes(&test_surface()).get();
+    assert bytes.len() == 18u + 8u;
+    assert bytes[12] == 2u8 && bytes[14] == 1u8;
+    assert bytes.slice(18u, 26u) == ~[0u8, 0u8, 255u8, 255u8, 255u8, 0u8, 0u8, 128u8];
+}
+
+#[test]
+fn should_refuse_tga_images_too_tall_for_the_header() {
+    let surface = image_surface(Size2D(1, 65536), fo_rgba_8888);
+    assert TgaEncoding.encode_to_bytes(&surface).is_err();
+    assert PpmEncoding.encode_to_bytes(&surface).is_ok();
+}
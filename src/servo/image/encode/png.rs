@@ -0,0 +1,11 @@
+//! PNG, written by cairo
+
+use gfx::surface;
+use core::io::BytesWriter;
+
+fn encode(writer: io::Writer, surface: &surface::image_surface) {
+    let cairo_surface = surface::cairo_from_image_surface(surface);
+    let buffer = BytesWriter();
+    cairo_surface.write_to_png_stream(&buffer);
+    writer.write(buffer.buf.get());
+}
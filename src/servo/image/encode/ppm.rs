@@ -0,0 +1,19 @@
+//! Binary PPM (P6). The format has no alpha channel, so it is dropped.
+
+use gfx::surface;
+use io::WriterUtil;
+
+fn encode(writer: io::Writer, surface: &surface::image_surface) {
+    assert surface.format == gfx::surface::fo_rgba_8888;
+
+    writer.write_str(fmt!("P6\n%d %d\n255\n", surface.size.width, surface.size.height));
+
+    let pixels = surface.buffer.len() / 4u;
+    let mut rgb = vec::with_capacity(pixels * 3u);
+    for uint::range(0u, pixels) |i| {
+        rgb.push(surface.buffer[i * 4u]);
+        rgb.push(surface.buffer[i * 4u + 1u]);
+        rgb.push(surface.buffer[i * 4u + 2u]);
+    }
+    writer.write(rgb);
+}
@@ -0,0 +1,22 @@
+//! The bare pixels, row by row from the top left, with no header. The reader
+//! has to know the size of the image.
+
+use gfx::surface;
+
+fn encode_rgba(writer: io::Writer, surface: &surface::image_surface) {
+    assert surface.format == gfx::surface::fo_rgba_8888;
+    writer.write(surface.buffer);
+}
+
+fn encode_bgra(writer: io::Writer, surface: &surface::image_surface) {
+    assert surface.format == gfx::surface::fo_rgba_8888;
+
+    let mut bgra = vec::with_capacity(surface.buffer.len());
+    for uint::range(0u, surface.buffer.len() / 4u) |i| {
+        bgra.push(surface.buffer[i * 4u + 2u]);
+        bgra.push(surface.buffer[i * 4u + 1u]);
+        bgra.push(surface.buffer[i * 4u]);
+        bgra.push(surface.buffer[i * 4u + 3u]);
+    }
+    writer.write(bgra);
+}
@@ -1,8 +1,16 @@
 use gfx::surface;
 use io::WriterUtil;
+use image::encode::raw;
 
-fn encode(writer: io::Writer, surface: surface::image_surface) {
+// The header holds the size in 16 bits
+const MAX_DIMENSION: int = 65535;
+
+fn encode(writer: io::Writer, surface: &surface::image_surface) -> Result<(), ~str> {
     assert surface.format == gfx::surface::fo_rgba_8888;
+    if surface.size.width > MAX_DIMENSION || surface.size.height > MAX_DIMENSION {
+        return Err(fmt!("%dx%d is too large for a TGA image, which is at most %d pixels wide \
+                         and high", surface.size.width, surface.size.height, MAX_DIMENSION));
+    }
 
     writer.write_u8(0u8);                               // identsize
     writer.write_u8(0u8);                               // colourmaptype
@@ -10,15 +18,16 @@ fn encode(writer: io::Writer, surface: surface::image_surface) {
 
     writer.write_le_u16(0u16);                          // colourmapstart
     writer.write_le_u16(0u16);                          // colourmaplength
-    writer.write_u8(16u8);                              // colourmapbits
+    writer.write_u8(0u8);                               // colourmapbits
 
     writer.write_le_u16(0u16);                          // xstart
     writer.write_le_u16(0u16);                          // ystart
     writer.write_le_u16(surface.size.width as u16);     // width
     writer.write_le_u16(surface.size.height as u16);    // height
     writer.write_u8(32u8);                              // bits
-    writer.write_u8(0x30u8);                            // descriptor
+    writer.write_u8(0x28u8);                            // descriptor: 8 alpha bits, top-left origin
 
-    writer.write(surface.buffer);
+    // TGA pixels are BGRA
+    raw::encode_bgra(writer, surface);
+    Ok(())
 }
-
@@ -0,0 +1,179 @@
+use au = gfx::geometry;
+use css::styles::StyleMethods;
+use css::values::*;
+use dl = gfx::display_list;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use gfx::geometry::au;
+use layout::box::{RenderBox, used_length, solve_absolute_offsets};
+use layout::context::LayoutContext;
+use layout::flow::{FlowContext, FlowTree, AbsoluteFlow};
+use util::tree;
+
+/* Absolutely positioned boxes (CSS 2.1, Section 9.6) are taken out of
+normal flow entirely. Their flows are children of the flow of their
+containing block: the nearest positioned block, or the viewport for
+'position: fixed' and when there is none. That flow places them
+against its padding box once its own height is known.
+
+Offsets that are 'auto' leave the box at its static position (CSS 2.1,
+Section 10.3.7): where it would have been in normal flow, below the in-flow
+flow before it in the flow it was found in. */
+
+struct AbsoluteFlowData {
+    mut box: Option<@RenderBox>,
+    // The flow the box would have been in, and the in-flow child of it before the box
+    mut static_parent: Option<@FlowContext>,
+    mut static_after: Option<@FlowContext>
+}
+
+fn AbsoluteFlowData() -> AbsoluteFlowData {
+    AbsoluteFlowData {
+        box: None,
+        static_parent: None,
+        static_after: None
+    }
+}
+
+trait AbsoluteLayout {
+    pure fn starts_absolute_flow() -> bool;
+    pure fn access_absolute<T>(fn(&&AbsoluteFlowData) -> T) -> T;
+
+    fn bubble_widths_absolute(ctx: &LayoutContext);
+    fn assign_widths_absolute(ctx: &LayoutContext);
+    fn assign_height_absolute(ctx: &LayoutContext);
+    fn place_absolute(containing_block: &Rect<au>);
+    fn static_position() -> Point2D<au>;
+
+    fn build_display_list_absolute(a: &dl::DisplayListBuilder, b: &Rect<au>, c: &Point2D<au>, d: &dl::DisplayList);
+}
+
+impl @FlowContext : AbsoluteLayout {
+
+    pure fn starts_absolute_flow() -> bool {
+        match self.kind {
+            AbsoluteFlow(*) => true,
+            _ => false
+        }
+    }
+
+    pure fn access_absolute<T>(cb:fn(&&AbsoluteFlowData) -> T) -> T {
+        match self.kind {
+            AbsoluteFlow(d) => cb(d),
+            _  => fail fmt!("Tried to access() data of AbsoluteFlow, but this is a %?", self.kind)
+        }
+    }
+
+    /* defer to the block algorithm, which shrinks absolutely positioned
+       boxes to fit their contents and offsets them horizontally */
+    fn bubble_widths_absolute(ctx: &LayoutContext) {
+        assert self.starts_absolute_flow();
+        self.bubble_widths_block(ctx)
+    }
+
+    fn assign_widths_absolute(ctx: &LayoutContext) {
+        assert self.starts_absolute_flow();
+        self.assign_widths_block(ctx)
+    }
+
+    /* An absolutely positioned box starts a block formatting context, so
+       its contents can be laid out before its containing block places it. */
+    fn assign_height_absolute(ctx: &LayoutContext) {
+        assert self.starts_absolute_flow();
+        self.assign_height_block(ctx)
+    }
+
+    /* Offsets the flow vertically within its containing block, whose padding
+    box is given in the coordinates of the parent flow (CSS 2.1, Section
+    10.6.4). An 'auto' height that is fixed by 'top' and 'bottom' stretches
+    the box; otherwise it keeps the height of its contents. */
+    fn place_absolute(containing_block: &Rect<au>) {
+        assert self.starts_absolute_flow();
+
+        let containing_height = containing_block.size.height;
+        let containing_width = containing_block.size.width;
+        let static_top = self.static_position().y.sub(containing_block.origin.y);
+        let mut top = au(0);
+        do self.with_block_box |box| {
+            let style = box.node.computed_style();
+            let (top_fringe, bottom_fringe) = box.get_used_height();
+            let content_height = box.data.position.size.height.sub(top_fringe.add(bottom_fringe));
+            let height = match style.height {
+                BoxLength(*) => Some(content_height),
+                BoxPercent(*) | BoxAuto => None
+            };
+
+            // Percentage margins refer to the width of the containing block
+            let (offset, margin_top, height, margin_bottom) =
+                solve_absolute_offsets(containing_height,
+                                       used_length(style.top, containing_height),
+                                       height,
+                                       used_length(style.bottom, containing_height),
+                                       used_length(style.margin_top, containing_width),
+                                       used_length(style.margin_bottom, containing_width),
+                                       top_fringe.add(bottom_fringe), content_height,
+                                       static_top);
+
+            // The contents move down with a top margin that has grown
+            let shift = margin_top.sub(box.data.margin.top);
+            for FlowTree.each_child(self) |child_ctx| {
+                child_ctx.data.position.origin.y = child_ctx.data.position.origin.y.add(shift);
+            }
+
+            top = offset;
+            box.data.margin.top = margin_top;
+            box.data.margin.bottom = margin_bottom;
+            box.data.position.origin.y = margin_top;
+            box.data.position.size.height = top_fringe.add(height).add(bottom_fringe);
+            self.data.position.size.height = box.margin_box().size.height;
+        }
+
+        self.data.position.origin.y = containing_block.origin.y.add(top);
+    }
+
+    /* Where the box would have been in normal flow, in the coordinates of
+    its parent flow, which holds its containing block. It is at the content
+    edge of the flow it was found in, below the flow before it there. */
+    fn static_position() -> Point2D<au> {
+        assert self.starts_absolute_flow();
+
+        let (static_parent, static_after) = do self.access_absolute |d| {
+            (d.static_parent, d.static_after)
+        };
+        let mut position = Point2D(au(0), au(0));
+        let static_parent = match static_parent {
+            Some(flow) => flow,
+            None => return position
+        };
+
+        do static_parent.with_block_box |box| {
+            position = box.content_box().origin;
+        }
+        match static_after {
+            Some(flow) => {
+                position.y = flow.data.position.origin.y.add(flow.data.position.size.height)
+            }
+            None => {}
+        }
+
+        // Up to the coordinates of the parent flow
+        let containing = tree::get_parent(FlowTree, self);
+        let mut flow = Some(static_parent);
+        loop {
+            match (flow, containing) {
+                (Some(f), Some(c)) if !f.eq(&c) => {
+                    position = position.add(&f.data.position.origin);
+                    flow = tree::get_parent(FlowTree, f);
+                }
+                _ => break
+            }
+        }
+        position
+    }
+
+    fn build_display_list_absolute(builder: &dl::DisplayListBuilder, dirty: &Rect<au>,
+                                   offset: &Point2D<au>, list: &dl::DisplayList) {
+        assert self.starts_absolute_flow();
+        self.build_display_list_block(builder, dirty, offset, list)
+    }
+}
@@ -1,22 +1,29 @@
 use au = gfx::geometry;
+use css::styles::StyleMethods;
 use css::values::*;
 use dl = gfx::display_list;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 use gfx::geometry::au;
-use layout::box::{RenderBox};
+use layout::box::{RenderBox, collapse_margins};
 use layout::context::LayoutContext;
-use layout::flow::{FlowContext, FlowTree, InlineBlockFlow, BlockFlow, RootFlow};
+use layout::floats::FloatContext;
+use layout::flow::{FlowContext, FlowTree, InlineBlockFlow, BlockFlow, FloatFlow, InlineFlow};
+use layout::flow::{AbsoluteFlow, RootFlow, TableFlow, TableRowFlow, TableCellFlow};
 use util::tree;
 
 struct BlockFlowData {
-    mut box: Option<@RenderBox>
+    mut box: Option<@RenderBox>,
+    // The top margin once collapsed with those of the first children. See
+    // assign_height_block.
+    mut collapsed_top: au
 }
 
 fn BlockFlowData() -> BlockFlowData {
     BlockFlowData {
-        box: None
+        box: None,
+        collapsed_top: au(0)
     }
 }
 
@@ -27,7 +34,12 @@ trait BlockLayout {
 
     fn bubble_widths_block(ctx: &LayoutContext);
     fn assign_widths_block(ctx: &LayoutContext);
+    pure fn starts_block_formatting_context() -> bool;
+    pure fn collapsible_margins() -> (au, au);
+    pure fn collapsed_top_margin() -> au;
+    fn clear_type() -> CSSClear;
     fn assign_height_block(ctx: &LayoutContext);
+    fn layout_block(ctx: &LayoutContext, +floats: FloatContext) -> FloatContext;
 
     fn build_display_list_block(a: &dl::DisplayListBuilder, b: &Rect<au>,
                                 c: &Point2D<au>, d: &dl::DisplayList);
@@ -37,11 +49,23 @@ impl @FlowContext : BlockLayout {
 
     pure fn starts_block_flow() -> bool {
         match self.kind {
-            RootFlow(*) | BlockFlow(*) | InlineBlockFlow(*) => true,
+            RootFlow(*) | BlockFlow(*) | FloatFlow(*) | AbsoluteFlow(*)
+            | InlineBlockFlow(*) | TableFlow(*) | TableCellFlow(*) => true,
             _ => false 
         }
     }
 
+    /* The root, floats, absolutely positioned boxes, inline-blocks, tables
+    and table cells start new block formatting contexts (CSS 2.1, Section
+    9.4.1): floats outside them do not affect their contents. */
+    pure fn starts_block_formatting_context() -> bool {
+        match self.kind {
+            RootFlow(*) | FloatFlow(*) | AbsoluteFlow(*) | InlineBlockFlow(*) | TableFlow(*)
+            | TableCellFlow(*) => true,
+            _ => false
+        }
+    }
+
     pure fn access_block<T>(cb:fn(&&BlockFlowData) -> T) -> T {
         match self.kind {
             BlockFlow(d) => cb(d),
@@ -50,7 +74,8 @@ impl @FlowContext : BlockLayout {
     }
 
     /* Get the current flow's corresponding block box, if it exists, and do something with it. 
-       This works on both BlockFlow and RootFlow, since they are mostly the same. */
+       This works on all flows that lay out their children as blocks, since they are
+       mostly the same. */
     pure fn with_block_box(cb:fn(&&@RenderBox) -> ()) -> () {
         match self.kind {
             BlockFlow(*) => { 
@@ -65,6 +90,42 @@ impl @FlowContext : BlockLayout {
                     box.iter(cb)
                 }
             },
+            FloatFlow(*) => {
+                do self.access_float |d| {
+                    let mut box = d.box;
+                    box.iter(cb)
+                }
+            },
+            AbsoluteFlow(*) => {
+                do self.access_absolute |d| {
+                    let mut box = d.box;
+                    box.iter(cb)
+                }
+            },
+            InlineBlockFlow(*) => {
+                do self.access_inline_block |d| {
+                    let mut box = d.box;
+                    box.iter(cb)
+                }
+            },
+            TableFlow(*) => {
+                do self.access_table |d| {
+                    let mut box = d.box;
+                    box.iter(cb)
+                }
+            },
+            TableRowFlow(*) => {
+                do self.access_table_row |d| {
+                    let mut box = d.box;
+                    box.iter(cb)
+                }
+            },
+            TableCellFlow(*) => {
+                do self.access_table_cell |d| {
+                    let mut box = d.box;
+                    box.iter(cb)
+                }
+            },
             _  => fail fmt!("Tried to do something with_block_box(), but this is a %?", self.kind)
         }
     }
@@ -75,7 +136,7 @@ impl @FlowContext : BlockLayout {
     min/pref widths based on child context widths and dimensions of
     any boxes it is responsible for flowing.  */
 
-    /* TODO: floats */
+    /* TODO: side-by-side floats */
     /* TODO: absolute contexts */
     /* TODO: inline-blocks */
     fn bubble_widths_block(_ctx: &LayoutContext) {
@@ -84,12 +145,15 @@ impl @FlowContext : BlockLayout {
         let mut min_width = au(0);
         let mut pref_width = au(0);
 
-        /* find max width from child block contexts */
+        /* find max width from child block contexts. Absolutely
+           positioned children do not affect the size of this one. */
         for FlowTree.each_child(self) |child_ctx| {
             assert child_ctx.starts_block_flow() || child_ctx.starts_inline_flow();
 
-            min_width  = au::max(min_width, child_ctx.data.min_width);
-            pref_width = au::max(pref_width, child_ctx.data.pref_width);
+            if !child_ctx.starts_absolute_flow() {
+                min_width  = au::max(min_width, child_ctx.data.min_width);
+                pref_width = au::max(pref_width, child_ctx.data.pref_width);
+            }
         }
 
         /* if not an anonymous block context, add in block box's widths.
@@ -108,50 +172,317 @@ impl @FlowContext : BlockLayout {
     had its width set by the parent context.
 
     Dual boxes consume some width first, and the remainder is assigned to
-    all child (block) contexts. */
+    all child (block) contexts. Floats then shrink to fit within it. */
 
     fn assign_widths_block(_ctx: &LayoutContext) { 
         assert self.starts_block_flow();
 
         let mut remaining_width = self.data.position.size.width;
-        let mut _right_used = au(0);
         let mut left_used = au(0);
+        // Absolutely positioned children are placed against the padding box
+        let mut padding_left = au(0);
+        let mut padding_width = remaining_width;
 
-        /* Let the box consume some width. It will return the amount remaining
-           for its children. */
+        /* Let the box consume some width for its margins, borders and
+           padding. It will return the amount remaining for its children. */
         do self.with_block_box |box| {
-            box.data.position.size.width = remaining_width;
-            let (left_used, right_used) = box.get_used_width();
-            remaining_width = remaining_width.sub(left_used.add(right_used));
+            remaining_width = match self.kind {
+                // Inline-blocks shrink to fit like floats (CSS 2.1, Section 10.3.9)
+                FloatFlow(*) | InlineBlockFlow(*) => {
+                    let width = box.assign_float_widths(remaining_width, self.data.min_width,
+                                                        self.data.pref_width);
+                    self.data.position.size.width = box.margin_box().size.width;
+                    width
+                }
+                AbsoluteFlow(*) => {
+                    // The parent flow has put this one at its containing block's padding edge
+                    let static_left = self.static_position().x.sub(self.data.position.origin.x);
+                    let (offset, width) =
+                        box.assign_absolute_widths(remaining_width, self.data.min_width,
+                                                   self.data.pref_width, static_left);
+                    self.data.position.origin.x = self.data.position.origin.x.add(offset);
+                    self.data.position.size.width = box.margin_box().size.width;
+                    width
+                }
+                // The row has placed the cell in its columns
+                TableCellFlow(*) => box.assign_cell_widths(remaining_width),
+                _ => box.assign_block_widths(remaining_width)
+            };
+            let (left_fringe, _) = box.get_used_width();
+            left_used = box.data.margin.left.add(left_fringe);
+            padding_left = box.data.margin.left.add(box.data.border.left);
+            padding_width = remaining_width.add(box.data.padding.left).add(box.data.padding.right);
         }
 
         for FlowTree.each_child(self) |child_ctx| {
             assert child_ctx.starts_block_flow() || child_ctx.starts_inline_flow();
-            child_ctx.data.position.origin.x = left_used;
-            child_ctx.data.position.size.width = remaining_width;
+            if child_ctx.starts_absolute_flow() {
+                child_ctx.data.position.origin.x = padding_left;
+                child_ctx.data.position.size.width = padding_width;
+            } else {
+                child_ctx.data.position.origin.x = left_used;
+                child_ctx.data.position.size.width = remaining_width;
+            }
+        }
+    }
+
+    /* The top and bottom margins of this flow that can collapse with
+    those of its neighbours. They are already included in its height. */
+    pure fn collapsible_margins() -> (au, au) {
+        match self.kind {
+            BlockFlow(d) => match d.box {
+                Some(box) => (box.data.margin.top, box.data.margin.bottom),
+                None => (au(0), au(0))
+            },
+            _ => (au(0), au(0))
+        }
+    }
+
+    /* The top margin of this flow once it has collapsed with those of its
+    first children, which is needed to place the flow before laying out its
+    contents. It agrees with the margin that layout_block settles on. */
+    pure fn collapsed_top_margin() -> au {
+        match self.kind {
+            BlockFlow(d) => d.collapsed_top,
+            _ => au(0)
+        }
+    }
+
+    /* The sides whose floats this flow must be placed below */
+    fn clear_type() -> CSSClear {
+        let mut clear = ClearNone;
+        match self.kind {
+            BlockFlow(*) | FloatFlow(*) => {
+                do self.with_block_box |box| {
+                    clear = box.node.computed_style().clear;
+                }
+            }
+            _ => {}
         }
+        clear
     }
 
-    fn assign_height_block(_ctx: &LayoutContext) {
+    /* Blocks are laid out from the top down by the flow that starts their
+    block formatting context, since the floats beside them are not known
+    until then. See layout_block. Heights are assigned from the bottom up,
+    though, so this is where a block collapses its top margin with its
+    first child's, which has already done the same. */
+    fn assign_height_block(ctx: &LayoutContext) {
         assert self.starts_block_flow();
 
+        match self.kind {
+            BlockFlow(d) => {
+                let mut margin = au(0);
+                let mut collapses = false;
+                do self.with_block_box |box| {
+                    box.assign_block_vertical_edges(self.data.position.size.width);
+                    let (top_fringe, _) = box.get_used_height();
+                    margin = box.data.margin.top;
+                    collapses = top_fringe == au(0);
+                }
+
+                // Floats and absolutely positioned boxes are out of the flow, so
+                // the first child is the first in-flow one
+                let mut is_first = true;
+                for FlowTree.each_child(self) |child_ctx| {
+                    let in_flow = !child_ctx.starts_float_flow()
+                        && !child_ctx.starts_absolute_flow();
+                    if collapses && is_first && in_flow {
+                        margin = collapse_margins(margin, child_ctx.collapsed_top_margin());
+                        is_first = false;
+                    }
+                }
+
+                d.collapsed_top = margin;
+            }
+            _ => {}
+        }
+
+        if self.starts_block_formatting_context() {
+            self.layout_block(ctx, FloatContext());
+        }
+    }
+
+    /* Stacks the child flows vertically, collapsing adjoining vertical
+    margins (CSS 2.1, Section 8.3.1), places floats and lays out lines
+    beside them (Section 9.5), and sizes the flow around its children. The
+    flow's height includes its margins, which may have absorbed those of
+    its first and last children.
+
+    `floats` are those of the enclosing block formatting context, in the
+    coordinates of this flow's content box. Returns them along with those
+    placed inside this flow, for the following flows to flow around.
+    Relatively and absolutely positioned children are offset once the
+    flow's size is known. */
+    fn layout_block(ctx: &LayoutContext, +floats: FloatContext) -> FloatContext {
+        assert self.starts_block_flow();
+
+        let starts_context = self.starts_block_formatting_context();
+        let mut floats = if starts_context { FloatContext() } else { move floats };
+
+        // The margins of the root element's box and of floats do not collapse
+        // with their children's
+        let mut collapse_top = false;
+        let mut collapse_bottom = false;
+        let mut content_left = au(0);
+        let mut content_width = self.data.position.size.width;
+        do self.with_block_box |box| {
+            box.assign_block_vertical_edges(self.data.position.size.width);
+            let (top_fringe, bottom_fringe) = box.get_used_height();
+            let auto_height = match box.node.computed_style().height {
+                BoxLength(*) => false,
+                BoxPercent(*) | BoxAuto => true
+            };
+            collapse_top = !starts_context && top_fringe == au(0);
+            collapse_bottom = !starts_context && bottom_fringe == au(0) && auto_height;
+
+            let content_box = box.content_box();
+            content_left = content_box.origin.x;
+            content_width = content_box.size.width;
+        }
+
+        // Child positions are relative to the content edge until the end
         let mut cur_y = au(0);
+        let mut pending_margin = au(0);
+        let mut first_margin = au(0);
+        let mut is_first = true;
 
         for FlowTree.each_child(self) |child_ctx| {
-            child_ctx.data.position.origin.y = cur_y;
-            cur_y = cur_y.add(child_ctx.data.position.size.height);
+            match child_ctx.kind {
+                // Absolutely positioned children are placed at the end
+                AbsoluteFlow(*) => {}
+                FloatFlow(*) => {
+                    // Floats are out of the flow: they go beside the current line,
+                    // and do not move the flows after them down
+                    let y = floats.clear(child_ctx.clear_type(), cur_y.add(pending_margin));
+                    let origin = floats.place_float(child_ctx.float_side(),
+                                                    &child_ctx.data.position.size,
+                                                    y, content_width);
+                    child_ctx.data.position.origin = Point2D(origin.x.add(content_left), origin.y);
+                }
+                _ => {
+                    let child_top = child_ctx.collapsed_top_margin();
+                    let border_top = if is_first && collapse_top {
+                        // The first child's top margin moves outside of this flow
+                        first_margin = child_top;
+                        cur_y
+                    } else {
+                        cur_y.add(collapse_margins(pending_margin, child_top))
+                    };
+                    // Clearance puts the child's border edge below the floats
+                    let border_top = floats.clear(child_ctx.clear_type(), border_top);
+                    child_ctx.data.position.origin.y = border_top.sub(child_top);
+
+                    // Lay out the child's contents among the floats, in the
+                    // coordinates of its content box
+                    let mut inner = Point2D(child_ctx.data.position.origin.x.sub(content_left),
+                                            border_top);
+                    let into_child = Point2D(inner.x.neg(), inner.y.neg());
+                    match child_ctx.kind {
+                        // The lines place the floats found among them
+                        InlineFlow(*) => {
+                            let child_floats = floats.translate(&into_child);
+                            floats = child_ctx.assign_lines_inline(ctx, child_floats)
+                                              .translate(&inner);
+                        }
+                        // Tables are laid out already, as they start their own
+                        // block formatting contexts
+                        // TODO: keep them from overlapping the floats
+                        TableFlow(*) => {}
+                        _ => {
+                            do child_ctx.with_block_box |box| {
+                                let (left_fringe, _) = box.get_used_width();
+                                let (top_fringe, _) = box.get_used_height();
+                                inner = Point2D(inner.x.add(box.data.margin.left).add(left_fringe),
+                                                inner.y.add(top_fringe));
+                            }
+                            let into_child = Point2D(inner.x.neg(), inner.y.neg());
+                            floats = child_ctx.layout_block(ctx, floats.translate(&into_child))
+                                              .translate(&inner);
+                        }
+                    }
+
+                    let (_, child_bottom) = child_ctx.collapsible_margins();
+                    cur_y = child_ctx.data.position.origin.y
+                                     .add(child_ctx.data.position.size.height).sub(child_bottom);
+                    pending_margin = child_bottom;
+                    is_first = false;
+                }
+            }
         }
 
-        self.data.position.size.height = cur_y;
+        // Likewise for the last child's bottom margin
+        let last_margin = if collapse_bottom {
+            pending_margin
+        } else {
+            cur_y = cur_y.add(pending_margin);
+            au(0)
+        };
+
+        if starts_context {
+            cur_y = au::max(cur_y, floats.bottom());
+        }
 
-        let _used_top = au(0);
-        let _used_bot = au(0);
-        
         do self.with_block_box |box| {
-            box.data.position.origin.y = au(0);
-            box.data.position.size.height = cur_y;
-            let (_used_top, _used_bot) = box.get_used_height();
+            // Percentage heights need a containing block with an explicit height, which
+            // we do not track yet, so they behave as 'auto' (CSS 2.1, Section 10.5).
+            match box.node.computed_style().height {
+                BoxLength(length) => cur_y = au::from_frac_px(length.abs()),
+                BoxPercent(*) | BoxAuto if box.is_replaced() => {
+                    cur_y = box.content_box().size.height
+                }
+                BoxPercent(*) | BoxAuto => {}
+            }
         }
+
+        let mut content_top = au(0);
+        let mut height = cur_y;
+        // The root's children are positioned against the viewport
+        let mut padding_box = copy ctx.screen_size;
+
+        do self.with_block_box |box| {
+            let (top_fringe, bottom_fringe) = box.get_used_height();
+            box.data.margin.top = collapse_margins(box.data.margin.top, first_margin);
+            box.data.margin.bottom = collapse_margins(box.data.margin.bottom, last_margin);
+
+            box.data.position.origin.y = box.data.margin.top;
+            box.data.position.size.height = top_fringe.add(cur_y).add(bottom_fringe);
+
+            content_top = box.data.margin.top.add(top_fringe);
+            height = box.margin_box().size.height;
+
+            let border = box.data.border;
+            let position = box.data.position;
+            padding_box = Rect(Point2D(position.origin.x.add(border.left),
+                                       position.origin.y.add(border.top)),
+                               Size2D(position.size.width.sub(border.left.add(border.right)),
+                                      position.size.height.sub(border.top.add(border.bottom))));
+        }
+
+        let content_size = Size2D(content_width, cur_y);
+        for FlowTree.each_child(self) |child_ctx| {
+            match child_ctx.kind {
+                AbsoluteFlow(*) => child_ctx.place_absolute(&padding_box),
+                _ => {
+                    child_ctx.data.position.origin.y =
+                        child_ctx.data.position.origin.y.add(content_top);
+                }
+            }
+
+            // Relative offsets do not affect the layout of anything else
+            match child_ctx.kind {
+                BlockFlow(*) | FloatFlow(*) => {
+                    do child_ctx.with_block_box |box| {
+                        let offset = box.get_relative_offset(&content_size);
+                        child_ctx.data.position.origin = child_ctx.data.position.origin.add(&offset);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.data.position.size.height = height;
+        move floats
     }
 
     fn build_display_list_block(builder: &dl::DisplayListBuilder, dirty: &Rect<au>, 
@@ -7,8 +7,12 @@ use au::au;
 use core::dvec::DVec;
 use core::to_str::ToStr;
 use core::rand;
-use css::styles::SpecifiedStyle;
-use css::values::{BoxSizing, Length, Px, CSSDisplay, Specified, BgColor, BgColorTransparent};
+use css::styles::StyleMethods;
+use css::values::{BoxSizing, BoxLength, BoxPercent, BoxAuto, Length, Px, CSSDisplay, BgColor,
+                  BgColorTransparent};
+use css::values::{PosStatic, PosRelative, PosAbsolute, PosFixed};
+use css::values::{CSSDirection, DirectionLtr, DirectionRtl};
+use css::values::{DecorationUnderline, DecorationOverline, DecorationLineThrough, DecorationBlink};
 use dl = gfx::display_list;
 use dom::element::{ElementKind, HTMLDivElement, HTMLImageElement};
 use dom::node::{Element, Node, NodeData, NodeKind, NodeTree};
@@ -20,8 +24,8 @@ use image::{Image, ImageHolder};
 use layout::context::LayoutContext;
 use layout::debug::DebugMethods;
 use layout::flow::FlowContext;
-use layout::text::TextBoxData;
-use servo_text::text_run::TextRun;
+use layout::text::{TextBoxData, wraps_lines, get_text_baseline};
+use servo_text::glyph::{Glyph, GlyphPos};
 use std::net::url::Url;
 use task::spawn;
 use util::color::Color;
@@ -68,9 +72,38 @@ padding, backgrounds. It is analogous to a CSS nonreplaced content box.
    It also holds data specific to different box types, such as text.
 */
 
+/* The used sizes of a box's margin, border or padding on each side
+   (CSS 2.1, Section 8.1) */
+struct BoxEdges {
+    mut top: au,
+    mut right: au,
+    mut bottom: au,
+    mut left: au
+}
+
+fn BoxEdges(top: au, right: au, bottom: au, left: au) -> BoxEdges {
+    BoxEdges {
+        top: top,
+        right: right,
+        bottom: bottom,
+        left: left
+    }
+}
+
+fn zero_edges() -> BoxEdges {
+    BoxEdges(au(0), au(0), au(0), au(0))
+}
+
 struct BoxLayoutData {
+    /* the border box, relative to the owning flow */
     mut position: Rect<au>,
     mut font_size: Length,
+    mut margin: BoxEdges,
+    mut border: BoxEdges,
+    mut padding: BoxEdges,
+    /* the embedding level of the box in its line (UAX #9): odd levels are
+       laid out from right to left */
+    mut bidi_level: uint,
 }
 
 /* TODO: this should eventually be just 'position', and
@@ -79,9 +112,134 @@ fn BoxLayoutData() -> BoxLayoutData {
     BoxLayoutData {
         position : au::zero_rect(),
         font_size : Px(0.0),
+        margin : zero_edges(),
+        border : zero_edges(),
+        padding : zero_edges(),
+        bidi_level : 0u,
+    }
+}
+
+/* The used value of a margin, padding or width, or None if it is 'auto'.
+   Percentages refer to the width of the containing block. */
+pure fn used_length(value: BoxSizing, containing_width: au) -> Option<au> {
+    match value {
+        BoxLength(length) => Some(au::from_frac_px(length.abs())),
+        BoxPercent(percent) => {
+            Some(au::from_frac_px(au::to_frac_px(containing_width) * percent / 100.0))
+        }
+        BoxAuto => None
+    }
+}
+
+/* Solves for the used width and horizontal margins of a block-level,
+   non-replaced box in normal flow (CSS 2.1, Section 10.3.3), given the
+   space `available` to them once borders and padding are taken out, and
+   whether the containing block is laid out from right to left.
+   Returns (margin-left, width, margin-right). */
+pure fn solve_block_widths(available: au, width: Option<au>, margin_left: Option<au>,
+                           margin_right: Option<au>, rtl: bool) -> (au, au, au) {
+    match width {
+        None => {
+            // An 'auto' width takes up the space left by the margins
+            let margin_left = margin_left.get_default(au(0));
+            let margin_right = margin_right.get_default(au(0));
+            let width = au::max(au(0), available.sub(margin_left.add(margin_right)));
+            (margin_left, width, margin_right)
+        }
+        Some(width) => {
+            let remaining = available.sub(width);
+            match (margin_left, margin_right) {
+                // Centered, unless the box is too wide to fit
+                (None, None) if remaining > au(0) => {
+                    let half = au(*remaining / 2);
+                    (half, width, remaining.sub(half))
+                }
+                (None, None) if rtl => (remaining, width, au(0)),
+                (None, None) => (au(0), width, remaining),
+                (None, Some(right)) if remaining.sub(right) > au(0) || rtl => {
+                    (remaining.sub(right), width, right)
+                }
+                (None, Some(_)) => (au(0), width, remaining),
+                (Some(left), None) if remaining.sub(left) > au(0) || !rtl => {
+                    (left, width, remaining.sub(left))
+                }
+                (Some(_), None) => (remaining, width, au(0)),
+                // Over-constrained: the margin at the end of the line gives way
+                (Some(_), Some(right)) if rtl => (remaining.sub(right), width, right),
+                (Some(left), Some(_)) => (left, width, remaining.sub(left))
+            }
+        }
+    }
+}
+
+/* The shrink-to-fit width of a float or absolutely positioned box (CSS 2.1,
+   Section 10.3.5), given its minimum and preferred widths and the space
+   available to it. */
+pure fn shrink_to_fit(min_width: au, pref_width: au, available: au) -> au {
+    au::max(au(0), au::min(au::max(min_width, available), pref_width))
+}
+
+/* Solves for the offset of an absolutely positioned box's margin box from
+   its containing block, its margins, and its width or height, along one
+   dimension (CSS 2.1, Sections 10.3.7 and 10.6.4). `start` and `end` are
+   the offsets from either edge, `fringe` the total of the box's borders and
+   padding along the dimension, `content` the size it takes when it is 'auto'
+   and not fixed by both offsets, and `static_start` the offset the box
+   would have had in normal flow. Returns (offset, start margin, size, end
+   margin). */
+pure fn solve_absolute_offsets(containing: au, start: Option<au>, size: Option<au>,
+                               end: Option<au>, margin_start: Option<au>, margin_end: Option<au>,
+                               fringe: au, content: au, static_start: au) -> (au, au, au, au) {
+    // With both offsets 'auto', the box stays where it would have been
+    let start = match (start, end) {
+        (None, None) => Some(static_start),
+        _ => start
+    };
+
+    match (start, size, end) {
+        (Some(start), Some(size), Some(end)) => {
+            // Only then do 'auto' margins take up the space that is left
+            let remaining = containing.sub(start).sub(end).sub(size).sub(fringe);
+            let (margin_start, margin_end) = match (margin_start, margin_end) {
+                (None, None) if remaining >= au(0) => {
+                    let half = au(*remaining / 2);
+                    (half, remaining.sub(half))
+                }
+                (None, None) => (au(0), remaining),
+                (None, Some(margin_end)) => (remaining.sub(margin_end), margin_end),
+                (Some(margin_start), None) => (margin_start, remaining.sub(margin_start)),
+                // Over-constrained: the end offset gives way
+                (Some(margin_start), Some(margin_end)) => (margin_start, margin_end)
+            };
+            (start, margin_start, size, margin_end)
+        }
+        _ => {
+            let margin_start = margin_start.get_default(au(0));
+            let margin_end = margin_end.get_default(au(0));
+            let fringe = fringe.add(margin_start).add(margin_end);
+            let (offset, size) = match (start, size, end) {
+                (Some(start), Some(size), _) => (start, size),
+                (None, Some(size), Some(end)) => (containing.sub(end).sub(fringe).sub(size), size),
+                (Some(start), None, Some(end)) => {
+                    (start, au::max(au(0), containing.sub(start).sub(end).sub(fringe)))
+                }
+                (Some(start), None, None) => (start, content),
+                (None, None, Some(end)) => (containing.sub(end).sub(fringe).sub(content), content),
+                // A missing start offset was replaced by the static position above
+                (None, _, None) => (static_start, size.get_default(content))
+            };
+            (offset, margin_start, size, margin_end)
+        }
     }
 }
 
+/* Combines two adjoining vertical margins into one (CSS 2.1, Section 8.3.1) */
+pure fn collapse_margins(a: au, b: au) -> au {
+    if a >= au(0) && b >= au(0) { au::max(a, b) }
+    else if a < au(0) && b < au(0) { au::min(a, b) }
+    else { a.add(b) }
+}
+
 enum BoxData {
     GenericBox,
     ImageBox(ImageHolder),
@@ -130,95 +288,334 @@ impl @RenderBox {
     */
     fn get_min_width() -> au {
         match self.kind {
-            // TODO: this should account for min/pref widths of the
-            // box element in isolation. That includes
-            // border/margin/padding but not child widths. The block
-            // FlowContext will combine the width of this element and
-            // that of its children to arrive at the context width.
-            GenericBox => au(0),
+            // The min/pref widths of the box element in isolation
+            // include border/margin/padding but not child widths. The
+            // block FlowContext will combine the width of this element
+            // and that of its children to arrive at the context width.
+            GenericBox => self.get_fringe_width(),
             // TODO: consult CSS 'width', margin, border.
             // TODO: If image isn't available, consult 'width'.
             ImageBox(i) => au::from_px(i.get_size().get_default(Size2D(0,0)).width),
-            TextBox(d) => d.runs.foldl(au(0), |sum, run| {
-                au::max(sum, run.min_break_width())
-            })
+            // Text that does not wrap cannot be broken at all
+            TextBox(d) if wraps_lines(self.node.computed_style().white_space) => {
+                d.run.min_break_width()
+            }
+            TextBox(d) => d.run.size().width
         }
     }
 
     fn get_pref_width() -> au {
         match self.kind {
-            // TODO: this should account for min/pref widths of the
-            // box element in isolation. That includes
-            // border/margin/padding but not child widths. The block
-            // FlowContext will combine the width of this element and
-            // that of its children to arrive at the context width.
-            GenericBox => au(0),
+            // See get_min_width.
+            GenericBox => self.get_fringe_width(),
             ImageBox(i) => au::from_px(i.get_size().get_default(Size2D(0,0)).width),
-            // TODO: account for line breaks, etc. The run should know
-            // how to compute its own min and pref widths, and should
-            // probably cache them.
-            TextBox(d) => d.runs.foldl(au(0), |sum, run| {
-                au::max(sum, run.size().width)
-            })
+            // Forced line breaks have split the text already
+            TextBox(d) => d.run.size().width
+        }
+    }
+
+    /* The widths of the margins, borders and padding of a box in
+    isolation, not counting percentages, which depend on the containing
+    block. */
+    fn get_fringe_width() -> au {
+        let style = self.node.computed_style();
+        let lengths = ~[style.margin_left, style.margin_right,
+                        style.padding_left, style.padding_right];
+        let fringe = do lengths.foldl(au(0)) |sum, length| {
+            match *length {
+                BoxLength(length) => sum.add(au::from_frac_px(length.abs())),
+                BoxPercent(*) | BoxAuto => sum
+            }
+        };
+        fringe.add(au::from_frac_px(style.border_left_width + style.border_right_width))
+    }
+
+    /* The 'direction' of the block containing this box: that of the parent
+    of its element, which over-constrained margins depend on */
+    fn get_containing_direction() -> CSSDirection {
+        match tree::get_parent(NodeTree, self.node) {
+            Some(parent) => parent.computed_style().direction,
+            None => DirectionLtr
         }
     }
 
-    /* Returns the amount of left, right "fringe" used by this
-    box. This should be based on margin, border, padding, width. */
-    fn get_used_width() -> (au, au) {
-        // TODO: this should actually do some computation!
-        // See CSS 2.1, Section 10.3, 10.4.
+    /* Computes the used borders and padding on the left and right of a box,
+    within a containing block of the given width. */
+    priv fn assign_horizontal_fringe(containing_width: au) {
+        let style = self.node.computed_style();
 
-        (au(0), au(0))
+        self.data.border.left = au::from_frac_px(style.border_left_width);
+        self.data.border.right = au::from_frac_px(style.border_right_width);
+        self.data.padding.left = used_length(style.padding_left, containing_width).get_default(au(0));
+        self.data.padding.right = used_length(style.padding_right, containing_width).get_default(au(0));
+    }
+
+    /* Computes the used margins, borders and padding on the left and right
+    of a block box, and its width, within a containing block of the given
+    width. The box is positioned inside the margin box that spans the
+    containing block. Returns the width of the content box. */
+    fn assign_block_widths(containing_width: au) -> au {
+        let style = self.node.computed_style();
+        self.assign_horizontal_fringe(containing_width);
+
+        let (left_fringe, right_fringe) = self.get_used_width();
+        let available = containing_width.sub(left_fringe.add(right_fringe));
+        let (margin_left, width, margin_right) =
+            solve_block_widths(available,
+                               used_length(style.width, containing_width),
+                               used_length(style.margin_left, containing_width),
+                               used_length(style.margin_right, containing_width),
+                               self.get_containing_direction() == DirectionRtl);
+
+        self.data.margin.left = margin_left;
+        self.data.margin.right = margin_right;
+        self.data.position.origin.x = margin_left;
+        self.data.position.size.width = left_fringe.add(width).add(right_fringe);
+        return width;
     }
-    
-    /* Returns the amount of left, right "fringe" used by this
-    box. This should be based on margin, border, padding, width. */
-    fn get_used_height() -> (au, au) {
-        // TODO: this should actually do some computation!
-        // See CSS 2.1, Section 10.5, 10.6.
 
-        (au(0), au(0))
+    /* Like assign_block_widths, but for a floated box, whose 'auto' margins
+    are zero and whose 'auto' width shrinks to fit its contents (CSS 2.1,
+    Sections 10.3.5 and 10.3.6). `min_width` and `pref_width` are those of
+    the float's flow, which include the box's own fringe. */
+    fn assign_float_widths(containing_width: au, min_width: au, pref_width: au) -> au {
+        let style = self.node.computed_style();
+        self.assign_horizontal_fringe(containing_width);
+
+        let margin_left = used_length(style.margin_left, containing_width).get_default(au(0));
+        let margin_right = used_length(style.margin_right, containing_width).get_default(au(0));
+        let (left_fringe, right_fringe) = self.get_used_width();
+
+        let available = containing_width.sub(margin_left.add(margin_right))
+                                        .sub(left_fringe.add(right_fringe));
+        let width = match used_length(style.width, containing_width) {
+            Some(width) => width,
+            None => self.get_shrink_to_fit_width(min_width, pref_width, available)
+        };
+
+        self.data.margin.left = margin_left;
+        self.data.margin.right = margin_right;
+        self.data.position.origin.x = margin_left;
+        self.data.position.size.width = left_fringe.add(width).add(right_fringe);
+        return width;
+    }
+
+    /* Like assign_block_widths, but for an absolutely positioned box, which
+    is also offset from the padding box of its containing block by 'left'
+    and 'right' (CSS 2.1, Section 10.3.7), or else placed where it would
+    have been in normal flow, `static_left` from that padding box. 'auto'
+    margins share the space the offsets and width leave. Returns the offset
+    of its margin box and the width of its content box. */
+    fn assign_absolute_widths(containing_width: au, min_width: au, pref_width: au,
+                              static_left: au) -> (au, au) {
+        let style = self.node.computed_style();
+        self.assign_horizontal_fringe(containing_width);
+
+        let margin_left = used_length(style.margin_left, containing_width);
+        let margin_right = used_length(style.margin_right, containing_width);
+        let (left_fringe, right_fringe) = self.get_used_width();
+        let fringe = left_fringe.add(right_fringe);
+        let margins = margin_left.get_default(au(0)).add(margin_right.get_default(au(0)));
+
+        let content = self.get_shrink_to_fit_width(min_width, pref_width,
+                                                   containing_width.sub(fringe).sub(margins));
+        let (offset, margin_left, width, margin_right) =
+            solve_absolute_offsets(containing_width,
+                                   used_length(style.left, containing_width),
+                                   used_length(style.width, containing_width),
+                                   used_length(style.right, containing_width),
+                                   margin_left, margin_right, fringe, content, static_left);
+
+        self.data.margin.left = margin_left;
+        self.data.margin.right = margin_right;
+        self.data.position.origin.x = margin_left;
+        self.data.position.size.width = left_fringe.add(width).add(right_fringe);
+        return (offset, width);
+    }
+
+    /* Like assign_block_widths, but for a table box, whose 'auto' width
+    shrinks to fit its columns, and which is never narrower than they need
+    (CSS 2.1, Section 17.5.2). `min_width` and `pref_width` are those of the
+    columns and the spacing around them. */
+    fn assign_table_widths(containing_width: au, min_width: au, pref_width: au) -> au {
+        let style = self.node.computed_style();
+        self.assign_horizontal_fringe(containing_width);
+
+        let margin_left = used_length(style.margin_left, containing_width);
+        let margin_right = used_length(style.margin_right, containing_width);
+        let (left_fringe, right_fringe) = self.get_used_width();
+        let available = containing_width.sub(left_fringe.add(right_fringe));
+
+        let width = match used_length(style.width, containing_width) {
+            Some(width) => au::max(width, min_width),
+            None => {
+                let margins = margin_left.get_default(au(0)).add(margin_right.get_default(au(0)));
+                shrink_to_fit(min_width, pref_width, available.sub(margins))
+            }
+        };
+        let (margin_left, width, margin_right) =
+            solve_block_widths(available, Some(width), margin_left, margin_right,
+                               self.get_containing_direction() == DirectionRtl);
+
+        self.data.margin.left = margin_left;
+        self.data.margin.right = margin_right;
+        self.data.position.origin.x = margin_left;
+        self.data.position.size.width = left_fringe.add(width).add(right_fringe);
+        return width;
+    }
+
+    /* Sizes the border box of a table cell to the width of the columns it
+    spans. Cells have no margins (CSS 2.1, Section 17.5). */
+    fn assign_cell_widths(width: au) -> au {
+        self.assign_horizontal_fringe(width);
+
+        let (left_fringe, right_fringe) = self.get_used_width();
+        self.data.margin.left = au(0);
+        self.data.margin.right = au(0);
+        self.data.position.origin.x = au(0);
+        self.data.position.size.width = width;
+        return au::max(au(0), width.sub(left_fringe.add(right_fringe)));
+    }
+
+    /* The width of the content box of a box whose 'width' is 'auto' and that
+    shrinks to fit its contents. `min_width` and `pref_width` are those of
+    the box's flow, which include the box's own fringe. Replaced content
+    keeps its intrinsic width. */
+    priv fn get_shrink_to_fit_width(min_width: au, pref_width: au, available: au) -> au {
+        if self.is_replaced() {
+            return self.get_pref_width();
+        }
+
+        let fringe = self.get_fringe_width();
+        shrink_to_fit(min_width.sub(fringe), pref_width.sub(fringe), available)
+    }
+
+    /* The offset of a relatively positioned box from its position in normal
+    flow (CSS 2.1, Section 9.4.3), given the size of its containing block.
+    Boxes that are not relatively positioned stay put. */
+    fn get_relative_offset(containing_block: &Size2D<au>) -> Point2D<au> {
+        let style = self.node.computed_style();
+        match style.position {
+            PosRelative => {}
+            PosStatic | PosAbsolute | PosFixed => return au::zero_point()
+        }
+
+        // When both offsets are given, 'left' and 'top' win
+        let x = match (used_length(style.left, containing_block.width),
+                       used_length(style.right, containing_block.width)) {
+            (Some(left), _) => left,
+            (None, Some(right)) => right.neg(),
+            (None, None) => au(0)
+        };
+        let y = match (used_length(style.top, containing_block.height),
+                       used_length(style.bottom, containing_block.height)) {
+            (Some(top), _) => top,
+            (None, Some(bottom)) => bottom.neg(),
+            (None, None) => au(0)
+        };
+        Point2D(x, y)
+    }
+
+    /* Computes the used margins, borders and padding on the top and bottom
+    of a block box. Percentages refer to the width of the containing block,
+    and 'auto' margins are zero (CSS 2.1, Section 10.6.3). */
+    fn assign_block_vertical_edges(containing_width: au) {
+        let style = self.node.computed_style();
+
+        self.data.margin.top = used_length(style.margin_top, containing_width).get_default(au(0));
+        self.data.margin.bottom = used_length(style.margin_bottom, containing_width).get_default(au(0));
+        self.data.border.top = au::from_frac_px(style.border_top_width);
+        self.data.border.bottom = au::from_frac_px(style.border_bottom_width);
+        self.data.padding.top = used_length(style.padding_top, containing_width).get_default(au(0));
+        self.data.padding.bottom = used_length(style.padding_bottom, containing_width).get_default(au(0));
+    }
+
+    /* Returns the amount of left, right "fringe" between the border
+    edge and the content edge of this box: its border and padding. */
+    pure fn get_used_width() -> (au, au) {
+        (self.data.border.left.add(self.data.padding.left),
+         self.data.border.right.add(self.data.padding.right))
+    }
+    
+    /* Returns the amount of top, bottom "fringe" between the border
+    edge and the content edge of this box: its border and padding. */
+    pure fn get_used_height() -> (au, au) {
+        (self.data.border.top.add(self.data.padding.top),
+         self.data.border.bottom.add(self.data.padding.bottom))
     }
 
     /* The box formed by the content edge, as defined in CSS 2.1 Section 8.1.
        Coordinates are relative to the owning flow. */
     pure fn content_box() -> Rect<au> {
+        let (offset_left, offset_right) = self.get_used_width();
+        let (offset_top, offset_bottom) = self.get_used_height();
+        let origin = Point2D(self.data.position.origin.x.add(offset_left),
+                             self.data.position.origin.y.add(offset_top));
+
         match self.kind {
             ImageBox(i) => {
                 let size = i.size();
                 Rect {
-                    origin: copy self.data.position.origin,
+                    origin: origin,
                     size:   Size2D(au::from_px(size.width),
                                    au::from_px(size.height))
                 }
             },
-            GenericBox(*) => {
-                copy self.data.position
-                /* FIXME: The following hits an ICE for whatever reason
-
-                let origin = self.data.position.origin;
-                let size   = self.data.position.size;
-                let (offset_left, offset_right) = self.get_used_width();
-                let (offset_top, offset_bottom) = self.get_used_height();
-
+            GenericBox(*) | TextBox(*) => {
+                let size = self.data.position.size;
                 Rect {
-                    origin: Point2D(origin.x + offset_left, origin.y + offset_top),
-                    size:   Size2D(size.width - (offset_left + offset_right),
-                                   size.height - (offset_top + offset_bottom))
-                }*/
-            },
-            TextBox(*) => {
-                copy self.data.position
+                    origin: origin,
+                    size:   Size2D(size.width.sub(offset_left.add(offset_right)),
+                                   size.height.sub(offset_top.add(offset_bottom)))
+                }
             }
         }
     }
 
     /* The box formed by the border edge, as defined in CSS 2.1 Section 8.1.
        Coordinates are relative to the owning flow. */
-    fn border_box() -> Rect<au> {
-        // TODO: actually compute content_box + padding + border
-        self.content_box()
+    pure fn border_box() -> Rect<au> {
+        copy self.data.position
+    }
+
+    /* The box formed by the margin edge, as defined in CSS 2.1 Section 8.1.
+       Coordinates are relative to the owning flow. */
+    pure fn margin_box() -> Rect<au> {
+        let position = self.data.position;
+        let margin = self.data.margin;
+        Rect {
+            origin: Point2D(position.origin.x.sub(margin.left), position.origin.y.sub(margin.top)),
+            size:   Size2D(position.size.width.add(margin.left).add(margin.right),
+                           position.size.height.add(margin.top).add(margin.bottom))
+        }
+    }
+
+    /* Adds items for the background and borders of the box, whose border
+    box is at `bounds` (CSS 2.1, Appendix E.2). */
+    fn build_background_and_borders(bounds: &Rect<au>, list: &dl::DisplayList) {
+        let style = self.node.computed_style();
+
+        match style.background_color {
+            BgColor(c) => list.push(~dl::SolidColor(copy *bounds, c.red, c.green, c.blue)),
+            BgColorTransparent => {}
+        }
+
+        let border = self.data.border;
+        let sides = dl::BorderSides {
+            top: dl::BorderSide { width: border.top, color: style.border_top_color,
+                                  style: style.border_top_style },
+            right: dl::BorderSide { width: border.right, color: style.border_right_color,
+                                    style: style.border_right_style },
+            bottom: dl::BorderSide { width: border.bottom, color: style.border_bottom_color,
+                                     style: style.border_bottom_style },
+            left: dl::BorderSide { width: border.left, color: style.border_left_color,
+                                   style: style.border_left_style }
+        };
+
+        if border.top > au(0) || border.right > au(0) || border.bottom > au(0) ||
+           border.left > au(0) {
+            list.push(~dl::Border(copy *bounds, sides));
+        }
     }
 
     // TODO: to implement stacking contexts correctly, we need to
@@ -250,37 +647,64 @@ impl @RenderBox {
 
         match self.kind {
             TextBox(d) => {
-                let mut runs = d.runs;
                 // TODO: don't paint background for text boxes
                 list.push(~dl::SolidColor(bounds, 255u8, 255u8, 255u8));
-                
-                let mut bounds = bounds;
-                for uint::range(0, runs.len()) |i| {
-                    bounds.size.height = runs[i].size().height;
-                    let glyph_run = make_glyph_run(&runs[i]);
-                    list.push(~dl::Glyphs(bounds, glyph_run));
-                    bounds.origin.y = bounds.origin.y.add(bounds.size.height);
+
+                let style = self.node.computed_style();
+                let baseline = get_text_baseline(bounds.size.height, d.font);
+                list.push(~dl::Glyphs(bounds, make_glyph_run(&d, style.text_color, baseline)));
+
+                // Decorations are drawn across the whole box, in the colors of the
+                // elements they come from
+                let metrics = &d.font.metrics;
+                let thickness = au::max(au::from_px(1), d.font.em_length(metrics.underline_size));
+                for style.text_decorations.each |decoration| {
+                    let (line, color) = *decoration;
+                    let raise = match line {
+                        DecorationUnderline => d.font.em_length(metrics.underline_offset),
+                        DecorationOverline => d.font.ascent(),
+                        DecorationLineThrough => d.font.em_length(metrics.x_height / 2.0),
+                        // Browsers may ignore blinking (CSS 2.1, Section 16.3.1)
+                        DecorationBlink => loop
+                    };
+                    let line_bounds = Rect(Point2D(bounds.origin.x,
+                                                   bounds.origin.y.add(baseline).sub(raise)),
+                                           Size2D(bounds.size.width, thickness));
+                    list.push(~dl::SolidColor(line_bounds, color.red, color.green, color.blue));
                 }
                 return;
 
-                pure fn make_glyph_run(text_run: &TextRun) -> dl::GlyphRun {
+                /* The glyphs of the text, with the spaces widened to justify its line */
+                fn make_glyph_run(d: &TextBoxData, color: Color, baseline: au) -> dl::GlyphRun {
+                    let space = d.font.glyph_index(' ');
+                    let glyphs = do d.run.glyphs.map |glyph| {
+                        match space {
+                            Some(index) if index == glyph.index => {
+                                let advance = Point2D(glyph.pos.advance.x.add(d.word_spacing),
+                                                      glyph.pos.advance.y);
+                                Glyph(glyph.index, GlyphPos(advance, glyph.pos.offset), glyph.cluster)
+                            }
+                            _ => copy *glyph
+                        }
+                    };
                     dl::GlyphRun {
-                        glyphs: copy text_run.glyphs
+                        glyphs: move glyphs,
+                        font: copy d.font.descriptor,
+                        color: color,
+                        baseline: baseline
                     }
                 }
             },
-            // TODO: items for background, border, outline
-            GenericBox(*) => { },
+            // TODO: items for outline
+            GenericBox(*) => self.build_background_and_borders(&bounds, list),
             ImageBox(i) => {
                 match i.get_image() {
                     Some(image) => list.push(~dl::Image(bounds, image)),
                     /* No image data at all? Okay, add some fallback content instead. */
                     None => {
-                        // TODO: shouldn't need to unbox CSSValue by now
-                        let boxed_color = self.node.style().background_color;
-                        let color = match boxed_color {
-                            Specified(BgColor(c)) => c,
-                            Specified(BgColorTransparent) | _ => util::color::rgba(0,0,0,0.0)
+                        let color = match self.node.computed_style().background_color {
+                            BgColor(c) => c,
+                            BgColorTransparent => util::color::rgba(0,0,0,0.0)
                         };
                         list.push(~dl::SolidColor(bounds, color.red, color.green, color.blue));
                     }
@@ -342,12 +766,7 @@ impl @RenderBox : DebugMethods {
         let repr = match self.kind {
             GenericBox(*) => ~"GenericBox",
             ImageBox(*) => ~"ImageBox",
-            TextBox(d) => {
-                let mut s = d.runs.foldl(~"TextBox(runs=", |s, run| {
-                    fmt!("%s  \"%s\"", s, run.text)
-                });
-                s += ~")"; s
-            }
+            TextBox(d) => fmt!("TextBox(text=\"%s\")", d.text)
         };
 
         fmt!("box b%?: %?", self.id, repr)
@@ -388,5 +807,73 @@ mod test {
     }
 
     // TODO: redo tests here, but probably is part of box_builder.rs
+
+    #[test]
+    fn should_solve_block_widths() {
+        let px = au::from_px;
+
+        // 'auto' widths fill the containing block, and 'auto' margins are zero
+        assert solve_block_widths(px(100), None, None, Some(px(10)), false)
+            == (px(0), px(90), px(10));
+        // 'auto' margins center the box
+        assert solve_block_widths(px(100), Some(px(60)), None, None, false)
+            == (px(20), px(60), px(20));
+        assert solve_block_widths(px(100), Some(px(60)), None, Some(px(10)), false)
+            == (px(30), px(60), px(10));
+        // Over-constrained, or too wide to fit: the right margin gives way
+        assert solve_block_widths(px(100), Some(px(60)), Some(px(10)), Some(px(10)), false)
+            == (px(10), px(60), px(30));
+        assert solve_block_widths(px(100), Some(px(120)), None, None, false)
+            == (px(0), px(120), au::from_frac_px(-20.0));
+        // In a right-to-left containing block, the left margin gives way instead
+        assert solve_block_widths(px(100), Some(px(60)), Some(px(10)), Some(px(10)), true)
+            == (px(30), px(60), px(10));
+        assert solve_block_widths(px(100), Some(px(120)), None, None, true)
+            == (au::from_frac_px(-20.0), px(120), px(0));
+    }
+
+    #[test]
+    fn should_solve_absolute_offsets() {
+        let px = au::from_px;
+
+        let zero = Some(px(0));
+
+        // Fixed by both offsets
+        assert solve_absolute_offsets(px(100), Some(px(10)), None, Some(px(20)), zero, Some(px(5)),
+                                      px(0), px(50), px(0)) == (px(10), px(0), px(65), px(5));
+        // Placed against the end edge
+        assert solve_absolute_offsets(px(100), None, Some(px(30)), Some(px(20)), zero, Some(px(5)),
+                                      px(0), px(50), px(0)) == (px(45), px(0), px(30), px(5));
+        assert solve_absolute_offsets(px(100), None, None, Some(px(20)), zero, zero, px(0),
+                                      px(50), px(0)) == (px(30), px(0), px(50), px(0));
+        // Over-constrained: the end offset is ignored
+        assert solve_absolute_offsets(px(100), Some(px(10)), Some(px(30)), Some(px(20)), zero, zero,
+                                      px(0), px(50), px(0)) == (px(10), px(0), px(30), px(0));
+        // Without offsets the box stays at its static position
+        assert solve_absolute_offsets(px(100), None, None, None, zero, zero, px(0), px(50), px(15))
+            == (px(15), px(0), px(50), px(0));
+        assert solve_absolute_offsets(px(100), None, Some(px(30)), None, None, None, px(4), px(50),
+                                      px(15)) == (px(15), px(0), px(30), px(0));
+        // 'auto' margins center a box fixed by its offsets and size, unless it is too big
+        assert solve_absolute_offsets(px(100), Some(px(0)), Some(px(50)), Some(px(0)), None, None,
+                                      px(10), px(0), px(0)) == (px(0), px(20), px(50), px(20));
+        assert solve_absolute_offsets(px(100), Some(px(0)), Some(px(50)), Some(px(0)), None,
+                                      Some(px(10)), px(10), px(0), px(0))
+            == (px(0), px(30), px(50), px(10));
+        assert solve_absolute_offsets(px(100), Some(px(0)), Some(px(120)), Some(px(0)), None, None,
+                                      px(0), px(0), px(0))
+            == (px(0), px(0), px(120), au::from_frac_px(-20.0));
+        assert shrink_to_fit(px(10), px(80), px(50)) == px(50);
+        assert shrink_to_fit(px(60), px(80), px(50)) == px(60);
+    }
+
+    #[test]
+    fn should_collapse_margins() {
+        let px = au::from_px;
+
+        assert collapse_margins(px(10), px(20)) == px(20);
+        assert collapse_margins(px(-10), px(-20)) == px(-20);
+        assert collapse_margins(px(30), px(-10)) == px(20);
+    }
 }
 
@@ -1,19 +1,30 @@
 /** Creates CSS boxes from a DOM. */
 use au = gfx::geometry;
 use core::dvec::DVec;
-use css::styles::{SpecifiedStyle, empty_style_for_node_kind};
+use css::styles::{ComputedStyle, StyleMethods};
 use css::values::{CSSDisplay, DisplayBlock, DisplayInline, DisplayInlineBlock, DisplayNone};
-use css::values::{Inherit, Initial, Specified};
+use css::values::{DisplayListItem, DisplayTable, DisplayInlineTable, DisplayTableRowGroup};
+use css::values::{DisplayTableHeaderGroup, DisplayTableFooterGroup, DisplayTableRow};
+use css::values::{DisplayTableColumnGroup, DisplayTableColumn, DisplayTableCell};
+use css::values::{DisplayTableCaption, FloatNone};
+use css::values::{CSSPosition, PosAbsolute, PosFixed};
 use dom::element::*;
 use dom::node::{Comment, Doctype, Element, Text, Node, NodeTree, LayoutData};
 use image::holder::ImageHolder;
-use layout::flow::{FlowContext, FlowContextData, BlockFlow, InlineFlow, InlineBlockFlow, RootFlow, FlowTree};
+use layout::flow::{FlowContext, FlowContextData, BlockFlow, FloatFlow, InlineFlow, InlineBlockFlow};
+use layout::flow::{AbsoluteFlow, RootFlow, FlowTree};
+use layout::flow::{TableFlow, TableRowFlow, TableCellFlow};
+use layout::absolute::AbsoluteFlowData;
 use layout::box::{RenderBox, BoxData, GenericBox, ImageBox, TextBox, RenderBoxTree};
 use layout::block::BlockFlowData;
 use layout::context::LayoutContext;
-use layout::inline::InlineFlowData;
+use layout::floats::FloatFlowData;
+use layout::inline::{InlineFlowData, InlineFloat};
+use layout::inline_block::InlineBlockFlowData;
 use layout::root::RootFlowData;
-use layout::text::TextBoxData;
+use layout::table::{TableFlowData, TableRowFlowData, TableCellFlowData};
+use layout::text::{TextBoxData, process_white_space, preserves_newlines};
+use layout::text::{font_descriptor, apply_text_transform};
 use option::is_none;
 use servo_text::font_cache::FontCache;
 use servo_text::text_run::TextRun;
@@ -45,49 +56,98 @@ impl LayoutTreeBuilder {
     fn construct_recursively(layout_ctx: &LayoutContext, cur_node: Node, 
                              parent_ctx: @FlowContext, parent_box: Option<@RenderBox>) {
 
-        let style = cur_node.style();
+        let style = cur_node.computed_style();
         
         // DEBUG
         let n_str = fmt!("%?", cur_node.read(|n| copy n.kind ));
         debug!("Considering node: %?", n_str);
 
-        // TODO: remove this once UA styles work
-        // TODO: handle interactions with 'float', 'position' (CSS 2.1, Section 9.7)
-        let simulated_display = match self.simulate_UA_display_rules(cur_node, style) {
+        // Floated and absolutely positioned boxes have already been made block-level by
+        // style resolution (CSS 2.1, Section 9.7)
+        let display = match self.box_display_type(style) {
             DisplayNone => return, // tree ends here if 'display: none'
             v => v
         };
 
+        // Whitespace between table parts does not make anonymous cells
+        let is_whitespace = do cur_node.read |n| {
+            match n.kind {
+                ~Text(string) => str::is_whitespace(string),
+                _ => false
+            }
+        };
+        if is_whitespace && (parent_ctx.starts_table_flow() || parent_ctx.starts_table_row_flow()) {
+            return;
+        }
+
+        // Nor does collapsible whitespace between block-level boxes make an anonymous block
+        // (CSS 2.1, Section 16.6.1)
+        if is_whitespace && !preserves_newlines(style.white_space)
+            && self.current_inline_flow(parent_ctx).is_none() {
+            return;
+        }
+
+        /* A block-level box inside an inline box splits it (CSS 2.1, Section 9.2.1.1): the
+           block goes in the block container, after the anonymous block holding the inline
+           content so far, and the content after it goes in a new one. Floats inside inline
+           content are instead placed by the lines they are found on; see below. */
+        let parent_ctx = match display {
+            DisplayInline | DisplayInlineBlock => parent_ctx,
+            _ if parent_ctx.starts_inline_flow() => tree::get_parent(FlowTree, parent_ctx).get(),
+            _ => parent_ctx
+        };
+
+        let parent_ctx = self.table_parent_for(display, parent_ctx);
+
         // first, create the proper box kind, based on node characteristics
-        let box_data = self.create_box_data(layout_ctx, cur_node, simulated_display);
+        let box_data = self.create_box_data(layout_ctx, cur_node, display);
+
+        // Inline-blocks are put on the lines of an inline flow, which places their own
+        // flow; replaced inline-blocks are laid out like any other inline replaced box
+        let parent_ctx = match box_data {
+            GenericBox if display == DisplayInlineBlock => self.inline_flow_for(parent_ctx),
+            _ => parent_ctx
+        };
 
         // then, figure out its proper context, possibly reorganizing.
         let next_ctx: @FlowContext = match box_data {
-            /* Text box is always an inline flow. create implicit inline
-            flow ctx if we aren't inside one already. */
-            TextBox(*) => {
-                if (parent_ctx.starts_inline_flow()) {
-                    parent_ctx
-                } else {
-                    self.make_ctx(InlineFlow(InlineFlowData()), tree::empty())
-                }
+            /* Text box is always in an inline flow, the anonymous block
+            of its block container. */
+            TextBox(*) => self.inline_flow_for(parent_ctx),
+            GenericBox if display == DisplayInlineBlock => {
+                self.make_ctx(InlineBlockFlow(InlineBlockFlowData()), tree::empty())
             },
             ImageBox(*) | GenericBox => {
-                match simulated_display {
-                    DisplayInline | DisplayInlineBlock => {
-                        /* if inline, try to put into inline context,
-                        making a new one if necessary */
-                        if (parent_ctx.starts_inline_flow()) {
-                            parent_ctx
-                        } else {
-                            self.make_ctx(InlineFlow(InlineFlowData()), tree::empty())
-                        }
+                match display {
+                    DisplayInline | DisplayInlineBlock => self.inline_flow_for(parent_ctx),
+                    /* absolutely positioned boxes are placed by their containing block */
+                    DisplayBlock if style.position == PosAbsolute || style.position == PosFixed => {
+                        self.make_ctx(AbsoluteFlow(AbsoluteFlowData()), tree::empty())
+                    },
+                    /* floats are placed by the block flow containing them */
+                    DisplayBlock if style.float_type != FloatNone => {
+                        self.make_ctx(FloatFlow(FloatFlowData()), tree::empty())
                     },
-                    /* block boxes always create a new context */
-                    DisplayBlock => {
+                    /* block boxes always create a new context, as do captions, which
+                       are laid out by their table */
+                    DisplayBlock | DisplayTableCaption => {
                         self.make_ctx(BlockFlow(BlockFlowData()), tree::empty())
                     },
-                    _ => fail fmt!("unsupported display type in box generation: %?", simulated_display)
+                    DisplayTable => {
+                        self.make_ctx(TableFlow(TableFlowData()), tree::empty())
+                    },
+                    /* row groups only order the rows of their table */
+                    // TODO: put header groups first and footer groups last
+                    DisplayTableRowGroup | DisplayTableHeaderGroup | DisplayTableFooterGroup => {
+                        parent_ctx
+                    },
+                    DisplayTableRow => {
+                        self.make_ctx(TableRowFlow(TableRowFlowData()), tree::empty())
+                    },
+                    DisplayTableCell => {
+                        self.make_ctx(TableCellFlow(TableCellFlowData()), tree::empty())
+                    },
+                    _ => fail fmt!("unsupported display type in box generation: %?", display)
                 }
             }
         };
@@ -98,27 +158,54 @@ impl LayoutTreeBuilder {
         do cur_node.aux |data| { data.flow = Some(next_ctx) }
 
         // make box, add box to any context-specific list.
-        let mut new_box = self.make_box(cur_node, parent_ctx, box_data);
+        let mut new_box = self.make_box(cur_node, next_ctx, box_data);
         debug!("Assign ^box to flow: %?", next_ctx.debug_str());
 
         match next_ctx.kind {
-            InlineFlow(d) => {
-                d.boxes.push(new_box);
-
-                if (parent_box.is_some()) {
-                    let parent = parent_box.get();
+            InlineFlow(*) => self.add_inline_box(next_ctx, new_box, parent_box),
+            InlineBlockFlow(d) => {
+                d.box = Some(new_box);
 
-                    // connect the box to its parent box
-                    debug!("In inline flow f%?, set child b%? of parent b%?", next_ctx.id, parent.id, new_box.id);
-                    RenderBoxTree.add_child(parent, new_box);
-                }
+                // the box takes part in the lines of its inline flow
+                self.add_inline_box(parent_ctx, new_box, parent_box);
             }
             BlockFlow(d) => { d.box = Some(new_box) }
-            _ => {} // TODO: float lists, etc.
+            FloatFlow(d) => { d.box = Some(new_box) }
+            AbsoluteFlow(d) => { d.box = Some(new_box) }
+            // A row group's box is not drawn
+            TableFlow(d) if display == DisplayTable => { d.box = Some(new_box) }
+            TableRowFlow(d) => { d.box = Some(new_box) }
+            TableCellFlow(d) => { d.box = Some(new_box) }
+            _ => {}
         };
 
-    
-        if (!next_ctx.eq(&parent_ctx)) {
+
+        // inline flows are added to their block containers as they are made
+        if (!next_ctx.eq(&parent_ctx) && !next_ctx.starts_inline_flow()) {
+            next_ctx.node = Some(cur_node);
+
+            let mut parent_ctx = parent_ctx;
+            if next_ctx.starts_absolute_flow() {
+                do next_ctx.access_absolute |d| {
+                    d.static_parent = Some(parent_ctx);
+                    d.static_after = self.last_in_flow_child(parent_ctx);
+                }
+                parent_ctx = self.containing_block_for(style.position, parent_ctx);
+            }
+            // A float after inline content goes beside the line it is found on, which
+            // the inline flow places it on
+            if next_ctx.starts_float_flow() {
+                match self.current_inline_flow(parent_ctx) {
+                    Some(inline_ctx) => {
+                        do inline_ctx.access_inline |d| {
+                            d.floats.push(InlineFloat { flow: next_ctx, before: None })
+                        }
+                        parent_ctx = inline_ctx;
+                    }
+                    None => {}
+                }
+            }
+
             debug!("Adding child flow f%? of f%?", parent_ctx.id, next_ctx.id);
             FlowTree.add_child(parent_ctx, next_ctx);
         }
@@ -127,56 +214,208 @@ impl LayoutTreeBuilder {
         do NodeTree.each_child(cur_node) |child_node| {
             self.construct_recursively(layout_ctx, child_node, next_ctx, Some(new_box)); true
         }
+    }
 
-        // Fixup any irregularities, such as split inlines (CSS 2.1 Section 9.2.1.1)
-        if (next_ctx.starts_inline_flow()) {
-            let mut found_child_inline = false;
-            let mut found_child_block = false;
+    /* The last child of a flow in the normal flow. Floats and absolutely positioned
+       boxes among inline content do not end its anonymous block. */
+    priv fn last_in_flow_child(ctx: @FlowContext) -> Option<@FlowContext> {
+        let mut child = ctx.tree.last_child;
+        loop {
+            match child {
+                Some(c) if c.starts_float_flow() || c.starts_absolute_flow() => {
+                    child = c.tree.prev_sibling
+                }
+                _ => return child
+            }
+        }
+    }
+
+    /* The anonymous block (CSS 2.1, Section 9.2.1.1) that inline content whose parent's
+       content went in `parent_ctx` continues in, if there is one: the last in-flow child
+       of the block container, unless a block-level box has come after it. */
+    priv fn current_inline_flow(parent_ctx: @FlowContext) -> Option<@FlowContext> {
+        let container = if parent_ctx.starts_inline_flow() {
+            tree::get_parent(FlowTree, parent_ctx).get()
+        } else {
+            parent_ctx
+        };
 
-            do FlowTree.each_child(next_ctx) |child_ctx| {
-                match child_ctx.kind {
-                    InlineFlow(*) | InlineBlockFlow => found_child_inline = true,
-                    BlockFlow(*) => found_child_block = true,
-                    _ => {}
-                }; true
+        match self.last_in_flow_child(container) {
+            Some(ctx) if ctx.starts_inline_flow() => Some(ctx),
+            _ => None
+        }
+    }
+
+    /* The inline flow to put inline-level content in: the current anonymous block of
+       the block container, or else a new one after its other children. */
+    priv fn inline_flow_for(parent_ctx: @FlowContext) -> @FlowContext {
+        match self.current_inline_flow(parent_ctx) {
+            Some(ctx) => ctx,
+            None => {
+                let container = if parent_ctx.starts_inline_flow() {
+                    tree::get_parent(FlowTree, parent_ctx).get()
+                } else {
+                    parent_ctx
+                };
+                let ctx = self.make_ctx(InlineFlow(InlineFlowData()), tree::empty());
+                // the lines of an anonymous block take their style from its container
+                ctx.node = container.node;
+                debug!("Adding anonymous child flow f%? of f%?", ctx.id, container.id);
+                FlowTree.add_child(container, ctx);
+                ctx
             }
+        }
+    }
+
+    /* Puts an inline-level box on the lines of an inline flow, as a child of the box of
+       its parent node. */
+    priv fn add_inline_box(inline_ctx: @FlowContext, box: @RenderBox,
+                           parent_box: Option<@RenderBox>) {
+        match parent_box {
+            Some(parent) => {
+                // find the parent first, so a continuation of it goes on the line before the box
+                let parent = self.inline_parent_box(inline_ctx, parent);
+                do inline_ctx.access_inline |d| { d.boxes.push(box) }
 
-            if found_child_block && found_child_inline {
-                self.fixup_split_inline(next_ctx)
+                debug!("In inline flow f%?, set child b%? of parent b%?", inline_ctx.id, parent.id, box.id);
+                RenderBoxTree.add_child(parent, box);
+            }
+            None => do inline_ctx.access_inline |d| { d.boxes.push(box) }
+        }
+
+        // The floats found since the last box are placed when the lines reach this one
+        do inline_ctx.access_inline |d| {
+            for d.floats.each |float| {
+                if float.before.is_none() { float.before = Some(box) }
             }
         }
     }
 
-    fn fixup_split_inline(_foo: @FlowContext) {
-        // TODO: finish me. 
-        fail ~"TODO: handle case where an inline is split by a block"
+    /* The box in `inline_ctx` to make the parent of the boxes of the children of the node
+       of `parent_box`. An inline box split by a block-level box (CSS 2.1, Section 9.2.1.1)
+       continues in the inline flow after the block with a new box for the same node, made
+       along with those of the inline boxes containing it. */
+    priv fn inline_parent_box(inline_ctx: @FlowContext, parent_box: @RenderBox) -> @RenderBox {
+        if parent_box.node.computed_style().display_type != DisplayInline
+            || parent_box.ctx.eq(&inline_ctx) {
+            return parent_box;
+        }
+
+        let mut continuation = None;
+        do inline_ctx.access_inline |d| {
+            for d.boxes.each |box| {
+                if box.node == parent_box.node { continuation = Some(*box); }
+            }
+        }
+
+        match continuation {
+            Some(box) => box,
+            None => {
+                let box = self.make_box(parent_box.node, inline_ctx, GenericBox);
+                debug!("Continuing split box b%? as b%?", parent_box.id, box.id);
+                self.add_inline_box(inline_ctx, box, tree::get_parent(RenderBoxTree, parent_box));
+                box
+            }
+        }
     }
 
-    priv fn simulate_UA_display_rules(node: Node, style: SpecifiedStyle) -> CSSDisplay {
-        let resolved = match style.display_type {
-            Inherit | Initial => DisplayInline, // TODO: remove once resolve works
-            Specified(v) => v
-        };
+    /* Finds the flow of the containing block of an absolutely positioned box
+       (CSS 2.1, Section 10.1): the nearest positioned block-level ancestor, or
+       the root flow, which stands in for the viewport. */
+    priv fn containing_block_for(position: CSSPosition, parent_ctx: @FlowContext) -> @FlowContext {
+        // TODO: positioned inline ancestors; 'fixed' should stay put when scrolling
+        let mut ctx = parent_ctx;
+        loop {
+            let positioned = match (position, ctx.kind) {
+                (PosFixed, _) | (_, RootFlow(*)) | (_, InlineFlow(*)) => false,
+                (_, AbsoluteFlow(*)) => true,
+                (_, _) => ctx.is_positioned()
+            };
+            if positioned || ctx.starts_root_flow() {
+                return ctx;
+            }
+            ctx = tree::get_parent(FlowTree, ctx).get();
+        }
+    }
 
-        if (resolved == DisplayNone) { return resolved; }
+    /* Wraps table parts that are missing their parents in anonymous table
+       objects, and other content inside tables and rows in anonymous cells
+       (CSS 2.1, Section 17.2.1). Returns the flow that the flow or boxes of
+       a node with the given display type go into. */
+    priv fn table_parent_for(display: CSSDisplay, parent_ctx: @FlowContext) -> @FlowContext {
+        // TODO: anonymous inline tables inside inline flows
+        match (display, parent_ctx.kind) {
+            (DisplayTableCell, TableRowFlow(*)) => parent_ctx,
+            (_, TableRowFlow(*)) => {
+                let cell = self.anonymous_child(parent_ctx, TableCellFlow(TableCellFlowData()));
+                self.table_parent_for(display, cell)
+            }
+            (DisplayTableRow, TableFlow(*)) | (DisplayTableRowGroup, TableFlow(*))
+            | (DisplayTableHeaderGroup, TableFlow(*)) | (DisplayTableFooterGroup, TableFlow(*))
+            | (DisplayTableCaption, TableFlow(*)) => parent_ctx,
+            (_, TableFlow(*)) => {
+                let row = self.anonymous_child(parent_ctx, TableRowFlow(TableRowFlowData()));
+                self.table_parent_for(display, row)
+            }
+            (DisplayTableCell, _) | (DisplayTableRow, _) | (DisplayTableRowGroup, _)
+            | (DisplayTableHeaderGroup, _) | (DisplayTableFooterGroup, _)
+            | (DisplayTableCaption, _) => {
+                let table = self.anonymous_child(parent_ctx, TableFlow(TableFlowData()));
+                self.table_parent_for(display, table)
+            }
+            (_, _) => parent_ctx
+        }
+    }
 
-        do node.read |n| {
-            match n.kind {
-                ~Doctype(*) | ~Comment(*) => DisplayNone,
-                ~Text(*) => DisplayInline,
-                ~Element(e) => match e.kind {
-                    ~HTMLHeadElement(*) => DisplayNone,
-                    ~HTMLScriptElement(*) => DisplayNone,
-                    _ => resolved
-                }
+    /* The anonymous table part of the given kind to put the next child of
+       `parent_ctx` in: its last child if that is one, so that consecutive
+       children share it, or else a new one. */
+    priv fn anonymous_child(parent_ctx: @FlowContext, +kind: FlowContextData) -> @FlowContext {
+        match parent_ctx.tree.last_child {
+            Some(ctx) if self.is_anonymous_part(ctx, &kind) => ctx,
+            _ => {
+                let ctx = self.make_ctx(move kind, tree::empty());
+                debug!("Adding anonymous child flow f%? of f%?", ctx.id, parent_ctx.id);
+                FlowTree.add_child(parent_ctx, ctx);
+                ctx
             }
         }
     }
 
+    priv fn is_anonymous_part(ctx: @FlowContext, kind: &FlowContextData) -> bool {
+        let same_kind = match *kind {
+            TableFlow(*) => ctx.starts_table_flow(),
+            TableRowFlow(*) => ctx.starts_table_row_flow(),
+            TableCellFlow(*) => ctx.starts_table_cell_flow(),
+            _ => false
+        };
+        if !same_kind { return false; }
+
+        let mut anonymous = true;
+        do ctx.with_block_box |_box| { anonymous = false; }
+        anonymous
+    }
+
+    /* Picks the display type used for box generation. Element defaults come from the
+       user agent stylesheet; display types without their own flow yet are laid out as
+       the nearest supported type. */
+    priv fn box_display_type(style: ComputedStyle) -> CSSDisplay {
+        match style.display_type {
+            // TODO: list markers
+            DisplayListItem => DisplayBlock,
+            // TODO: inline-level tables
+            DisplayInlineTable => DisplayTable,
+            // TODO: column widths and backgrounds
+            DisplayTableColumnGroup | DisplayTableColumn => DisplayNone,
+            v => v
+        }
+    }
+
     /** entry point for box creation. Should only be 
     called on root DOM element. */
     fn construct_trees(layout_ctx: &LayoutContext, root: Node) -> Result<@FlowContext, ()> {
         self.root_ctx = Some(self.make_ctx(RootFlow(RootFlowData()), tree::empty()));
+        self.root_ctx.get().node = Some(root);
 
         self.construct_recursively(layout_ctx, root, self.root_ctx.get(), None);
         return Ok(self.root_ctx.get())
@@ -197,14 +436,15 @@ impl LayoutTreeBuilder {
     /* Based on the DOM node type, create a specific type of box */
     fn create_box_data(layout_ctx: &LayoutContext, node: Node, display: CSSDisplay) -> BoxData {
         // TODO: handle more types of nodes.
+        let style = node.computed_style();
         do node.read |n| {
             match n.kind {
                 ~Doctype(*) | ~Comment(*) => fail ~"Hey, doctypes and comments shouldn't get here! They are display:none!",
                 ~Text(string) => {
-                    // TODO: clean this up. Fonts should not be created here.
-                    let font = layout_ctx.font_cache.get_test_font();
-                    let run = TextRun(font, string);
-                    TextBox(TextBoxData(copy string, ~[move run]))
+                    let font = layout_ctx.font_cache.get_font(&font_descriptor(&style));
+                    let text = process_white_space(string, style.white_space);
+                    let text = apply_text_transform(text, style.text_transform);
+                    TextBox(TextBoxData(font, TextRun(font, move text)))
                 }
                 ~Element(element) => {
                     match (element.kind, display) {
@@ -13,11 +13,12 @@ use geom::rect::Rect;
 use geom::size::Size2D;
 use layout::box::{RenderBox, RenderBoxTree, TextBox};
 use layout::context::LayoutContext;
-use layout::flow::FlowContext;
+use layout::flow::{FlowContext, FlowTree};
 use layout::text::TextBoxData;
 use servo_text::text_run::TextRun;
+use std::sort::merge_sort;
 use util::tree;
-use vec::push;
+use vec::{push, push_all};
 
 /** A builder object that manages display list builder should mainly
  hold information about the initial request and desired result---for
@@ -31,9 +32,26 @@ struct DisplayListBuilder {
 }
 
 
+/* A positioned flow to paint as part of a stacking context, with the
+dirty rect and offset it would have been painted with in tree order */
+struct PositionedFlow {
+    flow: @FlowContext,
+    z_index: int,
+    dirty: Rect<au>,
+    offset: Point2D<au>
+}
+
+pure fn z_order_le(a: &PositionedFlow, b: &PositionedFlow) -> bool {
+    a.z_index <= b.z_index
+}
+
 trait FlowDisplayListBuilderMethods {
     fn build_display_list(a: &DisplayListBuilder, b: &Rect<au>, c: &dl::DisplayList);
 
+    fn build_stacking_context(a: &DisplayListBuilder, b: &Rect<au>, c: &Point2D<au>,
+                              d: &dl::DisplayList);
+    fn collect_positioned(a: &Rect<au>, b: &Point2D<au>) -> ~[PositionedFlow];
+
     fn build_display_list_for_child(a: &DisplayListBuilder, b: @FlowContext,
                                     c: &Rect<au>, d: &Point2D<au>, e: &dl::DisplayList);
 }
@@ -41,23 +59,91 @@ trait FlowDisplayListBuilderMethods {
 impl @FlowContext: FlowDisplayListBuilderMethods {
 
     fn build_display_list(builder: &DisplayListBuilder, dirty: &Rect<au>, list: &dl::DisplayList) {
-        let zero = au::zero_point();
-        self.build_display_list_recurse(builder, dirty, &zero, list);
+        self.build_stacking_context(builder, dirty, &Point2D(au(0), au(0)), list);
+    }
+
+    /* Paints the root flow or a positioned flow as a stacking context (CSS
+    2.1, Appendix E): its own box, then the positioned descendants with a
+    negative z-index, then the normal flow, and then the other positioned
+    descendants. Positioned descendants are painted in z-index order, and
+    in tree order when their z-indices are equal. */
+    // TODO: positioned descendants with 'z-index: auto' should not start their own
+    // stacking context, and floats and inline content have their own layers
+    fn build_stacking_context(builder: &DisplayListBuilder, dirty: &Rect<au>,
+                              offset: &Point2D<au>, list: &dl::DisplayList) {
+        let positioned = merge_sort(z_order_le, self.collect_positioned(dirty, offset));
+
+        do self.with_block_box |box| {
+            box.build_display_list(builder, dirty, offset, list)
+        }
+
+        for positioned.each |p| {
+            if p.z_index < 0 {
+                p.flow.build_stacking_context(builder, &p.dirty, &p.offset, list);
+            }
+        }
+
+        for FlowTree.each_child(self) |child| {
+            self.build_display_list_for_child(builder, child, dirty, offset, list)
+        }
+
+        for positioned.each |p| {
+            if p.z_index >= 0 {
+                p.flow.build_stacking_context(builder, &p.dirty, &p.offset, list);
+            }
+        }
+    }
+
+    /* Finds the positioned descendants that belong to this stacking context,
+    in tree order. Those of positioned descendants belong to theirs. */
+    fn collect_positioned(dirty: &Rect<au>, offset: &Point2D<au>) -> ~[PositionedFlow] {
+        let mut found = ~[];
+
+        for FlowTree.each_child(self) |child| {
+            let adj_dirty = to_child_coords(dirty, child);
+            let adj_offset = offset.add(&child.data.position.origin);
+
+            if child.is_positioned() {
+                push(found, PositionedFlow {
+                    flow: child,
+                    z_index: child.z_index(),
+                    dirty: adj_dirty,
+                    offset: adj_offset
+                });
+            } else {
+                push_all(found, child.collect_positioned(&adj_dirty, &adj_offset));
+            }
+        }
+
+        found
     }
 
     fn build_display_list_for_child(builder: &DisplayListBuilder, child: @FlowContext,
                                     dirty: &Rect<au>, offset: &Point2D<au>, list: &dl::DisplayList) {
 
+        // positioned flows are painted by their stacking context
+        if child.is_positioned() {
+            return;
+        }
+
         // adjust the dirty rect to child flow context coordinates
-        let adj_dirty = dirty.translate(&child.data.position.origin);
+        let adj_dirty = to_child_coords(dirty, child);
         let adj_offset = offset.add(&child.data.position.origin);
 
-        if (adj_dirty.intersects(&child.data.position)) {
+        // the position of the child is in the coordinates of this flow, like the dirty rect
+        if (dirty.intersects(&child.data.position)) {
             child.build_display_list_recurse(builder, &adj_dirty, &adj_offset, list);
         }
     }
 }
 
+// Flows place their boxes and children relative to their own origin
+fn to_child_coords(dirty: &Rect<au>, child: @FlowContext) -> Rect<au> {
+    let origin = child.data.position.origin;
+    Rect(Point2D(dirty.origin.x.sub(origin.x), dirty.origin.y.sub(origin.y)),
+         copy dirty.size)
+}
+
 /* TODO: redo unit tests, if possible?gn
 
 fn should_convert_text_boxes_to_solid_color_background_items() {
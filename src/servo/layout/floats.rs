@@ -0,0 +1,282 @@
+use au = gfx::geometry;
+use css::styles::StyleMethods;
+use css::values::{CSSFloat, FloatLeft, FloatRight, FloatNone};
+use css::values::{CSSClear, ClearLeft, ClearRight, ClearBoth, ClearNone};
+use dl = gfx::display_list;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use gfx::geometry::au;
+use layout::box::RenderBox;
+use layout::context::LayoutContext;
+use layout::flow::{FlowContext, FloatFlow};
+
+/* Floats (CSS 2.1, Section 9.5) are laid out like blocks that start
+their own block formatting context, but are placed by the block flow
+containing them: as far up, and as far to their side, as the floats
+already placed in that context allow. Line boxes beside a float are
+shortened to make room for it. */
+
+struct FloatFlowData {
+    mut box: Option<@RenderBox>
+}
+
+fn FloatFlowData() -> FloatFlowData {
+    FloatFlowData {
+        box: None
+    }
+}
+
+/* The floats placed so far in a block formatting context: the margin
+boxes of the left and right floats, in the order they were placed.
+Coordinates are relative to the content box of the flow being laid
+out. */
+struct FloatContext {
+    mut left: ~[Rect<au>],
+    mut right: ~[Rect<au>]
+}
+
+fn FloatContext() -> FloatContext {
+    FloatContext {
+        left: ~[],
+        right: ~[]
+    }
+}
+
+/* Whether a float's margin box is beside a box of the given height at `y`.
+   Empty boxes still need the room for their line. */
+pure fn is_beside(rect: &Rect<au>, y: au, height: au) -> bool {
+    let bottom = y.add(au::max(height, au(1)));
+    rect.origin.y < bottom && rect.origin.y.add(rect.size.height) > y
+}
+
+pure fn lowest_bottom(rects: &[Rect<au>], y: au) -> au {
+    do vec::foldl(y, rects) |y, rect| {
+        au::max(y, rect.origin.y.add(rect.size.height))
+    }
+}
+
+impl FloatContext {
+    pure fn is_empty() -> bool {
+        self.left.is_empty() && self.right.is_empty()
+    }
+
+    /* Moves the floats by `offset`, e.g. into the coordinates of a child flow */
+    fn translate(offset: &Point2D<au>) -> FloatContext {
+        let move = |rect: &Rect<au>| {
+            Rect(Point2D(rect.origin.x.add(offset.x), rect.origin.y.add(offset.y)),
+                 copy rect.size)
+        };
+        FloatContext {
+            left: self.left.map(move),
+            right: self.right.map(move)
+        }
+    }
+
+    /* The position below the floats on the sides given by 'clear', or `y`
+       if it is already below them (CSS 2.1, Section 9.5.2) */
+    fn clear(clear: CSSClear, y: au) -> au {
+        match clear {
+            ClearLeft => lowest_bottom(self.left, y),
+            ClearRight => lowest_bottom(self.right, y),
+            ClearBoth => lowest_bottom(self.right, lowest_bottom(self.left, y)),
+            ClearNone => y
+        }
+    }
+
+    /* The bottom of the lowest float. The flow that starts a block formatting
+       context grows to contain its floats (CSS 2.1, Section 10.6.7). */
+    fn bottom() -> au {
+        self.clear(ClearBoth, au(0))
+    }
+
+    /* The horizontal extent (left, right) of the space that the floats leave
+       beside a box of the given height at `y`, in a containing block of the
+       given width. */
+    fn available_space(y: au, height: au, width: au) -> (au, au) {
+        let mut left = au(0);
+        let mut right = width;
+
+        for self.left.each |rect| {
+            if is_beside(rect, y, height) {
+                left = au::max(left, rect.origin.x.add(rect.size.width));
+            }
+        }
+        for self.right.each |rect| {
+            if is_beside(rect, y, height) {
+                right = au::min(right, rect.origin.x);
+            }
+        }
+
+        (left, right)
+    }
+
+    /* Finds the highest position, at or below `y`, where a box of the given
+       size fits beside the floats. Returns the position and the extent of the
+       space there. A box that is wider than the space left by every float is
+       placed below all of them. */
+    fn find_space(y: au, size: &Size2D<au>, width: au) -> (au, au, au) {
+        let mut y = y;
+        loop {
+            let (left, right) = self.available_space(y, size.height, width);
+            if right.sub(left) >= size.width {
+                return (y, left, right);
+            }
+
+            // Try again below the first float to end
+            let mut next = None;
+            for (self.left + self.right).each |rect| {
+                if is_beside(rect, y, size.height) {
+                    let bottom = rect.origin.y.add(rect.size.height);
+                    next = match next {
+                        Some(n) if n < bottom => Some(n),
+                        _ => Some(bottom)
+                    };
+                }
+            }
+
+            match next {
+                Some(bottom) => y = bottom,
+                None => return (y, left, right)
+            }
+        }
+    }
+
+    /* Places a float whose margin box has the given size, no higher than `y`,
+       in a containing block of the given width. Returns the position of its
+       margin box (CSS 2.1, Section 9.5.1). */
+    fn place_float(side: CSSFloat, size: &Size2D<au>, y: au, width: au) -> Point2D<au> {
+        // A float is never higher than one placed before it
+        let mut y = y;
+        for (self.left + self.right).each |rect| {
+            y = au::max(y, rect.origin.y);
+        }
+
+        let (y, left, right) = self.find_space(y, size, width);
+        match side {
+            FloatRight => {
+                let origin = Point2D(right.sub(size.width), y);
+                self.right += ~[Rect(copy origin, copy *size)];
+                origin
+            }
+            FloatLeft | FloatNone => {
+                let origin = Point2D(left, y);
+                self.left += ~[Rect(copy origin, copy *size)];
+                origin
+            }
+        }
+    }
+}
+
+trait FloatLayout {
+    pure fn starts_float_flow() -> bool;
+    pure fn access_float<T>(fn(&&FloatFlowData) -> T) -> T;
+    fn float_side() -> CSSFloat;
+
+    fn bubble_widths_float(ctx: &LayoutContext);
+    fn assign_widths_float(ctx: &LayoutContext);
+    fn assign_height_float(ctx: &LayoutContext);
+
+    fn build_display_list_float(a: &dl::DisplayListBuilder, b: &Rect<au>, c: &Point2D<au>, d: &dl::DisplayList);
+}
+
+impl @FlowContext : FloatLayout {
+
+    pure fn starts_float_flow() -> bool {
+        match self.kind {
+            FloatFlow(*) => true,
+            _ => false
+        }
+    }
+
+    pure fn access_float<T>(cb:fn(&&FloatFlowData) -> T) -> T {
+        match self.kind {
+            FloatFlow(d) => cb(d),
+            _  => fail fmt!("Tried to access() data of FloatFlow, but this is a %?", self.kind)
+        }
+    }
+
+    fn float_side() -> CSSFloat {
+        do self.access_float |d| {
+            match d.box {
+                Some(box) => box.node.computed_style().float_type,
+                None => FloatLeft
+            }
+        }
+    }
+
+    /* defer to the block algorithm, which shrinks floats to fit their contents */
+    fn bubble_widths_float(ctx: &LayoutContext) {
+        assert self.starts_float_flow();
+        self.bubble_widths_block(ctx)
+    }
+
+    fn assign_widths_float(ctx: &LayoutContext) {
+        assert self.starts_float_flow();
+        self.assign_widths_block(ctx)
+    }
+
+    /* A float starts a block formatting context, so its contents can be laid
+       out before the block flow containing it places it. */
+    fn assign_height_float(ctx: &LayoutContext) {
+        assert self.starts_float_flow();
+        self.assign_height_block(ctx)
+    }
+
+    fn build_display_list_float(builder: &dl::DisplayListBuilder, dirty: &Rect<au>,
+                                offset: &Point2D<au>, list: &dl::DisplayList) {
+        assert self.starts_float_flow();
+        self.build_display_list_block(builder, dirty, offset, list)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use au = gfx::geometry;
+    use css::values::{FloatLeft, FloatRight, ClearLeft, ClearRight, ClearBoth};
+    use geom::size::Size2D;
+
+    #[test]
+    fn should_place_floats_side_by_side() {
+        let px = au::from_px;
+        let floats = FloatContext();
+
+        let origin = floats.place_float(FloatLeft, &Size2D(px(100), px(50)), px(0), px(300));
+        assert (origin.x, origin.y) == (px(0), px(0));
+        let origin = floats.place_float(FloatRight, &Size2D(px(100), px(80)), px(0), px(300));
+        assert (origin.x, origin.y) == (px(200), px(0));
+        let origin = floats.place_float(FloatLeft, &Size2D(px(50), px(50)), px(0), px(300));
+        assert (origin.x, origin.y) == (px(100), px(0));
+        // Too wide to fit beside the others, so it moves below the first to end
+        let origin = floats.place_float(FloatLeft, &Size2D(px(150), px(10)), px(0), px(300));
+        assert (origin.x, origin.y) == (px(0), px(50));
+    }
+
+    #[test]
+    fn should_shorten_lines_beside_floats() {
+        let px = au::from_px;
+        let floats = FloatContext();
+        floats.place_float(FloatLeft, &Size2D(px(100), px(50)), px(0), px(300));
+        floats.place_float(FloatRight, &Size2D(px(100), px(80)), px(0), px(300));
+
+        assert floats.available_space(px(0), px(20), px(300)) == (px(100), px(200));
+        assert floats.available_space(px(60), px(20), px(300)) == (px(0), px(200));
+        assert floats.available_space(px(80), px(20), px(300)) == (px(0), px(300));
+        // A line too long for the space beside the floats is moved down
+        let (y, _, _) = floats.find_space(px(0), &Size2D(px(150), px(20)), px(300));
+        assert y == px(50);
+    }
+
+    #[test]
+    fn should_clear_floats() {
+        let px = au::from_px;
+        let floats = FloatContext();
+        floats.place_float(FloatLeft, &Size2D(px(100), px(50)), px(0), px(300));
+        floats.place_float(FloatRight, &Size2D(px(100), px(80)), px(0), px(300));
+
+        assert floats.clear(ClearLeft, px(10)) == px(50);
+        assert floats.clear(ClearRight, px(10)) == px(80);
+        assert floats.clear(ClearBoth, px(100)) == px(100);
+        assert floats.bottom() == px(80);
+    }
+}
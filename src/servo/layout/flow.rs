@@ -1,16 +1,22 @@
 use au = gfx::geometry;
 use au::au;
+use css::styles::StyleMethods;
+use css::values::{PosStatic, ZIndexAuto, ZIndexInteger};
 use dl = gfx::display_list;
 use dom::node::Node;
 use geom::rect::Rect;
 use geom::point::Point2D;
 // TODO: pub-use these
+use layout::absolute::AbsoluteFlowData;
 use layout::block::BlockFlowData;
 use layout::box::RenderBox;
 use layout::context::LayoutContext;
 use layout::debug::DebugMethods;
+use layout::floats::FloatFlowData;
 use layout::inline::InlineFlowData;
+use layout::inline_block::InlineBlockFlowData;
 use layout::root::RootFlowData;
+use layout::table::{TableFlowData, TableRowFlowData, TableCellFlowData};
 use util::tree;
 
 /** Servo's experimental layout system builds a tree of FlowContexts
@@ -38,6 +44,23 @@ Currently, the important types of flows are:
    and line breaking, and structs to represent line breaks and mapping
    to CSS boxes, for the purpose of handling `getClientRects()`.
 
+ * FloatFlow: a flow for a floated box. It lays out its children like a
+   BlockFlow, but is taken out of normal flow and placed by the block
+   flow containing it, which shortens the lines beside it.
+
+ * AbsoluteFlow: a flow for an absolutely positioned box. It too lays
+   out its children like a BlockFlow, but is a child of the flow of its
+   containing block, which offsets it from its padding box.
+
+ * InlineBlockFlow: a flow for an inline-block. Its box is put on a line
+   of the InlineFlow containing it, which places the flow along with
+   it; the flow lays out its children like a BlockFlow.
+
+ * TableFlow, TableRowFlow and TableCellFlow: the flows of a table, its
+   rows and its cells. The table sizes the columns and stacks the rows,
+   each row places its cells in the columns, and cells lay out their
+   children like a BlockFlow.
+
 */
 
 
@@ -60,13 +83,15 @@ fn FlowLayoutData() -> FlowLayoutData {
 /* The type of the formatting context, and data specific to each
 context, such as linebox structures or float lists */ 
 enum FlowContextData {
-    AbsoluteFlow, 
+    AbsoluteFlow(AbsoluteFlowData),
     BlockFlow(BlockFlowData),
-    FloatFlow,
-    InlineBlockFlow,
+    FloatFlow(FloatFlowData),
+    InlineBlockFlow(InlineBlockFlowData),
     InlineFlow(InlineFlowData),
     RootFlow(RootFlowData),
-    TableFlow
+    TableFlow(TableFlowData),
+    TableRowFlow(TableRowFlowData),
+    TableCellFlow(TableCellFlowData)
 }
 
 /* A particular kind of layout context. It manages the positioning of
@@ -103,8 +128,14 @@ impl @FlowContext {
     fn bubble_widths(ctx: &LayoutContext) {
         match self.kind {
             BlockFlow(*)  => self.bubble_widths_block(ctx),
+            FloatFlow(*)  => self.bubble_widths_float(ctx),
+            AbsoluteFlow(*) => self.bubble_widths_absolute(ctx),
             InlineFlow(*) => self.bubble_widths_inline(ctx),
+            InlineBlockFlow(*) => self.bubble_widths_inline_block(ctx),
             RootFlow(*)   => self.bubble_widths_root(ctx),
+            TableFlow(*)  => self.bubble_widths_table(ctx),
+            TableRowFlow(*) => self.bubble_widths_table_row(ctx),
+            TableCellFlow(*) => self.bubble_widths_table_cell(ctx),
             _ => fail fmt!("Tried to bubble_widths of flow: %?", self.kind)
         }
     }
@@ -112,8 +143,14 @@ impl @FlowContext {
     fn assign_widths(ctx: &LayoutContext) {
         match self.kind {
             BlockFlow(*)  => self.assign_widths_block(ctx),
+            FloatFlow(*)  => self.assign_widths_float(ctx),
+            AbsoluteFlow(*) => self.assign_widths_absolute(ctx),
             InlineFlow(*) => self.assign_widths_inline(ctx),
+            InlineBlockFlow(*) => self.assign_widths_inline_block(ctx),
             RootFlow(*)   => self.assign_widths_root(ctx),
+            TableFlow(*)  => self.assign_widths_table(ctx),
+            TableRowFlow(*) => self.assign_widths_table_row(ctx),
+            TableCellFlow(*) => self.assign_widths_table_cell(ctx),
             _ => fail fmt!("Tried to assign_widths of flow: %?", self.kind)
         }
     }
@@ -121,8 +158,14 @@ impl @FlowContext {
     fn assign_height(ctx: &LayoutContext) {
         match self.kind {
             BlockFlow(*)  => self.assign_height_block(ctx),
+            FloatFlow(*)  => self.assign_height_float(ctx),
+            AbsoluteFlow(*) => self.assign_height_absolute(ctx),
             InlineFlow(*) => self.assign_height_inline(ctx),
+            InlineBlockFlow(*) => self.assign_height_inline_block(ctx),
             RootFlow(*)   => self.assign_height_root(ctx),
+            TableFlow(*)  => self.assign_height_table(ctx),
+            TableRowFlow(*) => self.assign_height_table_row(ctx),
+            TableCellFlow(*) => self.assign_height_table_cell(ctx),
             _ => fail fmt!("Tried to assign_height of flow: %?", self.kind)
         }
     }
@@ -132,7 +175,13 @@ impl @FlowContext {
         match self.kind {
             RootFlow(*) => self.build_display_list_root(builder, dirty, offset, list),
             BlockFlow(*) => self.build_display_list_block(builder, dirty, offset, list),
+            FloatFlow(*) => self.build_display_list_float(builder, dirty, offset, list),
+            AbsoluteFlow(*) => self.build_display_list_absolute(builder, dirty, offset, list),
             InlineFlow(*) => self.build_display_list_inline(builder, dirty, offset, list),
+            InlineBlockFlow(*) => self.build_display_list_inline_block(builder, dirty, offset, list),
+            TableFlow(*) => self.build_display_list_table(builder, dirty, offset, list),
+            TableRowFlow(*) => self.build_display_list_table_row(builder, dirty, offset, list),
+            TableCellFlow(*) => self.build_display_list_table_cell(builder, dirty, offset, list),
             _ => fail fmt!("Tried to build_display_list_recurse of flow: %?", self.kind)
         }
     }
@@ -150,6 +199,30 @@ impl @FlowContext {
                 Some(box)  if box.node == node => { blk(seed, box) },
                 _ => seed
             },
+            FloatFlow(d) => match d.box {
+                Some(box)  if box.node == node => { blk(seed, box) },
+                _ => seed
+            },
+            AbsoluteFlow(d) => match d.box {
+                Some(box)  if box.node == node => { blk(seed, box) },
+                _ => seed
+            },
+            InlineBlockFlow(d) => match d.box {
+                Some(box)  if box.node == node => { blk(seed, box) },
+                _ => seed
+            },
+            TableFlow(d) => match d.box {
+                Some(box)  if box.node == node => { blk(seed, box) },
+                _ => seed
+            },
+            TableRowFlow(d) => match d.box {
+                Some(box)  if box.node == node => { blk(seed, box) },
+                _ => seed
+            },
+            TableCellFlow(d) => match d.box {
+                Some(box)  if box.node == node => { blk(seed, box) },
+                _ => seed
+            },
             InlineFlow(d) => do d.boxes.foldl(seed) |acc, box| {
                 if box.node == node { blk(acc, box) }
                 else { acc }
@@ -158,6 +231,34 @@ impl @FlowContext {
         }
     }
 
+    /* Whether the flow is positioned (CSS 2.1, Section 9.3.1), and so painted
+    apart from the normal flow, in z-index order */
+    fn is_positioned() -> bool {
+        match self.kind {
+            AbsoluteFlow(*) => true,
+            BlockFlow(*) | FloatFlow(*) => {
+                let mut positioned = false;
+                do self.with_block_box |box| {
+                    positioned = box.node.computed_style().position != PosStatic;
+                }
+                positioned
+            }
+            _ => false
+        }
+    }
+
+    /* The stack level of a positioned flow. 'auto' paints with level zero. */
+    fn z_index() -> int {
+        let mut z_index = 0;
+        do self.with_block_box |box| {
+            z_index = match box.node.computed_style().z_index {
+                ZIndexInteger(n) => n,
+                ZIndexAuto => 0
+            };
+        }
+        z_index
+    }
+
     pure fn iter_boxes_for_node<T>(node: Node, cb: pure fn&(@RenderBox) -> T) {
         match self.kind {
             RootFlow(d) => match d.box {
@@ -168,6 +269,30 @@ impl @FlowContext {
                 Some(box) if box.node == node => { cb(box); },
                 _ => {}
             },
+            FloatFlow(d) => match d.box {
+                Some(box) if box.node == node => { cb(box); },
+                _ => {}
+            },
+            AbsoluteFlow(d) => match d.box {
+                Some(box) if box.node == node => { cb(box); },
+                _ => {}
+            },
+            InlineBlockFlow(d) => match d.box {
+                Some(box) if box.node == node => { cb(box); },
+                _ => {}
+            },
+            TableFlow(d) => match d.box {
+                Some(box) if box.node == node => { cb(box); },
+                _ => {}
+            },
+            TableRowFlow(d) => match d.box {
+                Some(box) if box.node == node => { cb(box); },
+                _ => {}
+            },
+            TableCellFlow(d) => match d.box {
+                Some(box) if box.node == node => { cb(box); },
+                _ => {}
+            },
             InlineFlow(d) => {
                 for d.boxes.each |box| {
                     if box.node == node { cb(*box); }
@@ -239,6 +364,42 @@ impl @FlowContext : DebugMethods {
                     None => ~"BlockFlow",
                 }
             },
+            FloatFlow(d) => {
+                match d.box {
+                    Some(_b) => fmt!("FloatFlow(box=b%?)", d.box.get().id),
+                    None => ~"FloatFlow",
+                }
+            },
+            AbsoluteFlow(d) => {
+                match d.box {
+                    Some(_b) => fmt!("AbsoluteFlow(box=b%?)", d.box.get().id),
+                    None => ~"AbsoluteFlow",
+                }
+            },
+            InlineBlockFlow(d) => {
+                match d.box {
+                    Some(_b) => fmt!("InlineBlockFlow(box=b%?)", d.box.get().id),
+                    None => ~"InlineBlockFlow",
+                }
+            },
+            TableFlow(d) => {
+                match d.box {
+                    Some(_b) => fmt!("TableFlow(box=b%?)", d.box.get().id),
+                    None => ~"TableFlow",
+                }
+            },
+            TableRowFlow(d) => {
+                match d.box {
+                    Some(_b) => fmt!("TableRowFlow(box=b%?)", d.box.get().id),
+                    None => ~"TableRowFlow",
+                }
+            },
+            TableCellFlow(d) => {
+                match d.box {
+                    Some(_b) => fmt!("TableCellFlow(box=b%?)", d.box.get().id),
+                    None => ~"TableCellFlow",
+                }
+            },
             _ => fmt!("%?", self.kind)
         };
             
@@ -1,6 +1,14 @@
 use au = gfx::geometry;
 use core::dvec::DVec;
-use css::values::{BoxAuto, BoxLength, Px};
+use css::styles::{ComputedStyle, StyleMethods};
+use css::values::{BoxAuto, BoxLength, Px, Em, DisplayInline};
+use css::values::{CSSTextAlign, TextAlignStart, TextAlignEnd, TextAlignLeft, TextAlignRight};
+use css::values::{TextAlignCenter, TextAlignJustify, DirectionLtr, DirectionRtl};
+use css::values::{UnicodeBidiNormal, UnicodeBidiOverride};
+use css::values::{CSSVerticalAlign, VerticalAlignBaseline, VerticalAlignSub, VerticalAlignSuper};
+use css::values::{VerticalAlignTop, VerticalAlignTextTop, VerticalAlignMiddle};
+use css::values::{VerticalAlignBottom, VerticalAlignTextBottom, VerticalAlignLength};
+use css::values::VerticalAlignPercent;
 use dl = gfx::display_list;
 use dom::rcu;
 use geom::point::Point2D;
@@ -8,9 +16,16 @@ use geom::rect::Rect;
 use geom::size::Size2D;
 use gfx::geometry::au;
 use layout::box::{RenderBox, RenderBoxTree, ImageBox, TextBox, GenericBox};
-use layout::flow::{FlowContext, InlineFlow};
+use layout::floats::{FloatContext, is_beside};
+use css::values::{FloatLeft, FloatRight, FloatNone};
+use layout::flow::{FlowContext, FlowTree, InlineFlow, InlineBlockFlow};
 use layout::context::LayoutContext;
+use layout::text::{collapses_spaces, wraps_lines, font_descriptor, get_line_height};
+use layout::text::get_text_baseline;
 use num::Num;
+use servo_text::bidi::{BidiClass, BidiB, BidiBN, BidiON, BidiLRE, BidiLRO, BidiRLE, BidiRLO};
+use servo_text::bidi::{BidiPDF, bidi_class, resolve_levels, reorder};
+use servo_text::text_run::{line_break_opportunities, trim_end};
 use util::tree;
 
 /*
@@ -37,13 +52,586 @@ serve as the starting point, but the current design doesn't make it
 hard to try out that alternative.
 */
 
+/* A line box (CSS 2.1, Section 9.4.2): the range of the flow's boxes
+that are on the line, and the bounds of the line and its baseline, from
+the top of the line, in the coordinates of the flow. */
+struct LineBox {
+    start: uint,
+    length: uint,
+    bounds: Rect<au>,
+    baseline: au
+}
+
+/* A float found among the inline content of the flow (CSS 2.1, Section
+9.5.1): its FloatFlow, a child of the inline flow, and the box that
+follows it, if any. The float is placed when the lines reach that box,
+which stays the first part of its text when it is split. */
+struct InlineFloat {
+    flow: @FlowContext,
+    mut before: Option<@RenderBox>
+}
+
 struct InlineFlowData {
-    boxes: ~DVec<@RenderBox>
+    boxes: ~DVec<@RenderBox>,
+    lines: ~DVec<LineBox>,
+    floats: ~DVec<InlineFloat>
 }
 
 fn InlineFlowData() -> InlineFlowData {
     InlineFlowData {
-        boxes: ~DVec()
+        boxes: ~DVec(),
+        lines: ~DVec(),
+        floats: ~DVec()
+    }
+}
+
+/* Where a box sits on its line (CSS 2.1, Section 10.8.1): with its baseline
+raised some way above that of the line, or some way below the top or above
+the bottom of the line, when it or an inline box containing it is aligned
+with that edge. */
+enum LineAlignment {
+    AlignBaseline(au),
+    AlignTop(au),
+    AlignBottom(au)
+}
+
+/* The relative offset of a box and of the inline boxes containing it, whose
+contents move with them (CSS 2.1, Section 9.4.3). Offsets of the block
+containing the inline flow are applied to the whole flow instead. */
+fn get_inline_relative_offset(box: @RenderBox, containing_block: &Size2D<au>) -> Point2D<au> {
+    let mut offset = box.get_relative_offset(containing_block);
+    let mut parent = tree::get_parent(RenderBoxTree, box);
+    loop {
+        match parent {
+            Some(parent_box) => {
+                match parent_box.node.computed_style().display_type {
+                    DisplayInline => {}
+                    _ => return offset
+                }
+                offset = offset.add(&parent_box.get_relative_offset(containing_block));
+                parent = tree::get_parent(RenderBoxTree, parent_box);
+            }
+            None => return offset
+        }
+    }
+}
+
+/* The flow of an inline-block box: the child InlineBlockFlow of the inline
+flow holding the box that lays out its contents. */
+fn get_inline_block_flow(inline_ctx: @FlowContext, box: @RenderBox) -> Option<@FlowContext> {
+    for FlowTree.each_child(inline_ctx) |child_ctx| {
+        match child_ctx.kind {
+            InlineBlockFlow(d) => match d.box {
+                Some(b) if core::box::ptr_eq(b, box) => return Some(child_ctx),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    None
+}
+
+/* How far 'vertical-align' raises the baseline of a box of the given
+height and baseline above that of its parent, whose text has the given
+line height. 'sub' and 'super' lower and raise it by a fifth and a third of
+the font size, and the x-height is taken to be half of the font size. The
+text is taken to be as high as its line height, with its baseline at the
+bottom. */
+fn vertical_raise(align: CSSVerticalAlign, font_size: float, line_height: au, height: au,
+                  baseline: au) -> au {
+    match align {
+        VerticalAlignBaseline | VerticalAlignTop | VerticalAlignBottom => au(0),
+        VerticalAlignSub => au::from_frac_px(-font_size / 5.0),
+        VerticalAlignSuper => au::from_frac_px(font_size / 3.0),
+        VerticalAlignLength(Px(length)) => au::from_frac_px(length),
+        VerticalAlignLength(Em(length)) => au::from_frac_px(length * font_size),
+        VerticalAlignPercent(percent) => {
+            au::from_frac_px(au::to_frac_px(line_height) * percent / 100.0)
+        }
+        // The middle of the box goes half the x-height above the baseline
+        VerticalAlignMiddle => {
+            au::from_frac_px(font_size / 4.0).add(au(*height / 2)).sub(baseline)
+        }
+        VerticalAlignTextTop => line_height.sub(baseline),
+        VerticalAlignTextBottom => height.sub(baseline)
+    }
+}
+
+/* Where a box whose baseline is raised the given amount above that of an
+inline box with the given 'vertical-align' sits on the line, if that is
+'top' or 'bottom'. The inline box's text is as high as the line height, with
+its baseline at the bottom, and the box stays inside the line. */
+pure fn align_with_line_edge(align: CSSVerticalAlign, raise: au, height: au, baseline: au,
+                             line_height: au) -> Option<LineAlignment> {
+    match align {
+        VerticalAlignTop => Some(AlignTop(au::max(au(0), line_height.sub(baseline).sub(raise)))),
+        VerticalAlignBottom => Some(AlignBottom(au::max(au(0),
+                                                        raise.add(baseline).sub(height)))),
+        _ => None
+    }
+}
+
+/* How a box sits on its line: by its own 'vertical-align', raised along
+with the inline boxes containing it up to the first of them aligned with the
+top or bottom of the line. */
+fn get_line_alignment(box: @RenderBox, height: au, baseline: au, line_height: au) -> LineAlignment {
+    let style = box.node.computed_style();
+    match align_with_line_edge(style.vertical_align, au(0), height, baseline, line_height) {
+        Some(align) => return align,
+        None => {}
+    }
+
+    let mut raise = vertical_raise(style.vertical_align, style.font_size, line_height, height,
+                                   baseline);
+    let mut parent = tree::get_parent(RenderBoxTree, box);
+    loop {
+        match parent {
+            Some(parent_box) => {
+                let parent_style = parent_box.node.computed_style();
+                match parent_style.display_type {
+                    DisplayInline => {}
+                    _ => break
+                }
+                match align_with_line_edge(parent_style.vertical_align, raise, height, baseline,
+                                           line_height) {
+                    Some(align) => return align,
+                    None => {}
+                }
+                raise = raise.add(vertical_raise(parent_style.vertical_align,
+                                                 parent_style.font_size, line_height,
+                                                 line_height, line_height));
+                parent = tree::get_parent(RenderBoxTree, parent_box);
+            }
+            None => break
+        }
+    }
+    AlignBaseline(raise)
+}
+
+/* The size and baseline of a box on a line: those of its flow for an
+inline-block, those of its line height and font for text, and otherwise of
+the box, with the baseline at the bottom. */
+fn get_line_metrics(inline_ctx: @FlowContext, box: @RenderBox) -> (Size2D<au>, au) {
+    match get_inline_block_flow(inline_ctx, box) {
+        Some(ctx) => return (ctx.data.position.size, ctx.get_inline_block_baseline()),
+        None => {}
+    }
+    match box.kind {
+        TextBox(d) => (box.data.position.size, get_text_baseline(box.data.position.size.height,
+                                                                 d.font)),
+        _ => (box.data.position.size, box.data.position.size.height)
+    }
+}
+
+/* The style of the block container whose lines an inline flow lays out: that
+of the nearest flow above it made for an element. Its text-align applies to
+the lines, and its font and line height make their strut. */
+fn get_container_style(inline_ctx: @FlowContext) -> ComputedStyle {
+    let mut ctx = inline_ctx;
+    loop {
+        match ctx.node {
+            Some(node) => return node.computed_style(),
+            None => ctx = tree::get_parent(FlowTree, ctx).get()
+        }
+    }
+}
+
+/* The inline boxes around a box whose 'unicode-bidi' opens an embedding or
+an override for their contents, outermost first */
+fn get_bidi_embeddings(box: @RenderBox) -> ~[@RenderBox] {
+    let mut embeddings = ~[];
+    let mut parent = tree::get_parent(RenderBoxTree, box);
+    loop {
+        match parent {
+            Some(parent_box) => {
+                let style = parent_box.node.computed_style();
+                match style.display_type {
+                    DisplayInline => {}
+                    _ => break
+                }
+                if style.unicode_bidi != UnicodeBidiNormal {
+                    vec::push(embeddings, parent_box);
+                }
+                parent = tree::get_parent(RenderBoxTree, parent_box);
+            }
+            None => break
+        }
+    }
+    vec::reversed(embeddings)
+}
+
+/* The explicit formatting character that stands for an element opening an
+embedding or override with the given style (CSS 2.1, Section 9.10) */
+fn get_embedding_class(style: &ComputedStyle) -> BidiClass {
+    match (style.direction, style.unicode_bidi) {
+        (DirectionLtr, UnicodeBidiOverride) => BidiLRO,
+        (DirectionRtl, UnicodeBidiOverride) => BidiRLO,
+        (DirectionLtr, _) => BidiLRE,
+        (DirectionRtl, _) => BidiRLE
+    }
+}
+
+/* Resolves the embedding levels of the boxes of an inline flow with the
+Unicode bidirectional algorithm (UAX #9), as one paragraph in the
+'direction' of its block container, which preserved newlines end. Inline
+boxes whose 'unicode-bidi' is not 'normal' embed or override the direction
+of their contents, images and inline-blocks are neutral, and other boxes
+take the level around them. Text boxes are split where their level or script
+changes, and shaped in their direction; the boxes are returned in order. */
+fn resolve_bidi_levels(inline_ctx: @FlowContext, boxes: &[@RenderBox]) -> ~[@RenderBox] {
+    let style = get_container_style(inline_ctx);
+    let para_level = match style.direction {
+        DirectionLtr => 0u,
+        DirectionRtl => 1u
+    };
+    let container_override = match style.unicode_bidi {
+        UnicodeBidiOverride => Some(get_embedding_class(&style)),
+        _ => None
+    };
+
+    let mut classes = ~[];
+    // Where the characters of each box start in the paragraph
+    let mut starts = ~[];
+    // The inline boxes whose embeddings are open
+    let mut open: ~[@RenderBox] = ~[];
+    let mut para_start = true;
+    for boxes.each |box| {
+        if para_start {
+            match container_override {
+                Some(class) => vec::push(classes, class),
+                None => {}
+            }
+            para_start = false;
+        }
+
+        let embeddings = get_bidi_embeddings(*box);
+        let mut common = 0u;
+        while common < open.len() && common < embeddings.len()
+              && core::box::ptr_eq(open[common], embeddings[common]) {
+            common += 1;
+        }
+        while open.len() > common {
+            vec::pop(open);
+            vec::push(classes, BidiPDF);
+        }
+        for uint::range(common, embeddings.len()) |i| {
+            vec::push(classes, get_embedding_class(&embeddings[i].node.computed_style()));
+            vec::push(open, embeddings[i]);
+        }
+
+        vec::push(starts, classes.len());
+        if get_inline_block_flow(inline_ctx, *box).is_some() {
+            vec::push(classes, BidiON);
+            loop;
+        }
+        match box.kind {
+            TextBox(d) if d.text.is_not_empty() => {
+                for str::each_char(d.text) |c| {
+                    vec::push(classes, bidi_class(c));
+                }
+            }
+            ImageBox(*) => vec::push(classes, BidiON),
+            TextBox(*) | GenericBox(*) => vec::push(classes, BidiBN)
+        }
+        match box.kind {
+            TextBox(d) if d.forced_break => {
+                // The separator ends the embeddings, which the next box opens again
+                vec::push(classes, BidiB);
+                open = ~[];
+                para_start = true;
+            }
+            _ => {}
+        }
+    }
+
+    let levels = resolve_levels(classes, para_level);
+    let mut result = ~[];
+    for boxes.eachi |i, box| {
+        let start = starts[i];
+        let inline_block = get_inline_block_flow(inline_ctx, *box).is_some();
+        match box.kind {
+            TextBox(d) if d.text.is_not_empty() && !inline_block => {
+                let end = start + str::char_len(d.text);
+                result += box.split_at_bidi_runs(vec::view(levels, start, end));
+            }
+            _ => {
+                box.data.bidi_level = levels[start];
+                vec::push(result, *box);
+            }
+        }
+    }
+    result
+}
+
+/* The number of spaces in the text of a box, which justification widens */
+fn count_spaces(box: @RenderBox) -> uint {
+    match box.kind {
+        TextBox(d) => {
+            let mut spaces = 0u;
+            for str::each_char(d.text) |c| {
+                if c == ' ' { spaces += 1; }
+            }
+            spaces
+        }
+        _ => 0
+    }
+}
+
+/* Whether a box leaves its line empty, so that the line has no height
+(CSS 2.1, Section 9.4.2): text that is empty, or only collapsible spaces,
+and inline boxes, which do not have widths yet. */
+fn is_empty_on_line(inline_ctx: @FlowContext, box: @RenderBox) -> bool {
+    if get_inline_block_flow(inline_ctx, box).is_some() {
+        return false;
+    }
+    match box.kind {
+        TextBox(d) if d.forced_break => false,
+        TextBox(d) if collapses_spaces(box.node.computed_style().white_space) => {
+            str::is_whitespace(d.text)
+        }
+        TextBox(d) => d.text.is_empty(),
+        GenericBox(*) => true,
+        ImageBox(*) => false
+    }
+}
+
+/* The width a line starting with the given box needs: that of the box, or
+of its text up to the first line break opportunity. */
+fn get_line_start_width(inline_ctx: @FlowContext, box: @RenderBox) -> au {
+    match get_inline_block_flow(inline_ctx, box) {
+        Some(ctx) => return ctx.data.position.size.width,
+        None => {}
+    }
+    match box.kind {
+        TextBox(d) if wraps_lines(box.node.computed_style().white_space) => {
+            for line_break_opportunities(d.text).each |idx| {
+                if trim_end(d.text, *idx) > 0 {
+                    return d.run.prefix_width(*idx);
+                }
+            }
+            d.run.prefix_width(d.text.len())
+        }
+        _ => box.data.position.size.width
+    }
+}
+
+/* Places a float found among the inline content at `y` or below, clear of
+the floats it has to clear (CSS 2.1, Section 9.5.1). */
+fn place_inline_float(float_ctx: @FlowContext, floats: &FloatContext, y: au, width: au) {
+    let y = floats.clear(float_ctx.clear_type(), y);
+    float_ctx.data.position.origin = floats.place_float(float_ctx.float_side(),
+                                                        &float_ctx.data.position.size, y, width);
+}
+
+/* Places a float beside the open line that starts at box `start`, if it
+fits in the room the boxes on the line leave, and returns the narrowed
+extent (left, right) of the line. The boxes move right of a left float.
+Returns None if the float has to wait for the next line. */
+fn place_float_beside_line(inline_ctx: @FlowContext, float_ctx: @FlowContext,
+                           floats: &FloatContext, boxes: &[@RenderBox], start: uint,
+                           line: &Rect<au>, used: au, width: au) -> Option<(au, au)> {
+    let size = float_ctx.data.position.size;
+    let left = line.origin.x;
+    let right = left.add(line.size.width);
+    let y = line.origin.y;
+    if size.width > line.size.width.sub(used) || floats.clear(float_ctx.clear_type(), y) > y {
+        return None;
+    }
+
+    let origin = floats.place_float(float_ctx.float_side(), &size, y, width);
+    float_ctx.data.position.origin = origin;
+
+    // Earlier floats in the way may have pushed it below the line
+    if !is_beside(&Rect(origin, size), y, line.size.height) {
+        return Some((left, right));
+    }
+    match float_ctx.float_side() {
+        FloatRight => Some((left, au::min(right, origin.x))),
+        FloatLeft | FloatNone => {
+            let float_right = origin.x.add(size.width);
+            if float_right <= left {
+                return Some((left, right));
+            }
+            let shift = float_right.sub(left);
+            for uint::range(start, boxes.len()) |i| {
+                match get_inline_block_flow(inline_ctx, boxes[i]) {
+                    Some(ctx) => {
+                        ctx.data.position.origin.x = ctx.data.position.origin.x.add(shift)
+                    }
+                    None => {
+                        boxes[i].data.position.origin.x = boxes[i].data.position.origin.x.add(shift)
+                    }
+                }
+            }
+            Some((float_right, right))
+        }
+    }
+}
+
+/* The strut of a line (CSS 2.1, Section 10.8.1): the height and baseline
+of the text of the block container itself. */
+struct Strut {
+    height: au,
+    baseline: au
+}
+
+/* How the boxes of a line are placed across it: by the 'text-align' of the
+block container, whether the line is justified if that is 'justify', and
+whether the container is laid out from right to left, where lines start */
+struct LineAlign {
+    text_align: CSSTextAlign,
+    justify: bool,
+    rtl: bool
+}
+
+/* Places the boxes of a line, from `start` on, and returns its line box. A
+collapsible space at the end of the line is removed, and the boxes are
+moved across the `room` there is on the line by its 'text-align' (CSS 2.1,
+Section 16.2); justified lines widen their spaces. Boxes of mixed direction
+are then put in visual order (UAX #9, rule L2), and placed vertically
+(Section 10.8). The line is as high as the boxes on it and
+the strut, unless it is empty. */
+fn finish_line(inline_ctx: @FlowContext, boxes: &[@RenderBox], start: uint, left: au, y: au,
+               width: au, room: au, strut: &Strut, align: &LineAlign,
+               containing_block: &Size2D<au>) -> LineBox {
+    let mut width = width;
+    let mut i = boxes.len();
+    while i > start {
+        i -= 1;
+        let box = boxes[i];
+        match box.kind {
+            GenericBox(*) if get_inline_block_flow(inline_ctx, box).is_none() => loop,
+            TextBox(d) if collapses_spaces(box.node.computed_style().white_space)
+                          && d.text.ends_with(~" ") => {
+                let old_width = box.data.position.size.width;
+                box.trim_text(0, d.text.len() - 1);
+                width = width.sub(old_width.sub(box.data.position.size.width));
+            }
+            _ => {}
+        }
+        break;
+    }
+
+    let slack = au::max(room.sub(width), au(0));
+    let spaces = if align.text_align == TextAlignJustify && align.justify {
+        boxes.view(start, boxes.len()).foldl(0u, |n, box| n + count_spaces(*box))
+    } else {
+        0u
+    };
+    let word_spacing = if spaces > 0 { au(*slack / (spaces as i32)) } else { au(0) };
+    let mut shift = match align.text_align {
+        TextAlignLeft => au(0),
+        TextAlignRight => slack,
+        TextAlignCenter => au(*slack / 2),
+        // Lines that are not justified are aligned with their start
+        TextAlignStart | TextAlignJustify => if align.rtl && spaces == 0 { slack } else { au(0) },
+        TextAlignEnd => if align.rtl { au(0) } else { slack }
+    };
+    let line_left = left.add(shift);
+
+    for uint::range(start, boxes.len()) |i| {
+        match get_inline_block_flow(inline_ctx, boxes[i]) {
+            Some(ctx) => ctx.data.position.origin.x = ctx.data.position.origin.x.add(shift),
+            None => {
+                let box = boxes[i];
+                box.data.position.origin.x = box.data.position.origin.x.add(shift);
+                match box.kind {
+                    TextBox(d) => {
+                        let extra = au(*word_spacing * (count_spaces(box) as i32));
+                        d.word_spacing = word_spacing;
+                        box.data.position.size.width = box.data.position.size.width.add(extra);
+                        shift = shift.add(extra);
+                        width = width.add(extra);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if boxes.view(start, boxes.len()).any(|box| box.data.bidi_level > 0) {
+        let levels = boxes.view(start, boxes.len()).map(|box| box.data.bidi_level);
+        let mut x = line_left;
+        for reorder(levels).each |i| {
+            let box = boxes[start + *i];
+            match get_inline_block_flow(inline_ctx, box) {
+                Some(ctx) => {
+                    ctx.data.position.origin.x = x;
+                    x = x.add(ctx.data.position.size.width);
+                }
+                None => {
+                    box.data.position.origin.x = x;
+                    x = x.add(box.data.position.size.width);
+                }
+            }
+        }
+    }
+
+    let mut metrics = ~[];
+    let mut has_content = false;
+    let mut above = au(0);
+    let mut below = au(0);
+    let mut top_height = au(0);
+    let mut bottom_height = au(0);
+
+    for uint::range(start, boxes.len()) |i| {
+        let (size, baseline) = get_line_metrics(inline_ctx, boxes[i]);
+        let align = get_line_alignment(boxes[i], size.height, baseline, strut.height);
+        if !is_empty_on_line(inline_ctx, boxes[i]) {
+            has_content = true;
+            match align {
+                AlignBaseline(raise) => {
+                    above = au::max(above, baseline.add(raise));
+                    below = au::max(below, size.height.sub(baseline).sub(raise));
+                }
+                AlignTop(drop) => top_height = au::max(top_height, drop.add(size.height)),
+                AlignBottom(lift) => {
+                    bottom_height = au::max(bottom_height, lift.add(size.height))
+                }
+            }
+        }
+        vec::push(metrics, (size, baseline, align));
+    }
+
+    if has_content {
+        above = au::max(above, strut.baseline);
+        below = au::max(below, strut.height.sub(strut.baseline));
+    }
+    let mut baseline = above;
+    let mut height = above.add(below);
+    // Boxes against the bottom of the line make it taller above, and those
+    // against the top make it taller below
+    if bottom_height > height {
+        baseline = baseline.add(bottom_height.sub(height));
+        height = bottom_height;
+    }
+    height = au::max(height, top_height);
+
+    for uint::range(start, boxes.len()) |i| {
+        let (size, box_baseline, align) = metrics[i - start];
+        let top = match align {
+            AlignBaseline(raise) => baseline.sub(box_baseline).sub(raise),
+            AlignTop(drop) => drop,
+            AlignBottom(lift) => height.sub(size.height).sub(lift)
+        };
+        let offset = get_inline_relative_offset(boxes[i], containing_block);
+        match get_inline_block_flow(inline_ctx, boxes[i]) {
+            Some(ctx) => {
+                let x = ctx.data.position.origin.x;
+                ctx.data.position.origin = Point2D(x, y.add(top)).add(&offset);
+            }
+            None => {
+                let x = boxes[i].data.position.origin.x;
+                boxes[i].data.position.origin = Point2D(x, y.add(top)).add(&offset);
+            }
+        }
+    }
+
+    LineBox {
+        start: start,
+        length: boxes.len() - start,
+        bounds: Rect(Point2D(line_left, y), Size2D(width, height)),
+        baseline: baseline
     }
 }
 
@@ -53,7 +641,9 @@ trait InlineLayout {
     pure fn access_inline<T>(fn(&&InlineFlowData) -> T) -> T;
     fn bubble_widths_inline(ctx: &LayoutContext);
     fn assign_widths_inline(ctx: &LayoutContext);
+    fn assign_lines_inline(ctx: &LayoutContext, +floats: FloatContext) -> FloatContext;
     fn assign_height_inline(ctx: &LayoutContext);
+    fn get_inline_baseline() -> Option<au>;
     fn build_display_list_inline(a: &dl::DisplayListBuilder, b: &Rect<au>, c: &Point2D<au>, d: &dl::DisplayList);
 }
 
@@ -67,16 +657,51 @@ impl @FlowContext : InlineLayout {
         }
     }
 
-    fn bubble_widths_inline(_ctx: &LayoutContext) {
+    fn bubble_widths_inline(ctx: &LayoutContext) {
         assert self.starts_inline_flow();
 
         let mut min_width = au(0);
         let mut pref_width = au(0);
+        let mut line_width = au(0);
 
         do self.access_inline |d| {
+            // Text is split where fallback fonts take over, and preserved newlines
+            // end lines wherever the text ends up. Then it is split where its
+            // direction or script changes.
+            let mut boxes = ~[];
+            for d.boxes.each |box| {
+                match box.kind {
+                    TextBox(*) => {
+                        for box.split_at_font_changes(ctx.font_cache).each |run_box| {
+                            boxes += run_box.split_at_newlines();
+                        }
+                    }
+                    _ => vec::push(boxes, *box)
+                }
+            }
+            d.boxes.set(resolve_bidi_levels(self, boxes));
+
             for d.boxes.each |box| {
-                min_width = au::max(min_width, box.get_min_width());
-                pref_width = au::max(pref_width, box.get_pref_width());
+                // An inline-block is as wide as its flow says
+                let (box_min_width, box_pref_width) = match get_inline_block_flow(self, *box) {
+                    Some(block_ctx) => (block_ctx.data.min_width, block_ctx.data.pref_width),
+                    None => (box.get_min_width(), box.get_pref_width())
+                };
+
+                // Unless lines wrap, the boxes between forced breaks share one
+                min_width = au::max(min_width, box_min_width);
+                line_width = line_width.add(box_pref_width);
+                pref_width = au::max(pref_width, line_width);
+                match box.kind {
+                    TextBox(text) if text.forced_break => line_width = au(0),
+                    _ => {}
+                }
+            }
+
+            // Floats go beside the lines
+            for d.floats.each |float| {
+                min_width = au::max(min_width, float.flow.data.min_width);
+                pref_width = pref_width.add(float.flow.data.pref_width);
             }
         }
 
@@ -87,67 +712,278 @@ impl @FlowContext : InlineLayout {
     /* Recursively (top-down) determines the actual width of child
     contexts and boxes. When called on this context, the context has
     had its width set by the parent context. */
-    fn assign_widths_inline(ctx: &LayoutContext) {
+    fn assign_widths_inline(_ctx: &LayoutContext) {
         assert self.starts_inline_flow();
 
-        /* Perform inline flow with the available width. */
-        //let avail_width = self.data.position.size.width;
-
-        let line_height = au::from_px(20);
-        //let mut cur_x = au(0);
-        let mut cur_y = au(0);
-        
+        /* Size the boxes. They are put on lines, and text boxes split
+           between them, once the floats beside this flow are known; see
+           assign_lines_inline. */
         do self.access_inline |d| {
             for d.boxes.each |box| {
-                /* TODO: actually do inline flow.
-                - Create a working linebox, and successively put boxes
-                into it, splitting if necessary.
-                
-                - Set width and height for each positioned element based on 
-                where its chunks ended up.
+                // Inline-blocks are sized by their own flows
+                if get_inline_block_flow(self, *box).is_some() { loop; }
 
-                - Save the dvec of this context's lineboxes. */
-
-                /* hack: until text box splitting is hoisted into this
-                function, force "reflow" on TextBoxes.  */
-                match box.kind {
-                    TextBox(*) => box.reflow_text(ctx),
-                    _ => {}
-                }
-            
                 box.data.position.size.width = match box.kind {
                     ImageBox(img) => au::from_px(img.get_size().get_default(Size2D(0,0)).width),
-                    TextBox(d) => d.runs[0].size().width,
+                    TextBox(d) => d.run.size().width,
                     // TODO: this should be set to the extents of its children
                     GenericBox(*) => au(0)
                 };
 
                 box.data.position.size.height = match box.kind {
                     ImageBox(img) => au::from_px(img.get_size().get_default(Size2D(0,0)).height),
-                    TextBox(d) => d.runs[0].size().height,
+                    TextBox(d) => get_line_height(&box.node.computed_style(), d.font),
                     // TODO: this should be set to the extents of its children
                     GenericBox(*) => au(0)
                 };
-                
-                box.data.position.origin = Point2D(au(0), cur_y);
-                cur_y = cur_y.add(au::max(line_height, box.data.position.size.height));
             } // for boxes.each |box|
         }
 
-        self.data.position.size.height = cur_y;
-        
-        /* There are no child contexts, so stop here. */
-
-        // TODO: once there are 'inline-block' elements, this won't be
-        // true.  In that case, perform inline flow, and then set the
-        // block flow context's width as the width of the
-        // 'inline-block' box that created this flow.
+        /* The child contexts are those of inline-blocks and floats, which
+        shrink to fit within the width of this flow. */
+        for FlowTree.each_child(self) |child_ctx| {
+            child_ctx.data.position.size.width = self.data.position.size.width;
+        }
 
     } // fn assign_widths_inline
 
+    /* Packs the boxes into lines (CSS 2.1, Section 9.4.2), which are
+    shortened to leave room for the floats beside them (Section 9.5).
+    `floats` are in the coordinates of this flow. Called by the block flow
+    that contains this one, once it knows where the floats are.
+
+    Text boxes are split at line break opportunities where 'white-space'
+    lets their lines wrap, and spaces collapse across boxes and away at the
+    start of lines. The boxes of inline-blocks place their flows, which
+    have been laid out already.
+
+    Floats found among the boxes are placed as the lines reach them: beside
+    the current line if they fit there, which narrows it, or else below it.
+    Returns `floats` with them added, for the content after this flow. */
+    fn assign_lines_inline(ctx: &LayoutContext, +floats: FloatContext) -> FloatContext {
+        assert self.starts_inline_flow();
+
+        let style = get_container_style(self);
+        let rtl = style.direction == DirectionRtl;
+        let font = ctx.font_cache.get_font(&font_descriptor(&style));
+        let line_height = get_line_height(&style, font);
+        let strut = Strut { height: line_height, baseline: get_text_baseline(line_height, font) };
+        let avail_width = self.data.position.size.width;
+        let containing_block = Size2D(avail_width, au(0));
+        let mut cur_y = au(0);
+
+        do self.access_inline |d| {
+            let source = d.boxes.get();
+            let mut boxes = ~[];
+            d.lines.set(~[]);
+
+            let mut next = 0u;
+            let mut pending = None;
+            let mut line_open = false;
+            let mut line_start = 0u;
+            let mut line_y = au(0);
+            let mut line_left = au(0);
+            let mut line_right = au(0);
+            let mut used = au(0);
+            // whether a collapsible space at the start of the next box would collapse away
+            let mut after_space = true;
+            // floats that did not fit beside the line they were found on
+            let mut deferred = ~[];
+
+            loop {
+                let box = match pending {
+                    Some(box) => { pending = None; box }
+                    None if next < source.len() => { next += 1; source[next - 1] }
+                    None => break
+                };
+
+                for d.floats.each |float| {
+                    match float.before {
+                        Some(b) if core::box::ptr_eq(b, box) => {}
+                        _ => loop
+                    }
+                    if !line_open {
+                        place_inline_float(float.flow, &floats, cur_y, avail_width);
+                        loop;
+                    }
+                    let line = Rect(Point2D(line_left, line_y),
+                                    Size2D(line_right.sub(line_left), line_height));
+                    match place_float_beside_line(self, float.flow, &floats, boxes, line_start,
+                                                  &line, used, avail_width) {
+                        Some((left, right)) => {
+                            line_left = left;
+                            line_right = right;
+                        }
+                        None => deferred.push(float.flow)
+                    }
+                }
+
+                if !line_open {
+                    let start_width = get_line_start_width(self, box);
+                    let (y, left, right) = floats.find_space(cur_y, &Size2D(start_width, line_height),
+                                                             avail_width);
+                    line_y = y;
+                    line_left = left;
+                    line_right = right;
+                    line_start = boxes.len();
+                    used = au(0);
+                    after_space = true;
+                    line_open = true;
+                }
+
+                let inline_block = get_inline_block_flow(self, box);
+                let white_space = box.node.computed_style().white_space;
+                let room = line_right.sub(line_left).sub(used);
+                let mut place = true;
+                let mut end_line = false;
+
+                match box.kind {
+                    TextBox(text) => {
+                        if collapses_spaces(white_space) && after_space && text.text.starts_with(~" ") {
+                            box.trim_text(1, text.text.len());
+                        }
+
+                        let fits = !wraps_lines(white_space)
+                            || text.run.prefix_width(text.text.len()) <= room;
+                        if !fits {
+                            let break_at = match text.run.find_break(room) {
+                                Some(idx) => Some(idx),
+                                // Text too wide for an empty line overflows it up to
+                                // the first break
+                                None if used == au(0) => {
+                                    let mut first = None;
+                                    for line_break_opportunities(text.text).each |idx| {
+                                        if trim_end(text.text, *idx) > 0 {
+                                            first = Some(*idx);
+                                            break;
+                                        }
+                                    }
+                                    first
+                                }
+                                None => None
+                            };
+                            match break_at {
+                                Some(idx) => {
+                                    pending = Some(box.split_text(trim_end(text.text, idx), idx));
+                                    end_line = true;
+                                }
+                                None if used == au(0) => {}
+                                None => {
+                                    pending = Some(box);
+                                    place = false;
+                                    end_line = true;
+                                }
+                            }
+                        }
+
+                        if place {
+                            end_line = end_line || text.forced_break;
+                            after_space = collapses_spaces(white_space) && text.text.ends_with(~" ");
+                        }
+                    }
+                    _ => {
+                        let width = match inline_block {
+                            Some(block_ctx) => block_ctx.data.position.size.width,
+                            None => box.data.position.size.width
+                        };
+                        if used > au(0) && width > room && wraps_lines(white_space) {
+                            pending = Some(box);
+                            place = false;
+                            end_line = true;
+                        } else {
+                            match box.kind {
+                                GenericBox(*) if inline_block.is_none() => {}
+                                _ => after_space = false
+                            }
+                        }
+                    }
+                }
+
+                if place {
+                    let x = line_left.add(used);
+                    let width = match inline_block {
+                        Some(block_ctx) => {
+                            block_ctx.data.position.origin.x = x;
+                            block_ctx.data.position.size.width
+                        }
+                        None => {
+                            box.data.position.origin.x = x;
+                            box.data.position.size.width
+                        }
+                    };
+                    used = used.add(width);
+                    vec::push(boxes, box);
+                }
+
+                if end_line {
+                    // the last line of a paragraph, before a forced break, is not justified
+                    let forced_break = match box.kind {
+                        TextBox(text) => place && text.forced_break,
+                        _ => false
+                    };
+                    let align = LineAlign {
+                        text_align: style.text_align,
+                        justify: !forced_break,
+                        rtl: rtl
+                    };
+                    let line = finish_line(self, boxes, line_start, line_left, line_y, used,
+                                           line_right.sub(line_left), &strut, &align,
+                                           &containing_block);
+                    cur_y = line.bounds.origin.y.add(line.bounds.size.height);
+                    d.lines.push(line);
+                    line_open = false;
+
+                    for deferred.each |float_ctx| {
+                        place_inline_float(*float_ctx, &floats, cur_y, avail_width);
+                    }
+                    deferred = ~[];
+                }
+            }
+
+            if line_open {
+                let align = LineAlign { text_align: style.text_align, justify: false, rtl: rtl };
+                let line = finish_line(self, boxes, line_start, line_left, line_y, used,
+                                       line_right.sub(line_left), &strut, &align,
+                                       &containing_block);
+                cur_y = line.bounds.origin.y.add(line.bounds.size.height);
+                d.lines.push(line);
+            }
+            for deferred.each |float_ctx| {
+                place_inline_float(*float_ctx, &floats, cur_y, avail_width);
+            }
+
+            // Floats at the end of the content go below the last line
+            for d.floats.each |float| {
+                if float.before.is_none() {
+                    place_inline_float(float.flow, &floats, cur_y, avail_width);
+                }
+            }
+            d.boxes.set(move boxes);
+        }
+
+        self.data.position.size.height = cur_y;
+        move floats
+    }
+
     fn assign_height_inline(_ctx: &LayoutContext) {
         // Don't need to set box or ctx heights, since that is done
-        // during inline flowing.
+        // when the containing block flow assigns lines.
+    }
+
+    /* The baseline of the last line of this flow that is not empty, in its
+    coordinates, if it has one. */
+    fn get_inline_baseline() -> Option<au> {
+        assert self.starts_inline_flow();
+
+        let mut baseline = None;
+        do self.access_inline |d| {
+            for d.lines.each |line| {
+                if line.bounds.size.height > au(0) {
+                    baseline = Some(line.bounds.origin.y.add(line.baseline));
+                }
+            }
+        }
+        baseline
     }
 
     fn build_display_list_inline(builder: &dl::DisplayListBuilder, dirty: &Rect<au>, 
@@ -158,19 +994,64 @@ impl @FlowContext : InlineLayout {
         // TODO: if the CSS box introducing this inline context is *not* anonymous,
         // we need to draw it too, in a way similar to BlowFlowContext
 
-        // TODO: once we form line boxes and have their cached bounds, we can be 
-        // smarter and not recurse on a line if nothing in it can intersect dirty
+        // TODO: skip the lines whose bounds do not intersect dirty, once those
+        // account for relatively positioned boxes and inline-blocks that overflow
         do self.access_inline |d| {
+            // Floats are drawn below the inline content (CSS 2.1, Appendix E)
+            for d.floats.each |float| {
+                self.build_display_list_for_child(builder, float.flow, dirty, offset, list);
+            }
             for d.boxes.each |box| {
-                box.build_display_list(builder, dirty, offset, list)
+                match get_inline_block_flow(self, *box) {
+                    // the flow of an inline-block draws its box
+                    Some(ctx) => self.build_display_list_for_child(builder, ctx, dirty, offset, list),
+                    None => box.build_display_list(builder, dirty, offset, list)
+                }
             }
         }
-
-        // TODO: should inline-block elements have flows as children
-        // of the inline flow, or should the flow be nested inside the
-        // box somehow? Maybe it's best to unify flows and boxes into
-        // the same enum, so inline-block flows are normal
-        // (indivisible) children in the inline flow child list.
     }
 
 } // @FlowContext : InlineLayout
+
+#[cfg(test)]
+mod test {
+    use au = gfx::geometry;
+
+    #[test]
+    fn should_raise_vertically_aligned_boxes() {
+        let px = au::from_px;
+        // from_px does not take negative lengths
+        let neg_px = |n: float| au::from_frac_px(-n);
+        assert vertical_raise(VerticalAlignBaseline, 15.0, px(20), px(50), px(50)) == px(0);
+        assert vertical_raise(VerticalAlignSub, 15.0, px(20), px(20), px(20)) == neg_px(3.0);
+        assert vertical_raise(VerticalAlignSuper, 15.0, px(20), px(20), px(20)) == px(5);
+        assert vertical_raise(VerticalAlignLength(Px(4.0)), 15.0, px(20), px(8), px(8)) == px(4);
+        assert vertical_raise(VerticalAlignPercent(-50.0), 15.0, px(20), px(8), px(8)) == neg_px(10.0);
+        // The middle of a box 40px high is 4px above the baseline, half the x-height
+        assert vertical_raise(VerticalAlignMiddle, 16.0, px(20), px(40), px(40)) == neg_px(16.0);
+        // The tops of the box and the text line up
+        assert vertical_raise(VerticalAlignTextTop, 16.0, px(20), px(50), px(50)) == neg_px(30.0);
+        assert vertical_raise(VerticalAlignTextBottom, 16.0, px(20), px(50), px(30)) == px(20);
+    }
+
+    #[test]
+    fn should_align_boxes_inside_top_and_bottom_aligned_boxes() {
+        let px = au::from_px;
+        let neg_px = |n: float| au::from_frac_px(-n);
+        assert align_with_line_edge(VerticalAlignBaseline, px(0), px(10), px(8), px(20)).is_none();
+        // A box with the baseline of a top-aligned parent sits as low as the parent's text
+        match align_with_line_edge(VerticalAlignTop, px(0), px(10), px(8), px(20)) {
+            Some(AlignTop(drop)) => assert drop == px(12),
+            _ => fail
+        }
+        // and one lowered below a bottom-aligned parent's baseline stays on the line
+        match align_with_line_edge(VerticalAlignBottom, neg_px(3.0), px(10), px(8), px(20)) {
+            Some(AlignBottom(lift)) => assert lift == px(0),
+            _ => fail
+        }
+        match align_with_line_edge(VerticalAlignBottom, px(5), px(10), px(8), px(20)) {
+            Some(AlignBottom(lift)) => assert lift == px(3),
+            _ => fail
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use au = gfx::geometry;
+use dl = gfx::display_list;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use gfx::geometry::au;
+use layout::box::RenderBox;
+use layout::context::LayoutContext;
+use layout::flow::{FlowContext, FlowTree, InlineBlockFlow, InlineFlow, BlockFlow, RootFlow};
+use layout::flow::{TableCellFlow};
+
+/* Inline-blocks (CSS 2.1, Section 9.2.4) are atomic inline-level boxes:
+their box is put on a line of the inline flow containing them like any
+other, but their contents are laid out by an InlineBlockFlow, a child of
+that inline flow, which starts its own block formatting context. The
+inline flow places the InlineBlockFlow where the line puts its box. */
+
+struct InlineBlockFlowData {
+    mut box: Option<@RenderBox>
+}
+
+fn InlineBlockFlowData() -> InlineBlockFlowData {
+    InlineBlockFlowData {
+        box: None
+    }
+}
+
+trait InlineBlockLayout {
+    pure fn starts_inline_block_flow() -> bool;
+    pure fn access_inline_block<T>(fn(&&InlineBlockFlowData) -> T) -> T;
+
+    fn bubble_widths_inline_block(ctx: &LayoutContext);
+    fn assign_widths_inline_block(ctx: &LayoutContext);
+    fn assign_height_inline_block(ctx: &LayoutContext);
+    fn get_inline_block_baseline() -> au;
+    fn get_last_line_baseline() -> Option<au>;
+
+    fn build_display_list_inline_block(a: &dl::DisplayListBuilder, b: &Rect<au>, c: &Point2D<au>, d: &dl::DisplayList);
+}
+
+impl @FlowContext : InlineBlockLayout {
+
+    pure fn starts_inline_block_flow() -> bool {
+        match self.kind {
+            InlineBlockFlow(*) => true,
+            _ => false
+        }
+    }
+
+    pure fn access_inline_block<T>(cb:fn(&&InlineBlockFlowData) -> T) -> T {
+        match self.kind {
+            InlineBlockFlow(d) => cb(d),
+            _  => fail fmt!("Tried to access() data of InlineBlockFlow, but this is a %?", self.kind)
+        }
+    }
+
+    /* defer to the block algorithm, which shrinks inline-blocks to fit
+       their contents like floats (CSS 2.1, Section 10.3.9) */
+    fn bubble_widths_inline_block(ctx: &LayoutContext) {
+        assert self.starts_inline_block_flow();
+        self.bubble_widths_block(ctx)
+    }
+
+    fn assign_widths_inline_block(ctx: &LayoutContext) {
+        assert self.starts_inline_block_flow();
+        self.assign_widths_block(ctx)
+    }
+
+    /* An inline-block starts a block formatting context, so its contents
+       can be laid out before the inline flow containing it puts it on a line. */
+    fn assign_height_inline_block(ctx: &LayoutContext) {
+        assert self.starts_inline_block_flow();
+        self.assign_height_block(ctx)
+    }
+
+    /* The baseline of an inline-block, from the top of its margin box: that
+    of its last line box, or its bottom margin edge if it has none (CSS 2.1,
+    Section 10.8.1). */
+    fn get_inline_block_baseline() -> au {
+        assert self.starts_inline_block_flow();
+
+        match self.get_last_line_baseline() {
+            Some(baseline) => baseline,
+            None => self.data.position.size.height
+        }
+    }
+
+    /* The baseline of the last line box in normal flow inside this flow, in
+    its coordinates, if there is one. Lines inside tables and other
+    inline-blocks are not searched. */
+    fn get_last_line_baseline() -> Option<au> {
+        match self.kind {
+            InlineFlow(*) => self.get_inline_baseline(),
+            BlockFlow(*) | RootFlow(*) | InlineBlockFlow(*) | TableCellFlow(*) => {
+                let mut baseline = None;
+                for FlowTree.each_child(self) |child_ctx| {
+                    if child_ctx.starts_float_flow() || child_ctx.starts_absolute_flow() {
+                        loop;
+                    }
+                    match child_ctx.get_last_line_baseline() {
+                        Some(b) => baseline = Some(child_ctx.data.position.origin.y.add(b)),
+                        None => {}
+                    }
+                }
+                baseline
+            }
+            _ => None
+        }
+    }
+
+    fn build_display_list_inline_block(builder: &dl::DisplayListBuilder, dirty: &Rect<au>,
+                                       offset: &Point2D<au>, list: &dl::DisplayList) {
+        assert self.starts_inline_block_flow();
+        self.build_display_list_block(builder, dirty, offset, list)
+    }
+}
@@ -8,7 +8,9 @@ use au::au;
 use content::content_task;
 use core::dvec::DVec;
 use css::resolve::apply::apply_style;
-use css::values::Stylesheet;
+use css::user_agent::user_agent_stylesheet;
+use css::media::each_applicable_rule;
+use css::values::{AuthorOrigin, FontFaceRule, OriginSheet, Stylesheet, UserAgentOrigin};
 use dl = gfx::display_list;
 use dom::event::{Event, ReflowEvent};
 use dom::node::{Node, LayoutData};
@@ -23,7 +25,7 @@ use opt = core::option;
 use render_task::RenderTask;
 use resource::image_cache_task::ImageCacheTask;
 use servo_text::font_cache::FontCache;
-use std::arc::ARC;
+use std::arc::{ARC, clone, get};
 use std::net::url::Url;
 
 use layout::traverse::*;
@@ -33,7 +35,9 @@ use task::*;
 type LayoutTask = Chan<Msg>;
 
 enum LayoutQuery {
-    ContentBox(Node)
+    ContentBox(Node),
+    // The size of the document whose root is this node
+    DocumentSize(Node)
 }
 
 type LayoutQueryResponse = Result<LayoutQueryResponse_, ()>;
@@ -43,38 +47,83 @@ enum LayoutQueryResponse_ {
 }
 
 enum Msg {
-    BuildMsg(Node, ARC<Stylesheet>, Url, Chan<Event>),
+    // The size of the window, and the point of the document at its top left, in px
+    BuildMsg(Node, ARC<Stylesheet>, Url, Chan<Event>, Size2D<int>, Point2D<int>),
+    // Scrolls the last layout to put this point of the document at the top left
+    // of the window, in px
+    ScrollMsg(Point2D<int>),
     PingMsg(Chan<content_task::PingMsg>),
     QueryMsg(LayoutQuery, Chan<LayoutQueryResponse>),
     ExitMsg
 }
 
+/**
+A layout task. With `full_page` it paints the whole height of each document
+instead of just the window.
+*/
 fn LayoutTask(render_task: RenderTask,
-              img_cache_task: ImageCacheTask) -> LayoutTask {
+              img_cache_task: ImageCacheTask,
+              full_page: bool) -> LayoutTask {
     do spawn_listener::<Msg>|from_content| {
-        Layout(render_task, img_cache_task, from_content).start();
+        Layout(render_task, img_cache_task, from_content, full_page).start();
     }
 }
 
+pure fn px_to_au_rect(rect: &Rect<int>) -> Rect<au> {
+    Rect(Point2D(au::from_px(rect.origin.x), au::from_px(rect.origin.y)),
+         Size2D(au::from_px(rect.size.width), au::from_px(rect.size.height)))
+}
+
+/* The part of the document to put in a display list for a view of it: the
+view, and as much again on each side of it */
+pure fn area_to_build(visible: &Rect<au>) -> Rect<au> {
+    let x = au::max(au(0), visible.origin.x.sub(visible.size.width));
+    let y = au::max(au(0), visible.origin.y.sub(visible.size.height));
+    let right = visible.origin.x.add(visible.size.width).add(visible.size.width);
+    let bottom = visible.origin.y.add(visible.size.height).add(visible.size.height);
+    Rect(Point2D(x, y), Size2D(right.sub(x), bottom.sub(y)))
+}
+
+pure fn contains_rect(outer: &Rect<au>, inner: &Rect<au>) -> bool {
+    inner.origin.x >= outer.origin.x && inner.origin.y >= outer.origin.y &&
+        inner.origin.x.add(inner.size.width) <= outer.origin.x.add(outer.size.width) &&
+        inner.origin.y.add(inner.size.height) <= outer.origin.y.add(outer.size.height)
+}
+
 struct Layout {
     render_task: RenderTask,
     image_cache_task: ImageCacheTask,
     from_content: comm::Port<Msg>,
+    full_page: bool,
+
+    // Where the reflows that scrolling needs are asked for, the size of the
+    // page, in px, and the part of the document in the last display list
+    mut to_content: Option<Chan<Event>>,
+    mut view_size: Size2D<int>,
+    mut built_area: Rect<au>,
 
     font_cache: @FontCache,
+    // Parsed once, then shared by every layout
+    ua_stylesheet: ARC<Stylesheet>,
     // This is used to root auxilliary RCU reader data
     layout_refs: DVec<@LayoutData>
 }
 
 fn Layout(render_task: RenderTask, 
          image_cache_task: ImageCacheTask,
-         from_content: comm::Port<Msg>) -> Layout {
+         from_content: comm::Port<Msg>,
+         full_page: bool) -> Layout {
 
     Layout {
         render_task: render_task,
         image_cache_task: image_cache_task,
         from_content: from_content,
+        full_page: full_page,
+        to_content: None,
+        view_size: Size2D(0, 0),
+        built_area: Rect(Point2D(au(0), au(0)), Size2D(au(0), au(0))),
         font_cache: FontCache(),
+        ua_stylesheet: ARC(user_agent_stylesheet()),
         layout_refs: DVec()
     }
 }
@@ -110,7 +159,28 @@ impl Layout {
 
                 reply_chan.send(response)
             }
+            DocumentSize(node) => {
+                let response = match node.aux(|a| a).flow {
+                    None => Err(()),
+                    Some(flow) => {
+                        let size = flow.data.position.size;
+                        Ok(ContentSize(Size2D(au::to_px(size.width), au::to_px(size.height))))
+                    }
+                };
+
+                reply_chan.send(response)
+            }
+        }
+    }
+
+    // The size in px of what gets painted: the window, or in full-page mode
+    // the window stretched to the height of the document
+    fn page_size(layout_root: @FlowContext, window_size: Size2D<int>) -> Size2D<int> {
+        if !self.full_page {
+            return window_size;
         }
+        let height = au::to_px(layout_root.data.position.size.height);
+        Size2D(window_size.width, int::max(window_size.height, height))
     }
 
     fn start() {
@@ -127,7 +197,19 @@ impl Layout {
                 debug!("layout: ExitMsg received");
                 return false
             },
-            BuildMsg(node, styles, doc_url, to_content) => {
+            ScrollMsg(scroll_position) => {
+                debug!("layout: scrolling to %d, %d", scroll_position.x, scroll_position.y);
+                let visible = Rect(scroll_position, self.view_size);
+                self.render_task.send(render_task::ScrollMsg(copy visible));
+                // Content that is coming into view and not in the display list
+                // needs a new one
+                if !contains_rect(&self.built_area, &px_to_au_rect(&visible)) {
+                    do self.to_content.iter |to_content| {
+                        to_content.send(ReflowEvent);
+                    }
+                }
+            }
+            BuildMsg(node, styles, doc_url, to_content, window_size, scroll_position) => {
                 debug!("layout: received layout request for: %s", doc_url.to_str());
                 debug!("layout: parsed Node tree");
                 node.dump();
@@ -137,14 +219,37 @@ impl Layout {
                     font_cache: self.font_cache,
                     doc_url: doc_url,
                     reflow_cb: || to_content.send(ReflowEvent),
-                    // TODO: obtain screen size from a real data source
-                    screen_size: Rect(Point2D(au(0), au(0)), Size2D(au::from_px(800), au::from_px(600)))
+                    screen_size: Rect(Point2D(au(0), au(0)),
+                                      Size2D(au::from_px(window_size.width),
+                                             au::from_px(window_size.height)))
                 };
 
+                // Make the fonts downloaded by applicable @font-face rules available
+                for each_applicable_rule(*get(&styles), &layout_ctx.screen_size.size) |rule| {
+                    match *rule {
+                      FontFaceRule(ref face) => {
+                        match face.data {
+                          Some(ref data) => {
+                              // The renderer draws the text, so it needs the font too
+                              if self.font_cache.register_font_face(copy face.family, face.weight,
+                                                                    face.italic, copy *data) {
+                                  self.render_task.send(render_task::RegisterFontFaceMsg(
+                                      copy face.family, face.weight, face.italic, copy *data));
+                              }
+                          }
+                          None => ()
+                        }
+                      }
+                      _ => ()
+                    }
+                }
+
                 do util::time::time(~"layout") {
                     // TODO: this is dumb. we don't need 3 separate traversals.
                     node.initialize_style_for_subtree(&layout_ctx, &self.layout_refs);
-                    node.recompute_style_for_subtree(&layout_ctx, styles);
+                    let sheets = ~[OriginSheet(UserAgentOrigin, clone(&self.ua_stylesheet)),
+                                   OriginSheet(AuthorOrigin, move styles)];
+                    node.recompute_style_for_subtree(&layout_ctx, sheets);
                     /* resolve styles (convert relative values) down the node tree */
                     apply_style(&layout_ctx, node, layout_ctx.reflow_cb);
                     
@@ -167,9 +272,19 @@ impl Layout {
                         ctx: &layout_ctx,
                     };
                     // TODO: set options on the builder before building
-                    // TODO: be smarter about what needs painting
-                    layout_root.build_display_list(&builder, &copy layout_root.data.position, &dlist);
-                    self.render_task.send(render_task::RenderMsg(dlist));
+                    // The display list is in the coordinates of the document, and
+                    // holds what is on the screen and around it, so that scrolling
+                    // a little needs no new list. The renderer finds the parts of
+                    // the screen that changed since the last display list.
+                    let page_size = self.page_size(layout_root, window_size);
+                    let visible = Rect(scroll_position, page_size);
+                    let built_area = area_to_build(&px_to_au_rect(&visible));
+                    layout_root.build_display_list(&builder, &built_area, &dlist);
+                    self.render_task.send(render_task::RenderMsg(dlist, visible));
+
+                    self.to_content = Some(to_content);
+                    self.view_size = page_size;
+                    self.built_area = built_area;
                 } // time(layout)
             } // BuildMsg
         } // match
@@ -0,0 +1,897 @@
+use au = gfx::geometry;
+use css::styles::StyleMethods;
+use css::values::*;
+use dl = gfx::display_list;
+use dom::node::Element;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use gfx::geometry::au;
+use layout::box::{RenderBox, shrink_to_fit, used_length};
+use layout::context::LayoutContext;
+use layout::floats::FloatContext;
+use layout::flow::{FlowContext, FlowTree, TableFlow, TableRowFlow, TableCellFlow};
+use util::tree;
+
+/* Tables (CSS 2.1, Chapter 17) are made of three kinds of flows. A
+TableFlow holds the table's rows, and its captions as block flows; row
+groups add no flows of their own. Each TableRowFlow holds the row's
+TableCellFlows, which lay out their contents like any other flow that
+starts a block formatting context. The box builder wraps table parts
+that are missing their parents in anonymous ones, which have no box.
+
+The table sizes its columns from the cells in them and gives each row
+the column widths. Rows are as tall as their tallest cell, and the
+table stacks them between its captions. A cell that spans several rows
+is a child of the first of them, and makes the last one taller if the
+rows are not tall enough for it together. */
+
+struct TableFlowData {
+    mut box: Option<@RenderBox>,
+    // The minimum and preferred widths of each column, including the cells' fringes
+    mut column_min_widths: ~[au],
+    mut column_pref_widths: ~[au],
+    // The used width of each column
+    mut column_widths: ~[au],
+    // The column each cell starts in, for each row
+    mut cell_columns: ~[~[uint]]
+}
+
+fn TableFlowData() -> TableFlowData {
+    TableFlowData {
+        box: None,
+        column_min_widths: ~[],
+        column_pref_widths: ~[],
+        column_widths: ~[],
+        cell_columns: ~[]
+    }
+}
+
+struct TableRowFlowData {
+    mut box: Option<@RenderBox>
+}
+
+fn TableRowFlowData() -> TableRowFlowData {
+    TableRowFlowData {
+        box: None
+    }
+}
+
+struct TableCellFlowData {
+    mut box: Option<@RenderBox>
+}
+
+fn TableCellFlowData() -> TableCellFlowData {
+    TableCellFlowData {
+        box: None
+    }
+}
+
+/* The total width of `span` columns from `start`, and of the spacing between them */
+pure fn spanned_width(widths: &[au], start: uint, span: uint, spacing: au) -> au {
+    let mut width = au(0);
+    for uint::range(start, start + span) |i| {
+        width = width.add(widths[i]);
+    }
+    width.add(au(*spacing * ((span - 1) as i32)))
+}
+
+/* Widens `span` columns from `start` just enough for a cell spanning them
+to be `width` wide. The columns share the extra width evenly. */
+pure fn widen_columns(widths: &[au], start: uint, span: uint, width: au, spacing: au) -> ~[au] {
+    let extra = width.sub(spanned_width(widths, start, span, spacing));
+    let share = au(*extra / (span as i32));
+    do vec::from_fn(widths.len()) |i| {
+        if extra <= au(0) || i < start || i >= start + span {
+            widths[i]
+        } else if i == start + span - 1 {
+            // The last column takes what rounding left over
+            widths[i].add(extra.sub(au(*share * ((span - 1) as i32))))
+        } else {
+            widths[i].add(share)
+        }
+    }
+}
+
+/* The offset of the left edge of a column from the row's, past the
+spacing before it and the columns and spacing to its left */
+pure fn column_left(widths: &[au], column: uint, spacing: au) -> au {
+    let mut left = spacing;
+    for uint::range(0u, column) |i| {
+        left = left.add(widths[i]).add(spacing);
+    }
+    left
+}
+
+/* Finds the column each cell starts in, given the (column span, row span)
+of the cells of each row: the first one to the right of the cell before it
+that no cell from a row above reaches down into (CSS 2.1, Section 17.5). */
+fn find_cell_columns(rows: &[~[(uint, uint)]]) -> ~[~[uint]] {
+    // How many more rows the cells placed so far take up each column for
+    let mut taken = ~[];
+    let mut result = ~[];
+    for rows.each |row| {
+        let mut columns = ~[];
+        let mut column = 0u;
+        for row.each |cell| {
+            let (column_span, row_span) = *cell;
+            while column < taken.len() && taken[column] > 0u {
+                column += 1u;
+            }
+            vec::push(columns, column);
+            while taken.len() < column + column_span {
+                vec::push(taken, 0u);
+            }
+            for uint::range(column, column + column_span) |i| {
+                taken[i] = uint::max(taken[i], row_span);
+            }
+            column += column_span;
+        }
+        vec::push(result, move columns);
+        taken = do taken.map |rows| { if *rows > 0u { *rows - 1u } else { 0u } };
+    }
+    result
+}
+
+pure fn scale(length: au, ratio: float) -> au {
+    au(((*length as float) * ratio) as i32)
+}
+
+pure fn total_width(widths: &[au]) -> au {
+    do vec::foldl(au(0), widths) |total, width| { total.add(*width) }
+}
+
+/* Distributes the width available to the columns of a table with automatic
+layout (CSS 2.1, Section 17.5.2.2, which leaves the details to us). Every
+column gets at least its minimum width. The space left beyond those goes
+to the columns in proportion to how much wider they would like to be, and
+the space beyond their preferred widths in proportion to those. */
+pure fn distribute_auto_widths(min_widths: &[au], pref_widths: &[au], width: au) -> ~[au] {
+    let total_min = total_width(min_widths);
+    let total_pref = total_width(pref_widths);
+    let columns = min_widths.len();
+
+    if width <= total_min {
+        do vec::from_fn(columns) |i| { min_widths[i] }
+    } else if width <= total_pref {
+        let ratio = au::to_frac_px(width.sub(total_min))
+                  / au::to_frac_px(total_pref.sub(total_min));
+        do vec::from_fn(columns) |i| {
+            min_widths[i].add(scale(pref_widths[i].sub(min_widths[i]), ratio))
+        }
+    } else {
+        let extra = width.sub(total_pref);
+        do vec::from_fn(columns) |i| {
+            let share = if total_pref > au(0) {
+                scale(extra, au::to_frac_px(pref_widths[i]) / au::to_frac_px(total_pref))
+            } else {
+                au(*extra / (columns as i32))
+            };
+            pref_widths[i].add(share)
+        }
+    }
+}
+
+/* Distributes the width available to the columns of a table with fixed
+layout (CSS 2.1, Section 17.5.2.1). Columns whose cells in the first row
+have a width keep it, and the others share what is left evenly. If every
+column has a width, they all share the space left over. */
+pure fn distribute_fixed_widths(specified: &[Option<au>], width: au) -> ~[au] {
+    let mut fixed = au(0);
+    let mut auto_columns = 0;
+    for specified.each |column| {
+        match *column {
+            Some(w) => fixed = fixed.add(w),
+            None => auto_columns += 1
+        }
+    }
+
+    let remaining = au::max(au(0), width.sub(fixed));
+    do vec::from_fn(specified.len()) |i| {
+        match specified[i] {
+            Some(w) if auto_columns == 0 => w.add(au(*remaining / (specified.len() as i32))),
+            Some(w) => w,
+            None => au(*remaining / (auto_columns as i32))
+        }
+    }
+}
+
+/* The height of `span` rows from `start` and of the spacing between them */
+fn spanned_rows_height(rows: &[@FlowContext], start: uint, span: uint, spacing: au) -> au {
+    let mut height = au(*spacing * ((span - 1u) as i32));
+    for uint::range(start, start + span) |i| {
+        height = height.add(rows[i].data.position.size.height);
+    }
+    height
+}
+
+trait TableLayout {
+    pure fn starts_table_flow() -> bool;
+    pure fn starts_table_row_flow() -> bool;
+    pure fn starts_table_cell_flow() -> bool;
+    pure fn access_table<T>(fn(&&TableFlowData) -> T) -> T;
+    pure fn access_table_row<T>(fn(&&TableRowFlowData) -> T) -> T;
+    pure fn access_table_cell<T>(fn(&&TableCellFlowData) -> T) -> T;
+
+    fn get_table() -> @FlowContext;
+    fn collapses_borders() -> bool;
+    fn get_border_spacing() -> (au, au);
+    fn get_column_span() -> uint;
+    fn get_row_span() -> uint;
+    fn get_row_index() -> uint;
+    fn get_cell_spans() -> ~[~[(uint, uint)]];
+    fn set_row_height(height: au);
+    fn get_caption_side() -> CSSCaptionSide;
+    fn get_fixed_column_widths(columns: uint, width: au) -> ~[Option<au>];
+    fn get_row_borders() -> (au, au);
+
+    fn bubble_widths_table(ctx: &LayoutContext);
+    fn assign_widths_table(ctx: &LayoutContext);
+    fn assign_height_table(ctx: &LayoutContext);
+    fn layout_caption(ctx: &LayoutContext, caption: @FlowContext, y: au) -> au;
+    fn build_display_list_table(a: &dl::DisplayListBuilder, b: &Rect<au>, c: &Point2D<au>, d: &dl::DisplayList);
+
+    fn bubble_widths_table_row(ctx: &LayoutContext);
+    fn assign_widths_table_row(ctx: &LayoutContext);
+    fn assign_height_table_row(ctx: &LayoutContext);
+    fn build_display_list_table_row(a: &dl::DisplayListBuilder, b: &Rect<au>, c: &Point2D<au>, d: &dl::DisplayList);
+
+    fn bubble_widths_table_cell(ctx: &LayoutContext);
+    fn assign_widths_table_cell(ctx: &LayoutContext);
+    fn assign_height_table_cell(ctx: &LayoutContext);
+    fn build_display_list_table_cell(a: &dl::DisplayListBuilder, b: &Rect<au>, c: &Point2D<au>, d: &dl::DisplayList);
+}
+
+impl @FlowContext : TableLayout {
+
+    pure fn starts_table_flow() -> bool {
+        match self.kind {
+            TableFlow(*) => true,
+            _ => false
+        }
+    }
+
+    pure fn starts_table_row_flow() -> bool {
+        match self.kind {
+            TableRowFlow(*) => true,
+            _ => false
+        }
+    }
+
+    pure fn starts_table_cell_flow() -> bool {
+        match self.kind {
+            TableCellFlow(*) => true,
+            _ => false
+        }
+    }
+
+    pure fn access_table<T>(cb:fn(&&TableFlowData) -> T) -> T {
+        match self.kind {
+            TableFlow(d) => cb(d),
+            _  => fail fmt!("Tried to access() data of TableFlow, but this is a %?", self.kind)
+        }
+    }
+
+    pure fn access_table_row<T>(cb:fn(&&TableRowFlowData) -> T) -> T {
+        match self.kind {
+            TableRowFlow(d) => cb(d),
+            _  => fail fmt!("Tried to access() data of TableRowFlow, but this is a %?", self.kind)
+        }
+    }
+
+    pure fn access_table_cell<T>(cb:fn(&&TableCellFlowData) -> T) -> T {
+        match self.kind {
+            TableCellFlow(d) => cb(d),
+            _  => fail fmt!("Tried to access() data of TableCellFlow, but this is a %?", self.kind)
+        }
+    }
+
+    /* The table flow that a row or cell belongs to */
+    fn get_table() -> @FlowContext {
+        let mut ctx = self;
+        while !ctx.starts_table_flow() {
+            ctx = tree::get_parent(FlowTree, ctx).get();
+        }
+        ctx
+    }
+
+    /* Whether the table uses the collapsing border model (CSS 2.1, Section
+    17.6.2). Anonymous tables keep their borders separate. */
+    fn collapses_borders() -> bool {
+        let mut collapse = false;
+        do self.get_table().with_block_box |box| {
+            collapse = box.node.computed_style().border_collapse == BorderCollapseCollapse;
+        }
+        collapse
+    }
+
+    /* The horizontal and vertical spacing between the table's cells, and
+    between the outer cells and the table's padding (CSS 2.1, Section
+    17.6.1). Collapsing borders leave no space between cells. */
+    fn get_border_spacing() -> (au, au) {
+        let table = self.get_table();
+        let mut spacing = (au(0), au(0));
+        if !table.collapses_borders() {
+            do table.with_block_box |box| {
+                let style = box.node.computed_style();
+                spacing = (au::from_frac_px(style.border_spacing_horizontal),
+                           au::from_frac_px(style.border_spacing_vertical));
+            }
+        }
+        spacing
+    }
+
+    /* The number of columns a cell spans, from its 'colspan' attribute */
+    fn get_column_span() -> uint {
+        let mut span = 1u;
+        do self.with_block_box |box| {
+            span = do box.node.read |n| {
+                match n.kind {
+                    ~Element(element) => {
+                        match element.get_attr(~"colspan").chain(|s| uint::from_str(s)) {
+                            // Like other browsers, we cap spans at 1000 columns
+                            Some(n) if n > 0u => uint::min(n, 1000u),
+                            _ => 1u
+                        }
+                    }
+                    _ => 1u
+                }
+            };
+        }
+        span
+    }
+
+    /* The number of rows a cell spans, from its 'rowspan' attribute. A span
+    of 0, which HTML takes to the end of the row group, spans just one. */
+    fn get_row_span() -> uint {
+        let mut span = 1u;
+        do self.with_block_box |box| {
+            span = do box.node.read |n| {
+                match n.kind {
+                    ~Element(element) => {
+                        match element.get_attr(~"rowspan").chain(|s| uint::from_str(s)) {
+                            Some(n) if n > 0u => uint::min(n, 65534u),
+                            _ => 1u
+                        }
+                    }
+                    _ => 1u
+                }
+            };
+        }
+        span
+    }
+
+    /* The position of a row among the rows of its table */
+    fn get_row_index() -> uint {
+        let mut index = 0u;
+        for FlowTree.each_child(self.get_table()) |row| {
+            if core::box::ptr_eq(row, self) { break; }
+            if row.starts_table_row_flow() { index += 1u; }
+        }
+        index
+    }
+
+    /* The (column span, row span) of the cells of each row of a table */
+    fn get_cell_spans() -> ~[~[(uint, uint)]] {
+        let mut rows = ~[];
+        for FlowTree.each_child(self) |row| {
+            if !row.starts_table_row_flow() { loop; }
+            let mut cells = ~[];
+            for FlowTree.each_child(row) |cell| {
+                vec::push(cells, (cell.get_column_span(), cell.get_row_span()));
+            }
+            vec::push(rows, move cells);
+        }
+        rows
+    }
+
+    /* Makes a row the given height, and stretches the cells that span only
+    it to fill it. Their contents stay at the top. */
+    fn set_row_height(height: au) {
+        for FlowTree.each_child(self) |cell| {
+            if cell.get_row_span() > 1u { loop; }
+            cell.data.position.origin.y = au(0);
+            cell.data.position.size.height = height;
+            do cell.with_block_box |box| {
+                let margin = box.data.margin.top.add(box.data.margin.bottom);
+                box.data.position.size.height = height.sub(margin);
+            }
+        }
+
+        do self.with_block_box |box| {
+            box.data.position.origin.y = au(0);
+            box.data.position.size.height = height;
+        }
+        self.data.position.size.height = height;
+    }
+
+    fn get_caption_side() -> CSSCaptionSide {
+        let mut side = CaptionSideTop;
+        do self.with_block_box |box| {
+            side = box.node.computed_style().caption_side;
+        }
+        side
+    }
+
+    /* The widths that the cells in the table's first row give their columns
+    under fixed layout, including the cells' fringes. A cell spanning
+    several columns shares its width among them. */
+    fn get_fixed_column_widths(columns: uint, width: au) -> ~[Option<au>] {
+        let mut widths = vec::from_elem(columns, None);
+
+        for FlowTree.each_child(self) |row| {
+            if !row.starts_table_row_flow() { loop; }
+
+            let mut column = 0u;
+            for FlowTree.each_child(row) |cell| {
+                let span = uint::min(cell.get_column_span(), columns - column);
+                if span == 0u { break; }
+                do cell.with_block_box |box| {
+                    match used_length(box.node.computed_style().width, width) {
+                        Some(w) => {
+                            let share = au(*w.add(box.get_fringe_width()) / (span as i32));
+                            for uint::range(column, column + span) |i| {
+                                widths[i] = Some(share);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                column += span;
+            }
+            break;
+        }
+
+        widths
+    }
+
+    /* The widest top and bottom borders of the cells in a row, which the
+    borders of the neighbouring rows collapse with */
+    fn get_row_borders() -> (au, au) {
+        let mut top = au(0);
+        let mut bottom = au(0);
+        for FlowTree.each_child(self) |cell| {
+            do cell.with_block_box |box| {
+                let style = box.node.computed_style();
+                top = au::max(top, au::from_frac_px(style.border_top_width));
+                bottom = au::max(bottom, au::from_frac_px(style.border_bottom_width));
+            }
+        }
+        (top, bottom)
+    }
+
+    /* Finds the minimum and preferred widths of the columns from those of
+    the cells in them (CSS 2.1, Section 17.5.2.2). Cells that span several
+    columns widen them once the single columns are sized. The table is at
+    least as wide as its captions. */
+    fn bubble_widths_table(_ctx: &LayoutContext) {
+        assert self.starts_table_flow();
+
+        let (spacing, _) = self.get_border_spacing();
+        let mut min_widths = ~[];
+        let mut pref_widths = ~[];
+        let mut spanning = ~[];
+        let mut caption_min = au(0);
+        let mut caption_pref = au(0);
+        let cell_columns = find_cell_columns(self.get_cell_spans());
+        let mut row = 0u;
+
+        for FlowTree.each_child(self) |child_ctx| {
+            match child_ctx.kind {
+                TableRowFlow(*) => {
+                    let mut i = 0u;
+                    for FlowTree.each_child(child_ctx) |cell| {
+                        let column = cell_columns[row][i];
+                        i += 1u;
+                        let span = cell.get_column_span();
+                        while min_widths.len() < column + span {
+                            vec::push(min_widths, au(0));
+                            vec::push(pref_widths, au(0));
+                        }
+
+                        if span == 1u {
+                            min_widths[column] = au::max(min_widths[column], cell.data.min_width);
+                            pref_widths[column] = au::max(pref_widths[column], cell.data.pref_width);
+                        } else {
+                            vec::push(spanning, (column, span, cell.data.min_width,
+                                                 cell.data.pref_width));
+                        }
+                    }
+                    row += 1u;
+                }
+                _ => {
+                    caption_min = au::max(caption_min, child_ctx.data.min_width);
+                    caption_pref = au::max(caption_pref, child_ctx.data.pref_width);
+                }
+            }
+        }
+
+        for spanning.each |cell| {
+            let (start, span, min_width, pref_width) = *cell;
+            min_widths = widen_columns(min_widths, start, span, min_width, spacing);
+            pref_widths = widen_columns(pref_widths, start, span, pref_width, spacing);
+        }
+        let pref_widths = do vec::from_fn(min_widths.len()) |i| {
+            au::max(min_widths[i], pref_widths[i])
+        };
+
+        let spacing = au(*spacing * ((min_widths.len() + 1u) as i32));
+        let mut min_width = total_width(min_widths).add(spacing);
+        let mut pref_width = total_width(pref_widths).add(spacing);
+        do self.with_block_box |box| {
+            min_width = min_width.add(box.get_min_width());
+            pref_width = pref_width.add(box.get_pref_width());
+        }
+
+        do self.access_table |d| {
+            d.column_min_widths = copy min_widths;
+            d.column_pref_widths = copy pref_widths;
+            d.cell_columns = copy cell_columns;
+        }
+        self.data.min_width = au::max(min_width, caption_min);
+        self.data.pref_width = au::max(pref_width, caption_pref);
+    }
+
+    /* Sizes the table box and its columns. An 'auto' width shrinks to fit
+    the columns, and a table is never narrower than they need. The rows
+    span the table's content box, and the captions its border box. */
+    fn assign_widths_table(_ctx: &LayoutContext) {
+        assert self.starts_table_flow();
+
+        let (spacing, _) = self.get_border_spacing();
+        let (min_widths, pref_widths) = do self.access_table |d| {
+            (copy d.column_min_widths, copy d.column_pref_widths)
+        };
+        let columns = min_widths.len();
+        let spacing = au(*spacing * ((columns + 1u) as i32));
+        let min_width = total_width(min_widths).add(spacing);
+        let pref_width = total_width(pref_widths).add(spacing);
+
+        let containing_width = self.data.position.size.width;
+        let mut width = shrink_to_fit(min_width, pref_width, containing_width);
+        let mut content_left = au(0);
+        let mut border_left = au(0);
+        let mut border_width = width;
+        let mut fixed_layout = false;
+
+        do self.with_block_box |box| {
+            width = box.assign_table_widths(containing_width, min_width, pref_width);
+            let (left_fringe, _) = box.get_used_width();
+            content_left = box.data.margin.left.add(left_fringe);
+            border_left = box.data.margin.left;
+            border_width = box.data.position.size.width;
+
+            // Fixed layout needs a width to lay the columns out in
+            let style = box.node.computed_style();
+            fixed_layout = style.table_layout == TableLayoutFixed && style.width != BoxAuto;
+        }
+
+        let available = au::max(au(0), width.sub(spacing));
+        let column_widths = if fixed_layout {
+            distribute_fixed_widths(self.get_fixed_column_widths(columns, width), available)
+        } else {
+            distribute_auto_widths(min_widths, pref_widths, available)
+        };
+        do self.access_table |d| {
+            d.column_widths = copy column_widths;
+        }
+
+        for FlowTree.each_child(self) |child_ctx| {
+            if child_ctx.starts_table_row_flow() {
+                child_ctx.data.position.origin.x = content_left;
+                child_ctx.data.position.size.width = width;
+            } else {
+                child_ctx.data.position.origin.x = border_left;
+                child_ctx.data.position.size.width = border_width;
+            }
+        }
+    }
+
+    /* Stacks the rows inside the table box, with the spacing between them,
+    and the captions above and below it (CSS 2.1, Section 17.4). The rows
+    have been sized already; captions are laid out here, as the table
+    starts their block formatting context. A table's 'height' is the least
+    height of its box: rows do not stretch to fill it yet. */
+    fn assign_height_table(ctx: &LayoutContext) {
+        assert self.starts_table_flow();
+
+        let (_, spacing) = self.get_border_spacing();
+        let collapse = self.collapses_borders();
+
+        let mut cur_y = au(0);
+        for FlowTree.each_child(self) |child_ctx| {
+            if !child_ctx.starts_table_row_flow() && child_ctx.get_caption_side() == CaptionSideTop {
+                cur_y = self.layout_caption(ctx, child_ctx, cur_y);
+            }
+        }
+
+        let table_top = cur_y;
+        let mut content_top = table_top;
+        do self.with_block_box |box| {
+            box.assign_block_vertical_edges(self.data.position.size.width);
+            let (top_fringe, _) = box.get_used_height();
+            content_top = table_top.add(box.data.margin.top).add(top_fringe);
+        }
+
+        let mut rows = ~[];
+        for FlowTree.each_child(self) |child_ctx| {
+            if child_ctx.starts_table_row_flow() { vec::push(rows, child_ctx); }
+        }
+
+        // A cell spanning rows that are not tall enough for it makes the last one taller
+        for rows.eachi |r, row| {
+            for FlowTree.each_child(*row) |cell| {
+                let span = uint::min(cell.get_row_span(), rows.len() - r);
+                if span == 1u { loop; }
+                let last = rows[r + span - 1u];
+                let needed = cell.data.position.size.height.sub(spanned_rows_height(rows, r, span,
+                                                                                    spacing));
+                if needed > au(0) {
+                    last.set_row_height(last.data.position.size.height.add(needed));
+                }
+            }
+        }
+
+        let mut row_y = au(0);
+        let mut prev_bottom = au(0);
+        for FlowTree.each_child(self) |child_ctx| {
+            if !child_ctx.starts_table_row_flow() { loop; }
+
+            // Collapsing borders overlap those of the row above, and the wider one shows
+            let (top, bottom) = child_ctx.get_row_borders();
+            let overlap = if collapse { au::min(prev_bottom, top) } else { au(0) };
+            row_y = row_y.add(spacing).sub(overlap);
+            child_ctx.data.position.origin.y = content_top.add(row_y);
+            row_y = row_y.add(child_ctx.data.position.size.height);
+            prev_bottom = bottom;
+        }
+        if row_y > au(0) {
+            row_y = row_y.add(spacing);
+        }
+
+        // Then the cells spanning rows fill them
+        for rows.eachi |r, row| {
+            for FlowTree.each_child(*row) |cell| {
+                let span = uint::min(cell.get_row_span(), rows.len() - r);
+                if span == 1u { loop; }
+                let last = rows[r + span - 1u];
+                let height = last.data.position.origin.y.add(last.data.position.size.height)
+                                                        .sub(row.data.position.origin.y);
+                cell.data.position.origin.y = au(0);
+                cell.data.position.size.height = height;
+                do cell.with_block_box |box| {
+                    let margin = box.data.margin.top.add(box.data.margin.bottom);
+                    box.data.position.size.height = height.sub(margin);
+                }
+            }
+        }
+
+        cur_y = content_top.add(row_y);
+        do self.with_block_box |box| {
+            let (top_fringe, bottom_fringe) = box.get_used_height();
+            match box.node.computed_style().height {
+                BoxLength(length) => row_y = au::max(row_y, au::from_frac_px(length.abs())),
+                BoxPercent(*) | BoxAuto => {}
+            }
+            box.data.position.origin.y = table_top.add(box.data.margin.top);
+            box.data.position.size.height = top_fringe.add(row_y).add(bottom_fringe);
+            cur_y = table_top.add(box.margin_box().size.height);
+        }
+
+        for FlowTree.each_child(self) |child_ctx| {
+            if !child_ctx.starts_table_row_flow()
+                && child_ctx.get_caption_side() == CaptionSideBottom {
+                cur_y = self.layout_caption(ctx, child_ctx, cur_y);
+            }
+        }
+
+        self.data.position.size.height = cur_y;
+    }
+
+    /* Lays out a caption at `y`, returning the position below it */
+    fn layout_caption(ctx: &LayoutContext, caption: @FlowContext, y: au) -> au {
+        caption.data.position.origin.y = y;
+        caption.layout_block(ctx, FloatContext());
+        y.add(caption.data.position.size.height)
+    }
+
+    fn build_display_list_table(builder: &dl::DisplayListBuilder, dirty: &Rect<au>,
+                                offset: &Point2D<au>, list: &dl::DisplayList) {
+        assert self.starts_table_flow();
+
+        do self.with_block_box |box| {
+            box.build_display_list(builder, dirty, offset, list)
+        }
+
+        for FlowTree.each_child(self) |child| {
+            self.build_display_list_for_child(builder, child, dirty, offset, list)
+        }
+    }
+
+    /* The widths of a row are those of its columns, which the table finds */
+    fn bubble_widths_table_row(_ctx: &LayoutContext) {
+        assert self.starts_table_row_flow();
+
+        let mut min_width = au(0);
+        let mut pref_width = au(0);
+        for FlowTree.each_child(self) |cell| {
+            min_width = min_width.add(cell.data.min_width);
+            pref_width = pref_width.add(cell.data.pref_width);
+        }
+
+        self.data.min_width = min_width;
+        self.data.pref_width = pref_width;
+    }
+
+    /* Places the cells in the columns they span */
+    fn assign_widths_table_row(_ctx: &LayoutContext) {
+        assert self.starts_table_row_flow();
+
+        let table = self.get_table();
+        let (spacing, _) = self.get_border_spacing();
+        let collapse = self.collapses_borders();
+        let widths = do table.access_table |d| { copy d.column_widths };
+        let columns = do table.access_table |d| { copy d.cell_columns[self.get_row_index()] };
+
+        // Cells from the rows above may take up the columns between the cells
+        let mut prev_end = 0u;
+        let mut prev_right = au(0);
+        let mut i = 0u;
+        for FlowTree.each_child(self) |cell| {
+            let column = columns[i];
+            i += 1u;
+            let span = uint::min(cell.get_column_span(), widths.len() - column);
+            let mut width = spanned_width(widths, column, span, spacing);
+
+            // Collapsing borders overlap those of the cell to the left, and the
+            // wider one shows
+            let mut left = column_left(widths, column, spacing);
+            do cell.with_block_box |box| {
+                let style = box.node.computed_style();
+                if collapse && column == prev_end {
+                    let overlap = au::min(prev_right, au::from_frac_px(style.border_left_width));
+                    left = left.sub(overlap);
+                    width = width.add(overlap);
+                }
+                prev_right = au::from_frac_px(style.border_right_width);
+            }
+
+            cell.data.position.origin.x = left;
+            cell.data.position.size.width = width;
+            prev_end = column + span;
+        }
+
+        // The row's background shows behind its cells, but not the spacing around them
+        let row_width = self.data.position.size.width;
+        do self.with_block_box |box| {
+            box.data.position.origin.x = spacing;
+            box.data.position.size.width = au::max(au(0), row_width.sub(spacing).sub(spacing));
+        }
+    }
+
+    /* Makes the row as tall as its tallest cell that spans only this row, or
+    its 'height', and stretches those cells to fill it. The table sizes the
+    cells that span several rows once all the rows are. */
+    // TODO: 'vertical-align' in cells
+    fn assign_height_table_row(_ctx: &LayoutContext) {
+        assert self.starts_table_row_flow();
+
+        let mut height = au(0);
+        for FlowTree.each_child(self) |cell| {
+            if cell.get_row_span() == 1u {
+                height = au::max(height, cell.data.position.size.height);
+            }
+        }
+        do self.with_block_box |box| {
+            match box.node.computed_style().height {
+                BoxLength(length) => height = au::max(height, au::from_frac_px(length.abs())),
+                BoxPercent(*) | BoxAuto => {}
+            }
+        }
+
+        self.set_row_height(height);
+    }
+
+    fn build_display_list_table_row(builder: &dl::DisplayListBuilder, dirty: &Rect<au>,
+                                    offset: &Point2D<au>, list: &dl::DisplayList) {
+        assert self.starts_table_row_flow();
+
+        do self.with_block_box |box| {
+            box.build_display_list(builder, dirty, offset, list)
+        }
+
+        for FlowTree.each_child(self) |child| {
+            self.build_display_list_for_child(builder, child, dirty, offset, list)
+        }
+    }
+
+    /* A cell's contents are laid out like a block's. Its widths are at
+    least its 'width', which sets the least width of its column. */
+    fn bubble_widths_table_cell(ctx: &LayoutContext) {
+        assert self.starts_table_cell_flow();
+        self.bubble_widths_block(ctx);
+
+        do self.with_block_box |box| {
+            match box.node.computed_style().width {
+                BoxLength(length) => {
+                    let width = au::from_frac_px(length.abs()).add(box.get_fringe_width());
+                    self.data.min_width = au::max(self.data.min_width, width);
+                    self.data.pref_width = au::max(self.data.pref_width, width);
+                }
+                BoxPercent(*) | BoxAuto => {}
+            }
+        }
+    }
+
+    /* defer to the block algorithm, which fills the cell's columns */
+    fn assign_widths_table_cell(ctx: &LayoutContext) {
+        assert self.starts_table_cell_flow();
+        self.assign_widths_block(ctx)
+    }
+
+    /* A cell starts a block formatting context, so its contents can be laid
+       out before its row is sized. */
+    fn assign_height_table_cell(ctx: &LayoutContext) {
+        assert self.starts_table_cell_flow();
+        self.assign_height_block(ctx)
+    }
+
+    fn build_display_list_table_cell(builder: &dl::DisplayListBuilder, dirty: &Rect<au>,
+                                     offset: &Point2D<au>, list: &dl::DisplayList) {
+        assert self.starts_table_cell_flow();
+        self.build_display_list_block(builder, dirty, offset, list)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use au = gfx::geometry;
+
+    #[test]
+    fn should_widen_spanned_columns() {
+        let px = au::from_px;
+        let widths = widen_columns(~[px(10), px(20), px(30)], 0u, 2u, px(50), px(2));
+        assert widths == ~[px(19), px(29), px(30)];
+        // Columns that are wide enough already stay put
+        let widths = widen_columns(~[px(10), px(20), px(30)], 1u, 2u, px(40), px(2));
+        assert widths == ~[px(10), px(20), px(30)];
+        assert spanned_width(~[px(10), px(20), px(30)], 0u, 3u, px(2)) == px(64);
+    }
+
+    #[test]
+    fn should_distribute_auto_widths() {
+        let px = au::from_px;
+        let min_widths = ~[px(10), px(30)];
+        let pref_widths = ~[px(50), px(30)];
+
+        // Too narrow: the minimum widths
+        assert distribute_auto_widths(min_widths, pref_widths, px(20)) == ~[px(10), px(30)];
+        // Between the minimum and preferred widths
+        assert distribute_auto_widths(min_widths, pref_widths, px(60)) == ~[px(30), px(30)];
+        // Wider than preferred: in proportion to the preferred widths
+        assert distribute_auto_widths(min_widths, pref_widths, px(160)) == ~[px(100), px(60)];
+    }
+
+    #[test]
+    fn should_distribute_fixed_widths() {
+        let px = au::from_px;
+        assert distribute_fixed_widths(~[Some(px(100)), None, None], px(300))
+            == ~[px(100), px(100), px(100)];
+        assert distribute_fixed_widths(~[Some(px(100)), None], px(50)) == ~[px(100), px(0)];
+        assert distribute_fixed_widths(~[Some(px(100)), Some(px(50))], px(200))
+            == ~[px(125), px(75)];
+    }
+
+    #[test]
+    fn should_skip_columns_taken_by_cells_spanning_rows() {
+        // The first cell spans three rows, and the second two columns and two rows
+        let columns = find_cell_columns(~[~[(1u, 3u), (2u, 2u), (1u, 1u)],
+                                          ~[(1u, 1u)],
+                                          ~[(1u, 1u), (1u, 1u)],
+                                          ~[(1u, 1u), (1u, 1u)]]);
+        assert columns == ~[~[0u, 1u, 3u], ~[3u], ~[1u, 2u], ~[0u, 1u]];
+
+        let px = au::from_px;
+        assert column_left(~[px(10), px(20), px(30)], 0u, px(2)) == px(2);
+        assert column_left(~[px(10), px(20), px(30)], 2u, px(2)) == px(36);
+    }
+}
@@ -1,83 +1,416 @@
 #[doc="Text layout."]
 
 use au = gfx::geometry;
+use css::styles::ComputedStyle;
+use css::values::{CSSWhiteSpace, WhiteSpaceNormal, WhiteSpacePre, WhiteSpaceNowrap};
+use css::values::{WhiteSpacePreWrap, WhiteSpacePreLine};
+use css::values::{FamilyName, FamilySerif, FamilySansSerif, FamilyCursive, FamilyFantasy};
+use css::values::{FamilyMonospace, FontStyleNormal, FontStyleItalic, FontStyleOblique};
+use css::values::{LineHeightNormal, LineHeightNumber, LineHeightLength, LineHeightPercent};
+use css::values::{CSSTextTransform, TextTransformNone, TextTransformCapitalize};
+use css::values::{TextTransformUppercase, TextTransformLowercase, Px, Em};
 use geom::size::Size2D;
 use gfx::geometry::au;
-use servo_text::text_run::TextRun;
+use servo_text::font::{Font, FontDescriptor};
 use servo_text::font_cache::FontCache;
-use layout::box::{TextBox, RenderBox};
-use layout::context::LayoutContext;
+use servo_text::text_run::{TextRun, TextRunWithParams};
+use servo_text::shaper::ShapingParams;
+use servo_text::bidi::{is_rtl_level, resolve_scripts};
+use dom::node::{Element, Node, NodeTree};
+use layout::box::{TextBox, RenderBox, RenderBoxTree};
+use util::tree;
 
 struct TextBoxData {
-    text: ~str,
-    mut runs: ~[TextRun]
+    mut font: @Font,
+    mut text: ~str,
+    mut run: TextRun,
+    /* whether a preserved newline followed the text, ending its line */
+    mut forced_break: bool,
+    /* the space added to each space in the text, to justify its line */
+    mut word_spacing: au
 }
 
-fn TextBoxData(text: ~str, runs: ~[TextRun]) -> TextBoxData {
+fn TextBoxData(font: @Font, +run: TextRun) -> TextBoxData {
     TextBoxData {
-        text: text,
-        runs: runs
+        font: font,
+        text: copy run.text,
+        run: run,
+        forced_break: false,
+        word_spacing: au(0)
     }
 }
 
+/* The font that text in the given style asks for */
+fn font_descriptor(style: &ComputedStyle) -> FontDescriptor {
+    let families = do style.font_family.map |family| {
+        match *family {
+            FamilyName(ref name) => copy *name,
+            FamilySerif => ~"serif",
+            FamilySansSerif => ~"sans-serif",
+            FamilyCursive => ~"cursive",
+            FamilyFantasy => ~"fantasy",
+            FamilyMonospace => ~"monospace"
+        }
+    };
+    let italic = match style.font_style {
+        FontStyleNormal => false,
+        FontStyleItalic | FontStyleOblique => true
+    };
+    FontDescriptor(move families, style.font_weight, italic, style.font_size)
+}
+
+/* Changes the case of text as its 'text-transform' says (CSS 2.1, Section
+16.5). Words are capitalized by the first letter after each space. */
+fn apply_text_transform(text: &str, transform: CSSTextTransform) -> ~str {
+    match transform {
+        TextTransformNone => str::from_slice(text),
+        TextTransformUppercase => str::to_upper(text),
+        TextTransformLowercase => str::to_lower(text),
+        TextTransformCapitalize => {
+            let mut result = ~"";
+            let mut word_start = true;
+            for str::each_char(text) |c| {
+                if word_start {
+                    str::push_str(result, str::to_upper(str::from_char(c)));
+                } else {
+                    str::push_char(result, c);
+                }
+                word_start = char::is_whitespace(c);
+            }
+            result
+        }
+    }
+}
+
+/* The height of the inline boxes of text in the given style, whose font is
+`font` (CSS 2.1, Section 10.8.1). 'normal' is the height the font asks
+for, with its line gap. */
+fn get_line_height(style: &ComputedStyle, font: &Font) -> au {
+    match style.line_height {
+        LineHeightNormal => font.em_length(font.metrics.em_height + font.metrics.leading),
+        LineHeightNumber(n) => au::from_frac_px(n * style.font_size),
+        LineHeightLength(Px(px)) => au::from_frac_px(px),
+        LineHeightLength(Em(em)) => au::from_frac_px(em * style.font_size),
+        LineHeightPercent(percent) => au::from_frac_px(percent * style.font_size / 100.0)
+    }
+}
+
+/* The baseline of an inline box of text in the given font, from its top:
+the leading, the difference between the line height and the height of the
+glyphs, is split evenly above and below them. */
+fn get_text_baseline(line_height: au, font: &Font) -> au {
+    let leading = line_height.sub(font.ascent().add(font.descent()));
+    au(*leading / 2).add(font.ascent())
+}
+
+/* The language of the text of a node: that of the 'lang' attribute of the
+nearest element around it that has one, if it is not empty (HTML 4.01,
+Section 8.1) */
+fn get_language(node: Node) -> Option<~str> {
+    let mut node = node;
+    loop {
+        let lang = do node.read |n| {
+            match n.kind {
+                ~Element(element) => element.get_attr(~"lang"),
+                _ => None
+            }
+        };
+        match lang {
+            Some(lang) => return if lang.is_empty() { None } else { Some(lang) },
+            None => {}
+        }
+        match tree::get_parent(NodeTree, node) {
+            Some(parent) => node = parent,
+            None => return None
+        }
+    }
+}
+
+/* Whether lines may wrap inside text with the given 'white-space' */
+pure fn wraps_lines(white_space: CSSWhiteSpace) -> bool {
+    match white_space {
+        WhiteSpaceNormal | WhiteSpacePreWrap | WhiteSpacePreLine => true,
+        WhiteSpacePre | WhiteSpaceNowrap => false
+    }
+}
+
+/* Whether sequences of spaces collapse into one in text with the given 'white-space' */
+pure fn collapses_spaces(white_space: CSSWhiteSpace) -> bool {
+    match white_space {
+        WhiteSpaceNormal | WhiteSpaceNowrap | WhiteSpacePreLine => true,
+        WhiteSpacePre | WhiteSpacePreWrap => false
+    }
+}
+
+pure fn preserves_newlines(white_space: CSSWhiteSpace) -> bool {
+    match white_space {
+        WhiteSpacePre | WhiteSpacePreWrap | WhiteSpacePreLine => true,
+        WhiteSpaceNormal | WhiteSpaceNowrap => false
+    }
+}
+
+/* Applies the whitespace processing model (CSS 2.1, Section 16.6.1) to the
+text of a text node: spaces, tabs and newlines collapse or are kept as its
+'white-space' says, and kept tabs are expanded to the next tab stop, every
+8 characters. Collapsing spaces across text boxes, and removing them at the
+start of lines, is left to line breaking. */
+fn process_white_space(text: &str, white_space: CSSWhiteSpace) -> ~str {
+    let collapse = collapses_spaces(white_space);
+    let keep_newlines = preserves_newlines(white_space);
+
+    let mut result = ~"";
+    let mut column = 0u;
+    let mut pending_space = false;
+    let mut at_newline = false;
+    for str::each_char(text) |c| {
+        if c == '\r' {
+            loop;
+        }
+        if c == '\n' && keep_newlines {
+            // spaces around kept newlines are removed
+            str::push_char(result, '\n');
+            column = 0;
+            pending_space = false;
+            at_newline = true;
+        } else if collapse && (c == ' ' || c == '\t' || c == '\n') {
+            pending_space = !at_newline;
+        } else if c == '\t' {
+            let spaces = 8 - column % 8;
+            for spaces.times { str::push_char(result, ' '); }
+            column += spaces;
+        } else {
+            if pending_space {
+                str::push_char(result, ' ');
+                column += 1;
+                pending_space = false;
+            }
+            str::push_char(result, c);
+            column += 1;
+            at_newline = false;
+        }
+    }
+    if pending_space {
+        str::push_char(result, ' ');
+    }
+    return result;
+}
+
 trait TextLayout {
-    fn reflow_text(ctx: &LayoutContext);
+    fn trim_text(start: uint, end: uint);
+    fn split_text(first_end: uint, second_start: uint) -> @RenderBox;
+    fn split_at_newlines() -> ~[@RenderBox];
+    fn split_at_font_changes(font_cache: @FontCache) -> ~[@RenderBox];
+    fn split_at_bidi_runs(levels: &[uint]) -> ~[@RenderBox];
 }
 
-#[doc="The main reflow routine for text layout."]
+/* Text boxes are split into one box for each line they are laid out on. */
 impl @RenderBox : TextLayout {
-    fn reflow_text(ctx: &LayoutContext) {
-        let d = match self.kind {
-            TextBox(d) => { d }
-            _ => { fail ~"expected text box in reflow_text!" }
+    /* Keeps the text of this box between two byte offsets */
+    fn trim_text(start: uint, end: uint) {
+        match self.kind {
+            TextBox(d) => {
+                d.run = d.run.slice(start, end);
+                d.text = copy d.run.text;
+                self.data.position.size.width = d.run.size().width;
+            }
+            _ => fail ~"expected text box in trim_text!"
+        }
+    }
+
+    /* Keeps the text of this box up to `first_end`, and moves the text from
+    `second_start` on into a new box for the same node, which is returned.
+    The new box takes over any forced break after the text. */
+    fn split_text(first_end: uint, second_start: uint) -> @RenderBox {
+        let (font, rest, forced_break) = match self.kind {
+            TextBox(d) => {
+                let forced_break = d.forced_break;
+                d.forced_break = false;
+                (d.font, d.run.slice(second_start, d.text.len()), forced_break)
+            }
+            _ => fail ~"expected text box in split_text!"
         };
 
-        let font = ctx.font_cache.get_test_font();
+        let data = TextBoxData(font, rest);
+        data.forced_break = forced_break;
+        let box = @RenderBox(self.id, self.node, self.ctx, TextBox(move data));
+        box.data.bidi_level = self.data.bidi_level;
+        match box.kind {
+            TextBox(d) => box.data.position.size = Size2D(d.run.size().width,
+                                                          self.data.position.size.height),
+            _ => {}
+        }
 
-        // Do line breaking.
-        let mut current = TextRun(font, d.text);
-        let mut lines = dvec::DVec();
-        let mut width_left = au::from_px(800);
-        let mut max_width = au(0);
+        // the new box moves with the inline boxes containing this one
+        match tree::get_parent(RenderBoxTree, self) {
+            Some(parent) => RenderBoxTree.add_child(parent, box),
+            None => {}
+        }
 
-        while current.size().width > width_left {
-            let min_width = current.min_break_width();
+        self.trim_text(0, first_end);
+        box
+    }
 
-            debug!("line %d, current width %d, width left %d, min width %d",
-                   lines.len() as int,
-                   *current.size().width as int,
-                   *width_left as int,
-                   *min_width as int);
+    /* Splits this box after each preserved newline in its text. Returns
+    the boxes in order, starting with this one. */
+    fn split_at_newlines() -> ~[@RenderBox] {
+        let mut boxes = ~[self];
+        let mut box = self;
+        loop {
+            let newline = match box.kind {
+                TextBox(d) => str::find_char(d.text, '\n'),
+                _ => None
+            };
+            match newline {
+                Some(idx) => {
+                    let rest = box.split_text(idx, idx + 1);
+                    match box.kind {
+                        TextBox(d) => d.forced_break = true,
+                        _ => {}
+                    }
+                    vec::push(boxes, rest);
+                    box = rest;
+                }
+                None => return boxes
+            }
+        }
+    }
 
-            if min_width > width_left {
-                // Too bad, we couldn't break. Overflow.
-                break;
+    /* Splits this box where the font of its text changes: characters that
+    the font of its style has no glyphs for are drawn with fallback fonts.
+    Returns the boxes in order, starting with this one, which takes the
+    first run of text; the last box takes over any forced break. */
+    fn split_at_font_changes(font_cache: @FontCache) -> ~[@RenderBox] {
+        let (runs, forced_break) = match self.kind {
+            TextBox(d) => {
+                let desc = font_descriptor(&self.node.computed_style());
+                (font_cache.get_font_runs(&desc, d.text), d.forced_break)
             }
+            _ => fail ~"expected text box in split_at_font_changes!"
+        };
+        if runs.len() <= 1 {
+            return ~[self];
+        }
 
-            let (prev_line, next_line) = current.split(font, width_left);
-            let prev_width = prev_line.size().width;
-            if max_width < prev_width {
-                max_width = prev_width;
+        let mut boxes = ~[self];
+        for runs.eachi |i, run| {
+            let (font, text) = copy *run;
+            let box = if i == 0 {
+                match self.kind {
+                    TextBox(d) => {
+                        d.font = font;
+                        d.run = TextRun(font, copy text);
+                        d.text = move text;
+                        d.forced_break = false;
+                    }
+                    _ => {}
+                }
+                self
+            } else {
+                let box = @RenderBox(self.id, self.node, self.ctx,
+                                     TextBox(TextBoxData(font, TextRun(font, move text))));
+                // the new box moves with the inline boxes containing this one
+                match tree::get_parent(RenderBoxTree, self) {
+                    Some(parent) => RenderBoxTree.add_child(parent, box),
+                    None => {}
+                }
+                vec::push(boxes, box);
+                box
+            };
+            match box.kind {
+                TextBox(d) => {
+                    box.data.position.size = d.run.size();
+                    d.forced_break = forced_break && i == runs.len() - 1;
+                }
+                _ => {}
             }
+        }
+        boxes
+    }
 
-            lines.push(move prev_line);
-            current = next_line;
+    /* Splits this box where the embedding level or the script of its text
+    changes, given the level of each of its characters, and shapes each
+    piece in its direction, script and language. Returns the boxes in order,
+    starting with this one; the last box takes over any forced break. */
+    fn split_at_bidi_runs(levels: &[uint]) -> ~[@RenderBox] {
+        let text = match self.kind {
+            TextBox(d) => copy d.text,
+            _ => fail ~"expected text box in split_at_bidi_runs!"
+        };
+        if text.is_empty() {
+            return ~[self];
         }
+        let scripts = resolve_scripts(text);
 
-        let remaining_width = current.size().width;
-        if max_width < remaining_width {
-            max_width = remaining_width;
+        // The byte offsets at which the pieces start, with their levels and scripts
+        let mut pieces = ~[];
+        let mut offset = 0u;
+        let mut n = 0u;
+        while offset < text.len() {
+            if n == 0 || levels[n] != levels[n - 1] || scripts[n] != scripts[n - 1] {
+                vec::push(pieces, (offset, levels[n], scripts[n]));
+            }
+            offset = str::char_range_at(text, offset).next;
+            n += 1;
         }
 
-        let line_count = 1 + (lines.len() as i32);
-        let total_height = au(*current.size().height * line_count);
-        lines.push(move current);
+        let language = get_language(self.node);
+        let mut boxes = ~[];
+        let mut box = self;
+        let mut p = 0u;
+        while p < pieces.len() {
+            let (start, level, script) = pieces[p];
+            let rest = if p + 1 < pieces.len() {
+                let (end, _, _) = pieces[p + 1];
+                Some(box.split_text(end - start, end - start))
+            } else {
+                None
+            };
 
-        self.data.position.size = Size2D(max_width, total_height);
-        d.runs = move dvec::unwrap(lines);
+            box.data.bidi_level = level;
+            let params = ShapingParams {
+                rtl: is_rtl_level(level),
+                script: script,
+                language: copy language
+            };
+            // Text in one script written from left to right was shaped that way already
+            if pieces.len() > 1 || params.rtl || params.language.is_some() {
+                match box.kind {
+                    TextBox(d) => {
+                        d.run = TextRunWithParams(d.font, copy d.text, &params);
+                        box.data.position.size.width = d.run.size().width;
+                    }
+                    _ => {}
+                }
+            }
+            vec::push(boxes, box);
+
+            match rest {
+                Some(rest) => box = rest,
+                None => {}
+            }
+            p += 1;
+        }
+        boxes
     }
 }
 
+#[test]
+fn should_process_white_space() {
+    assert process_white_space(" a \t b\n\nc ", WhiteSpaceNormal) == ~" a b c ";
+    assert process_white_space(" a \t b\n\nc ", WhiteSpaceNowrap) == ~" a b c ";
+    assert process_white_space("a  b\tc\r\nd", WhiteSpacePre) == ~"a  b    c\nd";
+    assert process_white_space("a  \n  b\n", WhiteSpacePreLine) == ~"a\nb\n";
+    assert process_white_space("ab\tc", WhiteSpacePreWrap) == ~"ab      c";
+}
+
+#[test]
+fn should_apply_text_transforms() {
+    assert apply_text_transform("the quick fox", TextTransformNone) == ~"the quick fox";
+    assert apply_text_transform("the quick fox", TextTransformUppercase) == ~"THE QUICK FOX";
+    assert apply_text_transform("The Quick Fox", TextTransformLowercase) == ~"the quick fox";
+    assert apply_text_transform("the quick  fox", TextTransformCapitalize) == ~"The Quick  Fox";
+}
+
 /* TODO: new unit tests for TextBox splitting, etc
 fn should_calculate_the_size_of_the_text_box() {
     #[test];
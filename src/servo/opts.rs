@@ -1,14 +1,51 @@
 //! Configuration options for a single run of the servo application. Created
 //! from command line arguments.
 
+use geom::size::Size2D;
+use gfx::render_task::DEFAULT_RENDER_WORKERS;
+use image::encode::encoding;
+use encoding::{Encoding, PngEncoding};
+
 type Opts = {
     urls: ~[~str],
-    render_mode: RenderMode
+    render_mode: RenderMode,
+    viewport: Viewport,
+    // The number of tasks that draw tiles in parallel
+    render_workers: uint
+};
+
+// How big the page is laid out, and how finely it is drawn
+type Viewport = {
+    // In px
+    size: Size2D<int>,
+    // Whether the height grows to that of the whole document
+    full_page: bool,
+    // The number of device pixels drawn for each px
+    device_pixel_ratio: float
 };
 
+fn default_viewport() -> Viewport {
+    {
+        size: Size2D(800, 600),
+        full_page: false,
+        device_pixel_ratio: 1f
+    }
+}
+
+// The size of the surface the viewport is drawn onto
+fn device_size(viewport: &Viewport, size: &Size2D<int>) -> Size2D<int> {
+    Size2D((size.width as float * viewport.device_pixel_ratio) as int,
+           (size.height as float * viewport.device_pixel_ratio) as int)
+}
+
 enum RenderMode {
     Screen,
-    Png(~str)
+    // Writes the page as an image to the file
+    Image(~str, Encoding),
+    // Writes the first display list of the page as text to the file
+    DumpDisplayList(~str),
+    // Renders the display list saved in the first file to an image in the second
+    ReplayDisplayList(~str, ~str, Encoding)
 }
 
 #[allow(non_implicitly_copyable_typarams)]
@@ -18,7 +55,14 @@ fn from_cmdline_args(args: ~[~str]) -> Opts {
     let args = args.tail();
 
     let opts = ~[
-        getopts::optopt(~"o")
+        getopts::optopt(~"o"),
+        getopts::optopt(~"format"),
+        getopts::optopt(~"dump-display-list"),
+        getopts::optopt(~"replay-display-list"),
+        getopts::optopt(~"size"),
+        getopts::optflag(~"full-page"),
+        getopts::optopt(~"device-pixel-ratio"),
+        getopts::optopt(~"render-workers")
     ];
 
     let opt_match = match getopts::getopts(args, opts) {
@@ -26,19 +70,135 @@ fn from_cmdline_args(args: ~[~str]) -> Opts {
       result::Err(f) => { fail getopts::fail_str(copy f) }
     };
 
-    let urls = if opt_match.free.is_empty() {
+    let output_file = getopts::opt_maybe_str(opt_match, ~"o");
+    let replay_file = getopts::opt_maybe_str(opt_match, ~"replay-display-list");
+
+    // Replaying a display list does not load any page
+    let urls = if opt_match.free.is_empty() && replay_file.is_none() {
         fail ~"servo asks that you provide 1 or more URLs"
     } else {
         copy opt_match.free
     };
 
-    let render_mode = match getopts::opt_maybe_str(opt_match, ~"o") {
-      Some(output_file) => { Png(copy output_file) }
-      None => { Screen }
+    // --format wins over the extension of the output file, and PNG is the fallback
+    let encoding = match getopts::opt_maybe_str(opt_match, ~"format") {
+      Some(name) => match encoding::from_name(name) {
+        Some(encoding) => encoding,
+        None => fail fmt!("servo asks that the format be png, tga, ppm, rgba or bgra, not %s",
+                          name)
+      },
+      None => match output_file {
+        Some(output_file) => match encoding::from_path(output_file) {
+          Some(encoding) => encoding,
+          None => PngEncoding
+        },
+        None => PngEncoding
+      }
+    };
+
+    let render_mode = match (replay_file,
+                             getopts::opt_maybe_str(opt_match, ~"dump-display-list")) {
+      (Some(list_file), _) => match output_file {
+        Some(output_file) => ReplayDisplayList(copy list_file, copy output_file, encoding),
+        None => fail ~"servo asks that you give an image file with -o to replay a display list"
+      },
+      (None, Some(list_file)) => DumpDisplayList(copy list_file),
+      (None, None) => match output_file {
+        Some(output_file) => { Image(copy output_file, encoding) }
+        None => { Screen }
+      }
+    };
+
+    let default = default_viewport();
+    let size = match getopts::opt_maybe_str(opt_match, ~"size") {
+      Some(size) => match parse_size(size) {
+        Some(size) => size,
+        None => fail fmt!("servo asks that the size be given as WIDTHxHEIGHT, not %s", size)
+      },
+      None => default.size
+    };
+    let device_pixel_ratio = match getopts::opt_maybe_str(opt_match, ~"device-pixel-ratio") {
+      Some(ratio) => match float::from_str(ratio) {
+        Some(ratio) if ratio > 0f => ratio,
+        _ => fail fmt!("servo asks that the device pixel ratio be a positive number, not %s",
+                       ratio)
+      },
+      None => default.device_pixel_ratio
+    };
+    let render_workers = match getopts::opt_maybe_str(opt_match, ~"render-workers") {
+      Some(workers) => match uint::from_str(workers) {
+        Some(workers) if workers > 0u => workers,
+        _ => fail fmt!("servo asks that the number of render workers be a positive number, \
+                        not %s", workers)
+      },
+      None => DEFAULT_RENDER_WORKERS
     };
 
     {
         urls: urls,
-        render_mode: render_mode
+        render_mode: render_mode,
+        viewport: {
+            size: size,
+            full_page: getopts::opt_present(opt_match, ~"full-page"),
+            device_pixel_ratio: device_pixel_ratio
+        },
+        render_workers: render_workers
+    }
+}
+
+fn parse_size(size: &str) -> Option<Size2D<int>> {
+    let parts = str::split_char(size, 'x');
+    if parts.len() != 2u {
+        return None;
+    }
+    match (int::from_str(parts[0]), int::from_str(parts[1])) {
+      (Some(width), Some(height)) if width > 0 && height > 0 => Some(Size2D(width, height)),
+      _ => None
+    }
+}
+
+#[test]
+fn should_parse_viewport_options() {
+    let opts = from_cmdline_args(~[~"servo", ~"--size", ~"1024x2000", ~"--full-page",
+                                   ~"--device-pixel-ratio", ~"2", ~"-o", ~"out.png",
+                                   ~"page.html"]);
+    assert opts.viewport.size == Size2D(1024, 2000);
+    assert opts.viewport.full_page;
+    assert opts.viewport.device_pixel_ratio == 2f;
+    assert device_size(&opts.viewport, &Size2D(10, 20)) == Size2D(20, 40);
+
+    let opts = from_cmdline_args(~[~"servo", ~"page.html"]);
+    assert opts.viewport.size == Size2D(800, 600);
+    assert !opts.viewport.full_page;
+
+    assert parse_size(~"800x") == None;
+    assert parse_size(~"0x600") == None;
+}
+
+#[test]
+fn should_parse_the_render_worker_count() {
+    let opts = from_cmdline_args(~[~"servo", ~"page.html"]);
+    assert opts.render_workers == DEFAULT_RENDER_WORKERS;
+
+    let opts = from_cmdline_args(~[~"servo", ~"--render-workers", ~"8", ~"page.html"]);
+    assert opts.render_workers == 8u;
+}
+
+#[test]
+fn should_pick_the_image_format() {
+    use encoding::{TgaEncoding, RawBgraEncoding};
+
+    match from_cmdline_args(~[~"servo", ~"-o", ~"out.tga", ~"page.html"]).render_mode {
+      Image(_, encoding) => assert encoding == TgaEncoding,
+      _ => fail
+    }
+    match from_cmdline_args(~[~"servo", ~"-o", ~"out.png", ~"--format", ~"bgra",
+                              ~"page.html"]).render_mode {
+      Image(_, encoding) => assert encoding == RawBgraEncoding,
+      _ => fail
+    }
+    match from_cmdline_args(~[~"servo", ~"-o", ~"out", ~"page.html"]).render_mode {
+      Image(_, encoding) => assert encoding == PngEncoding,
+      _ => fail
     }
 }
@@ -11,7 +11,8 @@ use comm::*;
 use dvec::DVec;
 use azure::cairo::cairo_surface_t;
 use gfx::compositor::Compositor;
-use dom::event::{Event, ResizeEvent};
+use dom::event::{Event, ResizeEvent, WheelEvent, KeyScrollEvent, ScrollKey, ScrollLineUp};
+use dom::event::{ScrollLineDown, ScrollPageUp, ScrollPageDown, ScrollHome, ScrollEnd};
 use layers::ImageLayer;
 use geom::size::Size2D;
 use ShareGlContext = sharegl::platform::Context;
@@ -132,6 +133,35 @@ fn mainloop(+mode: Mode, po: Port<Msg>) {
 				}
 			}
 
+			do glut::keyboard_func |key, _x, _y| {
+				if key == ' ' as u8 {
+					for event_listeners.each |event_listener| {
+						event_listener.send(KeyScrollEvent(ScrollPageDown));
+					}
+				}
+			}
+
+			do glut::special_func |key, _x, _y| {
+				match scroll_key(key as int) {
+					Some(scroll_key) => {
+						for event_listeners.each |event_listener| {
+							event_listener.send(KeyScrollEvent(scroll_key));
+						}
+					}
+					None => ()
+				}
+			}
+
+			// GLUT reports the turns of a mouse wheel as presses of buttons 3 and 4
+			do glut::mouse_func |button, state, _x, _y| {
+				if state as int == GLUT_DOWN && (button as int == 3 || button as int == 4) {
+					let delta = if button as int == 3 { -WHEEL_SCROLL_PX } else { WHEEL_SCROLL_PX };
+					for event_listeners.each |event_listener| {
+						event_listener.send(WheelEvent(0, delta));
+					}
+				}
+			}
+
 			do glut::display_func() {
 				check_for_messages();
 
@@ -163,6 +193,27 @@ fn mainloop(+mode: Mode, po: Port<Msg>) {
 	}
 }
 
+// How far a turn of the mouse wheel scrolls
+const WHEEL_SCROLL_PX: int = 48;
+
+const GLUT_DOWN: int = 0;
+const GLUT_KEY_UP: int = 101;
+const GLUT_KEY_DOWN: int = 103;
+const GLUT_KEY_PAGE_UP: int = 104;
+const GLUT_KEY_PAGE_DOWN: int = 105;
+const GLUT_KEY_HOME: int = 106;
+const GLUT_KEY_END: int = 107;
+
+fn scroll_key(key: int) -> Option<ScrollKey> {
+    if key == GLUT_KEY_UP { Some(ScrollLineUp) }
+    else if key == GLUT_KEY_DOWN { Some(ScrollLineDown) }
+    else if key == GLUT_KEY_PAGE_UP { Some(ScrollPageUp) }
+    else if key == GLUT_KEY_PAGE_DOWN { Some(ScrollPageDown) }
+    else if key == GLUT_KEY_HOME { Some(ScrollHome) }
+    else if key == GLUT_KEY_END { Some(ScrollEnd) }
+    else { None }
+}
+
 #[doc = "
 Implementation to allow the osmain channel to be used as a graphics
 compositor for the renderer
@@ -174,6 +225,10 @@ impl OSMain : Compositor {
     fn draw(+next_dt: pipes::Chan<DrawTarget>, +draw_me: DrawTarget) {
         self.send(Draw(next_dt, draw_me))
     }
+    // The window keeps its size
+    fn resize(+next_dt: pipes::Chan<DrawTarget>, +old: DrawTarget, _size: Size2D<int>) {
+        next_dt.send(move old)
+    }
     fn add_event_listener(listener: comm::Chan<Event>) {
         self.send(AddEventListener(listener));
     }
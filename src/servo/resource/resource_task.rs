@@ -6,7 +6,7 @@ A task that takes a URL and streams back the binary data
 
 export ControlMsg, Load, Exit;
 export ProgressMsg, Payload, Done;
-export ResourceTask, ResourceManager, LoaderTaskFactory;
+export ResourceTask, ResourceManager, LoaderTaskFactory, create_resource_task_with_loaders;
 
 use comm::{Chan, Port};
 use task::{spawn, spawn_listener};
@@ -2,7 +2,8 @@ use comm::*;
 use option::swap_unwrap;
 use platform::osmain;
 use osmain::{OSMain, AddKeyHandler};
-use opts::{Opts, Screen, Png};
+use opts::{Opts, Viewport, Screen, Image, DumpDisplayList, ReplayDisplayList};
+use image::encode::encoding::{Encoding, RawRgbaEncoding};
 use engine::{EngineTask, EngineProto};
 
 use url_to_str = std::net::url::to_str;
@@ -17,18 +18,29 @@ fn main(args: ~[~str]) {
 #[allow(non_implicitly_copyable_typarams)]
 fn run(opts: Opts) {
     match opts.render_mode {
-      Screen => run_pipeline_screen(opts.urls),
-      Png(outfile) => {
+      Screen => run_pipeline_screen(opts.urls, opts.render_workers),
+      Image(outfile, encoding) => {
         assert opts.urls.is_not_empty();
         if opts.urls.len() > 1u {
-            fail ~"servo asks that you stick to a single URL in PNG output mode"
+            fail ~"servo asks that you stick to a single URL in image output mode"
         }
-        run_pipeline_png(opts.urls.head(), outfile)
+        run_pipeline_image(opts.urls.head(), outfile, encoding, opts.viewport,
+                           opts.render_workers)
+      }
+      DumpDisplayList(outfile) => {
+        assert opts.urls.is_not_empty();
+        if opts.urls.len() > 1u {
+            fail ~"servo asks that you stick to a single URL when dumping a display list"
+        }
+        run_pipeline_dump(opts.urls.head(), outfile, opts.viewport, opts.render_workers)
+      }
+      ReplayDisplayList(list_file, outfile, encoding) => {
+        run_replay_image(list_file, outfile, encoding, opts.viewport, opts.render_workers)
       }
     }
 }
 
-fn run_pipeline_screen(urls: ~[~str]) {
+fn run_pipeline_screen(urls: ~[~str], render_workers: uint) {
 
     // The platform event handler thread
     let osmain = OSMain();
@@ -38,7 +50,7 @@ fn run_pipeline_screen(urls: ~[~str]) {
     osmain.send(AddKeyHandler(keypress_to_engine));
 
     // Create a serve instance
-    let mut engine_task = Some(EngineTask(osmain));
+    let mut engine_task = Some(EngineTask(osmain, render_workers));
 
     for urls.each |filename| {
         let url = make_url(*filename, None);
@@ -62,33 +74,122 @@ fn run_pipeline_screen(urls: ~[~str]) {
     osmain.send(osmain::Exit);
 }
 
-fn run_pipeline_png(-url: ~str, outfile: ~str) {
+fn run_pipeline_image(-url: ~str, outfile: ~str, encoding: Encoding, viewport: Viewport,
+                      render_workers: uint) {
 
-    // Use a PNG encoder as the graphics compositor
-    use gfx::png_compositor;
-    use png_compositor::PngCompositor;
-    use io::{Writer, buffered_file_writer};
+    // Use an image encoder as the graphics compositor
+    use gfx::image_compositor;
+    use image_compositor::ImageCompositor;
     use resource::resource_task::ResourceTask;
     use resource::image_cache_task::SyncImageCacheTask;
     use engine::EngineTask_;
 
-    listen(|pngdata_from_compositor| {
-        let compositor = PngCompositor(pngdata_from_compositor);
+    listen(|imagedata_from_compositor| {
+        let compositor = ImageCompositor(imagedata_from_compositor,
+                                         opts::device_size(&viewport, &viewport.size),
+                                         encoding);
         let resource_task = ResourceTask();
-        // For the PNG pipeline we are using a synchronous image cache
+        // For the image pipeline we are using a synchronous image cache
         // so that all requests will be fullfilled before the first
         // render
         let image_cache_task = SyncImageCacheTask(resource_task);
-        let engine_task = EngineTask_(compositor, resource_task, image_cache_task);
+        let engine_task = EngineTask_(compositor, resource_task, image_cache_task, viewport,
+                                      render_workers, None);
         let engine_task = EngineProto::client::LoadURL(engine_task, make_url(url, None));
 
-        match buffered_file_writer(&Path(outfile)) {
-          Ok(writer) => writer.write(pngdata_from_compositor.recv()),
-          Err(e) => fail e
-        }
+        let image = imagedata_from_compositor.recv();
 
         let engine_task = EngineProto::client::Exit(engine_task);
         pipes::recv(engine_task);
-        compositor.send(png_compositor::Exit);
+        compositor.send(image_compositor::Exit);
+
+        write_image(image, outfile);
+    })
+}
+
+// Writes a frame from the image compositor to a file, or says why it could not be encoded
+fn write_image(+image: Result<~[u8], ~str>, outfile: &str) {
+    use io::{Writer, buffered_file_writer};
+
+    match move image {
+      Ok(move bytes) => match buffered_file_writer(&Path(outfile)) {
+        Ok(writer) => writer.write(bytes),
+        Err(e) => fail e
+      },
+      Err(e) => fail fmt!("servo could not write %s: %s", outfile, e)
+    }
+}
+
+// Runs the image pipeline, but writes the first display list of the page as text
+fn run_pipeline_dump(-url: ~str, outfile: ~str, viewport: Viewport, render_workers: uint) {
+    use gfx::image_compositor;
+    use image_compositor::ImageCompositor;
+    use io::{WriterUtil, buffered_file_writer};
+    use resource::resource_task::ResourceTask;
+    use resource::image_cache_task::SyncImageCacheTask;
+    use engine::EngineTask_;
+
+    listen(|imagedata_from_compositor| {
+        listen(|display_list_from_renderer| {
+            // The image is thrown away, so it is not worth compressing
+            let compositor = ImageCompositor(imagedata_from_compositor,
+                                             opts::device_size(&viewport, &viewport.size),
+                                             RawRgbaEncoding);
+            let resource_task = ResourceTask();
+            let image_cache_task = SyncImageCacheTask(resource_task);
+            let engine_task = EngineTask_(compositor, resource_task, image_cache_task, viewport,
+                                          render_workers, Some(display_list_from_renderer));
+            let engine_task = EngineProto::client::LoadURL(engine_task, make_url(url, None));
+
+            match buffered_file_writer(&Path(outfile)) {
+              Ok(writer) => writer.write_str(display_list_from_renderer.recv()),
+              Err(e) => fail e
+            }
+            imagedata_from_compositor.recv();
+
+            let engine_task = EngineProto::client::Exit(engine_task);
+            pipes::recv(engine_task);
+            compositor.send(image_compositor::Exit);
+        })
+    })
+}
+
+// Renders a display list saved by `run_pipeline_dump` without loading its page.
+// The list is painted onto a page of the viewport size.
+fn run_replay_image(list_file: ~str, outfile: ~str, encoding: Encoding, viewport: Viewport,
+                    render_workers: uint) {
+    use gfx::image_compositor;
+    use image_compositor::ImageCompositor;
+    use gfx::render_task;
+    use render_task::{RenderTask_, RenderMsg};
+    use gfx::display_list_text;
+    use geom::point::Point2D;
+    use geom::rect::Rect;
+
+    let text = match io::read_whole_file_str(&Path(list_file)) {
+      Ok(text) => text,
+      Err(e) => fail e
+    };
+    let display_list = match display_list_text::from_text(text) {
+      Ok(display_list) => display_list,
+      Err(e) => fail fmt!("%s: %s", list_file, e)
+    };
+
+    listen(|imagedata_from_compositor| {
+        let compositor = ImageCompositor(imagedata_from_compositor,
+                                         opts::device_size(&viewport, &viewport.size),
+                                         encoding);
+        let renderer = RenderTask_(compositor, viewport.device_pixel_ratio, render_workers,
+                                   None);
+        renderer.send(RenderMsg(move display_list, Rect(Point2D(0, 0), copy viewport.size)));
+
+        let image = imagedata_from_compositor.recv();
+
+        let (exit_chan, exit_response_from_renderer) = pipes::stream();
+        renderer.send(render_task::ExitMsg(exit_chan));
+        exit_response_from_renderer.recv();
+        compositor.send(image_compositor::Exit);
+
+        write_image(image, outfile);
     })
 }
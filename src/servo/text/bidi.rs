@@ -0,0 +1,571 @@
+/*!
+The Unicode bidirectional algorithm (UAX #9), which orders text that mixes
+left-to-right and right-to-left writing, such as Hebrew or Arabic with
+numbers and English in it, and the scripts of characters, which text is
+shaped by.
+*/
+
+export BidiClass, BidiL, BidiR, BidiAL, BidiEN, BidiES, BidiET, BidiAN, BidiCS, BidiNSM, BidiBN,
+       BidiB, BidiS, BidiWS, BidiON, BidiLRE, BidiLRO, BidiRLE, BidiRLO, BidiPDF;
+export bidi_class, resolve_levels, reorder, is_rtl_level;
+export script_tag, resolve_scripts;
+
+use util::true_type_tag;
+
+#[doc = "The bidirectional character types of UAX #9, Table 4"]
+enum BidiClass {
+    // Strong: left-to-right, right-to-left and Arabic letters
+    BidiL,
+    BidiR,
+    BidiAL,
+    // Weak: numbers, their separators and terminators, marks and boundary neutrals
+    BidiEN,
+    BidiES,
+    BidiET,
+    BidiAN,
+    BidiCS,
+    BidiNSM,
+    BidiBN,
+    // Neutral: paragraph and segment separators, whitespace and other neutrals
+    BidiB,
+    BidiS,
+    BidiWS,
+    BidiON,
+    // Explicit embeddings, overrides and the ends of them
+    BidiLRE,
+    BidiLRO,
+    BidiRLE,
+    BidiRLO,
+    BidiPDF
+}
+
+impl BidiClass: cmp::Eq {
+    pure fn eq(other: &BidiClass) -> bool {
+        self as uint == (*other) as uint
+    }
+    pure fn ne(other: &BidiClass) -> bool {
+        return !self.eq(other);
+    }
+}
+
+// The deepest level explicit embeddings reach (UAX #9, Section 3.3.1)
+const MAX_DEPTH: uint = 61;
+
+pure fn in_range(c: char, first: char, last: char) -> bool {
+    c >= first && c <= last
+}
+
+#[doc = "
+The bidirectional type of a character. The scripts written from right to
+left, numbers and their separators, whitespace, controls and the explicit
+formatting characters are told apart; other letters are strong
+left-to-right characters, and other punctuation and symbols are neutral.
+"]
+pure fn bidi_class(c: char) -> BidiClass {
+    if in_range(c, '0', '9') || c == '\u00b2' || c == '\u00b3' || c == '\u00b9'
+        || in_range(c, '\u06f0', '\u06f9') || in_range(c, '\uff10', '\uff19') {
+        return BidiEN;
+    }
+    if in_range(c, 'a', 'z') || in_range(c, 'A', 'Z') {
+        return BidiL;
+    }
+    match c {
+      '\n' | '\r' | '\x1c' | '\x1d' | '\x1e' | '\x85' | '\u2029' => return BidiB,
+      '\t' | '\x0b' | '\x1f' => return BidiS,
+      ' ' | '\x0c' | '\u1680' | '\u2028' | '\u205f' | '\u3000' => return BidiWS,
+      '+' | '-' | '\u207a' | '\u207b' | '\uff0b' | '\uff0d' => return BidiES,
+      '#' | '$' | '%' | '\u00b0' | '\u00b1' | '\u066a' | '\u2030' | '\u2031' | '\u2032'
+      | '\u2033' | '\u2034' => return BidiET,
+      ',' | '.' | '/' | ':' | '\u00a0' | '\u060c' | '\u202f' | '\u2044' | '\uff0c' | '\uff0e'
+      | '\uff0f' | '\uff1a' => return BidiCS,
+      '\u00ad' | '\u200b' | '\u200c' | '\u200d' | '\ufeff' => return BidiBN,
+      '\u200e' => return BidiL,
+      '\u200f' => return BidiR,
+      '\u202a' => return BidiLRE,
+      '\u202b' => return BidiRLE,
+      '\u202c' => return BidiPDF,
+      '\u202d' => return BidiLRO,
+      '\u202e' => return BidiRLO,
+      _ => {}
+    }
+    if c < ' ' || in_range(c, '\x7f', '\x9f') || in_range(c, '\u2060', '\u2064') {
+        return BidiBN;
+    }
+    if c < '\x7f' || in_range(c, '\u00a1', '\u00bf') || c == '\u00d7' || c == '\u00f7'
+        || in_range(c, '\u2010', '\u2027') || in_range(c, '\u2035', '\u205e') {
+        return BidiON;
+    }
+    if in_range(c, '\u00a2', '\u00a5') || in_range(c, '\u20a0', '\u20cf') {
+        return BidiET;
+    }
+    if in_range(c, '\u2000', '\u200a') {
+        return BidiWS;
+    }
+
+    // Combining marks
+    if in_range(c, '\u0300', '\u036f') || in_range(c, '\u0483', '\u0489')
+        || in_range(c, '\u0591', '\u05bd') || c == '\u05bf' || c == '\u05c1' || c == '\u05c2'
+        || c == '\u05c4' || c == '\u05c5' || c == '\u05c7'
+        || in_range(c, '\u0610', '\u061a') || in_range(c, '\u064b', '\u065f') || c == '\u0670'
+        || in_range(c, '\u06d6', '\u06dc') || in_range(c, '\u06df', '\u06e4')
+        || c == '\u06e7' || c == '\u06e8' || in_range(c, '\u06ea', '\u06ed')
+        || in_range(c, '\u20d0', '\u20ff') || in_range(c, '\ufe20', '\ufe2f') {
+        return BidiNSM;
+    }
+
+    // Arabic numbers and the signs that go with them
+    if in_range(c, '\u0600', '\u0605') || in_range(c, '\u0660', '\u0669') || c == '\u066b'
+        || c == '\u066c' || c == '\u06dd' {
+        return BidiAN;
+    }
+
+    // Hebrew, and the other scripts written from right to left
+    if in_range(c, '\u0590', '\u05ff') || in_range(c, '\u07c0', '\u085f')
+        || in_range(c, '\ufb1d', '\ufb4f') || in_range(c, '\U00010800', '\U00010fff')
+        || in_range(c, '\U0001e800', '\U0001edff') {
+        return BidiR;
+    }
+    // Arabic, Syriac and Thaana
+    if in_range(c, '\u0600', '\u07bf') || in_range(c, '\u0860', '\u08ff')
+        || in_range(c, '\ufb50', '\ufdff') || in_range(c, '\ufe70', '\ufefe')
+        || in_range(c, '\U0001ee00', '\U0001eeff') {
+        return BidiAL;
+    }
+
+    if in_range(c, '\u2100', '\u2bff') || in_range(c, '\u3001', '\u3003')
+        || in_range(c, '\ufe50', '\ufe6f') || in_range(c, '\ufff9', '\ufffd') {
+        return BidiON;
+    }
+    BidiL
+}
+
+#[doc = "Whether text at an embedding level is written from right to left"]
+pure fn is_rtl_level(level: uint) -> bool {
+    level % 2 == 1
+}
+
+pure fn is_strong(class: BidiClass) -> bool {
+    class == BidiL || class == BidiR || class == BidiAL
+}
+
+pure fn is_neutral(class: BidiClass) -> bool {
+    class == BidiB || class == BidiS || class == BidiWS || class == BidiON
+}
+
+// The direction a strong type or number counts as when resolving neutrals
+pure fn strong_direction(class: BidiClass) -> BidiClass {
+    match class {
+      BidiL => BidiL,
+      _ => BidiR
+    }
+}
+
+pure fn level_direction(level: uint) -> BidiClass {
+    if is_rtl_level(level) { BidiR } else { BidiL }
+}
+
+#[doc = "
+Resolves the embedding level of each character of a paragraph from their
+bidirectional types, starting at `para_level`: 0 for a paragraph written from
+left to right and 1 for one written from right to left. Even levels are
+written from left to right and odd levels from right to left.
+
+This follows rules X1 to I2 of UAX #9, and L1 for the separators. The
+explicit formatting characters are given the levels of the text around them.
+"]
+fn resolve_levels(classes: &[BidiClass], para_level: uint) -> ~[uint] {
+    let n = classes.len();
+    let mut levels = vec::from_elem(n, para_level);
+    let mut types = vec::map(classes, |class| *class);
+    let mut removed = vec::from_elem(n, false);
+
+    // Explicit levels and directions (X1 to X9)
+    let mut stack: ~[(uint, Option<BidiClass>)] = ~[(para_level, None)];
+    let mut overflow = 0u;
+    for uint::range(0, n) |i| {
+        let (level, override) = stack[stack.len() - 1];
+        levels[i] = level;
+        match classes[i] {
+          BidiRLE | BidiLRE | BidiRLO | BidiLRO => {
+            let rtl = classes[i] == BidiRLE || classes[i] == BidiRLO;
+            let next = if rtl { (level + 1) | 1 } else { (level + 2) & !1 };
+            if next <= MAX_DEPTH && overflow == 0 {
+                let override = match classes[i] {
+                  BidiRLO => Some(BidiR),
+                  BidiLRO => Some(BidiL),
+                  _ => None
+                };
+                vec::push(stack, (next, override));
+            } else {
+                overflow += 1;
+            }
+            removed[i] = true;
+          }
+          BidiPDF => {
+            if overflow > 0 {
+                overflow -= 1;
+            } else if stack.len() > 1 {
+                vec::pop(stack);
+            }
+            removed[i] = true;
+          }
+          BidiBN => removed[i] = true,
+          BidiB => {
+            // A paragraph separator ends every embedding
+            levels[i] = para_level;
+            stack = ~[(para_level, None)];
+            overflow = 0;
+          }
+          _ => match override {
+            Some(class) => types[i] = class,
+            None => {}
+          }
+        }
+    }
+
+    // The characters that are left, split into runs of the same level (X10)
+    let mut kept = ~[];
+    for uint::range(0, n) |i| {
+        if !removed[i] { vec::push(kept, i); }
+    }
+    let mut runs = ~[];
+    let mut start = 0u;
+    for uint::range(1, kept.len() + 1) |j| {
+        if j == kept.len() || levels[kept[j]] != levels[kept[start]] {
+            vec::push(runs, (start, j));
+            start = j;
+        }
+    }
+
+    let embedding_levels = copy levels;
+    for runs.eachi |r, run| {
+        let (start, end) = *run;
+        let level = embedding_levels[kept[start]];
+        let prev_level = if r == 0 { para_level } else { embedding_levels[kept[start - 1]] };
+        let next_level = if end == kept.len() { para_level } else { embedding_levels[kept[end]] };
+        let sos = level_direction(uint::max(level, prev_level));
+        let eos = level_direction(uint::max(level, next_level));
+
+        let t = vec::to_mut(vec::map(vec::view(kept, start, end), |i| types[*i]));
+        resolve_weak_types(t, sos);
+        resolve_neutral_types(t, sos, eos, level);
+
+        // Implicit levels (I1 and I2)
+        for t.eachi |j, class| {
+            let i = kept[start + j];
+            levels[i] = match (is_rtl_level(level), *class) {
+              (false, BidiR) => level + 1,
+              (false, BidiAN) | (false, BidiEN) => level + 2,
+              (true, BidiL) | (true, BidiEN) | (true, BidiAN) => level + 1,
+              _ => level
+            };
+        }
+    }
+
+    // Removed characters take the level of the one before them
+    for uint::range(0, n) |i| {
+        if removed[i] {
+            levels[i] = if i == 0 { para_level } else { levels[i - 1] };
+        }
+    }
+
+    // Separators, and the whitespace before them and at the end of the
+    // paragraph, go back to the paragraph level (L1)
+    let mut i = n;
+    let mut trailing = true;
+    while i > 0 {
+        i -= 1;
+        match classes[i] {
+          BidiB | BidiS => {
+            levels[i] = para_level;
+            trailing = true;
+          }
+          BidiWS | BidiBN | BidiLRE | BidiLRO | BidiRLE | BidiRLO | BidiPDF if trailing => {
+            levels[i] = para_level;
+          }
+          _ => trailing = false
+        }
+    }
+
+    return levels;
+}
+
+// Rules W1 to W7, on the types of a level run starting after `sos`
+fn resolve_weak_types(t: &[mut BidiClass], sos: BidiClass) {
+    let n = t.len();
+
+    // W1: marks take the type of the character before them
+    for uint::range(0, n) |j| {
+        if t[j] == BidiNSM {
+            t[j] = if j == 0 { sos } else { t[j - 1] };
+        }
+    }
+
+    // W2: European numbers after Arabic letters are Arabic numbers
+    let mut last_strong = sos;
+    for uint::range(0, n) |j| {
+        if is_strong(t[j]) {
+            last_strong = t[j];
+        } else if t[j] == BidiEN && last_strong == BidiAL {
+            t[j] = BidiAN;
+        }
+    }
+
+    // W3
+    for uint::range(0, n) |j| {
+        if t[j] == BidiAL { t[j] = BidiR; }
+    }
+
+    // W4: a single separator between two numbers of the same kind joins them
+    for uint::range(1, uint::max(n, 2) - 1) |j| {
+        let (before, after) = (t[j - 1], t[j + 1]);
+        if t[j] == BidiES && before == BidiEN && after == BidiEN {
+            t[j] = BidiEN;
+        } else if t[j] == BidiCS && before == after && (before == BidiEN || before == BidiAN) {
+            t[j] = before;
+        }
+    }
+
+    // W5: terminators next to European numbers are part of them
+    let mut j = 0u;
+    while j < n {
+        if t[j] != BidiET {
+            j += 1;
+            loop;
+        }
+        let mut k = j;
+        while k < n && t[k] == BidiET { k += 1; }
+        if (j > 0 && t[j - 1] == BidiEN) || (k < n && t[k] == BidiEN) {
+            for uint::range(j, k) |m| { t[m] = BidiEN; }
+        }
+        j = k;
+    }
+
+    // W6: other separators and terminators are neutral
+    for uint::range(0, n) |j| {
+        if t[j] == BidiES || t[j] == BidiET || t[j] == BidiCS { t[j] = BidiON; }
+    }
+
+    // W7: European numbers in left-to-right text are left-to-right
+    let mut last_strong = sos;
+    for uint::range(0, n) |j| {
+        if t[j] == BidiL || t[j] == BidiR {
+            last_strong = t[j];
+        } else if t[j] == BidiEN && last_strong == BidiL {
+            t[j] = BidiL;
+        }
+    }
+}
+
+// Rules N1 and N2: neutrals between text of the same direction take it, and
+// others that of the embedding level
+fn resolve_neutral_types(t: &[mut BidiClass], sos: BidiClass, eos: BidiClass, level: uint) {
+    let n = t.len();
+    let mut j = 0u;
+    while j < n {
+        if !is_neutral(t[j]) {
+            j += 1;
+            loop;
+        }
+        let mut k = j;
+        while k < n && is_neutral(t[k]) { k += 1; }
+        let before = if j == 0 { sos } else { strong_direction(t[j - 1]) };
+        let after = if k == n { eos } else { strong_direction(t[k]) };
+        let class = if before == after { before } else { level_direction(level) };
+        for uint::range(j, k) |m| { t[m] = class; }
+        j = k;
+    }
+}
+
+#[doc = "
+The visual order of the characters or boxes of a line from their embedding
+levels (UAX #9, rule L2): the indexes, in the order they are laid out from
+left to right.
+"]
+fn reorder(levels: &[uint]) -> ~[uint] {
+    let n = levels.len();
+    let mut order = vec::from_fn(n, |i| i);
+    if n == 0 {
+        return order;
+    }
+
+    let highest = levels.foldl(0u, |a, b| uint::max(a, *b));
+    let lowest = levels.foldl(highest, |a, b| uint::min(a, *b));
+    let lowest_odd = lowest | 1;
+
+    // From the highest level down to the lowest odd one, reverse every run
+    // of characters at that level or higher
+    let mut level = highest;
+    while level >= lowest_odd {
+        let mut i = 0u;
+        while i < n {
+            if levels[order[i]] < level {
+                i += 1;
+                loop;
+            }
+            let mut end = i;
+            while end < n && levels[order[end]] >= level { end += 1; }
+            let mut a = i;
+            let mut b = end - 1;
+            while a < b {
+                let tmp = order[a];
+                order[a] = order[b];
+                order[b] = tmp;
+                a += 1;
+                b -= 1;
+            }
+            i = end;
+        }
+        level -= 1;
+    }
+    return order;
+}
+
+#[doc = "
+The OpenType tag of the script a character is written in, as HarfBuzz
+takes it, or None for characters that are used with every script, such as
+spaces, digits, punctuation and combining marks.
+"]
+fn script_tag(c: char) -> Option<u32> {
+    if in_range(c, 'a', 'z') || in_range(c, 'A', 'Z') || c == '\u00aa' || c == '\u00ba'
+        || (in_range(c, '\u00c0', '\u024f') && c != '\u00d7' && c != '\u00f7')
+        || in_range(c, '\u1e00', '\u1eff') || in_range(c, '\ufb00', '\ufb06')
+        || in_range(c, '\uff21', '\uff3a') || in_range(c, '\uff41', '\uff5a') {
+        return Some(true_type_tag('L', 'a', 't', 'n'));
+    }
+    if in_range(c, '\u0370', '\u03ff') && c != '\u037e' && c != '\u0387'
+        || in_range(c, '\u1f00', '\u1fff') {
+        return Some(true_type_tag('G', 'r', 'e', 'k'));
+    }
+    if in_range(c, '\u0400', '\u0482') || in_range(c, '\u048a', '\u052f') {
+        return Some(true_type_tag('C', 'y', 'r', 'l'));
+    }
+    if in_range(c, '\u0531', '\u0588') || in_range(c, '\u058a', '\u058f') {
+        return Some(true_type_tag('A', 'r', 'm', 'n'));
+    }
+    if in_range(c, '\u05be', '\u05ff') && bidi_class(c) == BidiR
+        || in_range(c, '\ufb1d', '\ufb4f') {
+        return Some(true_type_tag('H', 'e', 'b', 'r'));
+    }
+    if (in_range(c, '\u0600', '\u06ff') && c != '\u060c' && c != '\u061b' && c != '\u061f'
+        && c != '\u0640' && bidi_class(c) != BidiNSM && !in_range(c, '\u06f0', '\u06f9'))
+        || in_range(c, '\u0750', '\u077f') || in_range(c, '\u08a0', '\u08ff')
+        || in_range(c, '\ufb50', '\ufdff') || in_range(c, '\ufe70', '\ufefe') {
+        return Some(true_type_tag('A', 'r', 'a', 'b'));
+    }
+    if in_range(c, '\u0700', '\u074f') { return Some(true_type_tag('S', 'y', 'r', 'c')); }
+    if in_range(c, '\u0780', '\u07b0') { return Some(true_type_tag('T', 'h', 'a', 'a')); }
+    if in_range(c, '\u0900', '\u0963') || in_range(c, '\u0966', '\u097f') {
+        return Some(true_type_tag('D', 'e', 'v', 'a'));
+    }
+    if in_range(c, '\u0980', '\u09ff') { return Some(true_type_tag('B', 'e', 'n', 'g')); }
+    if in_range(c, '\u0a00', '\u0a7f') { return Some(true_type_tag('G', 'u', 'r', 'u')); }
+    if in_range(c, '\u0a80', '\u0aff') { return Some(true_type_tag('G', 'u', 'j', 'r')); }
+    if in_range(c, '\u0b80', '\u0bff') { return Some(true_type_tag('T', 'a', 'm', 'l')); }
+    if in_range(c, '\u0c00', '\u0c7f') { return Some(true_type_tag('T', 'e', 'l', 'u')); }
+    if in_range(c, '\u0c80', '\u0cff') { return Some(true_type_tag('K', 'n', 'd', 'a')); }
+    if in_range(c, '\u0d00', '\u0d7f') { return Some(true_type_tag('M', 'l', 'y', 'm')); }
+    if in_range(c, '\u0e01', '\u0e3a') || in_range(c, '\u0e40', '\u0e7f') {
+        return Some(true_type_tag('T', 'h', 'a', 'i'));
+    }
+    if in_range(c, '\u0e80', '\u0eff') { return Some(true_type_tag('L', 'a', 'o', 'o')); }
+    if in_range(c, '\u0f00', '\u0fff') { return Some(true_type_tag('T', 'i', 'b', 't')); }
+    if in_range(c, '\u10a0', '\u10ff') { return Some(true_type_tag('G', 'e', 'o', 'r')); }
+    if in_range(c, '\u1100', '\u11ff') || in_range(c, '\u3131', '\u318e')
+        || in_range(c, '\uac00', '\ud7af') {
+        return Some(true_type_tag('H', 'a', 'n', 'g'));
+    }
+    if in_range(c, '\u1200', '\u137f') { return Some(true_type_tag('E', 't', 'h', 'i')); }
+    if in_range(c, '\u3041', '\u309f') { return Some(true_type_tag('H', 'i', 'r', 'a')); }
+    if in_range(c, '\u30a1', '\u30fa') || in_range(c, '\u30fd', '\u30ff') {
+        return Some(true_type_tag('K', 'a', 'n', 'a'));
+    }
+    if in_range(c, '\u2e80', '\u2fdf') || in_range(c, '\u3400', '\u4dbf')
+        || in_range(c, '\u4e00', '\u9fff') || in_range(c, '\uf900', '\ufaff') {
+        return Some(true_type_tag('H', 'a', 'n', 'i'));
+    }
+    None
+}
+
+#[doc = "
+The script of each character of some text. Characters used with every
+script take that of the text before them, or failing that of the text after
+them; text with no script of its own at all has None.
+"]
+fn resolve_scripts(text: &str) -> ~[Option<u32>] {
+    let mut scripts = ~[];
+    let mut last = None;
+    for str::each_char(text) |c| {
+        let script = match script_tag(c) {
+            Some(tag) => Some(tag),
+            None => last
+        };
+        vec::push(scripts, script);
+        last = script;
+    }
+
+    // The characters at the start take the first script that comes after them
+    let mut first = None;
+    for scripts.each |script| {
+        if script.is_some() {
+            first = *script;
+            break;
+        }
+    }
+    for uint::range(0, scripts.len()) |i| {
+        if scripts[i].is_some() { break; }
+        scripts[i] = first;
+    }
+    return scripts;
+}
+
+#[cfg(test)]
+fn levels_of(text: &str, para_level: uint) -> ~[uint] {
+    let classes = str::chars(text).map(|c| bidi_class(*c));
+    resolve_levels(classes, para_level)
+}
+
+#[test]
+fn should_classify_characters() {
+    assert bidi_class('a') == BidiL;
+    assert bidi_class('\u05d0') == BidiR;
+    assert bidi_class('\u0627') == BidiAL;
+    assert bidi_class('\u0663') == BidiAN;
+    assert bidi_class('7') == BidiEN;
+    assert bidi_class(' ') == BidiWS;
+    assert bidi_class('!') == BidiON;
+    assert bidi_class('\u202e') == BidiRLO;
+}
+
+#[test]
+fn should_resolve_levels_of_mixed_text() {
+    // Hebrew in English text, with a space between them
+    assert levels_of("ab \u05d0\u05d1", 0) == ~[0u, 0u, 0u, 1u, 1u];
+    // The space between Hebrew words stays right-to-left
+    assert levels_of("\u05d0 \u05d1", 0) == ~[1u, 1u, 1u];
+    // Numbers in right-to-left text are written from left to right
+    assert levels_of("\u05d0 12", 1) == ~[1u, 1u, 2u, 2u];
+    // Trailing whitespace takes the paragraph level
+    assert levels_of("\u05d0\u05d1 ", 0) == ~[1u, 1u, 0u];
+}
+
+#[test]
+fn should_resolve_explicit_overrides() {
+    // RLO ... PDF writes English from right to left
+    assert levels_of("\u202eab\u202c c", 0) == ~[0u, 1u, 1u, 1u, 0u, 0u];
+}
+
+#[test]
+fn should_reorder_levels() {
+    assert reorder(~[0u, 0u, 1u, 1u, 1u]) == ~[0u, 1u, 4u, 3u, 2u];
+    assert reorder(~[1u, 1u, 2u, 2u]) == ~[2u, 3u, 1u, 0u];
+    assert reorder(~[0u, 1u, 2u, 1u, 0u]) == ~[0u, 3u, 2u, 1u, 4u];
+}
+
+#[test]
+fn should_resolve_scripts() {
+    let latn = Some(true_type_tag('L', 'a', 't', 'n'));
+    let arab = Some(true_type_tag('A', 'r', 'a', 'b'));
+    assert resolve_scripts("1 ab \u0627\u0644") == ~[latn, latn, latn, latn, latn, arab, arab];
+    assert resolve_scripts("12") == ~[None, None];
+}
@@ -1,22 +1,41 @@
 pub use font_cache::FontCache;
-export Font, FontMetrics, test_font_bin, create_test_font;
+use font_cache::NativeLibrary;
+export Font, FontMetrics, FontDescriptor, test_font_bin, test_font_descriptor, create_test_font;
+export TEST_FONT_WEIGHT;
 
+use au = gfx::geometry;
+use gfx::geometry::au;
 use glyph::GlyphIndex;
 use vec_to_ptr = vec::raw::to_ptr;
 use libc::{ c_int, c_double, c_ulong };
 use ptr::{ null, addr_of };
 use native_font::NativeFont;
+use glyph::Glyph;
+use shaper::Shaper;
+use std::map::HashMap;
 
 #[doc = "
 A font handle. Layout can use this to calculate glyph metrics
 and the renderer can use it to render text.
 "]
 struct Font {
-    // A back reference to keep the library alive
-    lib: @FontCache,
-    fontbuf: @~[u8],
+    // Made when text is first shaped in this font. It reads the font data, and is
+    // destroyed before it
+    mut shaper: Option<@Shaper>,
+    // The glyphs of the texts shaped in this font so far
+    shape_cache: HashMap<~str, @~[Glyph]>,
     native_font: NativeFont,
-    metrics: FontMetrics
+    fontbuf: @~[u8],
+    metrics: FontMetrics,
+    // The font that was asked for, with the family of this one, which stands in for
+    // it at its size. The built-in font has no family.
+    descriptor: FontDescriptor,
+    // Whether the renderer has to make the glyphs bolder or slanted, because no font
+    // of the weight or style asked for was found
+    synthetic_bold: bool,
+    synthetic_italic: bool,
+    // A reference to keep the library alive until the native font is gone
+    lib: @NativeLibrary
 }
 
 impl Font {
@@ -24,27 +43,97 @@ impl Font {
         self.fontbuf
     }
 
+    fn get_shaper() -> @Shaper {
+        match self.shaper {
+          Some(shaper) => shaper,
+          None => {
+            let shaper = @Shaper(&self);
+            self.shaper = Some(shaper);
+            shaper
+          }
+        }
+    }
+
+    /// A length in ems of this font
+    fn em_length(ems: float) -> au {
+        au::from_frac_px(ems * self.descriptor.size)
+    }
+
+    /// How far the glyphs reach above the baseline
+    fn ascent() -> au {
+        self.em_length(self.metrics.em_ascent)
+    }
+
+    /// How far the glyphs reach below the baseline
+    fn descent() -> au {
+        self.em_length(self.metrics.em_descent)
+    }
+
     fn glyph_index(codepoint: char) -> Option<GlyphIndex> {
         self.native_font.glyph_index(codepoint)
     }
 
+    /// Glyphs that cannot be loaded are as wide as the glyph the font draws for
+    /// missing characters, or take up no space if it has none
     fn glyph_h_advance(glyph: GlyphIndex) -> int {
         match self.native_font.glyph_h_advance(glyph) {
           Some(adv) => adv,
-          None => /* FIXME: Need fallback strategy */ 10
+          None => self.native_font.glyph_h_advance(0).get_default(0)
         }
     }
 }
 
-fn Font(lib: @FontCache, fontbuf: @~[u8], +native_font: NativeFont, +metrics: FontMetrics) -> Font {
+fn Font(lib: @NativeLibrary, fontbuf: @~[u8], +native_font: NativeFont,
+        +metrics: FontMetrics, +descriptor: FontDescriptor, synthetic_bold: bool,
+        synthetic_italic: bool) -> Font {
     Font {
-        lib: lib,
-        fontbuf : fontbuf,
+        shaper: None,
+        shape_cache: HashMap::<~str, @~[Glyph]>(),
         native_font : move native_font,
-        metrics: move metrics
+        fontbuf : fontbuf,
+        metrics: move metrics,
+        descriptor: move descriptor,
+        synthetic_bold: synthetic_bold,
+        synthetic_italic: synthetic_italic,
+        lib: lib
     }
 }
 
+#[doc = "
+The font that text asks for: the families named by its style, most
+preferred first, its weight from 100 to 900, whether it is italic, and its
+size in px. Generic families go by their CSS names, such as 'serif', and
+no families at all stand for the built-in font.
+Descriptors are sendable, so that the renderer can find the fonts of the
+glyphs it draws.
+"]
+struct FontDescriptor {
+    families: ~[~str],
+    weight: uint,
+    italic: bool,
+    size: float
+}
+
+fn FontDescriptor(+families: ~[~str], weight: uint, italic: bool, size: float) -> FontDescriptor {
+    FontDescriptor {
+        families: move families,
+        weight: weight,
+        italic: italic,
+        size: size
+    }
+}
+
+impl FontDescriptor : cmp::Eq {
+    pure fn eq(other: &FontDescriptor) -> bool {
+        self.families == other.families && self.weight == other.weight
+            && self.italic == other.italic && self.size == other.size
+    }
+    pure fn ne(other: &FontDescriptor) -> bool {
+        !self.eq(other)
+    }
+}
+
+/// Lengths are in ems. Offsets are above the baseline, and negative below it.
 struct FontMetrics {
     underline_size:   float,
     underline_offset: float,
@@ -63,6 +152,14 @@ fn test_font_bin() -> ~[u8] {
     return vec::from_fn(33004, |i| TEST_FONT[i]);
 }
 
+/// The weight of the built-in font, which is used when no other font is found
+const TEST_FONT_WEIGHT: uint = 600;
+
+/// The built-in font at the size the tests measure it at
+fn test_font_descriptor() -> FontDescriptor {
+    FontDescriptor(~[], TEST_FONT_WEIGHT, false, 20.0)
+}
+
 fn should_destruct_on_fail_without_leaking() {
     #[test];
     #[should_fail];
@@ -1,11 +1,11 @@
-export FontCache, native;
-use font::{Font, test_font_bin};
+export FontCache, NativeLibrary, native;
+use font::{Font, FontDescriptor, TEST_FONT_WEIGHT, test_font_bin, test_font_descriptor};
+use font_list::{FontList, FontEntry};
+use dvec::DVec;
 
-struct FontCache {
-    // FIXME: This is a hack to hold onto a boxed reference to
-    // the self pointer until explicit self types work on methods.
-    // This is a huge space leak.
-    mut at_self: Option<@FontCache>,
+#[doc="The platform's font library. The fonts made with it keep it alive,
+       since their native fonts have to be destroyed before it is."]
+struct NativeLibrary {
     native_lib: native::NativeFontCache,
 
     drop {
@@ -13,40 +13,285 @@ struct FontCache {
     }
 }
 
+struct FontCache {
+    native_lib: @NativeLibrary,
+    // The fonts installed on the system
+    font_list: FontList,
+    // Downloaded fonts registered by @font-face rules: their family names, weights,
+    // whether they are italic, and their data
+    font_faces: DVec<(~str, uint, bool, @~[u8])>,
+    // The data of the installed fonts loaded so far, by their files
+    font_files: DVec<(~str, @~[u8])>,
+    // The fonts made so far, by the descriptors they were asked for with, so that
+    // text asking for the same font shares it
+    instances: DVec<(FontDescriptor, @Font)>
+}
+
 impl FontCache {
-    fn get_font() -> @Font {
-        assert self.at_self.is_some();
-        match create_font(self.at_self.get(), &self.native_lib) {
+    #[doc="Returns a font for text with the given descriptor: that of the first
+           of its families that has one, closest to it in style and weight, or
+           else the default serif font of the system, or the built-in font.
+           Downloaded fonts come before installed ones of the same family.
+           Weights and styles that the font lacks are synthesized by the
+           renderer."]
+    fn get_font(desc: &FontDescriptor) -> @Font {
+        for self.instances.each |instance| {
+            let (instance_desc, font) = *instance;
+            if instance_desc == *desc {
+                return font;
+            }
+        }
+
+        let font = self.create_font_for(desc);
+        self.instances.push((copy *desc, font));
+        return font;
+    }
+
+    #[doc="Returns the font to draw a character with that the font for `desc`
+           has no glyph for: the font of the first of its families that has
+           one, or else the installed font closest to it in style and weight
+           that has one. Failing that, it is the font for `desc`, which draws
+           its glyph for missing characters."]
+    fn get_fallback_font(desc: &FontDescriptor, codepoint: char) -> @Font {
+        for desc.families.each |family| {
+            let font = self.get_font(&family_descriptor(*family, desc));
+            if font.glyph_index(codepoint).is_some() {
+                return font;
+            }
+        }
+
+        let mut best = None;
+        let mut best_rank = 0u;
+        for self.font_list.entries.each |entry| {
+            let rank = match_rank(entry.weight, entry.italic, desc);
+            if (best.is_none() || rank < best_rank) && self.font_list.has_char(entry, codepoint) {
+                best = Some(copy entry.family);
+                best_rank = rank;
+            }
+        }
+        match best {
+          Some(family) => self.get_font(&family_descriptor(family, desc)),
+          None => self.get_font(desc)
+        }
+    }
+
+    #[doc="Splits text into runs that are each drawn with one font: the font
+           for `desc`, or the fallback fonts of the characters it has no
+           glyphs for. Whitespace stays in the run it is in."]
+    fn get_font_runs(desc: &FontDescriptor, text: &str) -> ~[(@Font, ~str)] {
+        let primary = self.get_font(desc);
+        let mut runs = ~[];
+        let mut run_font = primary;
+        let mut run = ~"";
+        for str::each_char(text) |c| {
+            let font = if char::is_whitespace(c) || run_font.glyph_index(c).is_some() {
+                run_font
+            } else if primary.glyph_index(c).is_some() {
+                primary
+            } else {
+                self.get_fallback_font(desc, c)
+            };
+            if !box::ptr_eq(font, run_font) && run.is_not_empty() {
+                vec::push(runs, (run_font, move run));
+                run = ~"";
+            }
+            run_font = font;
+            str::push_char(run, c);
+        }
+        vec::push(runs, (run_font, move run));
+        return runs;
+    }
+
+    #[doc="Makes the font data downloaded for an @font-face rule available
+           under `family`, replacing any font previously registered for it
+           with the same weight and style. Returns false if that font had
+           the same data already, and nothing changed."]
+    fn register_font_face(+family: ~str, weight: uint, italic: bool, +data: ~[u8]) -> bool {
+        let key = str::to_lower(family);
+        for uint::range(0u, self.font_faces.len()) |i| {
+            let (face_family, face_weight, face_italic, face_data) = self.font_faces[i];
+            if str::to_lower(face_family) == key && face_weight == weight && face_italic == italic {
+                if *face_data == data {
+                    return false;
+                }
+                // Fonts made before may have to give way to the new one
+                self.instances.set(~[]);
+                self.font_faces.set_elt(i, (family, weight, italic, @data));
+                return true;
+            }
+        }
+        self.instances.set(~[]);
+        self.font_faces.push((family, weight, italic, @data));
+        return true;
+    }
+
+    /* Descriptors without families ask for the built-in font */
+    priv fn create_font_for(desc: &FontDescriptor) -> @Font {
+        if desc.families.is_not_empty() {
+            for desc.families.each |family| {
+                match self.create_family_font(*family, desc) {
+                  Some(font) => return font,
+                  None => {}
+                }
+            }
+
+            match self.create_family_font("serif", desc) {
+              Some(font) => return font,
+              None => {}
+            }
+        }
+
+        let builtin_desc = FontDescriptor(~[], desc.weight, desc.italic, desc.size);
+        match create_font(self.native_lib, @test_font_bin(), &builtin_desc, TEST_FONT_WEIGHT,
+                          false) {
           Ok(font) => font,
           Err(*) => /* FIXME */ fail
         }
     }
 
+    /* Makes the font of a family that matches a descriptor best, if the
+       family has any: a downloaded font, or else an installed one. Generic
+       families stand for the installed families the system picks for them. */
+    priv fn create_family_font(family: &str, desc: &FontDescriptor) -> Option<@Font> {
+        match self.find_font_face(family, desc) {
+          Some((weight, italic, font_bin)) => {
+            let font_desc = family_descriptor(family, desc);
+            match create_font(self.native_lib, font_bin, &font_desc, weight, italic) {
+              Ok(font) => return Some(font),
+              Err(*) => #debug("font cache: failed to load font for %s", family)
+            }
+          }
+          None => {}
+        }
+
+        let family = match self.font_list.get_generic_family(family) {
+          Some(generic) => generic,
+          None => str::from_slice(family)
+        };
+        let entry = match self.find_installed_font(family, desc) {
+          Some(entry) => entry,
+          None => return None
+        };
+        let font_bin = match self.load_font_file(entry.path) {
+          Some(font_bin) => font_bin,
+          None => return None
+        };
+        let font_desc = family_descriptor(entry.family, desc);
+        match create_font(self.native_lib, font_bin, &font_desc, entry.weight, entry.italic) {
+          Ok(font) => Some(font),
+          Err(*) => {
+            #debug("font cache: failed to load font %s", entry.path);
+            None
+          }
+        }
+    }
+
+    /* The weight, style and data of the registered font of a family that
+       matches a descriptor best */
+    priv fn find_font_face(family: &str, desc: &FontDescriptor) -> Option<(uint, bool, @~[u8])> {
+        let family = str::to_lower(family);
+        let mut best = None;
+        let mut best_rank = 0u;
+        for uint::range(0u, self.font_faces.len()) |i| {
+            let (face_family, weight, italic, font_bin) = self.font_faces[i];
+            if str::to_lower(face_family) != family { loop; }
+
+            let rank = match_rank(weight, italic, desc);
+            if best.is_none() || rank < best_rank {
+                best = Some((weight, italic, font_bin));
+                best_rank = rank;
+            }
+        }
+        return best;
+    }
+
+    /* The installed font of a family that matches a descriptor best */
+    priv fn find_installed_font(family: &str, desc: &FontDescriptor) -> Option<FontEntry> {
+        let family = str::to_lower(family);
+        let mut best = None;
+        let mut best_rank = 0u;
+        for self.font_list.entries.each |entry| {
+            if str::to_lower(entry.family) != family { loop; }
+
+            let rank = match_rank(entry.weight, entry.italic, desc);
+            if best.is_none() || rank < best_rank {
+                best = Some(copy *entry);
+                best_rank = rank;
+            }
+        }
+        return best;
+    }
+
+    priv fn load_font_file(path: &str) -> Option<@~[u8]> {
+        for self.font_files.each |file| {
+            let (file_path, font_bin) = *file;
+            if file_path == str::from_slice(path) {
+                return Some(font_bin);
+            }
+        }
+
+        match io::read_whole_file(&Path(path)) {
+          Ok(data) => {
+            let font_bin = @data;
+            self.font_files.push((str::from_slice(path), font_bin));
+            Some(font_bin)
+          }
+          Err(e) => {
+            #debug("font cache: failed to read %s: %s", path, e);
+            None
+          }
+        }
+    }
+
     fn get_test_font() -> @Font {
-        self.get_font()
+        self.get_font(&test_font_descriptor())
     }
 }
 
 fn FontCache() -> @FontCache {
-    let lib = @FontCache {
-        mut at_self: None,
-        native_lib: native::create_native_lib()
-    };
+    @FontCache {
+        native_lib: @NativeLibrary { native_lib: native::create_native_lib() },
+        font_list: FontList(),
+        font_faces: DVec(),
+        font_files: DVec(),
+        instances: DVec()
+    }
+}
+
+/* The descriptor of a font of the given family that stands in for the
+   font asked for by `desc` */
+fn family_descriptor(family: &str, desc: &FontDescriptor) -> FontDescriptor {
+    FontDescriptor(~[str::from_slice(family)], desc.weight, desc.italic, desc.size)
+}
 
-    lib.at_self = Some(lib);
-    return lib;
+/* How well a font of the given weight and style matches a descriptor,
+   lower being better (CSS Fonts Level 3, Section 5.2): one of the same style
+   comes first, and then the nearest in weight, heavier ones first for bold
+   text and lighter ones first otherwise. */
+pure fn match_rank(weight: uint, italic: bool, desc: &FontDescriptor) -> uint {
+    // Weights differ by at most 800, so the style decides first
+    let style_rank = if italic == desc.italic { 0u } else { 2000u };
+    let heavier = weight >= desc.weight;
+    let distance = if heavier { weight - desc.weight } else { desc.weight - weight };
+    let direction_rank = if heavier == (desc.weight > 500) { 0u } else { 1u };
+    style_rank + distance * 2 + direction_rank
 }
 
-fn create_font(lib: @FontCache, native_lib: &native::NativeFontCache) -> Result<@Font, ()> {
-    let font_bin = @test_font_bin();
-    let native_font = native_font::create(native_lib, font_bin);
+/* Makes a font from font data of the given weight and style, at the size
+   of the descriptor it stands in for. */
+fn create_font(lib: @NativeLibrary, font_bin: @~[u8], desc: &FontDescriptor, weight: uint,
+               italic: bool) -> Result<@Font, ()> {
+    let native_font = native_font::create(&lib.native_lib, font_bin, desc.size);
     let native_font = if native_font.is_ok() {
         result::unwrap(native_font)
     } else {
         return Err(native_font.get_err());
     };
     let metrics = native_font.get_metrics();
-    return Ok(@Font(lib, font_bin, native_font, metrics));
+    let synthetic_bold = desc.weight >= 600 && weight < 600;
+    let synthetic_italic = desc.italic && !italic;
+    return Ok(@Font(lib, font_bin, native_font, metrics, copy *desc, synthetic_bold,
+                    synthetic_italic));
 }
 
 #[cfg(target_os = "linux")]
@@ -83,5 +328,62 @@ mod native {
 #[test]
 fn should_get_fonts() {
     let lib = FontCache();
-    lib.get_font();
+    lib.get_font(&test_font_descriptor());
+}
+
+#[test]
+fn should_get_registered_font_faces() {
+    use font::FontDescriptor;
+
+    let lib = FontCache();
+    assert lib.register_font_face(~"Test Face", 400, false, test_font_bin());
+    // Registering the same font again changes nothing
+    assert !lib.register_font_face(~"test face", 400, false, test_font_bin());
+    let font = lib.get_font(&FontDescriptor(~[~"missing", ~"test face"], 400, false, 16.0));
+    assert *font.buf() == test_font_bin();
+    assert !font.synthetic_bold && !font.synthetic_italic;
+    lib.get_font(&FontDescriptor(~[~"missing"], 400, false, 16.0));
+}
+
+#[test]
+fn should_match_font_faces_by_style_and_weight() {
+    use font::FontDescriptor;
+
+    let lib = FontCache();
+    lib.register_font_face(~"Face", 300, false, ~[1u8]);
+    lib.register_font_face(~"Face", 700, false, ~[2u8]);
+    lib.register_font_face(~"Face", 400, true, ~[3u8]);
+
+    let find = |weight, italic| {
+        match lib.find_font_face("face", &FontDescriptor(~[], weight, italic, 16.0)) {
+          Some((_, _, font_bin)) => font_bin[0],
+          None => 0u8
+        }
+    };
+    assert find(700, false) == 2u8;
+    assert find(600, false) == 2u8;
+    assert find(400, false) == 1u8;
+    assert find(500, false) == 1u8;
+    assert find(800, true) == 3u8;
+    assert lib.find_font_face("other", &test_font_descriptor()).is_none();
+}
+
+#[test]
+fn should_share_fonts_by_descriptor() {
+    use font::FontDescriptor;
+
+    let lib = FontCache();
+    let font = lib.get_font(&test_font_descriptor());
+    assert box::ptr_eq(font, lib.get_font(&test_font_descriptor()));
+    assert !box::ptr_eq(font, lib.get_font(&FontDescriptor(~[], 400, false, 10.0)));
+}
+
+#[test]
+fn should_keep_text_the_font_has_glyphs_for_in_one_run() {
+    let lib = FontCache();
+    let runs = lib.get_font_runs(&test_font_descriptor(), "fire cracker");
+    assert runs.len() == 1;
+    let (font, text) = copy runs[0];
+    assert box::ptr_eq(font, lib.get_test_font());
+    assert text == ~"fire cracker";
 }
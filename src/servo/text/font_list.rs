@@ -0,0 +1,249 @@
+export FontList, FontEntry;
+
+#[doc = "
+A font installed on the system: its family, its weight from 100 to 900,
+whether it is italic, and the file it is in.
+"]
+struct FontEntry {
+    family: ~str,
+    weight: uint,
+    italic: bool,
+    path: ~str,
+    // Where the platform keeps the font in its list
+    priv native_index: uint
+}
+
+#[doc = "
+The fonts installed on the system, as the platform lists them. Only the
+first font of each file is listed.
+"]
+struct FontList {
+    entries: ~[FontEntry],
+    priv native_list: native::NativeFontList
+}
+
+fn FontList() -> FontList {
+    let native_list = native::create_native_list();
+    let entries = native::get_entries(&native_list);
+    FontList {
+        entries: move entries,
+        native_list: move native_list
+    }
+}
+
+impl FontList {
+    #[doc = "The installed family that a generic family, such as 'serif',
+             stands for, if `family` is one and the system has a font for it."]
+    fn get_generic_family(family: &str) -> Option<~str> {
+        match str::to_lower(family) {
+            ~"serif" | ~"sans-serif" | ~"monospace" | ~"cursive" | ~"fantasy" => {
+                native::get_generic_family(&self.native_list, family)
+            }
+            _ => None
+        }
+    }
+
+    #[doc = "Whether the font of an entry has a glyph for a character"]
+    fn has_char(entry: &FontEntry, codepoint: char) -> bool {
+        native::has_char(&self.native_list, entry.native_index, codepoint)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod native {
+    use libc::{c_char, c_int, c_uint};
+    use ptr::{null, addr_of, offset};
+    use str::raw::from_c_str;
+
+    type FcBool = c_int;
+    type FcResult = c_int;
+    type FcChar32 = c_uint;
+    type FcPattern = ();
+    type FcObjectSet = ();
+    type FcCharSet = ();
+    type FcConfig = ();
+
+    struct FcFontSet {
+        nfont: c_int,
+        sfont: c_int,
+        fonts: **FcPattern
+    }
+
+    const FcResultMatch: FcResult = 0;
+    const FcMatchPattern: c_int = 0;
+
+    const FC_SLANT_ROMAN: c_int = 0;
+
+    extern mod fontconfig {
+        fn FcInit() -> FcBool;
+        fn FcPatternCreate() -> *FcPattern;
+        fn FcPatternDestroy(p: *FcPattern);
+        fn FcObjectSetCreate() -> *FcObjectSet;
+        fn FcObjectSetAdd(os: *FcObjectSet, object: *c_char) -> FcBool;
+        fn FcObjectSetDestroy(os: *FcObjectSet);
+        fn FcFontList(config: *FcConfig, p: *FcPattern, os: *FcObjectSet) -> *FcFontSet;
+        fn FcFontSetDestroy(s: *FcFontSet);
+        fn FcPatternGetString(p: *FcPattern, object: *c_char, n: c_int, s: **c_char) -> FcResult;
+        fn FcPatternGetInteger(p: *FcPattern, object: *c_char, n: c_int, i: *c_int) -> FcResult;
+        fn FcPatternGetCharSet(p: *FcPattern, object: *c_char, n: c_int,
+                               c: **FcCharSet) -> FcResult;
+        fn FcCharSetHasChar(fcs: *FcCharSet, ucs4: FcChar32) -> FcBool;
+        fn FcNameParse(name: *c_char) -> *FcPattern;
+        fn FcConfigSubstitute(config: *FcConfig, p: *FcPattern, kind: c_int) -> FcBool;
+        fn FcDefaultSubstitute(p: *FcPattern);
+        fn FcFontMatch(config: *FcConfig, p: *FcPattern, result: *FcResult) -> *FcPattern;
+    }
+
+    use fontconfig::*;
+
+    struct NativeFontList {
+        font_set: *FcFontSet,
+
+        drop {
+            if self.font_set.is_not_null() {
+                FcFontSetDestroy(self.font_set);
+            }
+        }
+    }
+
+    fn create_native_list() -> NativeFontList {
+        if FcInit() == 0 {
+            #warn("font list: unable to initialize fontconfig");
+            return NativeFontList { font_set: null() };
+        }
+
+        let pattern = FcPatternCreate();
+        let object_set = FcObjectSetCreate();
+        for [~"family", ~"file", ~"index", ~"weight", ~"slant", ~"charset"].each |object| {
+            str::as_c_str(*object, |object| FcObjectSetAdd(object_set, object));
+        }
+        let font_set = FcFontList(null(), pattern, object_set);
+        FcObjectSetDestroy(object_set);
+        FcPatternDestroy(pattern);
+
+        NativeFontList { font_set: font_set }
+    }
+
+    fn get_entries(list: &NativeFontList) -> ~[FontEntry] unsafe {
+        let mut entries = ~[];
+        if list.font_set.is_null() {
+            return entries;
+        }
+
+        for uint::range(0, (*list.font_set).nfont as uint) |i| {
+            let pattern = *offset((*list.font_set).fonts, i);
+            let family = get_string(pattern, "family");
+            let path = get_string(pattern, "file");
+            if family.is_none() || path.is_none() {
+                loop;
+            }
+            // TODO: the other fonts of font collections
+            if get_integer(pattern, "index").get_default(0) != 0 {
+                loop;
+            }
+
+            let weight = get_integer(pattern, "weight").get_default(80);
+            let slant = get_integer(pattern, "slant").get_default(FC_SLANT_ROMAN);
+            vec::push(entries, FontEntry {
+                family: family.get(),
+                weight: css_weight(weight),
+                italic: slant != FC_SLANT_ROMAN,
+                path: path.get(),
+                native_index: i
+            });
+        }
+        return entries;
+    }
+
+    fn get_generic_family(_list: &NativeFontList, family: &str) -> Option<~str> unsafe {
+        let pattern = str::as_c_str(family, |family| FcNameParse(family));
+        if pattern.is_null() {
+            return None;
+        }
+        FcConfigSubstitute(null(), pattern, FcMatchPattern);
+        FcDefaultSubstitute(pattern);
+
+        let result = FcResultMatch;
+        let font = FcFontMatch(null(), pattern, addr_of(result));
+        FcPatternDestroy(pattern);
+        if font.is_null() {
+            return None;
+        }
+        let family = get_string(font, "family");
+        FcPatternDestroy(font);
+        return family;
+    }
+
+    fn has_char(list: &NativeFontList, index: uint, codepoint: char) -> bool unsafe {
+        let pattern = *offset((*list.font_set).fonts, index);
+        let charset: *FcCharSet = null();
+        let res = str::as_c_str("charset", |object| {
+            FcPatternGetCharSet(pattern, object, 0, addr_of(charset))
+        });
+        res == FcResultMatch && FcCharSetHasChar(charset, codepoint as FcChar32) != 0
+    }
+
+    fn get_string(pattern: *FcPattern, object: &str) -> Option<~str> unsafe {
+        let value: *c_char = null();
+        let res = str::as_c_str(object, |object| {
+            FcPatternGetString(pattern, object, 0, addr_of(value))
+        });
+        if res == FcResultMatch { Some(from_c_str(value)) } else { None }
+    }
+
+    fn get_integer(pattern: *FcPattern, object: &str) -> Option<c_int> {
+        let value: c_int = 0;
+        let res = str::as_c_str(object, |object| {
+            FcPatternGetInteger(pattern, object, 0, addr_of(value))
+        });
+        if res == FcResultMatch { Some(value) } else { None }
+    }
+
+    /* Fontconfig weights go from 0, thin, through 80, regular, and 200,
+       bold, to 210, black */
+    pure fn css_weight(weight: c_int) -> uint {
+        if weight <= 0 { 100 }
+        else if weight <= 40 { 200 }
+        else if weight <= 50 { 300 }
+        else if weight <= 80 { 400 }
+        else if weight <= 100 { 500 }
+        else if weight <= 180 { 600 }
+        else if weight <= 200 { 700 }
+        else if weight <= 205 { 800 }
+        else { 900 }
+    }
+
+    #[test]
+    fn should_convert_fontconfig_weights() {
+        assert css_weight(0) == 100;
+        assert css_weight(75) == 400;
+        assert css_weight(80) == 400;
+        assert css_weight(180) == 600;
+        assert css_weight(200) == 700;
+        assert css_weight(210) == 900;
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod native {
+    type NativeFontList = ();
+
+    fn create_native_list() -> NativeFontList { () }
+
+    // TODO: list the installed fonts with CoreText
+    fn get_entries(_list: &NativeFontList) -> ~[FontEntry] { ~[] }
+
+    fn get_generic_family(_list: &NativeFontList, _family: &str) -> Option<~str> { None }
+
+    fn has_char(_list: &NativeFontList, _index: uint, _codepoint: char) -> bool { false }
+}
+
+#[test]
+fn should_list_fonts_with_their_files() {
+    let list = FontList();
+    for list.entries.each |entry| {
+        assert entry.family.is_not_empty();
+        assert entry.path.is_not_empty();
+        assert entry.weight >= 100 && entry.weight <= 900;
+    }
+}
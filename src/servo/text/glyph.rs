@@ -19,15 +19,36 @@ fn GlyphPos(advance: Point2D<au>, offset: Point2D<au>) -> GlyphPos {
     }
 }
 
+impl GlyphPos : cmp::Eq {
+    pure fn eq(other: &GlyphPos) -> bool {
+        self.advance == other.advance && self.offset == other.offset
+    }
+    pure fn ne(other: &GlyphPos) -> bool {
+        !self.eq(other)
+    }
+}
+
 #[doc="A single glyph."]
 struct Glyph {
     index: GlyphIndex,
     pos: GlyphPos,
+    // The byte offset in its text of the first character the glyph is drawn for
+    cluster: uint,
 }
 
-fn Glyph(index: GlyphIndex, pos: GlyphPos) -> Glyph {
+fn Glyph(index: GlyphIndex, pos: GlyphPos, cluster: uint) -> Glyph {
     Glyph {
         index : index,
         pos : copy pos,
+        cluster : cluster,
+    }
+}
+
+impl Glyph : cmp::Eq {
+    pure fn eq(other: &Glyph) -> bool {
+        self.index == other.index && self.pos == other.pos && self.cluster == other.cluster
+    }
+    pure fn ne(other: &Glyph) -> bool {
+        !self.eq(other)
     }
 }
@@ -17,14 +17,15 @@ type NativeFont/& = quartz_native_font::QuartzNativeFont;
 #[cfg(target_os = "linux")]
 type NativeFont/& = ft_native_font::FreeTypeNativeFont;
 
+/// Loads font data for drawing text `size` px high
 #[cfg(target_os = "macos")]
-fn create(_native_lib: &NativeFontCache, buf: @~[u8]) -> Result<NativeFont, ()> {
-    quartz_native_font::create(buf)
+fn create(_native_lib: &NativeFontCache, buf: @~[u8], size: float) -> Result<NativeFont, ()> {
+    quartz_native_font::create(buf, size)
 }
 
 #[cfg(target_os = "linux")]
-fn create(native_lib: &NativeFontCache, buf: @~[u8]) -> Result<NativeFont, ()> {
-    ft_native_font::create(native_lib, buf)
+fn create(native_lib: &NativeFontCache, buf: @~[u8], size: float) -> Result<NativeFont, ()> {
+    ft_native_font::create(native_lib, buf, size)
 }
 
 #[cfg(target_os = "macos")]
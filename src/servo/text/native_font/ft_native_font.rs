@@ -43,7 +43,7 @@ impl FreeTypeNativeFont {
         return if idx != 0 as FT_UInt {
             Some(idx as GlyphIndex)
         } else {
-            #warn("Invalid codepoint: %?", codepoint);
+            #debug("Invalid codepoint: %?", codepoint);
             None
         };
     }
@@ -69,29 +69,52 @@ impl FreeTypeNativeFont {
         }
     }
 
-    fn get_metrics() -> FontMetrics {
-        /* TODO: complete me (Issue #76) */
+    fn get_metrics() -> FontMetrics unsafe {
+        assert self.face.is_not_null();
+        let face = &*self.face;
+        let em = face.units_per_EM as float;
+        let ascent = face.ascender as float / em;
+        let descent = -(face.descender as float) / em;
+
         return FontMetrics {
-            underline_size:   0.0,
-            underline_offset: 0.0,
-            leading:          0.0,
-            x_height:         0.0,
-            em_ascent:        0.0,
-            em_descent:       0.0,
-            em_height:        0.0,
-            max_advance:      0.0
+            underline_size:   face.underline_thickness as float / em,
+            underline_offset: face.underline_position as float / em,
+            leading:          face.height as float / em - ascent - descent,
+            x_height:         self.glyph_height('x').get_default(0) as float / em,
+            em_ascent:        ascent,
+            em_descent:       descent,
+            em_height:        ascent + descent,
+            max_advance:      face.max_advance_width as float / em
+        }
+    }
+
+    // The height of the glyph of a character, in font units
+    priv fn glyph_height(codepoint: char) -> Option<int> {
+        const FT_LOAD_NO_SCALE: i32 = 1;
+
+        let glyph = match self.glyph_index(codepoint) {
+            Some(glyph) => glyph,
+            None => return None
+        };
+        if !FT_Load_Glyph(self.face, glyph as FT_UInt, FT_LOAD_NO_SCALE).succeeded() {
+            return None;
+        }
+        unsafe {
+            let void_glyph = (*self.face).glyph;
+            let slot: FT_GlyphSlot = reinterpret_cast(&void_glyph);
+            Some((*slot).metrics.height as int)
         }
     }
 }
 
-fn create(lib: &FT_Library, buf: @~[u8]) -> Result<FreeTypeNativeFont, ()> {
+fn create(lib: &FT_Library, buf: @~[u8], size: float) -> Result<FreeTypeNativeFont, ()> {
     assert lib.is_not_null();
     let face: FT_Face = null();
     return vec_as_buf(*buf, |cbuf, _len| {
            if FT_New_Memory_Face(*lib, cbuf, (*buf).len() as FT_Long,
                                  0 as FT_Long, addr_of(face)).succeeded() {
-               // FIXME: These values are placeholders
-               let res = FT_Set_Char_Size(face, 0, 20*64, 0, 72);
+               // In 1/64ths of a point, at 72 dpi, so that points are px
+               let res = FT_Set_Char_Size(face, 0, (size * 64.0) as FT_Long, 0, 72);
                if !res.succeeded() { fail ~"unable to set font char size" }
                Ok(FreeTypeNativeFont(face, buf))
            } else {
@@ -114,7 +137,7 @@ fn with_test_native_font(f: fn@(nf: &NativeFont)) {
 
     with_lib(|lib| {
         let buf = @test_font_bin();
-        let font = unwrap_result(create(lib, move buf));
+        let font = unwrap_result(create(lib, move buf, 20.0));
         f(&font);
     })
 }
@@ -130,6 +153,6 @@ fn with_lib(f: fn@((&FT_Library))) {
 fn create_should_return_err_if_buf_is_bogus() {
     with_lib(|lib| {
         let buf = @~[];
-        assert create(lib, buf).is_err();
+        assert create(lib, buf, 20.0).is_err();
     })
 }
@@ -65,6 +65,7 @@ mod coretext {
         fn CTFontGetDescent(font: CTFontRef) -> libc::c_float;
         fn CTFontGetLeading(font: CTFontRef) -> libc::c_float;
         fn CTFontGetUnitsPerEm(font: CTFontRef) -> libc::c_uint;
+        fn CTFontGetSize(font: CTFontRef) -> CGFloat;
         fn CTFontGetUnderlinePosition(font: CTFontRef) -> libc::c_float;
         fn CTFontGetUnderlineThickness(font: CTFontRef) -> libc::c_float;
         fn CTFontGetXHeight(font: CTFontRef) -> libc::c_float;
@@ -90,11 +91,11 @@ struct QuartzNativeFont {
     }
 }
 
-fn QuartzNativeFont(fontprov: CGDataProviderRef, cgfont: CGFontRef) -> QuartzNativeFont {
+fn QuartzNativeFont(fontprov: CGDataProviderRef, cgfont: CGFontRef, size: float) -> QuartzNativeFont {
     assert fontprov.is_not_null();
     assert cgfont.is_not_null();
 
-    let ctfont = ctfont_from_cgfont(cgfont);
+    let ctfont = ctfont_from_cgfont(cgfont, size);
     assert ctfont.is_not_null();
 
     QuartzNativeFont {
@@ -151,7 +152,8 @@ impl QuartzNativeFont {
         let ctfont = self.ctfont;
         assert ctfont.is_not_null();
 
-        let convFactor : float = 1.0 / (CTFontGetUnitsPerEm(ctfont) as float);
+        // Metrics are in points, at the size of the font
+        let convFactor : float = 1.0 / (CTFontGetSize(ctfont) as float);
         let bounding_rect: CGRect = CTFontGetBoundingBox(ctfont);
         let em_ascent = CTFontGetAscent(ctfont) as float * convFactor;
         let em_descent = CTFontGetDescent(ctfont) as float * convFactor;
@@ -169,15 +171,15 @@ impl QuartzNativeFont {
     }
 }
 
-fn ctfont_from_cgfont(cgfont: CGFontRef) -> coretext::CTFontRef {
+fn ctfont_from_cgfont(cgfont: CGFontRef, size: float) -> coretext::CTFontRef {
     use coretext::CGFloat;
     use coretext::coretext::CTFontCreateWithGraphicsFont;
 
     assert cgfont.is_not_null();
-    CTFontCreateWithGraphicsFont(cgfont, 21f as CGFloat, null(), null())
+    CTFontCreateWithGraphicsFont(cgfont, size as CGFloat, null(), null())
 }
 
-fn create(buf: @~[u8]) -> Result<QuartzNativeFont, ()> {
+fn create(buf: @~[u8], size: float) -> Result<QuartzNativeFont, ()> {
     let fontprov = vec::as_imm_buf(*buf, |cbuf, len| {
         CGDataProviderCreateWithData(
             null(),
@@ -190,7 +192,7 @@ fn create(buf: @~[u8]) -> Result<QuartzNativeFont, ()> {
     let cgfont = CGFontCreateWithDataProvider(fontprov);
 
     match cgfont.is_not_null() {
-        true => Ok(QuartzNativeFont(fontprov, cgfont)),
+        true => Ok(QuartzNativeFont(fontprov, cgfont, size)),
         false => Err(())
     }
     
@@ -201,7 +203,7 @@ fn with_test_native_font(f: fn@(nf: &NativeFont)) {
     use unwrap_result = result::unwrap;
 
     let buf = @test_font_bin();
-    let res = create(buf);
+    let res = create(buf, 20.0);
     let font = unwrap_result(res);
     f(&font);
 }
@@ -1,6 +1,6 @@
 extern mod harfbuzz;
 
-export shape_text;
+export Shaper, ShapingParams, default_shaping_params, shape_text, shape_text_with_params;
 
 use au = gfx::geometry;
 use libc::types::common::c99::int32_t;
@@ -14,10 +14,10 @@ use font_cache::FontCache;
 
 use cast::reinterpret_cast;
 use harfbuzz::{HB_MEMORY_MODE_READONLY,
-                  HB_DIRECTION_LTR};
-use harfbuzz::{hb_blob_t, hb_face_t, hb_font_t, hb_buffer_t,
+                  HB_DIRECTION_LTR, HB_DIRECTION_RTL};
+use harfbuzz::{hb_blob_t, hb_face_t, hb_font_t, hb_buffer_t, hb_font_funcs_t,
                   hb_codepoint_t, hb_bool_t, hb_glyph_position_t,
-		  hb_var_int_t, hb_position_t};
+		  hb_var_int_t, hb_position_t, hb_script_t};
 use harfbuzz::bindgen::{hb_blob_create, hb_blob_destroy,
                            hb_face_create, hb_face_destroy,
                            hb_font_create, hb_font_destroy,
@@ -27,6 +27,9 @@ use harfbuzz::bindgen::{hb_blob_create, hb_blob_destroy,
                            hb_buffer_get_glyph_positions,
                            hb_font_set_ppem, hb_font_set_scale,
                            hb_buffer_set_direction,
+                           hb_buffer_set_script, hb_buffer_set_language,
+                           hb_language_from_string,
+                           hb_buffer_guess_segment_properties,
                            hb_font_funcs_create, hb_font_funcs_destroy,
                            hb_font_set_funcs,
                            hb_font_funcs_set_glyph_h_advance_func,
@@ -34,12 +37,26 @@ use harfbuzz::bindgen::{hb_blob_create, hb_blob_destroy,
                            hb_font_funcs_set_glyph_h_kerning_func};
 
 #[doc = "
-Calculate the layout metrics associated with a some given text
-when rendered in a specific font.
+The HarfBuzz objects that shape text in a font. They are made once for each
+font, at its size, and kept as long as it is; glyph positions are in au.
 "]
-fn shape_text(font: &Font, text: &str) -> ~[Glyph] unsafe {
-    #debug("shaping text '%s'", text);
+struct Shaper {
+    priv face_blob: *hb_blob_t,
+    priv hbface: *hb_face_t,
+    priv hbfont: *hb_font_t,
+    priv funcs: *hb_font_funcs_t,
+
+    drop {
+        hb_font_destroy(self.hbfont);
+        hb_font_funcs_destroy(self.funcs);
+        hb_face_destroy(self.hbface);
+        hb_blob_destroy(self.face_blob);
+    }
+}
 
+// The font must stay where it is for as long as the shaper does, since
+// HarfBuzz calls back into it
+fn Shaper(font: &Font) -> Shaper unsafe {
     let face_blob = vec::as_imm_buf(*(*font).buf(), |buf, len| {
         hb_blob_create(reinterpret_cast(&buf),
                        len as c_uint,
@@ -51,59 +68,155 @@ fn shape_text(font: &Font, text: &str) -> ~[Glyph] unsafe {
     let hbface = hb_face_create(face_blob, 0 as c_uint);
     let hbfont = hb_font_create(hbface);
 
-    hb_font_set_ppem(hbfont, 10 as c_uint, 10 as c_uint);
-    hb_font_set_scale(hbfont, 10 as c_int, 10 as c_int);
+    // Positions from the tables of the font are scaled to au at its size
+    let ppem = font.descriptor.size as c_uint;
+    let scale = *font.em_length(1.0) as c_int;
+    hb_font_set_ppem(hbfont, ppem, ppem);
+    hb_font_set_scale(hbfont, scale, scale);
 
     let funcs = hb_font_funcs_create();
     hb_font_funcs_set_glyph_func(funcs, glyph_func, null(), null());
     hb_font_funcs_set_glyph_h_advance_func(funcs, glyph_h_advance_func, null(), null());
     hb_font_set_funcs(hbfont, funcs, reinterpret_cast(&addr_of(*font)), null());
 
-    let buffer = hb_buffer_create();
+    Shaper {
+        face_blob: face_blob,
+        hbface: hbface,
+        hbfont: hbfont,
+        funcs: funcs
+    }
+}
 
-    hb_buffer_set_direction(buffer, HB_DIRECTION_LTR);
+#[doc = "
+How a run of text is shaped: in which direction it is written, and its
+script and language, which choose the forms of its letters. Text written
+from right to left is shaped into glyphs in visual order, from left to right.
+"]
+struct ShapingParams {
+    rtl: bool,
+    // An OpenType script tag, or None to let HarfBuzz guess it from the text
+    script: Option<u32>,
+    // A BCP 47 language tag, such as 'ar' or 'he-IL'
+    language: Option<~str>
+}
 
-    // Using as_buf because it never does a copy - we don't need the trailing null
-    str::as_buf(text, |ctext, _l| {
-        hb_buffer_add_utf8(buffer, ctext as *c_char,
-                           text.len() as c_int,
-                           0 as c_uint,
-                           text.len() as c_int);
-    });
+#[doc = "Text shaped from left to right, in the script of its characters"]
+fn default_shaping_params() -> ShapingParams {
+    ShapingParams {
+        rtl: false,
+        script: None,
+        language: None
+    }
+}
 
-    hb_shape(hbfont, buffer, null(), 0 as c_uint);
+impl ShapingParams {
+    pure fn is_default() -> bool {
+        !self.rtl && self.script.is_none() && self.language.is_none()
+    }
+}
 
-    let info_len = 0 as c_uint;
-    let info_ = hb_buffer_get_glyph_infos(buffer, addr_of(info_len));
-    assert info_.is_not_null();
-    let pos_len = 0 as c_uint;
-    let pos = hb_buffer_get_glyph_positions(buffer, addr_of(pos_len));
-    assert pos.is_not_null();
+impl Shaper {
+    #[doc = "Shapes text, returning its glyphs, each with the byte offset
+             of the text it comes from."]
+    fn shape(text: &str, params: &ShapingParams) -> ~[Glyph] unsafe {
+        #debug("shaping text '%s' with %?", text, *params);
 
-    assert info_len == pos_len;
+        let buffer = hb_buffer_create();
 
-    let mut glyphs = ~[];
+        let direction = if params.rtl { HB_DIRECTION_RTL } else { HB_DIRECTION_LTR };
+        hb_buffer_set_direction(buffer, direction);
+        match params.script {
+          Some(script) => hb_buffer_set_script(buffer, script as hb_script_t),
+          None => {}
+        }
+        match params.language {
+          Some(ref language) => {
+            let hblanguage = str::as_c_str(*language, |language| {
+                hb_language_from_string(language, -1 as c_int)
+            });
+            hb_buffer_set_language(buffer, hblanguage);
+          }
+          None => {}
+        }
 
-    for uint::range(0u, info_len as uint) |i| {
-        let info_ = offset(info_, i);
-        let pos = offset(pos, i);
-        let codepoint = (*info_).codepoint as uint;
-        let pos = hb_glyph_pos_to_servo_glyph_pos(&*pos);
-        #debug("glyph %?: codep %?, x_adv %?, y_adv %?, x_off %?, y_of %?",
-               i, codepoint, pos.advance.x, pos.advance.y, pos.offset.x, pos.offset.y);
+        // Using as_buf because it never does a copy - we don't need the trailing null
+        str::as_buf(text, |ctext, _l| {
+            hb_buffer_add_utf8(buffer, ctext as *c_char,
+                               text.len() as c_int,
+                               0 as c_uint,
+                               text.len() as c_int);
+        });
 
-        glyphs += ~[Glyph(codepoint, pos)];
+        // Whatever was not given is guessed from the text; the direction set above stays
+        hb_buffer_guess_segment_properties(buffer);
+        hb_shape(self.hbfont, buffer, null(), 0 as c_uint);
+
+        let info_len = 0 as c_uint;
+        let info_ = hb_buffer_get_glyph_infos(buffer, addr_of(info_len));
+        assert info_.is_not_null();
+        let pos_len = 0 as c_uint;
+        let pos = hb_buffer_get_glyph_positions(buffer, addr_of(pos_len));
+        assert pos.is_not_null();
+
+        assert info_len == pos_len;
+
+        let mut glyphs = ~[];
+
+        for uint::range(0u, info_len as uint) |i| {
+            let info_ = offset(info_, i);
+            let pos = offset(pos, i);
+            let codepoint = (*info_).codepoint as uint;
+            let pos = hb_glyph_pos_to_servo_glyph_pos(&*pos);
+            #debug("glyph %?: codep %?, x_adv %?, y_adv %?, x_off %?, y_of %?",
+                   i, codepoint, pos.advance.x, pos.advance.y, pos.offset.x, pos.offset.y);
+
+            glyphs += ~[Glyph(codepoint, pos, (*info_).cluster as uint)];
+        }
+
+        hb_buffer_destroy(buffer);
+
+        return glyphs;
     }
+}
+
+// The number of texts whose glyphs a font keeps before starting over
+const SHAPE_CACHE_SIZE: uint = 4096;
+
+#[doc = "
+Calculate the layout metrics associated with a some given text
+when rendered in a specific font. The glyphs are kept by the font, so that
+text is shaped once for each font it is drawn in.
+"]
+fn shape_text(font: &Font, text: &str) -> ~[Glyph] {
+    shape_text_with_params(font, text, &default_shaping_params())
+}
 
-    hb_buffer_destroy(buffer);
-    hb_font_funcs_destroy(funcs);
-    hb_font_destroy(hbfont);
-    hb_face_destroy(hbface);
-    hb_blob_destroy(face_blob);
+#[doc = "Like shape_text, for text in a given direction, script and language"]
+fn shape_text_with_params(font: &Font, text: &str, params: &ShapingParams) -> ~[Glyph] {
+    let key = shape_cache_key(text, params);
+    match font.shape_cache.find(copy key) {
+      Some(glyphs) => return copy *glyphs,
+      None => {}
+    }
 
+    let glyphs = font.get_shaper().shape(text, params);
+    if font.shape_cache.size() >= SHAPE_CACHE_SIZE {
+        font.shape_cache.clear();
+    }
+    font.shape_cache.insert(key, @copy glyphs);
     return glyphs;
 }
 
+// Text shaped by default is kept by itself, and other text after a NUL and
+// the parameters, which text does not start with
+fn shape_cache_key(text: &str, params: &ShapingParams) -> ~str {
+    if params.is_default() {
+        return str::from_slice(text);
+    }
+    fmt!("\x00%s:%u:%s:%s", if params.rtl { ~"rtl" } else { ~"ltr" },
+         params.script.get_default(0) as uint, params.language.get_default(~""), text)
+}
+
 extern fn glyph_func(_font: *hb_font_t,
                      font_data: *c_void,
                      unicode: hb_codepoint_t,
@@ -134,14 +247,12 @@ extern fn glyph_h_advance_func(_font: *hb_font_t,
 
     let h_advance = (*font).glyph_h_advance(glyph as uint);
     #debug("h_advance for codepoint %? is %?", glyph, h_advance);
-    return h_advance as hb_position_t;
+    return *au::from_px(h_advance) as hb_position_t;
 }
 
 fn hb_glyph_pos_to_servo_glyph_pos(hb_pos: &hb_glyph_position_t) -> GlyphPos {
-    GlyphPos(Point2D(au::from_px(hb_pos.x_advance as int),
-                     au::from_px(hb_pos.y_advance as int)),
-             Point2D(au::from_px(hb_pos.x_offset as int),
-                     au::from_px(hb_pos.y_offset as int)))
+    GlyphPos(Point2D(au(hb_pos.x_advance as i32), au(hb_pos.y_advance as i32)),
+             Point2D(au(hb_pos.x_offset as i32), au(hb_pos.y_offset as i32)))
 }
 
 fn should_get_glyph_indexes() {
@@ -166,3 +277,41 @@ fn should_get_glyph_h_advance() {
     let expected = (~[6, 4, 7, 9, 8, 7, 10, 8, 9, 9, 7]).map(|a| au::from_px(a));
     assert expected == actual;
 }
+
+fn should_record_the_text_of_each_glyph() {
+    #[test];
+    #[ignore(cfg(target_os = "macos"), reason = "bad metrics")];
+
+    let lib = FontCache();
+    let font = lib.get_test_font();
+    let glyphs = shape_text(font, ~"fire cracker");
+    let clusters = glyphs.map(|glyph| glyph.cluster);
+    assert clusters == ~[0u, 1u, 2u, 3u, 4u, 5u, 6u, 7u, 8u, 9u, 10u, 11u];
+}
+
+fn should_shape_right_to_left_text_in_visual_order() {
+    #[test];
+    #[ignore(cfg(target_os = "macos"), reason = "bad metrics")];
+
+    let lib = FontCache();
+    let font = lib.get_test_font();
+    let params = ShapingParams { rtl: true, script: None, language: None };
+    let glyphs = shape_text_with_params(font, ~"fire", &params);
+    let clusters = glyphs.map(|glyph| glyph.cluster);
+    assert clusters == ~[3u, 2u, 1u, 0u];
+    // The same text shaped from left to right is kept apart
+    shape_text(font, ~"fire");
+    assert font.shape_cache.size() == 2u;
+}
+
+fn should_shape_text_once_for_each_font() {
+    #[test];
+
+    let lib = FontCache();
+    let font = lib.get_test_font();
+    let glyphs = shape_text(font, ~"firecracker");
+    assert font.shape_cache.size() == 1u;
+    let idxs = shape_text(font, ~"firecracker").map(|glyph| glyph.index);
+    assert idxs == glyphs.map(|glyph| glyph.index);
+    assert font.shape_cache.size() == 1u;
+}
@@ -6,7 +6,7 @@ use libc::{c_void};
 use font_cache::FontCache;
 use font::Font;
 use glyph::Glyph;
-use shaper::shape_text;
+use shaper::{ShapingParams, default_shaping_params, shape_text, shape_text_with_params};
 
 /// A single, unbroken line of text
 struct TextRun {
@@ -21,63 +21,92 @@ impl TextRun {
     pure fn size() -> Size2D<au> { self.size_ }
     pure fn min_break_width() -> au { self.min_break_width_ }
 
-    /// Split a run of text in two
-    // FIXME: Should be storing a reference to the Font inside
-    // of the TextRun, but I'm hitting cycle collector bugs
-    fn split(font: &Font, h_offset: au) -> (TextRun, TextRun) {
-        assert h_offset >= self.min_break_width();
-        assert h_offset <= self.size_.width;
-
-        let mut curr_run = ~"";
-
-        for iter_indivisible_slices(font, self.text) |slice| {
-            let mut candidate = copy curr_run;
+    /// The width of the text up to a byte offset, leaving out the
+    /// whitespace before it
+    fn prefix_width(end: uint) -> au {
+        let end = trim_end(self.text, end);
+        if end == self.text.len() {
+            return self.size_.width;
+        }
+        glyphs_width(self.glyphs, 0, end)
+    }
 
-            if candidate.is_not_empty() {
-                str::push_str(candidate, " "); // FIXME: just inserting spaces between words can't be right
+    /// The last line break opportunity at which the text before it, without
+    /// its trailing whitespace, fits in the given width
+    fn find_break(h_offset: au) -> Option<uint> {
+        let mut found = None;
+        for line_break_opportunities(self.text).each |idx| {
+            if self.prefix_width(*idx) > h_offset {
+                break;
             }
+            found = Some(*idx);
+        }
+        return found;
+    }
 
-            str::push_str(candidate, slice);
-
-            let glyphs = shape_text(font, candidate);
-            let size = glyph_run_size(glyphs);
-            if size.width <= h_offset {
-                curr_run = move candidate;
+    /// The run of the text between two byte offsets, drawn with the glyphs
+    /// this run has for it rather than shaping it again
+    fn slice(start: uint, end: uint) -> TextRun {
+        let glyphs = do vec::filter_map(self.glyphs) |glyph| {
+            if glyph.cluster >= start && glyph.cluster < end {
+                Some(Glyph(glyph.index, glyph.pos, glyph.cluster - start))
             } else {
-                break;
+                None
             }
+        };
+        let text = str::slice(self.text, start, end);
+        let width = glyphs_width(glyphs, 0, text.len());
+        let min_break_width = calc_glyphs_min_break_width(text, glyphs);
+
+        TextRun {
+            text: text,
+            glyphs: glyphs,
+            size_: Size2D(width, self.size_.height),
+            min_break_width_: min_break_width
         }
+    }
 
-        assert curr_run.is_not_empty();
+    /// Split a run of text in two, at the last line break opportunity that
+    /// leaves the first run no wider than `h_offset`. The whitespace at the
+    /// break is dropped.
+    fn split(h_offset: au) -> (TextRun, TextRun) {
+        assert h_offset >= self.min_break_width();
+        assert h_offset <= self.size_.width;
 
-        let first = move curr_run;
-        let second_start = match str::find_from(self.text, first.len(), |c| !char::is_whitespace(c)) {
+        let idx = match self.find_break(h_offset) {
             Some(idx) => idx,
-            None => {
-                // This will be an empty string
-                self.text.len()
-            }
+            None => fail ~"no line break opportunity fits in the given width"
         };
-        let second = str::slice(self.text, second_start, self.text.len());
-        return (TextRun(font, first), TextRun(font, second));
+
+        let first = self.slice(0, trim_end(self.text, idx));
+        let second = self.slice(idx, self.text.len());
+        return (first, second);
     }
 }
 
 fn TextRun(font: &Font, +text: ~str) -> TextRun {
-    let glyphs = shape_text(font, text);
-    let size = glyph_run_size(glyphs);
-    let min_break_width = calc_min_break_width(font, text);
+    TextRunWithParams(font, text, &default_shaping_params())
+}
+
+/// Like TextRun, for text in a given direction, script and language. The
+/// glyphs of text written from right to left are kept in visual order.
+fn TextRunWithParams(font: &Font, +text: ~str, params: &ShapingParams) -> TextRun {
+    let glyphs = shape_text_with_params(font, text, params);
+    let size = glyph_run_size(font, glyphs);
+    let min_break_width = calc_glyphs_min_break_width(text, glyphs);
 
     TextRun {
         text: text,
-        glyphs: shape_text(font, text),
+        glyphs: glyphs,
         size_: size,
         min_break_width_: min_break_width
     }
 }
 
-fn glyph_run_size(glyphs: &[Glyph]) -> Size2D<au> {
-    let height = au::from_px(20);
+/// The size of a run of glyphs: as wide as their advances, and as high as
+/// the font reaches above and below the baseline
+fn glyph_run_size(font: &Font, glyphs: &[Glyph]) -> Size2D<au> {
+    let height = font.ascent().add(font.descent());
     let pen_start_x = au::from_px(0);
     let pen_start_y = height;
     let pen_start = Point2D(pen_start_x, pen_start_y);
@@ -88,48 +117,122 @@ fn glyph_run_size(glyphs: &[Glyph]) -> Size2D<au> {
     return Size2D(pen_end.x, pen_end.y);
 }
 
+/// The width of the glyphs drawn for the text between two byte offsets
+fn glyphs_width(glyphs: &[Glyph], start: uint, end: uint) -> au {
+    let mut width = au(0);
+    for glyphs.each |glyph| {
+        if glyph.cluster >= start && glyph.cluster < end {
+            width = width.add(glyph.pos.offset.x).add(glyph.pos.advance.x);
+        }
+    }
+    return width;
+}
+
 /// Discovers the width of the largest indivisible substring
 fn calc_min_break_width(font: &Font, text: &str) -> au {
+    calc_glyphs_min_break_width(text, shape_text(font, text))
+}
+
+/// Discovers the width of the largest indivisible substring of text that
+/// has been shaped into the given glyphs
+fn calc_glyphs_min_break_width(text: &str, glyphs: &[Glyph]) -> au {
     let mut max_piece_width = au(0);
-    for iter_indivisible_slices(font, text) |slice| {
-        let glyphs = shape_text(font, slice);
-        let size = glyph_run_size(glyphs);
-        if size.width > max_piece_width {
-            max_piece_width = size.width
+    for iter_indivisible_ranges(text) |start, end| {
+        let width = glyphs_width(glyphs, start, end);
+        if width > max_piece_width {
+            max_piece_width = width
         }
     }
     return max_piece_width;
 }
 
-/// Iterates over all the indivisible substrings
-fn iter_indivisible_slices(_font: &Font, text: &r/str,
-                           f: fn((&r/str)) -> bool) {
+pure fn is_ascii_space(b: u8) -> bool {
+    b == ' ' as u8 || b == '\t' as u8 || b == '\n' as u8 || b == '\r' as u8
+}
 
-    let mut curr = text;
-    loop {
-        match str::find(curr, |c| !char::is_whitespace(c) ) {
-          Some(idx) => {
-            curr = str::view(curr, idx, curr.len());
-          }
-          None => {
-            // Everything else is whitespace
-            break
-          }
+/// The offset of the end of the text before `end`, without its trailing whitespace
+pure fn trim_end(text: &str, end: uint) -> uint {
+    let mut end = end;
+    while end > 0 && is_ascii_space(text[end - 1]) {
+        end -= 1;
+    }
+    return end;
+}
+
+/// Whether a character is written without spaces between words, so that
+/// lines may break around it
+pure fn is_ideographic(c: char) -> bool {
+    (c >= '\u3040' && c <= '\u30ff')      // Hiragana and Katakana
+        || (c >= '\u3400' && c <= '\u4dbf')   // CJK Unified Ideographs Extension A
+        || (c >= '\u4e00' && c <= '\u9fff')   // CJK Unified Ideographs
+        || (c >= '\uac00' && c <= '\ud7af')   // Hangul Syllables
+        || (c >= '\uf900' && c <= '\ufaff')   // CJK Compatibility Ideographs
+}
+
+/// Whether a character must stay on the line of the one before it, as
+/// closing punctuation does
+pure fn is_no_break_before(c: char) -> bool {
+    match c {
+      ',' | '.' | ':' | ';' | '!' | '?' | ')' | ']' | '}' | '\u3001' | '\u3002' | '\uff0c'
+      | '\uff0e' | '\uff09' => true,
+      _ => false
+    }
+}
+
+/// The byte offsets at which lines may be broken: before a word that
+/// follows whitespace, after a hyphen inside a word, and around ideographs.
+/// This is a simplified form of the Unicode line breaking algorithm (UAX
+/// #14); mandatory breaks are left to whitespace processing.
+fn line_break_opportunities(text: &str) -> ~[uint] {
+    let mut breaks = ~[];
+    let mut prev = None;
+    let mut prev_prev = None;
+    let mut i = 0u;
+    while i < text.len() {
+        let {ch, next} = str::char_range_at(text, i);
+        let can_break = match prev {
+            None => false,
+            Some(_) if char::is_whitespace(ch) || is_no_break_before(ch) => false,
+            Some(p) if char::is_whitespace(p) => true,
+            Some('-') => match prev_prev {
+                Some(pp) => char::is_alphanumeric(pp) && char::is_alphanumeric(ch),
+                None => false
+            },
+            Some(p) => is_ideographic(p) || is_ideographic(ch)
+        };
+        if can_break {
+            vec::push(breaks, i);
         }
+        prev_prev = prev;
+        prev = Some(ch);
+        i = next;
+    }
+    return breaks;
+}
 
-        match str::find(curr, |c| char::is_whitespace(c) ) {
-          Some(idx) => {
-            let piece = str::view(curr, 0, idx);
-            if !f(piece) { break }
-            curr = str::view(curr, idx, curr.len());
-          }
-          None => {
-            assert curr.is_not_empty();
-            if !f(curr) { break }
-            // This is the end of the string
-            break;
-          }
+/// Iterates over the byte ranges of all the indivisible substrings,
+/// without the whitespace around them
+fn iter_indivisible_ranges(text: &str, f: fn(uint, uint) -> bool) {
+    let mut start = 0u;
+    let breaks = line_break_opportunities(text) + ~[text.len()];
+    for breaks.each |idx| {
+        let end = trim_end(text, *idx);
+        while start < end && is_ascii_space(text[start]) {
+            start += 1;
+        }
+        if start < end {
+            if !f(start, end) { break }
         }
+        start = *idx;
+    }
+}
+
+/// Iterates over all the indivisible substrings, without the whitespace
+/// around them
+fn iter_indivisible_slices(_font: &Font, text: &r/str,
+                           f: fn((&r/str)) -> bool) {
+    for iter_indivisible_ranges(text) |start, end| {
+        if !f(str::view(text, start, end)) { break }
     }
 }
 
@@ -218,7 +321,7 @@ fn test_split() {
     let flib = FontCache();
     let font = flib.get_test_font();
     let run = TextRun(font, ~"firecracker yumyum");
-    let break_runs = run.split(font, run.min_break_width());
+    let break_runs = run.split(run.min_break_width());
     assert break_runs.first().text == ~"firecracker";
     assert break_runs.second().text == ~"yumyum";
 }
@@ -228,7 +331,7 @@ fn test_split2() {
     let flib = FontCache();
     let font = flib.get_test_font();
     let run = TextRun(font, ~"firecracker yum yum yum yum yum");
-    let break_runs = run.split(font, run.min_break_width());
+    let break_runs = run.split(run.min_break_width());
     assert break_runs.first().text == ~"firecracker";
     assert break_runs.second().text == ~"yum yum yum yum yum";
 }
@@ -238,12 +341,31 @@ fn test_split3() {
     let flib = FontCache();
     let font = flib.get_test_font();
     let run = TextRun(font, ~"firecracker firecracker");
-    let break_runs = run.split(font, run.min_break_width() + au::from_px(10));
+    let break_runs = run.split(run.min_break_width() + au::from_px(10));
     assert break_runs.first().text == ~"firecracker";
     assert break_runs.second().text == ~"firecracker";
 
 }
 
+#[test]
+fn test_split_keeps_spacing() {
+    let flib = FontCache();
+    let font = flib.get_test_font();
+    let run = TextRun(font, ~"firecracker  yum  yum");
+    let break_runs = run.split(run.min_break_width());
+    assert break_runs.first().text == ~"firecracker";
+    assert break_runs.second().text == ~"yum  yum";
+}
+
+#[test]
+fn test_line_break_opportunities() {
+    assert line_break_opportunities("firecracker yumyum") == ~[12u];
+    assert line_break_opportunities("  firecracker  ") == ~[2u];
+    assert line_break_opportunities("fire-cracker - yum") == ~[5u, 13u, 15u];
+    assert line_break_opportunities("(yum), yum.") == ~[7u];
+    assert line_break_opportunities("\u706b\u7bad\u3002") == ~[3u];
+}
+
 #[test]
 #[ignore(cfg(target_os = "macos"))]
 fn should_calculate_the_total_size() {
@@ -254,3 +376,15 @@ fn should_calculate_the_total_size() {
     assert run.size() == expected;
 }
 
+
+#[test]
+fn should_measure_slices_without_shaping_them_again() {
+    let flib = FontCache();
+    let font = flib.get_test_font();
+    let run = TextRun(font, ~"firecracker yumyum");
+    let slice = run.slice(12, run.text.len());
+    assert slice.text == ~"yumyum";
+    assert slice.size() == TextRun(font, ~"yumyum").size();
+    assert slice.min_break_width() == slice.size().width;
+    assert run.prefix_width(12) == run.slice(0, 11).size().width;
+}